@@ -0,0 +1,234 @@
+use crate::{asm, error::Result, mutex::Mutex};
+use core::{
+    alloc::{GlobalAlloc, Layout},
+    mem::{align_of, size_of},
+    ptr::null_mut,
+};
+
+const HEAP_SIZE: usize = 16 * 1024 * 1024; // 16MiB
+
+#[global_allocator]
+static ALLOCATOR: Allocator = Allocator;
+
+static mut HEAP: Mutex<Heap> = Mutex::new(Heap::new());
+static mut HEAP_REGION: HeapRegion = HeapRegion([0; HEAP_SIZE]);
+// blocks freed while the heap is locked, e.g. from an interrupt handler, are queued here and
+// returned by the next heap operation, only accessed with interrupts disabled
+static mut DEFERRED_FREE: *mut ListNode = null_mut();
+
+#[repr(C, align(4096))]
+struct HeapRegion([u8; HEAP_SIZE]);
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct HeapStats {
+    pub base: usize,
+    pub size: usize,
+    pub used: usize,
+    pub peak: usize,
+    pub alloc_count: usize,
+    pub free_count: usize,
+}
+
+struct ListNode {
+    size: usize,
+    next: *mut ListNode,
+}
+
+// first-fit free list allocator, free blocks are sorted by address and merged on dealloc
+struct Heap {
+    head: *mut ListNode,
+    stats: HeapStats,
+}
+
+impl Heap {
+    const fn new() -> Self {
+        Self {
+            head: null_mut(),
+            stats: HeapStats {
+                base: 0,
+                size: 0,
+                used: 0,
+                peak: 0,
+                alloc_count: 0,
+                free_count: 0,
+            },
+        }
+    }
+
+    fn init(&mut self, base: usize, size: usize) -> Result<()> {
+        if self.stats.size != 0 {
+            return Err("Heap is already initialized".into());
+        }
+
+        let start = align_up(base, align_of::<ListNode>());
+        let size = (size - (start - base)) & !(align_of::<ListNode>() - 1);
+        unsafe { self.add_free_region(start, size) };
+
+        self.stats.base = start;
+        self.stats.size = size;
+        Ok(())
+    }
+
+    fn size_align(layout: Layout) -> (usize, usize) {
+        let align = layout.align().max(align_of::<ListNode>());
+        let size = align_up(
+            layout.size().max(size_of::<ListNode>()),
+            align_of::<ListNode>(),
+        );
+        (size, align)
+    }
+
+    unsafe fn add_free_region(&mut self, addr: usize, size: usize) {
+        let mut prev: *mut ListNode = null_mut();
+        let mut next = self.head;
+
+        while !next.is_null() && (next as usize) < addr {
+            prev = next;
+            next = (*next).next;
+        }
+
+        let node = addr as *mut ListNode;
+        node.write(ListNode { size, next });
+
+        // merge with the next block
+        if !next.is_null() && addr + size == next as usize {
+            (*node).size += (*next).size;
+            (*node).next = (*next).next;
+        }
+
+        if prev.is_null() {
+            self.head = node;
+            return;
+        }
+
+        // merge with the previous block
+        if prev as usize + (*prev).size == addr {
+            (*prev).size += (*node).size;
+            (*prev).next = (*node).next;
+        } else {
+            (*prev).next = node;
+        }
+    }
+
+    fn alloc(&mut self, layout: Layout) -> *mut u8 {
+        let (size, align) = Self::size_align(layout);
+        let node_size = size_of::<ListNode>();
+
+        let mut prev: *mut ListNode = null_mut();
+        let mut current = self.head;
+
+        while !current.is_null() {
+            let region_start = current as usize;
+            let region_end = region_start + unsafe { (*current).size };
+
+            let mut alloc_start = align_up(region_start, align);
+            if alloc_start != region_start && alloc_start - region_start < node_size {
+                // leave enough room for a free block in front of the allocation
+                alloc_start = align_up(region_start + node_size, align);
+            }
+
+            let alloc_end = alloc_start + size;
+            let back_size = region_end.saturating_sub(alloc_end);
+
+            if alloc_end <= region_end && (back_size == 0 || back_size >= node_size) {
+                let next = unsafe { (*current).next };
+                if prev.is_null() {
+                    self.head = next;
+                } else {
+                    unsafe { (*prev).next = next };
+                }
+
+                unsafe {
+                    if alloc_start > region_start {
+                        self.add_free_region(region_start, alloc_start - region_start);
+                    }
+
+                    if back_size > 0 {
+                        self.add_free_region(alloc_end, back_size);
+                    }
+                }
+
+                self.stats.used += size;
+                self.stats.peak = self.stats.peak.max(self.stats.used);
+                self.stats.alloc_count += 1;
+                return alloc_start as *mut u8;
+            }
+
+            prev = current;
+            current = unsafe { (*current).next };
+        }
+
+        null_mut()
+    }
+
+    fn free_deferred(&mut self) {
+        let mut node = asm::disabled_int(|| unsafe {
+            let node = DEFERRED_FREE;
+            DEFERRED_FREE = null_mut();
+            node
+        });
+
+        while !node.is_null() {
+            let (size, next) = unsafe { ((*node).size, (*node).next) };
+            unsafe { self.add_free_region(node as usize, size) };
+            self.stats.used -= size;
+            self.stats.free_count += 1;
+            node = next;
+        }
+    }
+
+    fn dealloc(&mut self, ptr: *mut u8, layout: Layout) {
+        let (size, _) = Self::size_align(layout);
+        unsafe { self.add_free_region(ptr as usize, size) };
+
+        self.stats.used -= size;
+        self.stats.free_count += 1;
+    }
+}
+
+struct Allocator;
+
+unsafe impl GlobalAlloc for Allocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        match HEAP.try_lock() {
+            Ok(mut heap) => {
+                heap.free_deferred();
+                heap.alloc(layout)
+            }
+            Err(_) => null_mut(),
+        }
+    }
+
+    // never fails, every block is large enough to hold a list node
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        match HEAP.try_lock() {
+            Ok(mut heap) => {
+                heap.free_deferred();
+                heap.dealloc(ptr, layout);
+            }
+            Err(_) => asm::disabled_int(|| {
+                let (size, _) = Heap::size_align(layout);
+                let node = ptr as *mut ListNode;
+                node.write(ListNode {
+                    size,
+                    next: DEFERRED_FREE,
+                });
+                DEFERRED_FREE = node;
+            }),
+        }
+    }
+}
+
+fn align_up(value: usize, align: usize) -> usize {
+    (value + align - 1) & !(align - 1)
+}
+
+pub fn init() -> Result<()> {
+    let base = unsafe { HEAP_REGION.0.as_mut_ptr() } as usize;
+    unsafe { HEAP.try_lock() }?.init(base, HEAP_SIZE)
+}
+
+pub fn stats() -> Result<HeapStats> {
+    let heap = unsafe { HEAP.try_lock() }?;
+    Ok(heap.stats)
+}
@@ -0,0 +1,165 @@
+// parser for the VT100/xterm escape sequences used by the framebuffer console
+// https://vt100.net/emu/dec_ansi_parser
+
+const MAX_PARAMS: usize = 16;
+
+const ESC: char = '\x1b';
+const BEL: char = '\x07';
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Params {
+    values: [u16; MAX_PARAMS],
+    len: usize,
+}
+
+impl Params {
+    const fn new() -> Self {
+        Self {
+            values: [0; MAX_PARAMS],
+            len: 0,
+        }
+    }
+
+    pub fn as_slice(&self) -> &[u16] {
+        &self.values[..self.len]
+    }
+
+    // missing and zero parameters take the default
+    pub fn get(&self, index: usize, default: u16) -> u16 {
+        match self.as_slice().get(index) {
+            Some(&value) if value != 0 => value,
+            _ => default,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    Print(char),
+    // C0 control characters such as newline and backspace
+    Control(char),
+    // ESC followed by a final character, e.g. ESC 7
+    Escape(char),
+    // ESC [ <private> <params> <final>
+    Csi {
+        private: Option<char>,
+        params: Params,
+        final_char: char,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    Ground,
+    Escape,
+    Csi,
+    // operating system commands such as window titles are ignored
+    Osc,
+    OscEscape,
+}
+
+pub struct Parser {
+    state: State,
+    private: Option<char>,
+    params: Params,
+}
+
+impl Parser {
+    pub const fn new() -> Self {
+        Self {
+            state: State::Ground,
+            private: None,
+            params: Params::new(),
+        }
+    }
+
+    pub fn advance(&mut self, c: char) -> Option<Action> {
+        match self.state {
+            State::Ground => match c {
+                ESC => {
+                    self.state = State::Escape;
+                    None
+                }
+                c if c.is_control() => Some(Action::Control(c)),
+                c => Some(Action::Print(c)),
+            },
+            State::Escape => match c {
+                '[' => {
+                    self.state = State::Csi;
+                    self.private = None;
+                    self.params = Params::new();
+                    None
+                }
+                ']' => {
+                    self.state = State::Osc;
+                    None
+                }
+                // restarts the sequence
+                ESC => None,
+                c => {
+                    self.state = State::Ground;
+                    Some(Action::Escape(c))
+                }
+            },
+            State::Csi => self.csi(c),
+            State::Osc => {
+                match c {
+                    BEL => self.state = State::Ground,
+                    ESC => self.state = State::OscEscape,
+                    _ => (),
+                }
+                None
+            }
+            // ESC \ ends the command
+            State::OscEscape => {
+                self.state = if c == '\\' { State::Ground } else { State::Osc };
+                None
+            }
+        }
+    }
+
+    fn csi(&mut self, c: char) -> Option<Action> {
+        match c {
+            '0'..='9' => {
+                if self.params.len == 0 {
+                    self.params.len = 1;
+                }
+                let value = &mut self.params.values[self.params.len - 1];
+                *value = value
+                    .saturating_mul(10)
+                    .saturating_add(c as u16 - '0' as u16);
+                None
+            }
+            ';' | ':' => {
+                if self.params.len == 0 {
+                    self.params.len = 1;
+                }
+                // extra parameters are dropped
+                if self.params.len < MAX_PARAMS {
+                    self.params.len += 1;
+                }
+                None
+            }
+            '<'..='?' if self.params.len == 0 => {
+                self.private = Some(c);
+                None
+            }
+            // aborts the sequence and starts a new one
+            ESC => {
+                self.state = State::Escape;
+                None
+            }
+            '\x40'..='\x7e' => {
+                self.state = State::Ground;
+                Some(Action::Csi {
+                    private: self.private,
+                    params: self.params,
+                    final_char: c,
+                })
+            }
+            c if c.is_control() => Some(Action::Control(c)),
+            // intermediate bytes are not used by the supported sequences
+            _ => None,
+        }
+    }
+}
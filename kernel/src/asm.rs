@@ -34,6 +34,26 @@ pub fn read_main_id_reg() -> u32 {
     value
 }
 
+pub fn read_mpidr() -> u64 {
+    let value;
+
+    unsafe {
+        asm!("mrs {0}, mpidr_el1", out(reg) value);
+    }
+
+    value
+}
+
+pub fn read_fp() -> u64 {
+    let value;
+
+    unsafe {
+        asm!("mov {0}, x29", out(reg) value);
+    }
+
+    value
+}
+
 pub fn read_x0() -> u64 {
     let value;
 
@@ -44,9 +64,140 @@ pub fn read_x0() -> u64 {
     value
 }
 
-pub fn disabled_int<F: FnMut() -> R, R>(mut func: F) -> R {
+pub fn read_daif() -> u64 {
+    let value;
+
+    unsafe {
+        asm!("mrs {0}, daif", out(reg) value);
+    }
+
+    value
+}
+
+pub fn write_daif(value: u64) {
+    unsafe {
+        asm!("msr daif, {0}", in(reg) value);
+    }
+}
+
+pub fn enable_int() {
+    unsafe { asm!("msr daifclr, #3") }; // enable IRQ and FIQ interrupts
+}
+
+pub fn disable_int() {
     unsafe { asm!("msr daifset, #3") }; // disable IRQ and FIQ interrupts
+}
+
+pub fn disabled_int<F: FnMut() -> R, R>(mut func: F) -> R {
+    // restore the previous mask so that this is safe to call from interrupt handlers
+    let daif = read_daif();
+    disable_int();
     let res = func();
-    unsafe { asm!("msr daifclr, #3") }; // enable IRQ and FIQ interrupts
+    write_daif(daif);
     res
 }
+
+pub fn read_current_el() -> u64 {
+    let value: u64;
+
+    unsafe {
+        asm!("mrs {0}, CurrentEL", out(reg) value);
+    }
+
+    (value >> 2) & 0x3
+}
+
+pub fn write_vbar_el1(value: u64) {
+    unsafe {
+        asm!("msr vbar_el1, {0}", "isb", in(reg) value);
+    }
+}
+
+pub fn wait_for_interrupt() {
+    unsafe { asm!("wfi") }
+}
+
+// wakes the cores waiting in wfe once earlier writes are visible to them
+pub fn send_event() {
+    unsafe { asm!("dsb sy", "sev") }
+}
+
+pub fn read_mdscr_el1() -> u64 {
+    let value;
+
+    unsafe {
+        asm!("mrs {0}, mdscr_el1", out(reg) value);
+    }
+
+    value
+}
+
+pub fn write_mdscr_el1(value: u64) {
+    unsafe {
+        asm!("msr mdscr_el1, {0}", "isb", in(reg) value);
+    }
+}
+
+// clears the OS lock, debug exceptions are not generated while it is set
+pub fn unlock_os_lock() {
+    unsafe {
+        asm!("msr oslar_el1, xzr", "isb");
+    }
+}
+
+// unmasks debug exceptions (PSTATE.D), needed for hardware breakpoints and watchpoints
+pub fn enable_debug_exceptions() {
+    unsafe { asm!("msr daifclr, #8", "isb") };
+}
+
+pub fn read_id_aa64dfr0_el1() -> u64 {
+    let value;
+
+    unsafe {
+        asm!("mrs {0}, id_aa64dfr0_el1", out(reg) value);
+    }
+
+    value
+}
+
+// the debug register number is encoded in the instruction
+macro_rules! write_debug_register {
+    ($name:literal, $index:expr, $value:expr) => {
+        write_debug_register!($name, $index, $value, 0 1 2 3 4 5 6 7 8 9 10 11 12 13 14 15)
+    };
+    ($name:literal, $index:expr, $value:expr, $($n:literal)*) => {
+        match $index {
+            $($n => unsafe {
+                asm!(concat!("msr ", $name, $n, "_el1, {0}"), "isb", in(reg) $value)
+            },)*
+            _ => (),
+        }
+    };
+}
+
+pub fn write_dbgbvr_el1(index: usize, value: u64) {
+    write_debug_register!("dbgbvr", index, value);
+}
+
+pub fn write_dbgbcr_el1(index: usize, value: u64) {
+    write_debug_register!("dbgbcr", index, value);
+}
+
+pub fn write_dbgwvr_el1(index: usize, value: u64) {
+    write_debug_register!("dbgwvr", index, value);
+}
+
+pub fn write_dbgwcr_el1(index: usize, value: u64) {
+    write_debug_register!("dbgwcr", index, value);
+}
+
+pub fn invalidate_icache() {
+    unsafe {
+        asm!("dsb ish", "ic iallu", "dsb ish", "isb");
+    }
+}
+
+// must match gdb::BRK_ENTRY_IMM
+pub fn gdb_breakpoint() {
+    unsafe { asm!("brk #0xf000") }
+}
@@ -0,0 +1,76 @@
+use crate::{asm, symbols};
+use core::fmt::{self, Write};
+
+const MAX_FRAMES: usize = 32;
+
+fn stack_top() -> u64 {
+    extern "C" {
+        static _boot: u8;
+    }
+
+    // the boot stack grows down from the kernel entry point
+    unsafe { &_boot as *const u8 as u64 }
+}
+
+// follows the frame records (x29 -> [previous x29, x30]) and returns the return addresses
+pub fn walk(mut fp: u64, mut func: impl FnMut(u64)) {
+    let top = stack_top();
+
+    for _ in 0..MAX_FRAMES {
+        if fp == 0 || fp % 8 != 0 || fp > top.saturating_sub(16) {
+            break;
+        }
+
+        let record = fp as *const u64;
+        let (next_fp, lr) = unsafe { (record.read_volatile(), record.add(1).read_volatile()) };
+        if lr < 4 {
+            break;
+        }
+
+        // lr points after the call
+        func(lr - 4);
+
+        // callers are further up the stack
+        if next_fp <= fp {
+            break;
+        }
+        fp = next_fp;
+    }
+}
+
+fn write_frame(w: &mut dyn Write, index: usize, pc: u64) -> fmt::Result {
+    write!(w, "  #{:<2} 0x{:016x}", index, pc)?;
+    match symbols::lookup(pc) {
+        Some((symbol, offset)) => writeln!(w, " {}+0x{:x}", symbol.name, offset),
+        None => writeln!(w),
+    }
+}
+
+// pc is written first if given, e.g. the faulting instruction of an exception
+pub fn write(w: &mut dyn Write, pc: Option<u64>, fp: u64) -> fmt::Result {
+    if symbols::count() == 0 {
+        writeln!(w, "backtrace (no symbol table):")?;
+    } else {
+        writeln!(w, "backtrace:")?;
+    }
+
+    let mut index = 0;
+    if let Some(pc) = pc {
+        write_frame(w, index, pc)?;
+        index += 1;
+    }
+
+    let mut res = Ok(());
+    walk(fp, |pc| {
+        if res.is_ok() {
+            res = write_frame(w, index, pc);
+        }
+        index += 1;
+    });
+    res
+}
+
+#[inline(always)]
+pub fn current_fp() -> u64 {
+    asm::read_fp()
+}
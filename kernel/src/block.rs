@@ -0,0 +1,301 @@
+use crate::{
+    error::{Error, Result},
+    mutex::Mutex,
+};
+use alloc::{
+    rc::Rc,
+    string::{String, ToString},
+    vec,
+    vec::Vec,
+};
+use core::cell::RefCell;
+
+pub type SharedBlockDevice = Rc<RefCell<dyn BlockDevice>>;
+
+static mut BLOCK_DEVICES: Mutex<Vec<(String, SharedBlockDevice)>> = Mutex::new(Vec::new());
+
+pub trait BlockDevice {
+    fn block_size(&self) -> usize;
+    fn block_count(&self) -> u64;
+    fn read_blocks(&mut self, lba: u64, buf: &mut [u8]) -> Result<()>;
+    fn write_blocks(&mut self, lba: u64, buf: &[u8]) -> Result<()>;
+
+    fn sync(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    fn check_range(&self, lba: u64, len: usize) -> Result<u64> {
+        let block_size = self.block_size();
+        if len % block_size != 0 {
+            return Err(Error::InvalidArgument);
+        }
+
+        let count = (len / block_size) as u64;
+        if lba + count > self.block_count() {
+            return Err("Block address out of range".into());
+        }
+
+        Ok(count)
+    }
+
+    // byte granular access, partial blocks are read-modify-written
+    fn read_bytes(&mut self, offset: u64, buf: &mut [u8]) -> Result<()> {
+        let block_size = self.block_size();
+        let mut block = vec![0; block_size];
+        let mut done = 0;
+
+        while done < buf.len() {
+            let pos = offset + done as u64;
+            let lba = pos / block_size as u64;
+            let block_offset = (pos % block_size as u64) as usize;
+            let len = (block_size - block_offset).min(buf.len() - done);
+
+            self.read_blocks(lba, &mut block)?;
+            buf[done..done + len].copy_from_slice(&block[block_offset..block_offset + len]);
+            done += len;
+        }
+
+        Ok(())
+    }
+
+    fn write_bytes(&mut self, offset: u64, buf: &[u8]) -> Result<()> {
+        let block_size = self.block_size();
+        let mut block = vec![0; block_size];
+        let mut done = 0;
+
+        while done < buf.len() {
+            let pos = offset + done as u64;
+            let lba = pos / block_size as u64;
+            let block_offset = (pos % block_size as u64) as usize;
+            let len = (block_size - block_offset).min(buf.len() - done);
+
+            if len != block_size {
+                self.read_blocks(lba, &mut block)?;
+            }
+            block[block_offset..block_offset + len].copy_from_slice(&buf[done..done + len]);
+            self.write_blocks(lba, &block)?;
+            done += len;
+        }
+
+        Ok(())
+    }
+}
+
+impl<T: BlockDevice + ?Sized> BlockDevice for Rc<RefCell<T>> {
+    fn block_size(&self) -> usize {
+        self.borrow().block_size()
+    }
+
+    fn block_count(&self) -> u64 {
+        self.borrow().block_count()
+    }
+
+    fn read_blocks(&mut self, lba: u64, buf: &mut [u8]) -> Result<()> {
+        self.borrow_mut().read_blocks(lba, buf)
+    }
+
+    fn write_blocks(&mut self, lba: u64, buf: &[u8]) -> Result<()> {
+        self.borrow_mut().write_blocks(lba, buf)
+    }
+
+    fn sync(&mut self) -> Result<()> {
+        self.borrow_mut().sync()
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub write_backs: u64,
+}
+
+struct CacheEntry {
+    lba: u64,
+    data: Vec<u8>,
+    dirty: bool,
+    last_used: u64,
+}
+
+// write-back cache with LRU eviction, dirty blocks reach the device on eviction or sync()
+pub struct BufferCache<D: BlockDevice> {
+    device: D,
+    entries: Vec<CacheEntry>,
+    capacity: usize,
+    clock: u64,
+    stats: CacheStats,
+}
+
+impl<D: BlockDevice> BufferCache<D> {
+    pub fn new(device: D, capacity: usize) -> Self {
+        Self {
+            device,
+            entries: Vec::with_capacity(capacity),
+            capacity: capacity.max(1),
+            clock: 0,
+            stats: CacheStats::default(),
+        }
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        self.stats
+    }
+
+    fn write_back(&mut self, index: usize) -> Result<()> {
+        let entry = &mut self.entries[index];
+        if entry.dirty {
+            self.device.write_blocks(entry.lba, &entry.data)?;
+            entry.dirty = false;
+            self.stats.write_backs += 1;
+        }
+
+        Ok(())
+    }
+
+    // returns the index of the entry for lba, loading it from the device if needed
+    fn entry(&mut self, lba: u64, load: bool) -> Result<usize> {
+        self.clock += 1;
+
+        if let Some(index) = self.entries.iter().position(|e| e.lba == lba) {
+            self.entries[index].last_used = self.clock;
+            self.stats.hits += 1;
+            return Ok(index);
+        }
+
+        self.stats.misses += 1;
+
+        let index = if self.entries.len() < self.capacity {
+            self.entries.push(CacheEntry {
+                lba,
+                data: vec![0; self.device.block_size()],
+                dirty: false,
+                last_used: self.clock,
+            });
+            self.entries.len() - 1
+        } else {
+            let (index, _) = self
+                .entries
+                .iter()
+                .enumerate()
+                .min_by_key(|(_, e)| e.last_used)
+                .unwrap();
+            self.write_back(index)?;
+            index
+        };
+
+        let entry = &mut self.entries[index];
+        entry.lba = lba;
+        entry.last_used = self.clock;
+        entry.dirty = false;
+
+        if load {
+            if let Err(err) = self.device.read_blocks(lba, &mut self.entries[index].data) {
+                self.entries.swap_remove(index);
+                return Err(err);
+            }
+        }
+
+        Ok(index)
+    }
+}
+
+impl<D: BlockDevice> BlockDevice for BufferCache<D> {
+    fn block_size(&self) -> usize {
+        self.device.block_size()
+    }
+
+    fn block_count(&self) -> u64 {
+        self.device.block_count()
+    }
+
+    fn read_blocks(&mut self, lba: u64, buf: &mut [u8]) -> Result<()> {
+        let count = self.check_range(lba, buf.len())?;
+        let block_size = self.block_size();
+
+        for i in 0..count {
+            let index = self.entry(lba + i, true)?;
+            let offset = i as usize * block_size;
+            buf[offset..offset + block_size].copy_from_slice(&self.entries[index].data);
+        }
+
+        Ok(())
+    }
+
+    fn write_blocks(&mut self, lba: u64, buf: &[u8]) -> Result<()> {
+        let count = self.check_range(lba, buf.len())?;
+        let block_size = self.block_size();
+
+        for i in 0..count {
+            // whole block is overwritten, no need to load it
+            let index = self.entry(lba + i, false)?;
+            let offset = i as usize * block_size;
+            let entry = &mut self.entries[index];
+            entry
+                .data
+                .copy_from_slice(&buf[offset..offset + block_size]);
+            entry.dirty = true;
+        }
+
+        Ok(())
+    }
+
+    fn sync(&mut self) -> Result<()> {
+        self.entries.sort_by_key(|e| e.lba);
+        for index in 0..self.entries.len() {
+            self.write_back(index)?;
+        }
+
+        self.device.sync()
+    }
+}
+
+pub fn register(name: &str, device: SharedBlockDevice) -> Result<()> {
+    let mut devices = unsafe { BLOCK_DEVICES.try_lock() }?;
+    if devices.iter().any(|(n, _)| n == name) {
+        return Err("Block device already exists".into());
+    }
+
+    devices.push((name.to_string(), device));
+    Ok(())
+}
+
+pub fn unregister(name: &str) -> Result<SharedBlockDevice> {
+    let mut devices = unsafe { BLOCK_DEVICES.try_lock() }?;
+    let index = devices
+        .iter()
+        .position(|(n, _)| n == name)
+        .ok_or("Block device not found")?;
+    let (_, mut device) = devices.remove(index);
+    device.sync()?;
+    Ok(device)
+}
+
+pub fn get(name: &str) -> Result<SharedBlockDevice> {
+    let devices = unsafe { BLOCK_DEVICES.try_lock() }?;
+    let (_, device) = devices
+        .iter()
+        .find(|(n, _)| n == name)
+        .ok_or("Block device not found")?;
+    Ok(device.clone())
+}
+
+// returns (name, block size, block count)
+pub fn list() -> Result<Vec<(String, usize, u64)>> {
+    let devices = unsafe { BLOCK_DEVICES.try_lock() }?;
+    Ok(devices
+        .iter()
+        .map(|(name, device)| {
+            let device = device.borrow();
+            (name.clone(), device.block_size(), device.block_count())
+        })
+        .collect())
+}
+
+pub fn sync_all() -> Result<()> {
+    let devices = unsafe { BLOCK_DEVICES.try_lock() }?;
+    for (_, device) in devices.iter() {
+        device.borrow_mut().sync()?;
+    }
+
+    Ok(())
+}
@@ -6,10 +6,37 @@ _boot:
     and x1, x1, #0x3
     cbz x1, 2f
 
+    // secondary cores wait here until a panic asks them to halt
 1:  wfe
-    b 1b
+    ldr x2, =PARK_SECONDARY_CORES
+    ldr w2, [x2]
+    cbz w2, 1b
+    msr daifset, #0xf
+4:  wfi
+    b 4b
 2:
     ldr x1, =_boot
+
+    // x0 holds the FDT address, do not clobber it
+    mrs x2, CurrentEL
+    and x2, x2, #0xc
+    cmp x2, #0x8
+    b.ne 3f
+
+    // drop from EL2 to EL1
+    msr sp_el1, x1
+    mrs x2, cnthctl_el2
+    orr x2, x2, #0x3 // enable EL1 access to the physical timer
+    msr cnthctl_el2, x2
+    msr cntvoff_el2, xzr
+    mov x2, #(1 << 31) // EL1 is AArch64
+    msr hcr_el2, x2
+    mov x2, #0x3c5 // EL1h with DAIF masked
+    msr spsr_el2, x2
+    adr x2, 3f
+    msr elr_el2, x2
+    eret
+3:
     mov sp, x1
     bl kernel_main
     b 1b
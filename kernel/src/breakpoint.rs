@@ -0,0 +1,386 @@
+use crate::{
+    asm,
+    error::{Error, Result},
+    exception::{ExceptionClass, TrapFrame},
+    gdb, info,
+    mutex::Mutex,
+    symbols, warn,
+};
+use alloc::vec::Vec;
+
+// hardware breakpoints and watchpoints using the debug registers, see the ARMv8-A
+// reference manual D2.9 (breakpoints) and D2.10 (watchpoints)
+
+static mut DEBUG_REGISTERS: Mutex<DebugRegisters> = Mutex::new(DebugRegisters::new());
+
+// architectural limit, the Cortex-A53 has 6 breakpoints and 4 watchpoints
+const MAX_SLOTS: usize = 16;
+
+const MDSCR_SS: u64 = 1 << 0;
+const MDSCR_KDE: u64 = 1 << 13;
+const MDSCR_MDE: u64 = 1 << 15;
+const SPSR_SS: u64 = 1 << 21;
+const SPSR_D: u64 = 1 << 9;
+const SPSR_IF: u64 = 0b11 << 6;
+
+// common to DBGBCR and DBGWCR, matches at EL1 only
+const CTRL_ENABLE: u64 = 1 << 0;
+const CTRL_EL1: u64 = 0b01 << 1;
+
+// all bytes of an A64 instruction
+const BCR_BAS_A64: u64 = 0b1111 << 5;
+
+const WCR_LSC_SHIFT: u64 = 3;
+const WCR_BAS_SHIFT: u64 = 5;
+const WCR_MASK_SHIFT: u64 = 24;
+
+// ISS of a watchpoint exception
+const ESR_WNR: u64 = 1 << 6;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WatchKind {
+    Read,
+    Write,
+    Access,
+}
+
+impl WatchKind {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "r" => Some(Self::Read),
+            "w" => Some(Self::Write),
+            "rw" => Some(Self::Access),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Read => "r",
+            Self::Write => "w",
+            Self::Access => "rw",
+        }
+    }
+
+    fn lsc(self) -> u64 {
+        match self {
+            Self::Read => 0b01,
+            Self::Write => 0b10,
+            Self::Access => 0b11,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Watchpoint {
+    pub addr: u64,
+    pub len: u64,
+    pub kind: WatchKind,
+}
+
+impl Watchpoint {
+    // up to 8 bytes within a doubleword are selected by BAS, larger aligned power of two
+    // ranges by MASK, returns the value and control register
+    fn encode(&self) -> Option<(u64, u64)> {
+        let ctrl = CTRL_ENABLE | CTRL_EL1 | self.kind.lsc() << WCR_LSC_SHIFT;
+        let offset = self.addr % 8;
+
+        if self.len >= 1 && offset + self.len <= 8 {
+            let bas = ((1 << self.len) - 1) << offset;
+            Some((self.addr - offset, ctrl | bas << WCR_BAS_SHIFT))
+        } else if self.len.is_power_of_two() && self.len <= 1 << 31 && self.addr % self.len == 0 {
+            let mask = self.len.trailing_zeros() as u64;
+            Some((
+                self.addr,
+                ctrl | 0xff << WCR_BAS_SHIFT | mask << WCR_MASK_SHIFT,
+            ))
+        } else {
+            None
+        }
+    }
+
+    fn contains(&self, addr: u64) -> bool {
+        // the reported address can be anywhere in the access, compare doublewords
+        let start = self.addr & !7;
+        let end = self.addr.saturating_add(self.len).saturating_add(7) & !7;
+        (start..end).contains(&addr)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Slot<T> {
+    pub index: usize,
+    pub value: T,
+    pub hits: u64,
+    pub last_pc: u64,
+}
+
+// state of a step over a breakpoint or watchpoint hit
+#[derive(Debug, Clone, Copy)]
+struct StepOver {
+    // interrupt mask of the stepped code, None if GDB is stepping and restores it
+    daif: Option<u64>,
+}
+
+struct DebugRegisters {
+    breakpoint_count: usize,
+    watchpoint_count: usize,
+    breakpoints: [Option<Slot<u64>>; MAX_SLOTS],
+    watchpoints: [Option<Slot<Watchpoint>>; MAX_SLOTS],
+    step: Option<StepOver>,
+}
+
+impl DebugRegisters {
+    const fn new() -> Self {
+        Self {
+            breakpoint_count: 0,
+            watchpoint_count: 0,
+            breakpoints: [None; MAX_SLOTS],
+            watchpoints: [None; MAX_SLOTS],
+            step: None,
+        }
+    }
+
+    fn init(&mut self) {
+        let dfr0 = asm::read_id_aa64dfr0_el1();
+        self.breakpoint_count = ((dfr0 >> 12 & 0xf) as usize + 1).min(MAX_SLOTS);
+        self.watchpoint_count = ((dfr0 >> 20 & 0xf) as usize + 1).min(MAX_SLOTS);
+
+        // the firmware may leave them in an unknown state
+        for i in 0..self.breakpoint_count {
+            asm::write_dbgbcr_el1(i, 0);
+        }
+        for i in 0..self.watchpoint_count {
+            asm::write_dbgwcr_el1(i, 0);
+        }
+
+        asm::unlock_os_lock();
+        asm::write_mdscr_el1(asm::read_mdscr_el1() | MDSCR_MDE | MDSCR_KDE);
+        asm::enable_debug_exceptions();
+    }
+
+    fn add_breakpoint(&mut self, addr: u64) -> Result<usize> {
+        if addr % 4 != 0 {
+            return Err(Error::InvalidArgument);
+        }
+        if let Some(slot) = self
+            .breakpoints
+            .iter()
+            .flatten()
+            .find(|bp| bp.value == addr)
+        {
+            return Ok(slot.index);
+        }
+
+        let index = self.breakpoints[..self.breakpoint_count]
+            .iter()
+            .position(Option::is_none)
+            .ok_or("No free hardware breakpoint")?;
+
+        asm::write_dbgbvr_el1(index, addr);
+        asm::write_dbgbcr_el1(index, CTRL_ENABLE | CTRL_EL1 | BCR_BAS_A64);
+        self.breakpoints[index] = Some(Slot {
+            index,
+            value: addr,
+            hits: 0,
+            last_pc: 0,
+        });
+        Ok(index)
+    }
+
+    fn add_watchpoint(&mut self, watchpoint: Watchpoint) -> Result<usize> {
+        let (value, ctrl) = watchpoint.encode().ok_or(Error::InvalidArgument)?;
+        if let Some(slot) = self
+            .watchpoints
+            .iter()
+            .flatten()
+            .find(|wp| wp.value == watchpoint)
+        {
+            return Ok(slot.index);
+        }
+
+        let index = self.watchpoints[..self.watchpoint_count]
+            .iter()
+            .position(Option::is_none)
+            .ok_or("No free hardware watchpoint")?;
+
+        asm::write_dbgwvr_el1(index, value);
+        asm::write_dbgwcr_el1(index, ctrl);
+        self.watchpoints[index] = Some(Slot {
+            index,
+            value: watchpoint,
+            hits: 0,
+            last_pc: 0,
+        });
+        Ok(index)
+    }
+
+    fn remove_breakpoint(&mut self, index: usize) -> Result<()> {
+        self.breakpoints
+            .get_mut(index)
+            .and_then(Option::take)
+            .ok_or(Error::InvalidArgument)?;
+        asm::write_dbgbcr_el1(index, 0);
+        Ok(())
+    }
+
+    fn remove_watchpoint(&mut self, index: usize) -> Result<()> {
+        self.watchpoints
+            .get_mut(index)
+            .and_then(Option::take)
+            .ok_or(Error::InvalidArgument)?;
+        asm::write_dbgwcr_el1(index, 0);
+        Ok(())
+    }
+
+    fn record_hit(&mut self, frame: &TrapFrame) {
+        let pc = frame.elr;
+        let symbol = symbols::lookup(pc)
+            .map(|(symbol, _)| symbol.name)
+            .unwrap_or("?");
+
+        if frame.exception_class() == ExceptionClass::BreakpointSameEl {
+            match self
+                .breakpoints
+                .iter_mut()
+                .flatten()
+                .find(|bp| bp.value == pc)
+            {
+                Some(bp) => {
+                    bp.hits += 1;
+                    bp.last_pc = pc;
+                    warn!("Breakpoint {} hit at 0x{:x} ({})", bp.index, pc, symbol);
+                }
+                None => warn!("Unknown breakpoint hit at 0x{:x} ({})", pc, symbol),
+            }
+        } else {
+            let access = if frame.esr & ESR_WNR != 0 {
+                "write"
+            } else {
+                "read"
+            };
+            let addr = frame.far;
+            match self
+                .watchpoints
+                .iter_mut()
+                .flatten()
+                .find(|wp| wp.value.contains(addr))
+            {
+                Some(wp) => {
+                    wp.hits += 1;
+                    wp.last_pc = pc;
+                    warn!(
+                        "Watchpoint {} {} of 0x{:x} at 0x{:x} ({})",
+                        wp.index, access, addr, pc, symbol
+                    );
+                }
+                None => warn!(
+                    "Unknown watchpoint {} of 0x{:x} at 0x{:x} ({})",
+                    access, addr, pc, symbol
+                ),
+            }
+        }
+    }
+
+    // the exception is taken before the instruction executes, so all breakpoints and
+    // watchpoints are disabled while it is single stepped
+    fn step_over(&mut self, frame: &mut TrapFrame) {
+        asm::write_mdscr_el1(asm::read_mdscr_el1() & !MDSCR_MDE);
+
+        if frame.spsr & SPSR_SS != 0 {
+            self.step = Some(StepOver { daif: None });
+            return;
+        }
+
+        // step with interrupts masked so that the step does not land in a handler
+        self.step = Some(StepOver {
+            daif: Some(frame.spsr & SPSR_IF),
+        });
+        asm::write_mdscr_el1(asm::read_mdscr_el1() | MDSCR_SS | MDSCR_KDE);
+        frame.spsr = (frame.spsr | SPSR_SS | SPSR_IF) & !SPSR_D;
+    }
+
+    // returns false if the step belongs to GDB
+    fn finish_step(&mut self, frame: &mut TrapFrame) -> bool {
+        let step = match self.step.take() {
+            Some(step) => step,
+            None => return false,
+        };
+
+        asm::write_mdscr_el1(asm::read_mdscr_el1() | MDSCR_MDE);
+
+        match step.daif {
+            Some(daif) => {
+                asm::write_mdscr_el1(asm::read_mdscr_el1() & !MDSCR_SS);
+                frame.spsr = (frame.spsr & !(SPSR_SS | SPSR_IF)) | daif;
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+pub fn init() -> Result<()> {
+    let mut regs = unsafe { DEBUG_REGISTERS.try_lock() }?;
+    regs.init();
+    info!(
+        "Hardware breakpoints: {}, watchpoints: {}",
+        regs.breakpoint_count, regs.watchpoint_count
+    );
+    Ok(())
+}
+
+pub fn add_breakpoint(addr: u64) -> Result<usize> {
+    unsafe { DEBUG_REGISTERS.try_lock() }?.add_breakpoint(addr)
+}
+
+pub fn add_watchpoint(addr: u64, len: u64, kind: WatchKind) -> Result<usize> {
+    unsafe { DEBUG_REGISTERS.try_lock() }?.add_watchpoint(Watchpoint { addr, len, kind })
+}
+
+pub fn remove_breakpoint(index: usize) -> Result<()> {
+    unsafe { DEBUG_REGISTERS.try_lock() }?.remove_breakpoint(index)
+}
+
+pub fn remove_watchpoint(index: usize) -> Result<()> {
+    unsafe { DEBUG_REGISTERS.try_lock() }?.remove_watchpoint(index)
+}
+
+pub fn breakpoints() -> Result<Vec<Slot<u64>>> {
+    let regs = unsafe { DEBUG_REGISTERS.try_lock() }?;
+    Ok(regs.breakpoints.iter().flatten().copied().collect())
+}
+
+pub fn watchpoints() -> Result<Vec<Slot<Watchpoint>>> {
+    let regs = unsafe { DEBUG_REGISTERS.try_lock() }?;
+    Ok(regs.watchpoints.iter().flatten().copied().collect())
+}
+
+// called for breakpoint, watchpoint and software step exceptions, returns false if not handled
+pub fn handle_exception(frame: &mut TrapFrame) -> bool {
+    match frame.exception_class() {
+        ExceptionClass::BreakpointSameEl | ExceptionClass::WatchpointSameEl => (),
+        ExceptionClass::SoftwareStepSameEl => {
+            return match unsafe { DEBUG_REGISTERS.try_lock() } {
+                Ok(mut regs) => regs.finish_step(frame),
+                Err(_) => false,
+            };
+        }
+        _ => return false,
+    }
+
+    match unsafe { DEBUG_REGISTERS.try_lock() } {
+        Ok(mut regs) => regs.record_hit(frame),
+        Err(_) => return false,
+    }
+
+    // stops in the debugger if one is attached, the lock is released since it may
+    // change breakpoints
+    gdb::handle_exception(frame);
+
+    match unsafe { DEBUG_REGISTERS.try_lock() } {
+        Ok(mut regs) => regs.step_over(frame),
+        Err(_) => return false,
+    }
+    true
+}
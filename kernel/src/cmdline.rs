@@ -0,0 +1,218 @@
+use crate::{
+    device_tree,
+    error::Result,
+    log::Level,
+    mailbox,
+    mutex::Mutex,
+    uart::{self, UartPort},
+};
+use alloc::{string::String, vec::Vec};
+
+// options are gathered from the firmware (mailbox) and the device tree (/chosen/bootargs),
+// later occurrences of an option override earlier ones
+//
+//   console=pl011|mini|fb|memory[,...] console backends, also accepts ttyAMA0, ttyS0 and tty0
+//   loglevel=<0-7|name>, quiet, debug global log level
+//   log=<module>:<level>[,...]   per module log levels, e.g. log=emmc:trace
+//   video=<width>x<height>[-<depth>] initial framebuffer mode
+//   init=<path>                  shell script run before the interactive shell
+//   gdb=pl011|mini               enables the GDB stub on a serial port
+//   font=<path>                  PSF font for the framebuffer console, loaded after mounting
+//   splash=<path>                BMP, QOI or PPM image shown after mounting
+
+static mut CMDLINE: Mutex<Option<CommandLine>> = Mutex::new(None);
+
+// accepts level names and Linux console log levels (0-7)
+fn parse_log_level(s: &str) -> Option<Level> {
+    let level = match s.parse::<u8>() {
+        Ok(0..=3) => Level::Error,
+        Ok(4) => Level::Warn,
+        Ok(5 | 6) => Level::Info,
+        Ok(7) => Level::Debug,
+        Ok(_) => Level::Trace,
+        Err(_) => return Level::parse(s),
+    };
+
+    Some(level)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VideoMode {
+    pub width: u32,
+    pub height: u32,
+    pub depth: u32,
+}
+
+#[derive(Debug, Clone)]
+pub struct CommandLine {
+    raw: String,
+    params: Vec<(String, Option<String>)>,
+}
+
+impl CommandLine {
+    pub fn parse(raw: &str) -> Self {
+        let mut params = Vec::new();
+        let mut chars = raw.chars().peekable();
+
+        loop {
+            while chars.next_if(|c| c.is_whitespace()).is_some() {}
+            if chars.peek().is_none() {
+                break;
+            }
+
+            // double quotes group whitespace, e.g. init="/bin/my script"
+            let mut token = String::new();
+            let mut in_quotes = false;
+            while let Some(c) = chars.next_if(|c| in_quotes || !c.is_whitespace()) {
+                if c == '"' {
+                    in_quotes = !in_quotes;
+                } else {
+                    token.push(c);
+                }
+            }
+
+            match token.split_once('=') {
+                Some((key, value)) => params.push((key.into(), Some(value.into()))),
+                None => params.push((token, None)),
+            }
+        }
+
+        Self {
+            raw: raw.into(),
+            params,
+        }
+    }
+
+    pub fn raw(&self) -> &str {
+        &self.raw
+    }
+
+    pub fn params(&self) -> &[(String, Option<String>)] {
+        &self.params
+    }
+
+    pub fn has(&self, key: &str) -> bool {
+        self.params.iter().any(|(k, _)| k == key)
+    }
+
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.params
+            .iter()
+            .rev()
+            .find(|(k, _)| k == key)
+            .and_then(|(_, v)| v.as_deref())
+    }
+
+    // every console= occurrence adds backends, like Linux
+    pub fn consoles(&self) -> Vec<&'static str> {
+        let mut devices = Vec::new();
+
+        let values = self
+            .params
+            .iter()
+            .filter(|(k, _)| k == "console")
+            .filter_map(|(_, v)| v.as_deref());
+        for name in values.flat_map(|v| v.split(',')) {
+            let device = match name {
+                "pl011" | "serial0" | "ttyAMA0" => "pl011",
+                "mini" | "serial1" | "ttyS0" => "mini",
+                "fb" | "tty0" | "tty1" => "fb",
+                "memory" => "memory",
+                // baud rates and unknown devices
+                _ => continue,
+            };
+
+            if !devices.contains(&device) {
+                devices.push(device);
+            }
+        }
+
+        devices
+    }
+
+    pub fn log_level(&self) -> Option<Level> {
+        let mut level = None;
+
+        for (key, value) in self.params.iter() {
+            match (key.as_str(), value.as_deref()) {
+                ("loglevel", Some(v)) => level = parse_log_level(v).or(level),
+                ("quiet", None) => level = Some(Level::Warn),
+                ("debug", None) => level = Some(Level::Debug),
+                _ => (),
+            }
+        }
+
+        level
+    }
+
+    // log=<module>:<level>[,...]
+    pub fn module_log_levels(&self) -> Vec<(&str, Level)> {
+        self.params
+            .iter()
+            .filter(|(k, _)| k == "log")
+            .filter_map(|(_, v)| v.as_deref())
+            .flat_map(|v| v.split(','))
+            .filter_map(|filter| {
+                let (module, level) = filter.split_once(':')?;
+                Some((module, parse_log_level(level)?))
+            })
+            .collect()
+    }
+
+    pub fn video_mode(&self) -> Option<VideoMode> {
+        let value = self.get("video")?;
+        let (resolution, depth) = match value.split_once('-') {
+            Some((resolution, depth)) => (resolution, depth.parse().ok()?),
+            None => (value, 32),
+        };
+        let (width, height) = resolution.split_once('x')?;
+
+        Some(VideoMode {
+            width: width.parse().ok()?,
+            height: height.parse().ok()?,
+            depth,
+        })
+    }
+
+    pub fn gdb_port(&self) -> Option<UartPort> {
+        uart::parse_port(self.get("gdb")?)
+    }
+
+    pub fn font(&self) -> Option<&str> {
+        self.get("font")
+    }
+
+    pub fn splash(&self) -> Option<&str> {
+        self.get("splash")
+    }
+
+    pub fn init_program(&self) -> Option<&str> {
+        self.get("init").filter(|path| !path.is_empty())
+    }
+}
+
+pub fn init() -> Result<()> {
+    let mut sources = Vec::new();
+
+    if let Ok(firmware) = mailbox::get_command_line() {
+        sources.push(firmware);
+    }
+
+    if let Ok(bootargs) = device_tree::get_property("/chosen", "bootargs") {
+        // the firmware usually copies its command line into the device tree as well
+        if let Some(bootargs) = bootargs.as_str() {
+            if !sources.iter().any(|s| s == bootargs) {
+                sources.push(bootargs.into());
+            }
+        }
+    }
+
+    let raw = sources.join(" ");
+    *unsafe { CMDLINE.try_lock() }? = Some(CommandLine::parse(raw.trim()));
+    Ok(())
+}
+
+pub fn get() -> Result<CommandLine> {
+    let cmdline = unsafe { CMDLINE.try_lock() }?;
+    Ok(cmdline.clone().unwrap_or_else(|| CommandLine::parse("")))
+}
@@ -25,6 +25,85 @@ impl ColorCode {
         Self { r, g, b }
     }
 
+    // xterm 256 colour palette: 16 standard colours, a 6x6x6 cube and a grey ramp
+    pub const fn from_ansi(index: u8) -> Self {
+        const STANDARD: [ColorCode; 16] = [
+            ColorCode::new(0, 0, 0),
+            ColorCode::new(205, 0, 0),
+            ColorCode::new(0, 205, 0),
+            ColorCode::new(205, 205, 0),
+            ColorCode::new(0, 0, 238),
+            ColorCode::new(205, 0, 205),
+            ColorCode::new(0, 205, 205),
+            ColorCode::new(229, 229, 229),
+            ColorCode::new(127, 127, 127),
+            ColorCode::new(255, 0, 0),
+            ColorCode::new(0, 255, 0),
+            ColorCode::new(255, 255, 0),
+            ColorCode::new(92, 92, 255),
+            ColorCode::new(255, 0, 255),
+            ColorCode::new(0, 255, 255),
+            ColorCode::new(255, 255, 255),
+        ];
+        const CUBE_LEVELS: [u8; 6] = [0, 95, 135, 175, 215, 255];
+
+        match index {
+            0..=15 => STANDARD[index as usize],
+            16..=231 => {
+                let i = index - 16;
+                Self::new(
+                    CUBE_LEVELS[(i / 36) as usize],
+                    CUBE_LEVELS[(i / 6 % 6) as usize],
+                    CUBE_LEVELS[(i % 6) as usize],
+                )
+            }
+            _ => {
+                let level = 8 + (index - 232) * 10;
+                Self::new(level, level, level)
+            }
+        }
+    }
+
+    // nearest colour of the cube or the grey ramp, the standard colours are skipped
+    // since terminals commonly redefine them
+    pub fn to_ansi(&self) -> u8 {
+        fn cube_index(value: u8) -> u8 {
+            match value {
+                0..=47 => 0,
+                48..=114 => 1,
+                _ => (value - 35) / 40,
+            }
+        }
+
+        let cube = 16 + 36 * cube_index(self.r) + 6 * cube_index(self.g) + cube_index(self.b);
+        let average = (self.r as usize + self.g as usize + self.b as usize) / 3;
+        let grey = 232 + (average.saturating_sub(3) / 10).min(23) as u8;
+
+        if self.distance(&Self::from_ansi(grey)) < self.distance(&Self::from_ansi(cube)) {
+            grey
+        } else {
+            cube
+        }
+    }
+
+    // alpha 0 keeps this colour and 255 gives the other one
+    pub fn blend(&self, other: Self, alpha: u8) -> Self {
+        let mix = |a: u8, b: u8| {
+            let alpha = alpha as u32;
+            ((a as u32 * (255 - alpha) + b as u32 * alpha + 127) / 255) as u8
+        };
+        Self::new(
+            mix(self.r, other.r),
+            mix(self.g, other.g),
+            mix(self.b, other.b),
+        )
+    }
+
+    fn distance(&self, other: &Self) -> u32 {
+        let d = |a: u8, b: u8| (a as i32 - b as i32).unsigned_abs().pow(2);
+        d(self.r, other.r) + d(self.g, other.g) + d(self.b, other.b)
+    }
+
     pub fn from_u32(value: u32, pixel_format: PixelFormat) -> Self {
         match pixel_format {
             PixelFormat::Bgr => Self::new(value as u8, (value >> 8) as u8, (value >> 16) as u8),
@@ -38,4 +117,38 @@ impl ColorCode {
             PixelFormat::Rgb => (self.r as u32) << 16 | (self.g as u32) << 8 | self.b as u32,
         }
     }
+
+    // 8-bit pixels index the xterm palette, 16-bit pixels are RGB565 and 24-bit pixels
+    // are the low three bytes of the 32-bit value
+    pub fn from_pixel(value: u32, depth: usize, pixel_format: PixelFormat) -> Self {
+        match depth {
+            8 => Self::from_ansi(value as u8),
+            16 => {
+                let expand5 = |v: u32| ((v << 3) | (v >> 2)) as u8;
+                let high = expand5(value >> 11 & 0x1f);
+                let green = (value >> 5 & 0x3f) as u8;
+                let green = (green << 2) | (green >> 4);
+                let low = expand5(value & 0x1f);
+                match pixel_format {
+                    PixelFormat::Bgr => Self::new(low, green, high),
+                    PixelFormat::Rgb => Self::new(high, green, low),
+                }
+            }
+            _ => Self::from_u32(value, pixel_format),
+        }
+    }
+
+    pub fn to_pixel(&self, depth: usize, pixel_format: PixelFormat) -> u32 {
+        match depth {
+            8 => self.to_ansi() as u32,
+            16 => {
+                let (high, low) = match pixel_format {
+                    PixelFormat::Bgr => (self.b, self.r),
+                    PixelFormat::Rgb => (self.r, self.b),
+                };
+                (high as u32 >> 3) << 11 | (self.g as u32 >> 2) << 5 | low as u32 >> 3
+            }
+            _ => self.to_u32(pixel_format),
+        }
+    }
 }
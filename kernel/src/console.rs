@@ -1,18 +1,208 @@
-use crate::{framebuffer_console, mutex::Mutex, uart};
+use crate::{
+    error::{Error, Result},
+    gdb, log,
+    mutex::Mutex,
+    uart,
+};
+use alloc::{boxed::Box, collections::VecDeque, format, vec::Vec};
 use core::fmt::{self, Write};
 
-static mut CONSOLE: Mutex<Console> = Mutex::new(Console);
+static mut CONSOLES: Mutex<ConsoleRegistry> = Mutex::new(ConsoleRegistry::new());
+static mut MEMORY_LOG: Mutex<VecDeque<u8>> = Mutex::new(VecDeque::new());
 static mut DEBUG_CONSOLE: DebugConsole = DebugConsole;
 
-struct Console;
+const MEMORY_LOG_SIZE: usize = 16 * 1024;
 
-impl fmt::Write for Console {
+pub trait ConsoleBackend {
+    fn name(&self) -> &'static str;
+    fn write_str(&mut self, s: &str) -> Result<()>;
+
+    fn has_input(&self) -> bool {
+        false
+    }
+
+    // must not block
+    fn try_receive(&mut self) -> Result<Option<char>> {
+        Ok(None)
+    }
+
+    // called while waiting for input, e.g. to blink a cursor
+    fn poll(&mut self) {}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ConsoleInfo {
+    pub name: &'static str,
+    pub enabled: bool,
+    pub primary: bool,
+    pub has_input: bool,
+}
+
+struct ConsoleEntry {
+    backend: Box<dyn ConsoleBackend>,
+    enabled: bool,
+    replayed: bool,
+}
+
+impl ConsoleEntry {
+    fn set_enabled(&mut self, enabled: bool) {
+        // early messages are replayed once, when the console is first enabled, so that
+        // late consoles see the whole boot without touching disabled hardware
+        if enabled && !self.replayed {
+            for record in log::records().unwrap_or_default() {
+                let _ = self.backend.write_str(&format!("{}\n", record));
+            }
+            self.replayed = true;
+        }
+
+        self.enabled = enabled;
+    }
+}
+
+struct ConsoleRegistry {
+    entries: Vec<ConsoleEntry>,
+    primary: Option<&'static str>,
+}
+
+impl ConsoleRegistry {
+    const fn new() -> Self {
+        Self {
+            entries: Vec::new(),
+            primary: None,
+        }
+    }
+
+    fn entry_mut(&mut self, name: &str) -> Result<&mut ConsoleEntry> {
+        self.entries
+            .iter_mut()
+            .find(|e| e.backend.name() == name)
+            .ok_or("Console not found".into())
+    }
+
+    fn register(&mut self, backend: Box<dyn ConsoleBackend>, enabled: bool) -> Result<()> {
+        if self
+            .entries
+            .iter()
+            .any(|e| e.backend.name() == backend.name())
+        {
+            return Err("Console already registered".into());
+        }
+
+        // the first enabled console with input becomes the primary one
+        if self.primary.is_none() && enabled && backend.has_input() {
+            self.primary = Some(backend.name());
+        }
+
+        let mut entry = ConsoleEntry {
+            backend,
+            enabled: false,
+            replayed: false,
+        };
+        entry.set_enabled(enabled);
+        self.entries.push(entry);
+        Ok(())
+    }
+
+    fn unregister(&mut self, name: &str) -> Result<()> {
+        let index = self
+            .entries
+            .iter()
+            .position(|e| e.backend.name() == name)
+            .ok_or("Console not found")?;
+        self.entries.remove(index);
+        self.update_primary();
+        Ok(())
+    }
+
+    fn set_enabled(&mut self, name: &str, enabled: bool) -> Result<()> {
+        self.entry_mut(name)?.set_enabled(enabled);
+        self.update_primary();
+        Ok(())
+    }
+
+    // the primary console is kept while it is enabled, otherwise the first enabled console with
+    // input takes over, or there is none
+    fn update_primary(&mut self) {
+        let usable = |e: &&ConsoleEntry| e.enabled && e.backend.has_input();
+        if self
+            .entries
+            .iter()
+            .filter(usable)
+            .any(|e| self.primary == Some(e.backend.name()))
+        {
+            return;
+        }
+
+        self.primary = self.entries.iter().find(usable).map(|e| e.backend.name());
+    }
+
+    fn set_primary(&mut self, name: &str) -> Result<()> {
+        let entry = self.entry_mut(name)?;
+        if !entry.enabled {
+            return Err("Console is disabled".into());
+        }
+        if !entry.backend.has_input() {
+            return Err("Console has no input".into());
+        }
+
+        self.primary = Some(entry.backend.name());
+        Ok(())
+    }
+
+    fn poll(&mut self) {
+        for entry in self.entries.iter_mut().filter(|e| e.enabled) {
+            entry.backend.poll();
+        }
+    }
+
+    fn try_receive(&mut self) -> Result<Option<char>> {
+        let primary = self.primary.ok_or(Error::NotInitialized)?;
+        self.entry_mut(primary)?.backend.try_receive()
+    }
+
+    fn list(&self) -> Vec<ConsoleInfo> {
+        self.entries
+            .iter()
+            .map(|e| ConsoleInfo {
+                name: e.backend.name(),
+                enabled: e.enabled,
+                primary: self.primary == Some(e.backend.name()),
+                has_input: e.backend.has_input(),
+            })
+            .collect()
+    }
+}
+
+impl fmt::Write for ConsoleRegistry {
     fn write_str(&mut self, s: &str) -> fmt::Result {
-        let _ = uart::puts(s);
+        // a failing backend must not keep the others from printing
+        for entry in self.entries.iter_mut().filter(|e| e.enabled) {
+            let _ = entry.backend.write_str(s);
+        }
+        Ok(())
+    }
+}
+
+// keeps the most recent output in memory, e.g. for boards without a serial cable
+pub struct MemoryConsole;
+
+impl ConsoleBackend for MemoryConsole {
+    fn name(&self) -> &'static str {
+        "memory"
+    }
+
+    fn write_str(&mut self, s: &str) -> Result<()> {
+        let mut log = unsafe { MEMORY_LOG.try_lock() }?;
+        let overflow = (log.len() + s.len()).saturating_sub(MEMORY_LOG_SIZE);
+        let len = log.len();
+        log.drain(..overflow.min(len));
+        let bytes = s.as_bytes();
+        log.extend(&bytes[bytes.len().saturating_sub(MEMORY_LOG_SIZE)..]);
         Ok(())
     }
 }
 
+// writes without taking any lock, used when the registry is busy
 struct DebugConsole;
 
 impl fmt::Write for DebugConsole {
@@ -22,14 +212,94 @@ impl fmt::Write for DebugConsole {
     }
 }
 
-pub fn _print(args: fmt::Arguments) {
-    let _ = unsafe { DEBUG_CONSOLE.write_fmt(args) };
+pub fn init() -> Result<()> {
+    register(Box::new(MemoryConsole), true)
+}
+
+pub fn register(backend: Box<dyn ConsoleBackend>, enabled: bool) -> Result<()> {
+    let mut consoles = unsafe { CONSOLES.try_lock() }?;
+    consoles.register(backend, enabled)
+}
+
+pub fn unregister(name: &str) -> Result<()> {
+    let mut consoles = unsafe { CONSOLES.try_lock() }?;
+    consoles.unregister(name)
+}
+
+pub fn set_enabled(name: &str, enabled: bool) -> Result<()> {
+    let mut consoles = unsafe { CONSOLES.try_lock() }?;
+    consoles.set_enabled(name, enabled)
+}
 
-    if let Ok(mut console) = unsafe { CONSOLE.try_lock() } {
-        let _ = console.write_fmt(args);
+pub fn set_primary(name: &str) -> Result<()> {
+    let mut consoles = unsafe { CONSOLES.try_lock() }?;
+    consoles.set_primary(name)
+}
+
+pub fn list() -> Result<Vec<ConsoleInfo>> {
+    let consoles = unsafe { CONSOLES.try_lock() }?;
+    Ok(consoles.list())
+}
+
+// enables exactly the given consoles, the memory log is always kept
+pub fn select(names: &[&str]) -> Result<()> {
+    let mut consoles = unsafe { CONSOLES.try_lock() }?;
+    for entry in consoles.entries.iter_mut() {
+        let name = entry.backend.name();
+        entry.set_enabled(name == MemoryConsole.name() || names.contains(&name));
+    }
+
+    // the first selected console with input becomes the primary one
+    consoles.primary = None;
+    consoles.update_primary();
+    Ok(())
+}
+
+pub fn memory_log() -> Result<Vec<u8>> {
+    let log = unsafe { MEMORY_LOG.try_lock() }?;
+    Ok(log.iter().copied().collect())
+}
+
+// blocks until the primary console receives a character
+pub fn receive() -> Result<char> {
+    loop {
+        let (received, primary) = {
+            let mut consoles = unsafe { CONSOLES.try_lock() }?;
+            let received = consoles.try_receive()?;
+            if received.is_none() {
+                consoles.poll();
+            }
+            (received, consoles.primary)
+        };
+
+        match received {
+            Some(c) if gdb::check_console_break(primary, c) => (),
+            Some(c) => return Ok(c),
+            None => gdb::poll(primary),
+        }
+        core::hint::spin_loop();
+    }
+}
+
+// for code that takes a fmt::Write
+pub struct ConsoleWriter;
+
+impl fmt::Write for ConsoleWriter {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        _print(format_args!("{}", s));
+        Ok(())
     }
+}
 
-    let _ = framebuffer_console::write_fmt(args);
+pub fn _print(args: fmt::Arguments) {
+    match unsafe { CONSOLES.try_lock() } {
+        Ok(mut consoles) => {
+            let _ = consoles.write_fmt(args);
+        }
+        Err(_) => {
+            let _ = unsafe { DEBUG_CONSOLE.write_fmt(args) };
+        }
+    }
 }
 
 #[macro_export]
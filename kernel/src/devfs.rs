@@ -0,0 +1,271 @@
+use crate::{
+    error::{Error, Result},
+    framebuffer::{self, FramebufferInfo},
+    gpio::{self, PinFunction},
+    rng,
+    uart::{self, UartPort},
+    vfs::{DirEntry, FileSystem, FileSystemError, FileType, InodeId, Metadata},
+};
+use alloc::{string::ToString, vec::Vec};
+use core::mem::size_of;
+
+const ROOT_INODE: InodeId = 1;
+
+pub const FBIOGET_INFO: u32 = 0x4600;
+
+pub const GPIO_GET_FUNCTION: u32 = 0x4701;
+pub const GPIO_SET_FUNCTION: u32 = 0x4702;
+pub const GPIO_GET_LEVEL: u32 = 0x4703;
+pub const GPIO_SET_LEVEL: u32 = 0x4704;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Device {
+    Tty(UartPort),
+    Framebuffer,
+    GpioChip,
+    Null,
+    Zero,
+    Random,
+}
+
+const DEVICES: [(&str, Device); 7] = [
+    ("ttyAMA0", Device::Tty(UartPort::Pl011)),
+    ("ttyS0", Device::Tty(UartPort::Mini)),
+    ("fb0", Device::Framebuffer),
+    ("gpiochip0", Device::GpioChip),
+    ("null", Device::Null),
+    ("zero", Device::Zero),
+    ("random", Device::Random),
+];
+
+// layout of the FBIOGET_INFO argument
+#[derive(Debug, Clone, Copy, Default)]
+#[repr(C)]
+pub struct FbIoctlInfo {
+    pub p_width: u32,
+    pub p_height: u32,
+    pub v_width: u32,
+    pub v_height: u32,
+    pub depth: u32,
+    pub pixel_format: u32,
+    pub buf_base: u64,
+    pub buf_size: u64,
+    pub pitch: u32,
+}
+
+impl From<FramebufferInfo> for FbIoctlInfo {
+    fn from(info: FramebufferInfo) -> Self {
+        Self {
+            p_width: info.p_width as u32,
+            p_height: info.p_height as u32,
+            v_width: info.v_width as u32,
+            v_height: info.v_height as u32,
+            depth: info.depth as u32,
+            pixel_format: info.pixel_format as u32,
+            buf_base: info.buf_base.get(),
+            buf_size: info.buf_size as u64,
+            pitch: info.pitch as u32,
+        }
+    }
+}
+
+fn read_arg_u32(arg: &[u8], index: usize) -> Result<u32> {
+    let bytes = arg
+        .get(index * 4..index * 4 + 4)
+        .ok_or(Error::InvalidArgument)?;
+    Ok(u32::from_le_bytes(bytes.try_into().unwrap()))
+}
+
+fn write_arg_u32(arg: &mut [u8], index: usize, value: u32) -> Result<()> {
+    let bytes = arg
+        .get_mut(index * 4..index * 4 + 4)
+        .ok_or(Error::InvalidArgument)?;
+    bytes.copy_from_slice(&value.to_le_bytes());
+    Ok(())
+}
+
+pub struct Devfs;
+
+impl Default for Devfs {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Devfs {
+    pub fn new() -> Self {
+        Self
+    }
+
+    fn device(&self, inode: InodeId) -> Result<Device> {
+        if inode == ROOT_INODE {
+            return Err(FileSystemError::IsDirectory.into());
+        }
+
+        let index = inode
+            .checked_sub(ROOT_INODE + 1)
+            .ok_or(FileSystemError::NotFound)? as usize;
+        Ok(DEVICES.get(index).ok_or(FileSystemError::NotFound)?.1)
+    }
+
+    fn read_tty(&self, port: UartPort, buf: &mut [u8]) -> Result<usize> {
+        // never block here, the caller holds the VFS lock
+        let mut len = 0;
+
+        while len < buf.len() {
+            match uart::try_receive_port(port)? {
+                Some(c) => buf[len] = c as u8,
+                None => break,
+            }
+            len += 1;
+        }
+
+        Ok(len)
+    }
+
+    fn ioctl_gpio(&self, cmd: u32, arg: &mut [u8]) -> Result<usize> {
+        let pin = read_arg_u32(arg, 0)?;
+
+        match cmd {
+            GPIO_GET_FUNCTION => {
+                let function = gpio::get_function(pin)?;
+                write_arg_u32(arg, 1, function as u32)?;
+            }
+            GPIO_SET_FUNCTION => {
+                let function = PinFunction::try_from(read_arg_u32(arg, 1)?)?;
+                gpio::set_function(pin, function)?;
+            }
+            GPIO_GET_LEVEL => {
+                let level = gpio::get_level(pin)?;
+                write_arg_u32(arg, 1, level as u32)?;
+            }
+            GPIO_SET_LEVEL => {
+                gpio::set_level(pin, read_arg_u32(arg, 1)? != 0)?;
+            }
+            _ => return Err(FileSystemError::Unsupported.into()),
+        }
+
+        Ok(8)
+    }
+}
+
+impl FileSystem for Devfs {
+    fn name(&self) -> &'static str {
+        "devfs"
+    }
+
+    fn root(&self) -> InodeId {
+        ROOT_INODE
+    }
+
+    fn lookup(&mut self, dir: InodeId, name: &str) -> Result<InodeId> {
+        if dir != ROOT_INODE {
+            return Err(FileSystemError::NotDirectory.into());
+        }
+
+        let index = DEVICES
+            .iter()
+            .position(|(n, _)| *n == name)
+            .ok_or(FileSystemError::NotFound)?;
+        Ok(ROOT_INODE + 1 + index as InodeId)
+    }
+
+    fn metadata(&mut self, inode: InodeId) -> Result<Metadata> {
+        if inode == ROOT_INODE {
+            return Ok(Metadata::new(
+                inode,
+                FileType::Directory,
+                DEVICES.len(),
+                0o755,
+            ));
+        }
+
+        let metadata = match self.device(inode)? {
+            Device::Framebuffer => {
+                let size = framebuffer::get_info().map_or(0, |info| info.buf_size);
+                Metadata::new(inode, FileType::CharDevice, size, 0o660)
+            }
+            Device::GpioChip => Metadata::new(inode, FileType::CharDevice, 8, 0o660),
+            _ => Metadata::new(inode, FileType::CharDevice, 0, 0o666),
+        };
+
+        Ok(metadata)
+    }
+
+    fn read(&mut self, inode: InodeId, offset: usize, buf: &mut [u8]) -> Result<usize> {
+        match self.device(inode)? {
+            Device::Tty(port) => self.read_tty(port, buf),
+            Device::Framebuffer => framebuffer::read_raw(offset, buf),
+            Device::GpioChip => {
+                let levels = gpio::read_levels().to_le_bytes();
+                if offset >= levels.len() {
+                    return Ok(0);
+                }
+
+                let len = buf.len().min(levels.len() - offset);
+                buf[..len].copy_from_slice(&levels[offset..offset + len]);
+                Ok(len)
+            }
+            Device::Null => Ok(0),
+            Device::Zero => {
+                buf.fill(0);
+                Ok(buf.len())
+            }
+            Device::Random => {
+                rng::fill_bytes(buf)?;
+                Ok(buf.len())
+            }
+        }
+    }
+
+    fn write(&mut self, inode: InodeId, offset: usize, buf: &[u8]) -> Result<usize> {
+        match self.device(inode)? {
+            Device::Tty(port) => {
+                uart::write_bytes(port, buf)?;
+                Ok(buf.len())
+            }
+            Device::Framebuffer => framebuffer::write_raw(offset, buf),
+            Device::GpioChip => Err(FileSystemError::Unsupported.into()),
+            Device::Null | Device::Zero | Device::Random => Ok(buf.len()),
+        }
+    }
+
+    fn read_dir(&mut self, dir: InodeId) -> Result<Vec<DirEntry>> {
+        if dir != ROOT_INODE {
+            return Err(FileSystemError::NotDirectory.into());
+        }
+
+        Ok(DEVICES
+            .iter()
+            .enumerate()
+            .map(|(i, (name, _))| DirEntry {
+                name: name.to_string(),
+                inode: ROOT_INODE + 1 + i as InodeId,
+                file_type: FileType::CharDevice,
+            })
+            .collect())
+    }
+
+    fn truncate(&mut self, inode: InodeId, _size: usize) -> Result<()> {
+        // opening a device for writing truncates it, which is a no-op
+        self.device(inode)?;
+        Ok(())
+    }
+
+    fn ioctl(&mut self, inode: InodeId, cmd: u32, arg: &mut [u8]) -> Result<usize> {
+        match (self.device(inode)?, cmd) {
+            (Device::Framebuffer, FBIOGET_INFO) => {
+                let info = FbIoctlInfo::from(framebuffer::get_info()?);
+                let len = size_of::<FbIoctlInfo>();
+                if arg.len() < len {
+                    return Err(Error::InvalidArgument);
+                }
+
+                unsafe { (arg.as_mut_ptr() as *mut FbIoctlInfo).write_unaligned(info) };
+                Ok(len)
+            }
+            (Device::GpioChip, cmd) => self.ioctl_gpio(cmd, arg),
+            _ => Err(FileSystemError::Unsupported.into()),
+        }
+    }
+}
@@ -1,9 +1,57 @@
-use crate::{addr::VirtualAddress, error::Result, fdt::FdtHeader};
-use core::{mem::size_of, slice};
+use crate::{
+    addr::VirtualAddress,
+    error::{Error, Result},
+    fdt::{FdtHeader, FDT_BEGIN_NODE, FDT_END, FDT_END_NODE, FDT_NOP, FDT_PROP},
+    mutex::Mutex,
+};
+use alloc::{string::String, vec::Vec};
+use core::{mem::size_of, slice, str};
+
+static mut DEVICE_TREE: Mutex<Option<DeviceTree<'static>>> = Mutex::new(None);
+
+#[derive(Debug, Clone, Copy)]
+pub struct DeviceTreeProperty<'a> {
+    pub name: &'a str,
+    pub value: &'a [u8],
+}
+
+impl<'a> DeviceTreeProperty<'a> {
+    pub fn as_str(&self) -> Option<&'a str> {
+        let value = self.value.strip_suffix(&[0]).unwrap_or(self.value);
+        str::from_utf8(value).ok()
+    }
+
+    pub fn as_str_list(&self) -> impl Iterator<Item = &'a str> {
+        self.value
+            .split(|&b| b == 0)
+            .filter(|s| !s.is_empty())
+            .filter_map(|s| str::from_utf8(s).ok())
+    }
+
+    pub fn as_u32(&self) -> Option<u32> {
+        Some(u32::from_be_bytes(self.value.get(..4)?.try_into().ok()?))
+    }
+
+    pub fn as_u64(&self) -> Option<u64> {
+        match self.value.len() {
+            4 => self.as_u32().map(|v| v as u64),
+            _ => Some(u64::from_be_bytes(self.value.get(..8)?.try_into().ok()?)),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct DeviceTreeNode<'a> {
+    pub name: &'a str,
+    pub parent: Option<usize>,
+    pub children: Vec<usize>,
+    pub properties: Vec<DeviceTreeProperty<'a>>,
+}
 
 #[derive(Debug)]
 pub struct DeviceTree<'a> {
     data: &'a [u8],
+    nodes: Vec<DeviceTreeNode<'a>>,
 }
 
 impl<'a> DeviceTree<'a> {
@@ -19,10 +67,176 @@ impl<'a> DeviceTree<'a> {
         }
 
         let data = unsafe { slice::from_raw_parts(fdt_addr.as_ptr(), total_size) };
-        Ok(Self { data })
+        let mut device_tree = Self {
+            data,
+            nodes: Vec::new(),
+        };
+        device_tree.parse()?;
+
+        Ok(device_tree)
     }
 
     fn fdt_header(&self) -> &FdtHeader {
         unsafe { &*(self.data.as_ptr() as *const FdtHeader) }
     }
+
+    fn read_u32(&self, offset: usize) -> Result<u32> {
+        let bytes = self
+            .data
+            .get(offset..offset + 4)
+            .ok_or("Invalid FDT structure")?;
+        Ok(u32::from_be_bytes(bytes.try_into().unwrap()))
+    }
+
+    fn read_str(&self, offset: usize) -> Result<&'a str> {
+        let data: &'a [u8] = self.data;
+        let bytes = data.get(offset..).ok_or("Invalid FDT structure")?;
+        let len = bytes
+            .iter()
+            .position(|&b| b == 0)
+            .ok_or("Invalid FDT structure")?;
+        str::from_utf8(&bytes[..len]).map_err(|_| "Invalid FDT string".into())
+    }
+
+    fn parse(&mut self) -> Result<()> {
+        let header = *self.fdt_header();
+        let strings_offset = header.off_dt_strings() as usize;
+        let mut offset = header.off_dt_struct() as usize;
+        let mut stack: Vec<usize> = Vec::new();
+
+        loop {
+            let token = self.read_u32(offset)?;
+            offset += 4;
+
+            match token {
+                FDT_BEGIN_NODE => {
+                    let name = self.read_str(offset)?;
+                    offset = align_up(offset + name.len() + 1, 4);
+
+                    let index = self.nodes.len();
+                    let parent = stack.last().copied();
+                    if let Some(parent) = parent {
+                        self.nodes[parent].children.push(index);
+                    }
+
+                    self.nodes.push(DeviceTreeNode {
+                        name,
+                        parent,
+                        children: Vec::new(),
+                        properties: Vec::new(),
+                    });
+                    stack.push(index);
+                }
+                FDT_END_NODE => {
+                    stack.pop().ok_or("Invalid FDT structure")?;
+                }
+                FDT_PROP => {
+                    let len = self.read_u32(offset)? as usize;
+                    let name_offset = self.read_u32(offset + 4)? as usize;
+                    offset += 8;
+
+                    let data: &'a [u8] = self.data;
+                    let value = data
+                        .get(offset..offset + len)
+                        .ok_or("Invalid FDT structure")?;
+                    let name = self.read_str(strings_offset + name_offset)?;
+                    offset = align_up(offset + len, 4);
+
+                    let node = *stack.last().ok_or("Invalid FDT structure")?;
+                    self.nodes[node]
+                        .properties
+                        .push(DeviceTreeProperty { name, value });
+                }
+                FDT_NOP => (),
+                FDT_END => break,
+                _ => return Err("Invalid FDT token".into()),
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn nodes(&self) -> &[DeviceTreeNode<'a>] {
+        &self.nodes
+    }
+
+    pub fn find_node(&self, path: &str) -> Option<usize> {
+        let mut index = 0;
+        if self.nodes.is_empty() {
+            return None;
+        }
+
+        for name in path.split('/').filter(|s| !s.is_empty()) {
+            index = *self.nodes[index].children.iter().find(|&&child| {
+                let child_name = self.nodes[child].name;
+                // unit address is optional
+                child_name == name || child_name.split('@').next() == Some(name)
+            })?;
+        }
+
+        Some(index)
+    }
+
+    pub fn property(&self, path: &str, name: &str) -> Option<DeviceTreeProperty<'a>> {
+        let node = self.find_node(path)?;
+        self.nodes[node]
+            .properties
+            .iter()
+            .find(|p| p.name == name)
+            .copied()
+    }
+
+    pub fn path(&self, index: usize) -> String {
+        let mut names = Vec::new();
+        let mut current = Some(index);
+
+        while let Some(i) = current {
+            if self.nodes[i].parent.is_some() {
+                names.push(self.nodes[i].name);
+            }
+            current = self.nodes[i].parent;
+        }
+
+        names.reverse();
+        let mut path = String::from("/");
+        path.push_str(&names.join("/"));
+        path
+    }
+}
+
+fn align_up(value: usize, align: usize) -> usize {
+    (value + align - 1) & !(align - 1)
+}
+
+pub fn init(fdt_addr: VirtualAddress) -> Result<()> {
+    let device_tree = DeviceTree::new(fdt_addr)?;
+    *unsafe { DEVICE_TREE.try_lock() }? = Some(device_tree);
+    Ok(())
+}
+
+pub fn get_property(path: &str, name: &str) -> Result<DeviceTreeProperty<'static>> {
+    let device_tree = unsafe { DEVICE_TREE.try_lock() }?;
+    device_tree
+        .as_ref()
+        .ok_or(Error::NotInitialized)?
+        .property(path, name)
+        .ok_or("Device tree property not found".into())
+}
+
+pub fn nodes() -> Result<Vec<DeviceTreeNode<'static>>> {
+    let device_tree = unsafe { DEVICE_TREE.try_lock() }?;
+    Ok(device_tree
+        .as_ref()
+        .ok_or(Error::NotInitialized)?
+        .nodes()
+        .to_vec())
+}
+
+pub fn find_node(path: &str) -> Result<usize> {
+    let device_tree = unsafe { DEVICE_TREE.try_lock() }?;
+    device_tree
+        .as_ref()
+        .ok_or(Error::NotInitialized)?
+        .find_node(path)
+        .ok_or("Device tree node not found".into())
 }
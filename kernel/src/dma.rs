@@ -0,0 +1,233 @@
+use crate::{
+    addr::MmioAddress,
+    asm,
+    error::{Error, Result},
+    mailbox,
+    mutex::Mutex,
+    timer,
+};
+
+// DMA channels 0-14 share the same register layout, channel 15 is separate
+const CHANNEL_COUNT: usize = 15;
+const TIMEOUT_US: u64 = 1_000_000;
+
+// bus address aliases
+const BUS_PERIPHERAL_BASE: u32 = 0x7e000000;
+const BUS_UNCACHED_RAM_BASE: u32 = 0xc0000000;
+
+pub const DREQ_EMMC: u32 = 11;
+
+const CS_ACTIVE: u32 = 1 << 0;
+const CS_END: u32 = 1 << 1;
+const CS_INT: u32 = 1 << 2;
+const CS_ERROR: u32 = 1 << 8;
+const CS_RESET: u32 = 1 << 31;
+
+const TI_WAIT_RESP: u32 = 1 << 3;
+const TI_DEST_INC: u32 = 1 << 4;
+const TI_DEST_DREQ: u32 = 1 << 6;
+const TI_SRC_INC: u32 = 1 << 8;
+const TI_SRC_DREQ: u32 = 1 << 10;
+
+static mut DMA: Mutex<Dma> = Mutex::new(Dma::new());
+
+fn mmio_base_dma() -> MmioAddress {
+    MmioAddress::new(0x7000)
+}
+
+fn channel_base(channel: usize) -> MmioAddress {
+    mmio_base_dma().offset(channel * 0x100)
+}
+
+fn read_cs(channel: usize) -> u32 {
+    channel_base(channel).offset(0x00).read()
+}
+
+fn write_cs(channel: usize, value: u32) {
+    channel_base(channel).offset(0x00).write(value);
+}
+
+fn write_conblk_ad(channel: usize, value: u32) {
+    channel_base(channel).offset(0x04).write(value);
+}
+
+fn read_enable() -> u32 {
+    mmio_base_dma().offset(0xff0).read()
+}
+
+fn write_enable(value: u32) {
+    mmio_base_dma().offset(0xff0).write(value);
+}
+
+pub fn peripheral_bus_address(mmio: MmioAddress) -> u32 {
+    mmio.get() - crate::cpu::mmio_base() + BUS_PERIPHERAL_BASE
+}
+
+pub fn ram_bus_address(addr: usize) -> u32 {
+    addr as u32 | BUS_UNCACHED_RAM_BASE
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DmaDirection {
+    // peripheral FIFO to memory
+    FromDevice,
+    // memory to peripheral FIFO
+    ToDevice,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+#[repr(C, align(32))]
+struct ControlBlock {
+    transfer_info: u32,
+    source_addr: u32,
+    dest_addr: u32,
+    transfer_len: u32,
+    stride: u32,
+    next_control_block: u32,
+    _reserved: [u32; 2],
+}
+
+struct Dma {
+    allocated: u32,
+    usable: Option<u32>,
+}
+
+impl Dma {
+    const fn new() -> Self {
+        Self {
+            allocated: 0,
+            usable: None,
+        }
+    }
+
+    fn alloc_channel(&mut self) -> Result<usize> {
+        let usable = match self.usable {
+            Some(mask) => mask,
+            None => {
+                let mask = mailbox::get_dma_channels()? & ((1 << CHANNEL_COUNT) - 1);
+                self.usable = Some(mask);
+                mask
+            }
+        };
+
+        let free = usable & !self.allocated;
+        if free == 0 {
+            return Err("No DMA channel available".into());
+        }
+
+        let channel = free.trailing_zeros() as usize;
+        self.allocated |= 1 << channel;
+        write_enable(read_enable() | 1 << channel);
+        write_cs(channel, CS_RESET);
+
+        Ok(channel)
+    }
+
+    fn free_channel(&mut self, channel: usize) {
+        write_cs(channel, CS_RESET);
+        self.allocated &= !(1 << channel);
+    }
+}
+
+pub struct DmaChannel {
+    channel: usize,
+    control_block: ControlBlock,
+}
+
+impl DmaChannel {
+    pub fn alloc() -> Result<Self> {
+        let channel = unsafe { DMA.try_lock() }?.alloc_channel()?;
+        Ok(Self {
+            channel,
+            control_block: ControlBlock::default(),
+        })
+    }
+
+    pub fn channel(&self) -> usize {
+        self.channel
+    }
+
+    // buffer must be 4 bytes aligned, self and buffer must not move until wait() returns
+    pub fn start(
+        &mut self,
+        direction: DmaDirection,
+        fifo: MmioAddress,
+        dreq: u32,
+        buf_addr: usize,
+        len: usize,
+    ) -> Result<()> {
+        if buf_addr % 4 != 0 || len % 4 != 0 {
+            return Err(Error::InvalidArgument);
+        }
+
+        if read_cs(self.channel) & CS_ACTIVE != 0 {
+            return Err("DMA channel is busy".into());
+        }
+
+        let fifo_addr = peripheral_bus_address(fifo);
+        let buf_addr = ram_bus_address(buf_addr);
+        let permap = (dreq & 0x1f) << 16;
+
+        // completion is polled in wait(), so the channel raises no interrupt
+        self.control_block = match direction {
+            DmaDirection::FromDevice => ControlBlock {
+                transfer_info: TI_WAIT_RESP | TI_DEST_INC | TI_SRC_DREQ | permap,
+                source_addr: fifo_addr,
+                dest_addr: buf_addr,
+                transfer_len: len as u32,
+                ..Default::default()
+            },
+            DmaDirection::ToDevice => ControlBlock {
+                transfer_info: TI_WAIT_RESP | TI_SRC_INC | TI_DEST_DREQ | permap,
+                source_addr: buf_addr,
+                dest_addr: fifo_addr,
+                transfer_len: len as u32,
+                ..Default::default()
+            },
+        };
+
+        let cb_addr = &self.control_block as *const ControlBlock as usize;
+        write_cs(self.channel, CS_END | CS_INT); // clear status
+        write_conblk_ad(self.channel, ram_bus_address(cb_addr));
+        write_cs(self.channel, CS_ACTIVE);
+
+        Ok(())
+    }
+
+    pub fn is_done(&self) -> Result<bool> {
+        let cs = read_cs(self.channel);
+        if cs & CS_ERROR != 0 {
+            return Err("DMA transfer error".into());
+        }
+
+        Ok(cs & CS_END != 0 || cs & CS_ACTIVE == 0)
+    }
+
+    pub fn wait(&self) -> Result<()> {
+        let start = timer::uptime_us();
+
+        while !self.is_done()? {
+            if timer::uptime_us() - start > TIMEOUT_US {
+                self.abort();
+                return Err("DMA transfer timeout".into());
+            }
+
+            asm::wait_cycles(1);
+        }
+
+        write_cs(self.channel, CS_END | CS_INT);
+        Ok(())
+    }
+
+    pub fn abort(&self) {
+        write_cs(self.channel, CS_RESET);
+    }
+}
+
+impl Drop for DmaChannel {
+    fn drop(&mut self) {
+        if let Ok(mut dma) = unsafe { DMA.try_lock() } {
+            dma.free_channel(self.channel);
+        }
+    }
+}
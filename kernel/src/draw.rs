@@ -1,6 +1,182 @@
-use crate::{color::ColorCode, error::Result};
+use crate::{
+    color::ColorCode,
+    error::{Error, Result},
+    font::{self, Glyph, TAB_DISP_STR},
+    image::Image,
+};
+use alloc::{vec, vec::Vec};
+use core::ops::Range;
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Rect {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+impl Rect {
+    pub const fn new(x: usize, y: usize, width: usize, height: usize) -> Self {
+        Self {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.width == 0 || self.height == 0
+    }
+
+    pub fn contains(&self, x: isize, y: isize) -> bool {
+        let (left, top) = (self.x as isize, self.y as isize);
+        (left..left + self.width as isize).contains(&x)
+            && (top..top + self.height as isize).contains(&y)
+    }
+
+    // empty if the rectangles do not overlap
+    pub fn intersect(&self, other: &Rect) -> Rect {
+        let x = self.x.max(other.x);
+        let y = self.y.max(other.y);
+        let right = self
+            .x
+            .saturating_add(self.width)
+            .min(other.x.saturating_add(other.width));
+        let bottom = self
+            .y
+            .saturating_add(self.height)
+            .min(other.y.saturating_add(other.height));
+        Rect::new(x, y, right.saturating_sub(x), bottom.saturating_sub(y))
+    }
+
+    // the part of a rectangle with signed coordinates that lies within this one
+    fn clip(&self, x: isize, y: isize, width: usize, height: usize) -> Rect {
+        let right = x.saturating_add_unsigned(width).max(0) as usize;
+        let bottom = y.saturating_add_unsigned(height).max(0) as usize;
+        let (x, y) = (x.max(0) as usize, y.max(0) as usize);
+        self.intersect(&Rect::new(
+            x,
+            y,
+            right - x.min(right),
+            bottom - y.min(bottom),
+        ))
+    }
+
+    // Cohen-Sutherland, the ends outside of this rectangle are moved onto its edges
+    fn clip_line(
+        &self,
+        x0: isize,
+        y0: isize,
+        x1: isize,
+        y1: isize,
+    ) -> Option<(isize, isize, isize, isize)> {
+        if self.is_empty() {
+            return None;
+        }
+
+        let (left, top) = (self.x as i128, self.y as i128);
+        let (right, bottom) = (left + self.width as i128 - 1, top + self.height as i128 - 1);
+        let outcode = |x: i128, y: i128| {
+            (x < left) as u8
+                | ((x > right) as u8) << 1
+                | ((y < top) as u8) << 2
+                | ((y > bottom) as u8) << 3
+        };
+
+        let (mut x0, mut y0, mut x1, mut y1) = (x0 as i128, y0 as i128, x1 as i128, y1 as i128);
+        loop {
+            let (code0, code1) = (outcode(x0, y0), outcode(x1, y1));
+            if code0 | code1 == 0 {
+                return Some((x0 as isize, y0 as isize, x1 as isize, y1 as isize));
+            }
+            if code0 & code1 != 0 {
+                return None;
+            }
+
+            // the edge lies between the ends, so the fractions are at most one
+            let code = if code0 != 0 { code0 } else { code1 };
+            let (x, y) = if code & 0b0001 != 0 {
+                (left, lerp(y0, y1, left - x0, x1 - x0))
+            } else if code & 0b0010 != 0 {
+                (right, lerp(y0, y1, right - x0, x1 - x0))
+            } else if code & 0b0100 != 0 {
+                (lerp(x0, x1, top - y0, y1 - y0), top)
+            } else {
+                (lerp(x0, x1, bottom - y0, y1 - y0), bottom)
+            };
+
+            if code == code0 {
+                (x0, y0) = (x, y);
+            } else {
+                (x1, y1) = (x, y);
+            }
+        }
+    }
+}
+
+// the point a fraction num / den of the way from a to b rounded to the nearest pixel, the
+// product fits as the distances between isize coordinates are below 2^64
+fn lerp(a: i128, b: i128, num: i128, den: i128) -> i128 {
+    let den = den.unsigned_abs();
+    let offset = ((a.abs_diff(b) * num.unsigned_abs() + den / 2) / den) as i128;
+    if b < a {
+        a - offset
+    } else {
+        a + offset
+    }
+}
+
+// distances from cy of the rows within the bounds, up to r
+fn visible_rows(bounds: &Rect, cy: isize, r: usize) -> Range<usize> {
+    if bounds.is_empty() {
+        return 0..0;
+    }
+
+    let (top, bottom) = (bounds.y as i128, (bounds.y + bounds.height) as i128 - 1);
+    let cy = cy as i128;
+    let near = (top - cy).max(cy - bottom).max(0);
+    let far = (cy - top).abs().max((bottom - cy).abs());
+    let end = far.min(r as i128) + 1;
+    near.min(end) as usize..end as usize
+}
+
+// half width of the row dy rows away from the centre of an ellipse, the radii are extended by
+// half a pixel in doubled coordinates so that the ends are not reduced to single pixels
+fn ellipse_half_width(rx: usize, ry: usize, dy: usize) -> Result<isize> {
+    let (a, b, d) = (rx as u128 * 2 + 1, ry as u128 * 2 + 1, dy as u128 * 2);
+    let b2 = b.checked_mul(b).ok_or(Error::InvalidArgument)?;
+    let scaled = a
+        .checked_mul(a)
+        .and_then(|a2| a2.checked_mul(b2 - d * d))
+        .ok_or(Error::InvalidArgument)?;
+    Ok(((scaled / b2).isqrt() / 2) as isize)
+}
+
+// (dy, first dx, last dx) of the outline of a quarter of an ellipse for the given rows, rows are
+// joined so that the outline has no gaps where it is steep
+fn quarter_ellipse(
+    rx: usize,
+    ry: usize,
+    rows: Range<usize>,
+) -> impl Iterator<Item = Result<(isize, isize, isize)>> {
+    rows.map(move |dy| {
+        let last = ellipse_half_width(rx, ry, dy)?;
+        let first = if dy < ry {
+            ellipse_half_width(rx, ry, dy + 1)? + 1
+        } else {
+            0
+        };
+        Ok((dy as isize, first.min(last), last))
+    })
+}
+
+// primitives take signed coordinates and are clipped to the bounds, so shapes may extend past
+// the edges
 pub trait Draw {
+    // the area that can be drawn to
+    fn bounds(&self) -> Result<Rect>;
+
     fn draw_rect(
         &mut self,
         x: usize,
@@ -9,6 +185,11 @@ pub trait Draw {
         height: usize,
         color: ColorCode,
     ) -> Result<()>;
+    fn fill(&mut self, color: ColorCode) -> Result<()>;
+    fn copy(&mut self, x: usize, y: usize, to_x: usize, to_y: usize) -> Result<()>;
+    fn read(&self, x: usize, y: usize) -> Result<ColorCode>;
+    fn write(&mut self, x: usize, y: usize, color: ColorCode) -> Result<()>;
+
     fn draw_string(
         &mut self,
         x: usize,
@@ -16,7 +197,34 @@ pub trait Draw {
         s: &str,
         fore_color: ColorCode,
         back_color: ColorCode,
-    ) -> Result<()>;
+    ) -> Result<()> {
+        let (font_width, font_height) = font::default_font()?.get_wh();
+
+        let mut char_x = x;
+        let mut char_y = y;
+
+        for c in s.chars() {
+            match c {
+                '\n' => {
+                    char_y += font_height;
+                    continue;
+                }
+                '\t' => {
+                    for c in TAB_DISP_STR.chars() {
+                        self.draw_font(char_x, char_y, c, fore_color, back_color)?;
+                        char_x += font_width;
+                    }
+                }
+                _ => (),
+            }
+
+            self.draw_font(char_x, char_y, c, fore_color, back_color)?;
+            char_x += font_width;
+        }
+
+        Ok(())
+    }
+
     fn draw_font(
         &mut self,
         x: usize,
@@ -24,9 +232,476 @@ pub trait Draw {
         c: char,
         fore_color: ColorCode,
         back_color: ColorCode,
-    ) -> Result<()>;
-    fn fill(&mut self, color: ColorCode) -> Result<()>;
-    fn copy(&mut self, x: usize, y: usize, to_x: usize, to_y: usize) -> Result<()>;
-    fn read(&self, x: usize, y: usize) -> Result<ColorCode>;
-    fn write(&mut self, x: usize, y: usize, color: ColorCode) -> Result<()>;
+    ) -> Result<()> {
+        let glyph = font::default_font()?.glyph(c);
+        self.draw_glyph(x, y, &glyph, fore_color, back_color)
+    }
+
+    fn draw_glyph(
+        &mut self,
+        x: usize,
+        y: usize,
+        glyph: &Glyph,
+        fore_color: ColorCode,
+        back_color: ColorCode,
+    ) -> Result<()> {
+        for h in 0..glyph.height {
+            for w in 0..glyph.width {
+                let color = if glyph.is_set(w, h) {
+                    fore_color
+                } else {
+                    back_color
+                };
+                self.draw_rect(x + w, y + h, 1, 1, color)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    // the source and destination may overlap
+    fn copy_rect(
+        &mut self,
+        x: usize,
+        y: usize,
+        width: usize,
+        height: usize,
+        to_x: usize,
+        to_y: usize,
+    ) -> Result<()> {
+        // copy away from the destination so that source pixels are read before overwritten
+        for row in 0..height {
+            let row = if to_y > y { height - 1 - row } else { row };
+            for col in 0..width {
+                let col = if to_x > x { width - 1 - col } else { col };
+                self.copy(x + col, y + row, to_x + col, to_y + row)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    // pixels are given row by row
+    fn blit(
+        &mut self,
+        x: usize,
+        y: usize,
+        width: usize,
+        height: usize,
+        pixels: &[ColorCode],
+    ) -> Result<()> {
+        for (i, color) in pixels.iter().take(width * height).enumerate() {
+            self.write(x + i % width, y + i / width, *color)?;
+        }
+
+        Ok(())
+    }
+
+    fn read_rect(
+        &self,
+        x: usize,
+        y: usize,
+        width: usize,
+        height: usize,
+        pixels: &mut [ColorCode],
+    ) -> Result<()> {
+        for (i, color) in pixels.iter_mut().take(width * height).enumerate() {
+            *color = self.read(x + i % width, y + i / width)?;
+        }
+
+        Ok(())
+    }
+
+    fn draw_point(&mut self, x: isize, y: isize, color: ColorCode) -> Result<()> {
+        if self.bounds()?.contains(x, y) {
+            self.write(x as usize, y as usize, color)?;
+        }
+
+        Ok(())
+    }
+
+    // horizontal line including both ends
+    fn draw_span(&mut self, x0: isize, x1: isize, y: isize, color: ColorCode) -> Result<()> {
+        let bounds = self.bounds()?;
+        // clipped before the width is taken, so that long spans do not overflow
+        let left = x0.min(x1).max(bounds.x as isize);
+        let right = x0.max(x1).min((bounds.x + bounds.width) as isize - 1);
+        if left > right || !bounds.contains(left, y) {
+            return Ok(());
+        }
+
+        self.draw_rect(
+            left as usize,
+            y as usize,
+            (right - left + 1) as usize,
+            1,
+            color,
+        )
+    }
+
+    // Bresenham's algorithm
+    fn draw_line(
+        &mut self,
+        x0: isize,
+        y0: isize,
+        x1: isize,
+        y1: isize,
+        color: ColorCode,
+    ) -> Result<()> {
+        if y0 == y1 {
+            return self.draw_span(x0, x1, y0, color);
+        }
+
+        // the clipped ends lie within the bounds, so the differences cannot overflow
+        let Some((x0, y0, x1, y1)) = self.bounds()?.clip_line(x0, y0, x1, y1) else {
+            return Ok(());
+        };
+
+        let (dx, dy) = ((x1 - x0).abs(), -(y1 - y0).abs());
+        let (sx, sy) = ((x1 - x0).signum(), (y1 - y0).signum());
+        let (mut x, mut y) = (x0, y0);
+        let mut err = dx + dy;
+
+        loop {
+            self.draw_point(x, y, color)?;
+            if x == x1 && y == y1 {
+                return Ok(());
+            }
+
+            let e2 = err * 2;
+            if e2 >= dy {
+                err += dy;
+                x += sx;
+            }
+            if e2 <= dx {
+                err += dx;
+                y += sy;
+            }
+        }
+    }
+
+    fn draw_polygon(&mut self, points: &[(isize, isize)], color: ColorCode) -> Result<()> {
+        for (i, &(x0, y0)) in points.iter().enumerate() {
+            let (x1, y1) = points[(i + 1) % points.len()];
+            self.draw_line(x0, y0, x1, y1, color)?;
+        }
+
+        Ok(())
+    }
+
+    // even-odd rule, rows are sampled through the pixel centres
+    fn fill_polygon(&mut self, points: &[(isize, isize)], color: ColorCode) -> Result<()> {
+        let bounds = self.bounds()?;
+        let (Some(top), Some(bottom)) = (
+            points.iter().map(|&(_, y)| y).min(),
+            points.iter().map(|&(_, y)| y).max(),
+        ) else {
+            return Ok(());
+        };
+        let top = top.max(bounds.y as isize);
+        let bottom = bottom.min((bounds.y + bounds.height) as isize - 1);
+
+        let mut crossings = Vec::new();
+        for y in top..=bottom {
+            // doubled coordinates put the pixel centre on an integer
+            let center = y * 2 + 1;
+            crossings.clear();
+            for (i, &(x0, y0)) in points.iter().enumerate() {
+                let (x1, y1) = points[(i + 1) % points.len()];
+                if (y0 * 2 < center) != (y1 * 2 < center) {
+                    let x = x0 * 2 + (center - y0 * 2) * (x1 - x0) / (y1 - y0);
+                    crossings.push(x.div_euclid(2));
+                }
+            }
+            crossings.sort_unstable();
+
+            for pair in crossings.chunks_exact(2) {
+                if pair[0] < pair[1] {
+                    self.draw_span(pair[0], pair[1] - 1, y, color)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn draw_ellipse(
+        &mut self,
+        cx: isize,
+        cy: isize,
+        rx: usize,
+        ry: usize,
+        color: ColorCode,
+    ) -> Result<()> {
+        if rx == 0 || ry == 0 {
+            return self.draw_line(
+                cx.saturating_sub_unsigned(rx),
+                cy.saturating_sub_unsigned(ry),
+                cx.saturating_add_unsigned(rx),
+                cy.saturating_add_unsigned(ry),
+                color,
+            );
+        }
+
+        let rows = visible_rows(&self.bounds()?, cy, ry);
+        for row in quarter_ellipse(rx, ry, rows) {
+            let (dy, first, last) = row?;
+            for y in [cy.saturating_sub(dy), cy.saturating_add(dy)] {
+                let (left, right) = (cx.saturating_sub(last), cx.saturating_add(last));
+                self.draw_span(left, cx.saturating_sub(first), y, color)?;
+                self.draw_span(cx.saturating_add(first), right, y, color)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn fill_ellipse(
+        &mut self,
+        cx: isize,
+        cy: isize,
+        rx: usize,
+        ry: usize,
+        color: ColorCode,
+    ) -> Result<()> {
+        if rx == 0 || ry == 0 {
+            return self.draw_ellipse(cx, cy, rx, ry, color);
+        }
+
+        let rows = visible_rows(&self.bounds()?, cy, ry);
+        for row in quarter_ellipse(rx, ry, rows) {
+            let (dy, _, last) = row?;
+            let (left, right) = (cx.saturating_sub(last), cx.saturating_add(last));
+            self.draw_span(left, right, cy.saturating_sub(dy), color)?;
+            if dy != 0 {
+                self.draw_span(left, right, cy.saturating_add(dy), color)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn draw_circle(&mut self, cx: isize, cy: isize, r: usize, color: ColorCode) -> Result<()> {
+        self.draw_ellipse(cx, cy, r, r, color)
+    }
+
+    fn fill_circle(&mut self, cx: isize, cy: isize, r: usize, color: ColorCode) -> Result<()> {
+        self.fill_ellipse(cx, cy, r, r, color)
+    }
+
+    // the corner radius is limited to half the shorter side
+    fn draw_round_rect(
+        &mut self,
+        x: isize,
+        y: isize,
+        width: usize,
+        height: usize,
+        radius: usize,
+        color: ColorCode,
+    ) -> Result<()> {
+        if width == 0 || height == 0 {
+            return Ok(());
+        }
+
+        let r = radius.min((width - 1) / 2).min((height - 1) / 2);
+        let (right, bottom) = (x + width as isize - 1, y + height as isize - 1);
+        let ri = r as isize;
+
+        self.draw_span(x + ri, right - ri, y, color)?;
+        self.draw_span(x + ri, right - ri, bottom, color)?;
+        self.draw_line(x, y + ri, x, bottom - ri, color)?;
+        self.draw_line(right, y + ri, right, bottom - ri, color)?;
+
+        if r == 0 {
+            return Ok(());
+        }
+
+        let bounds = self.bounds()?;
+        for (cy, up) in [(y + ri, true), (bottom - ri, false)] {
+            for row in quarter_ellipse(r, r, visible_rows(&bounds, cy, r)) {
+                let (dy, first, last) = row?;
+                let corner_y = if up { cy - dy } else { cy + dy };
+                self.draw_span(x + ri - last, x + ri - first, corner_y, color)?;
+                self.draw_span(right - ri + first, right - ri + last, corner_y, color)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn fill_round_rect(
+        &mut self,
+        x: isize,
+        y: isize,
+        width: usize,
+        height: usize,
+        radius: usize,
+        color: ColorCode,
+    ) -> Result<()> {
+        if width == 0 || height == 0 {
+            return Ok(());
+        }
+
+        let r = radius.min((width - 1) / 2).min((height - 1) / 2);
+        let (right, bottom) = (x + width as isize - 1, y + height as isize - 1);
+        let ri = r as isize;
+
+        let middle = self.bounds()?.clip(x, y + ri, width, height - r * 2);
+        if !middle.is_empty() {
+            self.draw_rect(middle.x, middle.y, middle.width, middle.height, color)?;
+        }
+
+        if r == 0 {
+            return Ok(());
+        }
+
+        // the middle already covers the rows through the corner centres
+        let bounds = self.bounds()?;
+        for (cy, up) in [(y + ri, true), (bottom - ri, false)] {
+            let rows = visible_rows(&bounds, cy, r);
+            for row in quarter_ellipse(r, r, rows.start.max(1)..rows.end) {
+                let (dy, _, last) = row?;
+                let corner_y = if up { cy - dy } else { cy + dy };
+                self.draw_span(x + ri - last, right - ri + last, corner_y, color)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn draw_image(&mut self, x: isize, y: isize, image: &Image) -> Result<()> {
+        let rect = self.bounds()?.clip(x, y, image.width(), image.height());
+        if rect.is_empty() {
+            return Ok(());
+        }
+
+        // the part of the image within the bounds, row by row
+        let (left, top) = (
+            (rect.x as isize - x) as usize,
+            (rect.y as isize - y) as usize,
+        );
+        for row in 0..rect.height {
+            let start = (top + row) * image.width() + left;
+            let pixels = &image.pixels()[start..start + rect.width];
+            self.blit(rect.x, rect.y + row, rect.width, 1, pixels)?;
+        }
+
+        Ok(())
+    }
+
+    // alpha 0 leaves the pixels unchanged and 255 paints the colour over them
+    fn blend_rect(
+        &mut self,
+        x: usize,
+        y: usize,
+        width: usize,
+        height: usize,
+        color: ColorCode,
+        alpha: u8,
+    ) -> Result<()> {
+        let rect = self.bounds()?.intersect(&Rect::new(x, y, width, height));
+        if rect.is_empty() {
+            return Ok(());
+        }
+
+        let mut pixels = vec![ColorCode::default(); rect.width * rect.height];
+        self.read_rect(rect.x, rect.y, rect.width, rect.height, &mut pixels)?;
+        for pixel in pixels.iter_mut() {
+            *pixel = pixel.blend(color, alpha);
+        }
+        self.blit(rect.x, rect.y, rect.width, rect.height, &pixels)
+    }
+}
+
+// restricts drawing to a rectangle, anything outside of it is silently dropped
+pub struct Clip<'a> {
+    draw: &'a mut dyn Draw,
+    rect: Rect,
+}
+
+impl<'a> Clip<'a> {
+    pub fn new(draw: &'a mut dyn Draw, rect: Rect) -> Self {
+        Self { draw, rect }
+    }
+}
+
+impl Draw for Clip<'_> {
+    fn bounds(&self) -> Result<Rect> {
+        Ok(self.rect.intersect(&self.draw.bounds()?))
+    }
+
+    fn draw_rect(
+        &mut self,
+        x: usize,
+        y: usize,
+        width: usize,
+        height: usize,
+        color: ColorCode,
+    ) -> Result<()> {
+        let rect = self.bounds()?.intersect(&Rect::new(x, y, width, height));
+        if rect.is_empty() {
+            return Ok(());
+        }
+
+        self.draw
+            .draw_rect(rect.x, rect.y, rect.width, rect.height, color)
+    }
+
+    fn fill(&mut self, color: ColorCode) -> Result<()> {
+        let rect = self.bounds()?;
+        self.draw_rect(rect.x, rect.y, rect.width, rect.height, color)
+    }
+
+    fn copy(&mut self, x: usize, y: usize, to_x: usize, to_y: usize) -> Result<()> {
+        if !self.bounds()?.contains(to_x as isize, to_y as isize) {
+            return Ok(());
+        }
+
+        self.draw.copy(x, y, to_x, to_y)
+    }
+
+    fn read(&self, x: usize, y: usize) -> Result<ColorCode> {
+        self.draw.read(x, y)
+    }
+
+    fn write(&mut self, x: usize, y: usize, color: ColorCode) -> Result<()> {
+        if !self.bounds()?.contains(x as isize, y as isize) {
+            return Ok(());
+        }
+
+        self.draw.write(x, y, color)
+    }
+
+    // only the destination is clipped
+    fn copy_rect(
+        &mut self,
+        x: usize,
+        y: usize,
+        width: usize,
+        height: usize,
+        to_x: usize,
+        to_y: usize,
+    ) -> Result<()> {
+        let rect = self
+            .bounds()?
+            .intersect(&Rect::new(to_x, to_y, width, height));
+        if rect.is_empty() {
+            return Ok(());
+        }
+
+        let (from_x, from_y) = (x + rect.x - to_x, y + rect.y - to_y);
+        self.draw
+            .copy_rect(from_x, from_y, rect.width, rect.height, rect.x, rect.y)
+    }
+
+    fn read_rect(
+        &self,
+        x: usize,
+        y: usize,
+        width: usize,
+        height: usize,
+        pixels: &mut [ColorCode],
+    ) -> Result<()> {
+        self.draw.read_rect(x, y, width, height, pixels)
+    }
 }
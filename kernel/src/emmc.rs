@@ -0,0 +1,707 @@
+use crate::{
+    addr::MmioAddress,
+    asm,
+    block::BlockDevice,
+    dma::{self, DmaChannel, DmaDirection},
+    error::{Error, Result},
+    interrupt,
+    mailbox::{self, ClockId},
+    mutex::Mutex,
+    timer,
+};
+use alloc::vec;
+
+// https://www.raspberrypi.org/app/uploads/2012/02/BCM2835-ARM-Peripherals.pdf (chapter 5)
+// https://www.sdcard.org/downloads/pls/ (Physical Layer Simplified Specification)
+
+pub const BLOCK_SIZE: usize = 512;
+
+const COMMAND_TIMEOUT_US: u64 = 1_000_000;
+const DATA_TIMEOUT_US: u64 = 5_000_000;
+const RESET_TIMEOUT_US: u64 = 1_000_000;
+
+const IDENTIFICATION_CLOCK_HZ: u32 = 400_000;
+const TRANSFER_CLOCK_HZ: u32 = 25_000_000;
+
+// STATUS
+const STATUS_CMD_INHIBIT: u32 = 1 << 0;
+const STATUS_DAT_INHIBIT: u32 = 1 << 1;
+
+// CONTROL0
+const CONTROL0_HCTL_DWIDTH: u32 = 1 << 1;
+
+// CONTROL1
+const CONTROL1_CLK_INTLEN: u32 = 1 << 0;
+const CONTROL1_CLK_STABLE: u32 = 1 << 1;
+const CONTROL1_CLK_EN: u32 = 1 << 2;
+const CONTROL1_DATA_TOUNIT_MAX: u32 = 0xe << 16;
+const CONTROL1_SRST_HC: u32 = 1 << 24;
+const CONTROL1_SRST_CMD: u32 = 1 << 25;
+const CONTROL1_SRST_DATA: u32 = 1 << 26;
+
+// INTERRUPT
+const INT_CMD_DONE: u32 = 1 << 0;
+const INT_DATA_DONE: u32 = 1 << 1;
+const INT_WRITE_RDY: u32 = 1 << 4;
+const INT_READ_RDY: u32 = 1 << 5;
+const INT_ERR: u32 = 1 << 15;
+const INT_ERROR_MASK: u32 = 0xffff_0000;
+const INT_ALL: u32 = 0xffff_ffff;
+
+// CMDTM
+const CMD_BLKCNT_EN: u32 = 1 << 1;
+const CMD_AUTO_CMD12: u32 = 1 << 2;
+const CMD_DAT_DIR_READ: u32 = 1 << 4;
+const CMD_MULTI_BLOCK: u32 = 1 << 5;
+const CMD_RSPNS_136: u32 = 1 << 16;
+const CMD_RSPNS_48: u32 = 2 << 16;
+const CMD_RSPNS_48_BUSY: u32 = 3 << 16;
+const CMD_CRCCHK_EN: u32 = 1 << 19;
+const CMD_IXCHK_EN: u32 = 1 << 20;
+const CMD_ISDATA: u32 = 1 << 21;
+
+static mut EMMC: Mutex<Emmc> = Mutex::new(Emmc::new());
+
+// interrupt flags collected by the interrupt handler
+static mut IRQ_FLAGS: u32 = 0;
+
+fn mmio_base_emmc() -> MmioAddress {
+    MmioAddress::new(0x300000)
+}
+
+fn write_blksizecnt(value: u32) {
+    mmio_base_emmc().offset(0x04).write(value);
+}
+
+fn write_arg1(value: u32) {
+    mmio_base_emmc().offset(0x08).write(value);
+}
+
+fn write_cmdtm(value: u32) {
+    mmio_base_emmc().offset(0x0c).write(value);
+}
+
+fn read_resp(index: usize) -> u32 {
+    mmio_base_emmc().offset(0x10 + index * 4).read()
+}
+
+fn data_register() -> MmioAddress {
+    mmio_base_emmc().offset(0x20)
+}
+
+fn read_status() -> u32 {
+    mmio_base_emmc().offset(0x24).read()
+}
+
+fn read_control0() -> u32 {
+    mmio_base_emmc().offset(0x28).read()
+}
+
+fn write_control0(value: u32) {
+    mmio_base_emmc().offset(0x28).write(value);
+}
+
+fn read_control1() -> u32 {
+    mmio_base_emmc().offset(0x2c).read()
+}
+
+fn write_control1(value: u32) {
+    mmio_base_emmc().offset(0x2c).write(value);
+}
+
+fn read_interrupt() -> u32 {
+    mmio_base_emmc().offset(0x30).read()
+}
+
+fn write_interrupt(value: u32) {
+    mmio_base_emmc().offset(0x30).write(value);
+}
+
+fn write_irpt_mask(value: u32) {
+    mmio_base_emmc().offset(0x34).write(value);
+}
+
+fn write_irpt_en(value: u32) {
+    mmio_base_emmc().offset(0x38).write(value);
+}
+
+fn read_slotisr_ver() -> u32 {
+    mmio_base_emmc().offset(0xfc).read()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EmmcError {
+    Timeout,
+    CommandError { cmd: u32, status: u32 },
+    DataError { status: u32 },
+    NoCard,
+    UnsupportedCard,
+    InvalidBufferSize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TransferMode {
+    Polling,
+    InterruptDma,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Command {
+    GoIdleState,
+    AllSendCid,
+    SendRelativeAddr,
+    SelectCard,
+    SendIfCond,
+    SendCsd,
+    SetBlockLen,
+    ReadSingleBlock,
+    ReadMultipleBlock,
+    WriteSingleBlock,
+    WriteMultipleBlock,
+    AppCmd,
+    SetBusWidth,
+    SdSendOpCond,
+    SendScr,
+}
+
+impl Command {
+    fn index(&self) -> u32 {
+        match self {
+            Self::GoIdleState => 0,
+            Self::AllSendCid => 2,
+            Self::SendRelativeAddr => 3,
+            Self::SelectCard => 7,
+            Self::SendIfCond => 8,
+            Self::SendCsd => 9,
+            Self::SetBlockLen => 16,
+            Self::ReadSingleBlock => 17,
+            Self::ReadMultipleBlock => 18,
+            Self::WriteSingleBlock => 24,
+            Self::WriteMultipleBlock => 25,
+            Self::AppCmd => 55,
+            Self::SetBusWidth => 6,
+            Self::SdSendOpCond => 41,
+            Self::SendScr => 51,
+        }
+    }
+
+    fn is_app_cmd(&self) -> bool {
+        matches!(self, Self::SetBusWidth | Self::SdSendOpCond | Self::SendScr)
+    }
+
+    fn cmdtm(&self) -> u32 {
+        let flags = match self {
+            Self::GoIdleState => 0,
+            Self::AllSendCid | Self::SendCsd => CMD_RSPNS_136 | CMD_CRCCHK_EN,
+            Self::SelectCard => CMD_RSPNS_48_BUSY | CMD_CRCCHK_EN | CMD_IXCHK_EN,
+            // R3 response has no CRC and index
+            Self::SdSendOpCond => CMD_RSPNS_48,
+            Self::ReadSingleBlock | Self::SendScr => {
+                CMD_RSPNS_48 | CMD_CRCCHK_EN | CMD_IXCHK_EN | CMD_ISDATA | CMD_DAT_DIR_READ
+            }
+            Self::ReadMultipleBlock => {
+                CMD_RSPNS_48
+                    | CMD_CRCCHK_EN
+                    | CMD_IXCHK_EN
+                    | CMD_ISDATA
+                    | CMD_DAT_DIR_READ
+                    | CMD_MULTI_BLOCK
+                    | CMD_BLKCNT_EN
+                    | CMD_AUTO_CMD12
+            }
+            Self::WriteSingleBlock => CMD_RSPNS_48 | CMD_CRCCHK_EN | CMD_IXCHK_EN | CMD_ISDATA,
+            Self::WriteMultipleBlock => {
+                CMD_RSPNS_48
+                    | CMD_CRCCHK_EN
+                    | CMD_IXCHK_EN
+                    | CMD_ISDATA
+                    | CMD_MULTI_BLOCK
+                    | CMD_BLKCNT_EN
+                    | CMD_AUTO_CMD12
+            }
+            _ => CMD_RSPNS_48 | CMD_CRCCHK_EN | CMD_IXCHK_EN,
+        };
+
+        self.index() << 24 | flags
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CardInfo {
+    pub rca: u32,
+    pub is_sdhc: bool,
+    pub bus_width_4bit: bool,
+    pub block_count: u64,
+    pub cid: [u32; 4],
+}
+
+struct Emmc {
+    card: Option<CardInfo>,
+    mode: TransferMode,
+    dma_channel: Option<DmaChannel>,
+}
+
+impl Emmc {
+    const fn new() -> Self {
+        Self {
+            card: None,
+            mode: TransferMode::Polling,
+            dma_channel: None,
+        }
+    }
+
+    fn card(&self) -> Result<CardInfo> {
+        self.card.ok_or(Error::NotInitialized)
+    }
+
+    fn wait_status_clear(&self, mask: u32, timeout_us: u64) -> Result<()> {
+        let start = timer::uptime_us();
+
+        while read_status() & mask != 0 {
+            if timer::uptime_us() - start > timeout_us {
+                return Err(EmmcError::Timeout.into());
+            }
+
+            asm::wait_cycles(1);
+        }
+
+        Ok(())
+    }
+
+    fn take_irq_flags(&self, mask: u32) -> u32 {
+        match self.mode {
+            TransferMode::Polling => {
+                let flags = read_interrupt() & (mask | INT_ERR | INT_ERROR_MASK);
+                write_interrupt(flags);
+                flags
+            }
+            TransferMode::InterruptDma => asm::disabled_int(|| unsafe {
+                let flags = IRQ_FLAGS & (mask | INT_ERR | INT_ERROR_MASK);
+                IRQ_FLAGS &= !flags;
+                flags
+            }),
+        }
+    }
+
+    // waits for any of the bits in mask, returns an error on an error interrupt
+    fn wait_interrupt(&self, mask: u32, timeout_us: u64) -> Result<u32> {
+        let start = timer::uptime_us();
+
+        loop {
+            let flags = self.take_irq_flags(mask);
+            if flags & (INT_ERR | INT_ERROR_MASK) != 0 {
+                return Err(EmmcError::DataError { status: flags }.into());
+            }
+
+            if flags & mask != 0 {
+                return Ok(flags);
+            }
+
+            if timer::uptime_us() - start > timeout_us {
+                return Err(EmmcError::Timeout.into());
+            }
+
+            match self.mode {
+                TransferMode::Polling => asm::wait_cycles(1),
+                TransferMode::InterruptDma => {
+                    // check again with interrupts masked so that a wakeup is not missed
+                    let daif = asm::read_daif();
+                    asm::disable_int();
+                    if unsafe { IRQ_FLAGS } & (mask | INT_ERR | INT_ERROR_MASK) == 0 {
+                        asm::wait_for_interrupt();
+                    }
+                    asm::write_daif(daif);
+                }
+            }
+        }
+    }
+
+    fn reset_lines(&self) {
+        write_control1(read_control1() | CONTROL1_SRST_CMD | CONTROL1_SRST_DATA);
+        let _ = self.wait_reset(CONTROL1_SRST_CMD | CONTROL1_SRST_DATA);
+        write_interrupt(INT_ALL);
+        asm::disabled_int(|| unsafe { IRQ_FLAGS = 0 });
+    }
+
+    fn wait_reset(&self, mask: u32) -> Result<()> {
+        let start = timer::uptime_us();
+
+        while read_control1() & mask != 0 {
+            if timer::uptime_us() - start > RESET_TIMEOUT_US {
+                return Err(EmmcError::Timeout.into());
+            }
+
+            asm::wait_cycles(1);
+        }
+
+        Ok(())
+    }
+
+    fn send_command(&mut self, cmd: Command, arg: u32) -> Result<[u32; 4]> {
+        if cmd.is_app_cmd() {
+            let rca = self.card.map_or(0, |c| c.rca);
+            self.send_command(Command::AppCmd, rca << 16)?;
+        }
+
+        let mut inhibit = STATUS_CMD_INHIBIT;
+        if cmd.cmdtm() & CMD_ISDATA != 0 {
+            inhibit |= STATUS_DAT_INHIBIT;
+        }
+        self.wait_status_clear(inhibit, COMMAND_TIMEOUT_US)?;
+
+        write_interrupt(INT_ALL);
+        write_arg1(arg);
+
+        write_cmdtm(cmd.cmdtm());
+
+        if let Err(err) = self.wait_interrupt(INT_CMD_DONE, COMMAND_TIMEOUT_US) {
+            self.reset_lines();
+            return Err(match err {
+                Error::EmmcError(EmmcError::DataError { status }) => EmmcError::CommandError {
+                    cmd: cmd.index(),
+                    status,
+                }
+                .into(),
+                err => err,
+            });
+        }
+
+        Ok([read_resp(0), read_resp(1), read_resp(2), read_resp(3)])
+    }
+
+    fn set_clock(&self, freq: u32) -> Result<()> {
+        self.wait_status_clear(STATUS_CMD_INHIBIT | STATUS_DAT_INHIBIT, COMMAND_TIMEOUT_US)?;
+
+        let base_clock = mailbox::get_clock_rate(ClockId::Emmc)?;
+        if base_clock == 0 {
+            return Err("Invalid EMMC base clock".into());
+        }
+
+        // SD clock = base clock / (2 * divisor), 10-bit divisor
+        let mut divisor = base_clock.div_ceil(2 * freq);
+        if divisor > 0x3ff {
+            divisor = 0x3ff;
+        }
+
+        let mut control1 = read_control1() & !CONTROL1_CLK_EN;
+        write_control1(control1);
+        timer::wait_us(10);
+
+        control1 &= !(0x3ff << 6);
+        control1 |= (divisor & 0xff) << 8 | ((divisor >> 8) & 0x3) << 6;
+        write_control1(control1);
+        timer::wait_us(10);
+
+        let start = timer::uptime_us();
+        while read_control1() & CONTROL1_CLK_STABLE == 0 {
+            if timer::uptime_us() - start > RESET_TIMEOUT_US {
+                return Err(EmmcError::Timeout.into());
+            }
+
+            asm::wait_cycles(1);
+        }
+
+        write_control1(read_control1() | CONTROL1_CLK_EN);
+        timer::wait_us(10);
+        Ok(())
+    }
+
+    fn read_scr(&mut self) -> Result<[u32; 2]> {
+        write_blksizecnt(1 << 16 | 8);
+        self.send_command(Command::SendScr, 0)?;
+        self.wait_interrupt(INT_READ_RDY, DATA_TIMEOUT_US)?;
+
+        let scr = [data_register().read(), data_register().read()];
+        self.wait_interrupt(INT_DATA_DONE, DATA_TIMEOUT_US)?;
+        Ok(scr)
+    }
+
+    fn parse_block_count(csd: [u32; 4]) -> u64 {
+        // the response registers hold CSD[127:8]
+        let csd = ((csd[3] as u128) << 96
+            | (csd[2] as u128) << 64
+            | (csd[1] as u128) << 32
+            | csd[0] as u128)
+            << 8;
+        let bits = |hi: u32, lo: u32| ((csd >> lo) & ((1 << (hi - lo + 1)) - 1)) as u64;
+
+        match bits(127, 126) {
+            // CSD version 1.0
+            0 => {
+                let c_size = bits(73, 62);
+                let c_size_mult = bits(49, 47);
+                let read_bl_len = bits(83, 80);
+                let bytes = (c_size + 1) << (c_size_mult + 2 + read_bl_len);
+                bytes / BLOCK_SIZE as u64
+            }
+            // CSD version 2.0, unit is 512KiB
+            _ => (bits(69, 48) + 1) * 1024,
+        }
+    }
+
+    fn init(&mut self, mode: TransferMode) -> Result<()> {
+        self.card = None;
+        self.mode = TransferMode::Polling;
+
+        let version = (read_slotisr_ver() >> 16) & 0xff;
+        if version < 2 {
+            return Err("Unsupported SDHCI version".into());
+        }
+
+        // reset the host controller
+        write_control0(0);
+        write_control1(read_control1() | CONTROL1_SRST_HC);
+        self.wait_reset(CONTROL1_SRST_HC)?;
+
+        write_control1(read_control1() | CONTROL1_CLK_INTLEN | CONTROL1_DATA_TOUNIT_MAX);
+        self.set_clock(IDENTIFICATION_CLOCK_HZ)?;
+
+        // report all status bits but do not signal interrupts while identifying
+        write_irpt_en(0);
+        write_irpt_mask(INT_ALL);
+        write_interrupt(INT_ALL);
+
+        self.send_command(Command::GoIdleState, 0)?;
+
+        // voltage 2.7-3.6V, check pattern 0xaa
+        let is_v2 = match self.send_command(Command::SendIfCond, 0x1aa) {
+            Ok(resp) if resp[0] & 0xfff == 0x1aa => true,
+            Ok(_) => return Err(EmmcError::UnsupportedCard.into()),
+            Err(_) => false,
+        };
+
+        // HCS (if version 2), 3.2-3.4V
+        let mut ocr_arg = 0x00ff_8000;
+        if is_v2 {
+            ocr_arg |= 1 << 30;
+        }
+
+        let start = timer::uptime_us();
+        let ocr = loop {
+            let ocr = self
+                .send_command(Command::SdSendOpCond, ocr_arg)
+                .map_err(|_| Error::from(EmmcError::NoCard))?[0];
+            if ocr & (1 << 31) != 0 {
+                break ocr;
+            }
+
+            if timer::uptime_us() - start > DATA_TIMEOUT_US {
+                return Err(EmmcError::Timeout.into());
+            }
+
+            timer::wait_ms(10);
+        };
+
+        let cid = self.send_command(Command::AllSendCid, 0)?;
+        let rca = self.send_command(Command::SendRelativeAddr, 0)?[0] >> 16;
+        let csd = self.send_command(Command::SendCsd, rca << 16)?;
+
+        let mut card = CardInfo {
+            rca,
+            is_sdhc: ocr & (1 << 30) != 0,
+            bus_width_4bit: false,
+            block_count: Self::parse_block_count(csd),
+            cid,
+        };
+        self.card = Some(card);
+
+        self.set_clock(TRANSFER_CLOCK_HZ)?;
+        self.send_command(Command::SelectCard, rca << 16)?;
+
+        if !card.is_sdhc {
+            self.send_command(Command::SetBlockLen, BLOCK_SIZE as u32)?;
+        }
+
+        // switch to 4-bit bus if the card supports it (SCR is big endian)
+        let scr = self.read_scr()?;
+        let bus_widths = (u32::from_be(scr[0]) >> 16) & 0xf;
+        if bus_widths & 0b100 != 0 {
+            self.send_command(Command::SetBusWidth, 2)?;
+            write_control0(read_control0() | CONTROL0_HCTL_DWIDTH);
+            card.bus_width_4bit = true;
+        }
+
+        self.card = Some(card);
+        write_blksizecnt(BLOCK_SIZE as u32);
+
+        if mode == TransferMode::InterruptDma {
+            self.dma_channel = Some(DmaChannel::alloc()?);
+            write_irpt_en(INT_ALL);
+            self.mode = TransferMode::InterruptDma;
+        }
+
+        Ok(())
+    }
+
+    fn block_addr(&self, lba: u64) -> Result<u32> {
+        let card = self.card()?;
+        if lba >= card.block_count {
+            return Err(Error::InvalidArgument);
+        }
+
+        let addr = if card.is_sdhc {
+            lba
+        } else {
+            lba * BLOCK_SIZE as u64
+        };
+
+        Ok(addr as u32)
+    }
+
+    fn transfer_pio(&mut self, read: bool, buf: *mut u8, len: usize) -> Result<()> {
+        let ready = if read { INT_READ_RDY } else { INT_WRITE_RDY };
+
+        for block in 0..len / BLOCK_SIZE {
+            self.wait_interrupt(ready, DATA_TIMEOUT_US)?;
+
+            for i in 0..BLOCK_SIZE / 4 {
+                let ptr = unsafe { buf.add(block * BLOCK_SIZE + i * 4) } as *mut [u8; 4];
+                if read {
+                    unsafe { ptr.write_unaligned(data_register().read().to_le_bytes()) };
+                } else {
+                    data_register().write(u32::from_le_bytes(unsafe { ptr.read_unaligned() }));
+                }
+            }
+        }
+
+        self.wait_interrupt(INT_DATA_DONE, DATA_TIMEOUT_US)?;
+        Ok(())
+    }
+
+    fn transfer_dma(&mut self, read: bool, buf: *mut u8, len: usize) -> Result<()> {
+        let channel = self.dma_channel.as_mut().ok_or(Error::NotInitialized)?;
+        let direction = if read {
+            DmaDirection::FromDevice
+        } else {
+            DmaDirection::ToDevice
+        };
+
+        // DMA needs a word aligned buffer
+        let mut bounce = vec![0u32; len / 4];
+        let bounce_ptr = bounce.as_mut_ptr() as *mut u8;
+        if !read {
+            unsafe { bounce_ptr.copy_from_nonoverlapping(buf, len) };
+        }
+
+        channel.start(
+            direction,
+            data_register(),
+            dma::DREQ_EMMC,
+            bounce_ptr as usize,
+            len,
+        )?;
+
+        let res = self.wait_interrupt(INT_DATA_DONE, DATA_TIMEOUT_US);
+        let channel = self.dma_channel.as_ref().ok_or(Error::NotInitialized)?;
+        if let Err(err) = res {
+            channel.abort();
+            return Err(err);
+        }
+        channel.wait()?;
+
+        if read {
+            unsafe { buf.copy_from_nonoverlapping(bounce_ptr, len) };
+        }
+
+        Ok(())
+    }
+
+    fn transfer(&mut self, read: bool, lba: u64, buf: *mut u8, len: usize) -> Result<()> {
+        if len == 0 || len % BLOCK_SIZE != 0 {
+            return Err(EmmcError::InvalidBufferSize.into());
+        }
+
+        let count = len / BLOCK_SIZE;
+        let addr = self.block_addr(lba)?;
+        self.block_addr(lba + count as u64 - 1)?;
+
+        let cmd = match (read, count) {
+            (true, 1) => Command::ReadSingleBlock,
+            (true, _) => Command::ReadMultipleBlock,
+            (false, 1) => Command::WriteSingleBlock,
+            (false, _) => Command::WriteMultipleBlock,
+        };
+
+        write_blksizecnt((count as u32) << 16 | BLOCK_SIZE as u32);
+        self.send_command(cmd, addr)?;
+
+        let res = match self.mode {
+            TransferMode::Polling => self.transfer_pio(read, buf, len),
+            TransferMode::InterruptDma => self.transfer_dma(read, buf, len),
+        };
+
+        if res.is_err() {
+            self.reset_lines();
+        }
+
+        res
+    }
+}
+
+fn handle_interrupt() {
+    let flags = read_interrupt();
+    write_interrupt(flags);
+    unsafe { IRQ_FLAGS |= flags };
+}
+
+pub fn init(mode: TransferMode) -> Result<()> {
+    // the controller does not signal interrupts until init() enables them
+    let _ = interrupt::unregister_handler(interrupt::LINE_EMMC);
+    if mode == TransferMode::InterruptDma {
+        interrupt::register_handler(interrupt::LINE_EMMC, "emmc", handle_interrupt)?;
+    }
+
+    let res = unsafe { EMMC.try_lock() }?.init(mode);
+    if res.is_err() {
+        let _ = interrupt::unregister_handler(interrupt::LINE_EMMC);
+    }
+
+    res
+}
+
+pub fn card_info() -> Result<CardInfo> {
+    unsafe { EMMC.try_lock() }?.card()
+}
+
+pub fn read_blocks(lba: u64, buf: &mut [u8]) -> Result<()> {
+    let len = buf.len();
+    unsafe { EMMC.try_lock() }?.transfer(true, lba, buf.as_mut_ptr(), len)
+}
+
+pub fn write_blocks(lba: u64, buf: &[u8]) -> Result<()> {
+    let len = buf.len();
+    unsafe { EMMC.try_lock() }?.transfer(false, lba, buf.as_ptr() as *mut u8, len)
+}
+
+// block device view of the card, block_count is read at construction
+pub struct EmmcBlockDevice {
+    block_count: u64,
+}
+
+impl EmmcBlockDevice {
+    pub fn new() -> Result<Self> {
+        Ok(Self {
+            block_count: card_info()?.block_count,
+        })
+    }
+}
+
+impl BlockDevice for EmmcBlockDevice {
+    fn block_size(&self) -> usize {
+        BLOCK_SIZE
+    }
+
+    fn block_count(&self) -> u64 {
+        self.block_count
+    }
+
+    fn read_blocks(&mut self, lba: u64, buf: &mut [u8]) -> Result<()> {
+        self.check_range(lba, buf.len())?;
+        read_blocks(lba, buf)
+    }
+
+    fn write_blocks(&mut self, lba: u64, buf: &[u8]) -> Result<()> {
+        self.check_range(lba, buf.len())?;
+        write_blocks(lba, buf)
+    }
+}
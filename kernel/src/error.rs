@@ -1,4 +1,4 @@
-use crate::{cpu::CpuModel, framebuffer::FramebufferError};
+use crate::{cpu::CpuModel, emmc::EmmcError, framebuffer::FramebufferError, vfs::FileSystemError};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Error {
@@ -7,6 +7,8 @@ pub enum Error {
     NotInitialized,
     InvalidArgument,
     FramebufferError(FramebufferError),
+    FileSystemError(FileSystemError),
+    EmmcError(EmmcError),
 }
 
 impl From<&'static str> for Error {
@@ -21,4 +23,16 @@ impl From<FramebufferError> for Error {
     }
 }
 
+impl From<FileSystemError> for Error {
+    fn from(err: FileSystemError) -> Self {
+        Self::FileSystemError(err)
+    }
+}
+
+impl From<EmmcError> for Error {
+    fn from(err: EmmcError) -> Self {
+        Self::EmmcError(err)
+    }
+}
+
 pub type Result<T> = core::result::Result<T, Error>;
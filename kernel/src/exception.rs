@@ -0,0 +1,225 @@
+use crate::{asm, breakpoint, error::Result, gdb, interrupt};
+use core::{
+    arch::global_asm,
+    fmt::{self, Write},
+};
+
+const TRAP_FRAME_SIZE: usize = 36 * 8;
+
+// state of the exception that is being reported by the panic handler
+static mut FAULT_FRAME: Option<(ExceptionKind, TrapFrame)> = None;
+
+global_asm!(
+    r#"
+.macro SAVE_AND_HANDLE kind
+.balign 0x80
+    sub sp, sp, #{size}
+    stp x0, x1, [sp, #16 * 0]
+    stp x2, x3, [sp, #16 * 1]
+    stp x4, x5, [sp, #16 * 2]
+    stp x6, x7, [sp, #16 * 3]
+    stp x8, x9, [sp, #16 * 4]
+    stp x10, x11, [sp, #16 * 5]
+    stp x12, x13, [sp, #16 * 6]
+    stp x14, x15, [sp, #16 * 7]
+    stp x16, x17, [sp, #16 * 8]
+    stp x18, x19, [sp, #16 * 9]
+    stp x20, x21, [sp, #16 * 10]
+    stp x22, x23, [sp, #16 * 11]
+    stp x24, x25, [sp, #16 * 12]
+    stp x26, x27, [sp, #16 * 13]
+    stp x28, x29, [sp, #16 * 14]
+    mov x0, #\kind
+    b exception_common
+.endm
+
+.section .text
+.balign 0x800
+.global exception_vector_table
+exception_vector_table:
+    SAVE_AND_HANDLE 0
+    SAVE_AND_HANDLE 1
+    SAVE_AND_HANDLE 2
+    SAVE_AND_HANDLE 3
+    SAVE_AND_HANDLE 4
+    SAVE_AND_HANDLE 5
+    SAVE_AND_HANDLE 6
+    SAVE_AND_HANDLE 7
+    SAVE_AND_HANDLE 8
+    SAVE_AND_HANDLE 9
+    SAVE_AND_HANDLE 10
+    SAVE_AND_HANDLE 11
+    SAVE_AND_HANDLE 12
+    SAVE_AND_HANDLE 13
+    SAVE_AND_HANDLE 14
+    SAVE_AND_HANDLE 15
+
+exception_common:
+    mrs x1, elr_el1
+    stp x30, x1, [sp, #16 * 15]
+    mrs x2, spsr_el1
+    mrs x3, esr_el1
+    stp x2, x3, [sp, #16 * 16]
+    mrs x4, far_el1
+    add x5, sp, #{size}
+    stp x4, x5, [sp, #16 * 17]
+
+    mov x1, sp
+    bl handle_exception
+
+    // the handler may modify the return address and the saved state
+    ldp x30, x1, [sp, #16 * 15]
+    msr elr_el1, x1
+    ldr x2, [sp, #16 * 16]
+    msr spsr_el1, x2
+
+    ldp x0, x1, [sp, #16 * 0]
+    ldp x2, x3, [sp, #16 * 1]
+    ldp x4, x5, [sp, #16 * 2]
+    ldp x6, x7, [sp, #16 * 3]
+    ldp x8, x9, [sp, #16 * 4]
+    ldp x10, x11, [sp, #16 * 5]
+    ldp x12, x13, [sp, #16 * 6]
+    ldp x14, x15, [sp, #16 * 7]
+    ldp x16, x17, [sp, #16 * 8]
+    ldp x18, x19, [sp, #16 * 9]
+    ldp x20, x21, [sp, #16 * 10]
+    ldp x22, x23, [sp, #16 * 11]
+    ldp x24, x25, [sp, #16 * 12]
+    ldp x26, x27, [sp, #16 * 13]
+    ldp x28, x29, [sp, #16 * 14]
+    add sp, sp, #{size}
+    eret
+"#,
+    size = const TRAP_FRAME_SIZE,
+);
+
+#[derive(Debug, Clone, Copy, Default)]
+#[repr(C)]
+pub struct TrapFrame {
+    pub x: [u64; 31],
+    pub elr: u64,
+    pub spsr: u64,
+    pub esr: u64,
+    pub far: u64,
+    pub sp: u64,
+}
+
+impl TrapFrame {
+    pub fn exception_class(&self) -> ExceptionClass {
+        ExceptionClass::from((self.esr >> 26) as u32 & 0x3f)
+    }
+
+    pub fn write_registers(&self, w: &mut dyn Write) -> fmt::Result {
+        for (i, pair) in self.x.chunks(2).enumerate() {
+            match pair {
+                [a, b] => writeln!(
+                    w,
+                    "x{:<2}: 0x{:016x} x{:<2}: 0x{:016x}",
+                    i * 2,
+                    a,
+                    i * 2 + 1,
+                    b
+                )?,
+                [a] => writeln!(w, "x{:<2}: 0x{:016x}", i * 2, a)?,
+                _ => (),
+            }
+        }
+        writeln!(w, "elr: 0x{:016x} spsr: 0x{:08x}", self.elr, self.spsr)?;
+        writeln!(w, "esr: 0x{:08x} ({:?})", self.esr, self.exception_class())?;
+        writeln!(w, "far: 0x{:016x} sp:   0x{:016x}", self.far, self.sp)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExceptionKind {
+    Synchronous,
+    Irq,
+    Fiq,
+    SError,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExceptionClass {
+    Unknown,
+    InstructionAbortLowerEl,
+    InstructionAbortSameEl,
+    PcAlignmentFault,
+    DataAbortLowerEl,
+    DataAbortSameEl,
+    SpAlignmentFault,
+    BreakpointLowerEl,
+    BreakpointSameEl,
+    SoftwareStepLowerEl,
+    SoftwareStepSameEl,
+    WatchpointLowerEl,
+    WatchpointSameEl,
+    Brk,
+    Other(u32),
+}
+
+impl From<u32> for ExceptionClass {
+    fn from(value: u32) -> Self {
+        match value {
+            0x00 => Self::Unknown,
+            0x20 => Self::InstructionAbortLowerEl,
+            0x21 => Self::InstructionAbortSameEl,
+            0x22 => Self::PcAlignmentFault,
+            0x24 => Self::DataAbortLowerEl,
+            0x25 => Self::DataAbortSameEl,
+            0x26 => Self::SpAlignmentFault,
+            0x30 => Self::BreakpointLowerEl,
+            0x31 => Self::BreakpointSameEl,
+            0x32 => Self::SoftwareStepLowerEl,
+            0x33 => Self::SoftwareStepSameEl,
+            0x34 => Self::WatchpointLowerEl,
+            0x35 => Self::WatchpointSameEl,
+            0x3c => Self::Brk,
+            other => Self::Other(other),
+        }
+    }
+}
+
+#[no_mangle]
+extern "C" fn handle_exception(kind: u64, frame: &mut TrapFrame) {
+    let kind = match kind & 0x3 {
+        0 => ExceptionKind::Synchronous,
+        1 => ExceptionKind::Irq,
+        2 => ExceptionKind::Fiq,
+        _ => ExceptionKind::SError,
+    };
+
+    match kind {
+        ExceptionKind::Irq | ExceptionKind::Fiq => interrupt::handle_irq(),
+        ExceptionKind::Synchronous
+            if breakpoint::handle_exception(frame) || gdb::handle_exception(frame) => {}
+        _ => {
+            unsafe { FAULT_FRAME = Some((kind, *frame)) };
+            panic!(
+                "Unhandled exception: {:?}, {:?}, ELR: 0x{:x}, FAR: 0x{:x}",
+                kind,
+                frame.exception_class(),
+                frame.elr,
+                frame.far
+            )
+        }
+    }
+}
+
+pub fn fault_frame() -> Option<(ExceptionKind, TrapFrame)> {
+    unsafe { FAULT_FRAME }
+}
+
+pub fn init() -> Result<()> {
+    extern "C" {
+        static exception_vector_table: u8;
+    }
+
+    if asm::read_current_el() != 1 {
+        return Err("Kernel is not running at EL1".into());
+    }
+
+    let vbar = unsafe { &exception_vector_table as *const u8 as u64 };
+    asm::write_vbar_el1(vbar);
+    Ok(())
+}
@@ -0,0 +1,1299 @@
+use crate::{
+    block::{BlockDevice, SharedBlockDevice},
+    error::Result,
+    timer,
+    vfs::{DirEntry, FileSystem, FileSystemError, FileType, InodeId, Metadata},
+};
+use alloc::{
+    string::{String, ToString},
+    vec,
+    vec::Vec,
+};
+
+const SUPERBLOCK_OFFSET: u64 = 1024;
+const SUPERBLOCK_SIZE: usize = 1024;
+const EXT2_MAGIC: u16 = 0xef53;
+const ROOT_INODE: InodeId = 2;
+
+const GOOD_OLD_REV: u32 = 0;
+const GOOD_OLD_INODE_SIZE: u64 = 128;
+const GOOD_OLD_FIRST_INO: u32 = 11;
+const GROUP_DESC_SIZE: u64 = 32;
+
+const DIRECT_BLOCKS: usize = 12;
+const IND_BLOCK: usize = 12;
+const DIND_BLOCK: usize = 13;
+const TIND_BLOCK: usize = 14;
+
+// a symlink target shorter than this is stored in i_block
+const FAST_SYMLINK_MAX: usize = 60;
+
+const FEATURE_INCOMPAT_FILETYPE: u32 = 0x0002;
+const FEATURE_RO_COMPAT_SPARSE_SUPER: u32 = 0x0001;
+const FEATURE_RO_COMPAT_LARGE_FILE: u32 = 0x0002;
+const FEATURE_RO_COMPAT_BTREE_DIR: u32 = 0x0004;
+
+const INODE_FLAG_INDEX: u32 = 0x1000;
+
+const S_IFMT: u16 = 0xf000;
+const S_IFCHR: u16 = 0x2000;
+const S_IFDIR: u16 = 0x4000;
+const S_IFBLK: u16 = 0x6000;
+const S_IFREG: u16 = 0x8000;
+const S_IFLNK: u16 = 0xa000;
+
+const FT_REG_FILE: u8 = 1;
+const FT_DIR: u8 = 2;
+const FT_CHRDEV: u8 = 3;
+const FT_BLKDEV: u8 = 4;
+const FT_SYMLINK: u8 = 7;
+
+fn read_u16(buf: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes(buf[offset..offset + 2].try_into().unwrap())
+}
+
+fn read_u32(buf: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(buf[offset..offset + 4].try_into().unwrap())
+}
+
+fn write_u16(buf: &mut [u8], offset: usize, value: u16) {
+    buf[offset..offset + 2].copy_from_slice(&value.to_le_bytes());
+}
+
+fn write_u32(buf: &mut [u8], offset: usize, value: u32) {
+    buf[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+}
+
+// directory entries are 4 bytes aligned
+fn dir_entry_len(name_len: usize) -> usize {
+    (8 + name_len).next_multiple_of(4)
+}
+
+fn validate_name(name: &str) -> Result<()> {
+    if name.is_empty()
+        || name == "."
+        || name == ".."
+        || name.len() > 255
+        || name.contains(['/', '\0'])
+    {
+        return Err(FileSystemError::InvalidPath.into());
+    }
+
+    Ok(())
+}
+
+#[derive(Debug, Clone, Copy)]
+struct GroupDesc {
+    block_bitmap: u32,
+    inode_bitmap: u32,
+    inode_table: u32,
+    free_blocks: u16,
+    free_inodes: u16,
+    used_dirs: u16,
+}
+
+#[derive(Debug, Clone, Default)]
+struct Inode {
+    mode: u16,
+    uid: u32,
+    gid: u32,
+    size: u64,
+    atime: u32,
+    ctime: u32,
+    mtime: u32,
+    dtime: u32,
+    links_count: u16,
+    // in 512 byte units
+    blocks: u32,
+    flags: u32,
+    block: [u32; 15],
+    file_acl: u32,
+}
+
+impl Inode {
+    fn parse(raw: &[u8]) -> Self {
+        let mode = read_u16(raw, 0);
+        let mut size = read_u32(raw, 4) as u64;
+        if mode & S_IFMT == S_IFREG {
+            size |= (read_u32(raw, 108) as u64) << 32;
+        }
+
+        let mut block = [0; 15];
+        for (i, b) in block.iter_mut().enumerate() {
+            *b = read_u32(raw, 40 + i * 4);
+        }
+
+        Self {
+            mode,
+            uid: read_u16(raw, 2) as u32 | (read_u16(raw, 120) as u32) << 16,
+            gid: read_u16(raw, 24) as u32 | (read_u16(raw, 122) as u32) << 16,
+            size,
+            atime: read_u32(raw, 8),
+            ctime: read_u32(raw, 12),
+            mtime: read_u32(raw, 16),
+            dtime: read_u32(raw, 20),
+            links_count: read_u16(raw, 26),
+            blocks: read_u32(raw, 28),
+            flags: read_u32(raw, 32),
+            block,
+            file_acl: read_u32(raw, 104),
+        }
+    }
+
+    // fields not known to the driver are left untouched
+    fn store(&self, raw: &mut [u8]) {
+        write_u16(raw, 0, self.mode);
+        write_u16(raw, 2, self.uid as u16);
+        write_u16(raw, 120, (self.uid >> 16) as u16);
+        write_u16(raw, 24, self.gid as u16);
+        write_u16(raw, 122, (self.gid >> 16) as u16);
+        write_u32(raw, 4, self.size as u32);
+        if self.is_file() {
+            write_u32(raw, 108, (self.size >> 32) as u32);
+        }
+        write_u32(raw, 8, self.atime);
+        write_u32(raw, 12, self.ctime);
+        write_u32(raw, 16, self.mtime);
+        write_u32(raw, 20, self.dtime);
+        write_u16(raw, 26, self.links_count);
+        write_u32(raw, 28, self.blocks);
+        write_u32(raw, 32, self.flags);
+        for (i, &b) in self.block.iter().enumerate() {
+            write_u32(raw, 40 + i * 4, b);
+        }
+        write_u32(raw, 104, self.file_acl);
+    }
+
+    fn is_dir(&self) -> bool {
+        self.mode & S_IFMT == S_IFDIR
+    }
+
+    fn is_file(&self) -> bool {
+        self.mode & S_IFMT == S_IFREG
+    }
+
+    fn is_symlink(&self) -> bool {
+        self.mode & S_IFMT == S_IFLNK
+    }
+
+    fn file_type(&self) -> FileType {
+        match self.mode & S_IFMT {
+            S_IFDIR => FileType::Directory,
+            S_IFLNK => FileType::Symlink,
+            S_IFCHR => FileType::CharDevice,
+            S_IFBLK => FileType::BlockDevice,
+            _ => FileType::File,
+        }
+    }
+}
+
+fn dir_file_type(file_type: FileType) -> u8 {
+    match file_type {
+        FileType::File => FT_REG_FILE,
+        FileType::Directory => FT_DIR,
+        FileType::Symlink => FT_SYMLINK,
+        FileType::CharDevice => FT_CHRDEV,
+        FileType::BlockDevice => FT_BLKDEV,
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Ext2DirEntry {
+    inode: u32,
+    name: String,
+    // device offsets of the entry and of the previous entry in the same block
+    offset: u64,
+    prev_offset: Option<u64>,
+}
+
+pub struct Ext2 {
+    device: SharedBlockDevice,
+    block_size: u64,
+    blocks_count: u32,
+    inodes_count: u32,
+    first_data_block: u32,
+    blocks_per_group: u32,
+    inodes_per_group: u32,
+    inode_size: u64,
+    first_ino: u32,
+    free_blocks: u32,
+    free_inodes: u32,
+    has_file_type: bool,
+    read_only: bool,
+    // there is no RTC, time continues from the last superblock update
+    time_base: u32,
+    groups: Vec<GroupDesc>,
+}
+
+impl Ext2 {
+    pub fn new(device: SharedBlockDevice) -> Result<Self> {
+        let mut device = device;
+        let mut sb = [0; SUPERBLOCK_SIZE];
+        device.read_bytes(SUPERBLOCK_OFFSET, &mut sb)?;
+
+        if read_u16(&sb, 56) != EXT2_MAGIC {
+            return Err("Invalid ext2 superblock magic".into());
+        }
+
+        // directory record lengths are 16 bits, so blocks are limited to 4KiB here
+        let log_block_size = read_u32(&sb, 24);
+        if log_block_size > 2 {
+            return Err("Unsupported ext2 block size".into());
+        }
+
+        let rev_level = read_u32(&sb, 76);
+        let (inode_size, first_ino, incompat, ro_compat) = if rev_level == GOOD_OLD_REV {
+            (GOOD_OLD_INODE_SIZE, GOOD_OLD_FIRST_INO, 0, 0)
+        } else {
+            (
+                read_u16(&sb, 88) as u64,
+                read_u32(&sb, 84),
+                read_u32(&sb, 96),
+                read_u32(&sb, 100),
+            )
+        };
+
+        if incompat & !FEATURE_INCOMPAT_FILETYPE != 0 {
+            return Err(FileSystemError::Unsupported.into());
+        }
+
+        // unknown read-only features still allow reading
+        let read_only = ro_compat
+            & !(FEATURE_RO_COMPAT_SPARSE_SUPER
+                | FEATURE_RO_COMPAT_LARGE_FILE
+                | FEATURE_RO_COMPAT_BTREE_DIR)
+            != 0;
+
+        let mut ext2 = Self {
+            device,
+            block_size: 1024 << log_block_size,
+            blocks_count: read_u32(&sb, 4),
+            inodes_count: read_u32(&sb, 0),
+            first_data_block: read_u32(&sb, 20),
+            blocks_per_group: read_u32(&sb, 32),
+            inodes_per_group: read_u32(&sb, 40),
+            inode_size,
+            first_ino,
+            free_blocks: read_u32(&sb, 12),
+            free_inodes: read_u32(&sb, 16),
+            has_file_type: incompat & FEATURE_INCOMPAT_FILETYPE != 0,
+            read_only,
+            time_base: read_u32(&sb, 44).max(read_u32(&sb, 48)),
+            groups: Vec::new(),
+        };
+
+        // each group's bitmaps are a single block
+        let bitmap_bits = ext2.block_size * 8;
+        if ext2.blocks_per_group == 0
+            || ext2.inodes_per_group == 0
+            || ext2.blocks_per_group as u64 > bitmap_bits
+            || ext2.inodes_per_group as u64 > bitmap_bits
+            || ext2.inode_size < GOOD_OLD_INODE_SIZE
+            || ext2.blocks_count <= ext2.first_data_block
+            || ext2.inodes_count as u64 > ext2.group_count() as u64 * ext2.inodes_per_group as u64
+        {
+            return Err("Invalid ext2 superblock".into());
+        }
+
+        ext2.read_groups()?;
+        Ok(ext2)
+    }
+
+    fn now(&self) -> u32 {
+        self.time_base + (timer::uptime_ms() / 1000) as u32
+    }
+
+    fn gdt_offset(&self) -> u64 {
+        (self.first_data_block as u64 + 1) * self.block_size
+    }
+
+    fn group_count(&self) -> usize {
+        (self.blocks_count - self.first_data_block).div_ceil(self.blocks_per_group) as usize
+    }
+
+    fn read_groups(&mut self) -> Result<()> {
+        let count = self.group_count();
+        let mut gdt = vec![0; count * GROUP_DESC_SIZE as usize];
+        self.device.read_bytes(self.gdt_offset(), &mut gdt)?;
+
+        self.groups = gdt
+            .chunks_exact(GROUP_DESC_SIZE as usize)
+            .map(|raw| GroupDesc {
+                block_bitmap: read_u32(raw, 0),
+                inode_bitmap: read_u32(raw, 4),
+                inode_table: read_u32(raw, 8),
+                free_blocks: read_u16(raw, 12),
+                free_inodes: read_u16(raw, 14),
+                used_dirs: read_u16(raw, 16),
+            })
+            .collect();
+
+        Ok(())
+    }
+
+    // only the primary superblock and descriptor table are updated, like Linux does
+    fn write_super(&mut self) -> Result<()> {
+        let mut gdt = vec![0; self.groups.len() * GROUP_DESC_SIZE as usize];
+        self.device.read_bytes(self.gdt_offset(), &mut gdt)?;
+        for (raw, group) in gdt
+            .chunks_exact_mut(GROUP_DESC_SIZE as usize)
+            .zip(self.groups.iter())
+        {
+            write_u16(raw, 12, group.free_blocks);
+            write_u16(raw, 14, group.free_inodes);
+            write_u16(raw, 16, group.used_dirs);
+        }
+        self.device.write_bytes(self.gdt_offset(), &gdt)?;
+
+        let mut sb = [0; SUPERBLOCK_SIZE];
+        self.device.read_bytes(SUPERBLOCK_OFFSET, &mut sb)?;
+        write_u32(&mut sb, 12, self.free_blocks);
+        write_u32(&mut sb, 16, self.free_inodes);
+        write_u32(&mut sb, 48, self.now());
+        self.device.write_bytes(SUPERBLOCK_OFFSET, &sb)
+    }
+
+    fn check_writable(&self) -> Result<()> {
+        if self.read_only {
+            return Err(FileSystemError::ReadOnly.into());
+        }
+
+        Ok(())
+    }
+
+    fn block_offset(&self, block: u32) -> u64 {
+        block as u64 * self.block_size
+    }
+
+    fn ptrs_per_block(&self) -> u64 {
+        self.block_size / 4
+    }
+
+    fn inode_group(&self, ino: u32) -> usize {
+        ((ino - 1) / self.inodes_per_group) as usize
+    }
+
+    fn inode_offset(&self, ino: u32) -> Result<u64> {
+        if ino == 0 || ino > self.inodes_count {
+            return Err(FileSystemError::NotFound.into());
+        }
+
+        let group = &self.groups[self.inode_group(ino)];
+        let index = ((ino - 1) % self.inodes_per_group) as u64;
+        Ok(self.block_offset(group.inode_table) + index * self.inode_size)
+    }
+
+    fn read_inode(&mut self, ino: u32) -> Result<Inode> {
+        let offset = self.inode_offset(ino)?;
+        let mut raw = vec![0; self.inode_size as usize];
+        self.device.read_bytes(offset, &mut raw)?;
+        Ok(Inode::parse(&raw))
+    }
+
+    fn write_inode(&mut self, ino: u32, inode: &Inode) -> Result<()> {
+        let offset = self.inode_offset(ino)?;
+        let mut raw = vec![0; self.inode_size as usize];
+        self.device.read_bytes(offset, &mut raw)?;
+        inode.store(&mut raw);
+        self.device.write_bytes(offset, &raw)
+    }
+
+    fn read_ptr(&mut self, block: u32, index: u64) -> Result<u32> {
+        let mut buf = [0; 4];
+        self.device
+            .read_bytes(self.block_offset(block) + index * 4, &mut buf)?;
+        Ok(u32::from_le_bytes(buf))
+    }
+
+    fn write_ptr(&mut self, block: u32, index: u64, value: u32) -> Result<()> {
+        self.device
+            .write_bytes(self.block_offset(block) + index * 4, &value.to_le_bytes())
+    }
+
+    // finds a clear bit in a bitmap block and sets it
+    fn alloc_bit(&mut self, bitmap: u32, count: u32) -> Result<Option<u32>> {
+        let mut data = vec![0; self.block_size as usize];
+        self.device
+            .read_bytes(self.block_offset(bitmap), &mut data)?;
+
+        for bit in 0..count {
+            let (byte, mask) = ((bit / 8) as usize, 1 << (bit % 8));
+            if data[byte] & mask == 0 {
+                self.device.write_bytes(
+                    self.block_offset(bitmap) + byte as u64,
+                    &[data[byte] | mask],
+                )?;
+                return Ok(Some(bit));
+            }
+        }
+
+        Ok(None)
+    }
+
+    fn free_bit(&mut self, bitmap: u32, bit: u32) -> Result<()> {
+        let offset = self.block_offset(bitmap) + (bit / 8) as u64;
+        let mut byte = [0];
+        self.device.read_bytes(offset, &mut byte)?;
+        byte[0] &= !(1 << (bit % 8));
+        self.device.write_bytes(offset, &byte)
+    }
+
+    // allocates a zeroed block, preferably in the given group
+    fn alloc_block(&mut self, goal_group: usize) -> Result<u32> {
+        let group_count = self.groups.len();
+
+        for i in 0..group_count {
+            let g = (goal_group + i) % group_count;
+            if self.groups[g].free_blocks == 0 {
+                continue;
+            }
+
+            let first = self.first_data_block + g as u32 * self.blocks_per_group;
+            let count = self.blocks_per_group.min(self.blocks_count - first);
+            let bit = match self.alloc_bit(self.groups[g].block_bitmap, count)? {
+                Some(bit) => bit,
+                None => continue,
+            };
+
+            self.groups[g].free_blocks -= 1;
+            self.free_blocks = self.free_blocks.saturating_sub(1);
+
+            let block = first + bit;
+            let zero = vec![0; self.block_size as usize];
+            self.device.write_bytes(self.block_offset(block), &zero)?;
+            return Ok(block);
+        }
+
+        Err(FileSystemError::NoSpace.into())
+    }
+
+    fn free_block(&mut self, block: u32) -> Result<()> {
+        // a block pointer outside the data area or a counter past its limit means corruption
+        let index = block
+            .checked_sub(self.first_data_block)
+            .filter(|_| block < self.blocks_count)
+            .ok_or(FileSystemError::Corrupted)?;
+        let g = (index / self.blocks_per_group) as usize;
+        let bit = index % self.blocks_per_group;
+        let group = self.groups.get(g).ok_or(FileSystemError::Corrupted)?;
+        let bitmap = group.block_bitmap;
+        let group_free = group
+            .free_blocks
+            .checked_add(1)
+            .ok_or(FileSystemError::Corrupted)?;
+        let free = self
+            .free_blocks
+            .checked_add(1)
+            .ok_or(FileSystemError::Corrupted)?;
+
+        self.free_bit(bitmap, bit)?;
+        self.groups[g].free_blocks = group_free;
+        self.free_blocks = free;
+        Ok(())
+    }
+
+    fn alloc_inode(&mut self, goal_group: usize, is_dir: bool) -> Result<u32> {
+        let group_count = self.groups.len();
+
+        for i in 0..group_count {
+            let g = (goal_group + i) % group_count;
+            if self.groups[g].free_inodes == 0 {
+                continue;
+            }
+
+            let bit = match self.alloc_bit(self.groups[g].inode_bitmap, self.inodes_per_group)? {
+                Some(bit) => bit,
+                None => continue,
+            };
+
+            let ino = g as u32 * self.inodes_per_group + bit + 1;
+            if ino < self.first_ino {
+                return Err(FileSystemError::Corrupted.into());
+            }
+
+            self.groups[g].free_inodes -= 1;
+            if is_dir {
+                self.groups[g].used_dirs += 1;
+            }
+            self.free_inodes = self.free_inodes.saturating_sub(1);
+            return Ok(ino);
+        }
+
+        Err(FileSystemError::NoSpace.into())
+    }
+
+    fn free_inode(&mut self, ino: u32, is_dir: bool) -> Result<()> {
+        if ino == 0 || ino > self.inodes_count {
+            return Err(FileSystemError::Corrupted.into());
+        }
+
+        let g = self.inode_group(ino);
+        let bit = (ino - 1) % self.inodes_per_group;
+        let group = self.groups.get(g).ok_or(FileSystemError::Corrupted)?;
+        let bitmap = group.inode_bitmap;
+        let group_free = group
+            .free_inodes
+            .checked_add(1)
+            .ok_or(FileSystemError::Corrupted)?;
+        let free = self
+            .free_inodes
+            .checked_add(1)
+            .ok_or(FileSystemError::Corrupted)?;
+
+        self.free_bit(bitmap, bit)?;
+        self.groups[g].free_inodes = group_free;
+        if is_dir {
+            self.groups[g].used_dirs = self.groups[g].used_dirs.saturating_sub(1);
+        }
+        self.free_inodes = free;
+        Ok(())
+    }
+
+    // i_block slot, indirection level and index below that slot for a logical block
+    fn block_path(&self, index: u64) -> Result<(usize, u32, u64)> {
+        let p = self.ptrs_per_block();
+        let mut index = index;
+
+        if index < DIRECT_BLOCKS as u64 {
+            return Ok((index as usize, 0, 0));
+        }
+        index -= DIRECT_BLOCKS as u64;
+
+        if index < p {
+            return Ok((IND_BLOCK, 1, index));
+        }
+        index -= p;
+
+        if index < p * p {
+            return Ok((DIND_BLOCK, 2, index));
+        }
+        index -= p * p;
+
+        if index < p * p * p {
+            return Ok((TIND_BLOCK, 3, index));
+        }
+
+        Err(FileSystemError::NoSpace.into())
+    }
+
+    // maps a logical block to a physical one, 0 for holes unless alloc is set
+    fn map_block(&mut self, ino: u32, inode: &mut Inode, index: u64, alloc: bool) -> Result<u32> {
+        let (slot, level, mut rem) = self.block_path(index)?;
+        let goal = self.inode_group(ino);
+        let sectors = (self.block_size / 512) as u32;
+
+        let mut block = inode.block[slot];
+        if block == 0 {
+            if !alloc {
+                return Ok(0);
+            }
+            block = self.alloc_block(goal)?;
+            inode.block[slot] = block;
+            inode.blocks += sectors;
+        }
+
+        for l in (0..level).rev() {
+            let span = self.ptrs_per_block().pow(l);
+            let i = rem / span;
+            rem %= span;
+
+            let mut next = self.read_ptr(block, i)?;
+            if next == 0 {
+                if !alloc {
+                    return Ok(0);
+                }
+                next = self.alloc_block(goal)?;
+                self.write_ptr(block, i, next)?;
+                inode.blocks += sectors;
+            }
+            block = next;
+        }
+
+        Ok(block)
+    }
+
+    // frees the part of a block tree covering logical blocks >= keep,
+    // returns true if the tree block itself was freed
+    fn free_tree(
+        &mut self,
+        block: u32,
+        level: u32,
+        base: u64,
+        keep: u64,
+        freed: &mut u32,
+    ) -> Result<bool> {
+        if level == 0 {
+            if base >= keep {
+                self.free_block(block)?;
+                *freed += 1;
+                return Ok(true);
+            }
+            return Ok(false);
+        }
+
+        let p = self.ptrs_per_block();
+        let span = p.pow(level - 1);
+        let mut ptrs = vec![0; self.block_size as usize];
+        self.device
+            .read_bytes(self.block_offset(block), &mut ptrs)?;
+
+        let mut changed = false;
+        let mut in_use = false;
+        for i in 0..p {
+            let child = read_u32(&ptrs, i as usize * 4);
+            if child == 0 {
+                continue;
+            }
+
+            let child_base = base + i * span;
+            if child_base + span > keep
+                && self.free_tree(child, level - 1, child_base, keep, freed)?
+            {
+                write_u32(&mut ptrs, i as usize * 4, 0);
+                changed = true;
+            } else {
+                in_use = true;
+            }
+        }
+
+        if !in_use {
+            self.free_block(block)?;
+            *freed += 1;
+            return Ok(true);
+        }
+
+        if changed {
+            self.device.write_bytes(self.block_offset(block), &ptrs)?;
+        }
+        Ok(false)
+    }
+
+    fn free_blocks_from(&mut self, inode: &mut Inode, keep: u64) -> Result<()> {
+        let p = self.ptrs_per_block();
+        let mut freed = 0;
+
+        for i in 0..DIRECT_BLOCKS {
+            if i as u64 >= keep && inode.block[i] != 0 {
+                self.free_block(inode.block[i])?;
+                inode.block[i] = 0;
+                freed += 1;
+            }
+        }
+
+        let trees = [
+            (IND_BLOCK, 1, DIRECT_BLOCKS as u64),
+            (DIND_BLOCK, 2, DIRECT_BLOCKS as u64 + p),
+            (TIND_BLOCK, 3, DIRECT_BLOCKS as u64 + p + p * p),
+        ];
+        for (slot, level, base) in trees {
+            let block = inode.block[slot];
+            if block != 0 && self.free_tree(block, level, base, keep, &mut freed)? {
+                inode.block[slot] = 0;
+            }
+        }
+
+        let sectors = (self.block_size / 512) as u32;
+        inode.blocks = inode.blocks.saturating_sub(freed * sectors);
+        Ok(())
+    }
+
+    fn is_fast_symlink(&self, inode: &Inode) -> bool {
+        let acl_sectors = if inode.file_acl != 0 {
+            (self.block_size / 512) as u32
+        } else {
+            0
+        };
+        inode.is_symlink() && inode.blocks == acl_sectors
+    }
+
+    fn read_data(
+        &mut self,
+        ino: u32,
+        inode: &mut Inode,
+        offset: u64,
+        buf: &mut [u8],
+    ) -> Result<usize> {
+        if offset >= inode.size {
+            return Ok(0);
+        }
+
+        let len = buf.len().min((inode.size - offset) as usize);
+        let mut done = 0;
+
+        while done < len {
+            let pos = offset + done as u64;
+            let block_offset = pos % self.block_size;
+            let n = ((self.block_size - block_offset) as usize).min(len - done);
+
+            // holes read as zero
+            match self.map_block(ino, inode, pos / self.block_size, false)? {
+                0 => buf[done..done + n].fill(0),
+                block => self.device.read_bytes(
+                    self.block_offset(block) + block_offset,
+                    &mut buf[done..done + n],
+                )?,
+            }
+            done += n;
+        }
+
+        Ok(len)
+    }
+
+    fn write_data(
+        &mut self,
+        ino: u32,
+        inode: &mut Inode,
+        offset: u64,
+        buf: &[u8],
+    ) -> Result<usize> {
+        let mut done = 0;
+
+        while done < buf.len() {
+            let pos = offset + done as u64;
+            let block_offset = pos % self.block_size;
+            let n = ((self.block_size - block_offset) as usize).min(buf.len() - done);
+
+            let block = self.map_block(ino, inode, pos / self.block_size, true)?;
+            self.device.write_bytes(
+                self.block_offset(block) + block_offset,
+                &buf[done..done + n],
+            )?;
+            done += n;
+        }
+
+        let end = offset + buf.len() as u64;
+        if end > inode.size {
+            inode.size = end;
+        }
+
+        Ok(buf.len())
+    }
+
+    fn read_entries(&mut self, dir: u32) -> Result<Vec<Ext2DirEntry>> {
+        let mut inode = self.read_inode(dir)?;
+        if !inode.is_dir() {
+            return Err(FileSystemError::NotDirectory.into());
+        }
+
+        let mut entries = Vec::new();
+        let mut data = vec![0; self.block_size as usize];
+
+        for index in 0..inode.size.div_ceil(self.block_size) {
+            let block = self.map_block(dir, &mut inode, index, false)?;
+            if block == 0 {
+                continue;
+            }
+
+            let block_offset = self.block_offset(block);
+            self.device.read_bytes(block_offset, &mut data)?;
+
+            let mut pos = 0;
+            let mut prev_offset = None;
+            while pos + 8 <= data.len() {
+                let entry_inode = read_u32(&data, pos);
+                let rec_len = read_u16(&data, pos + 4) as usize;
+                let name_len = data[pos + 6] as usize;
+
+                if rec_len < 8 || pos + rec_len > data.len() || 8 + name_len > rec_len {
+                    return Err(FileSystemError::Corrupted.into());
+                }
+
+                let offset = block_offset + pos as u64;
+                if entry_inode != 0 {
+                    entries.push(Ext2DirEntry {
+                        inode: entry_inode,
+                        name: String::from_utf8_lossy(&data[pos + 8..pos + 8 + name_len])
+                            .to_string(),
+                        offset,
+                        prev_offset,
+                    });
+                }
+
+                prev_offset = Some(offset);
+                pos += rec_len;
+            }
+        }
+
+        Ok(entries)
+    }
+
+    fn find(&mut self, dir: u32, name: &str) -> Result<Ext2DirEntry> {
+        Ok(self
+            .read_entries(dir)?
+            .into_iter()
+            .find(|e| e.name == name)
+            .ok_or(FileSystemError::NotFound)?)
+    }
+
+    fn is_empty_dir(&mut self, dir: u32) -> Result<bool> {
+        Ok(self
+            .read_entries(dir)?
+            .iter()
+            .all(|e| e.name == "." || e.name == ".."))
+    }
+
+    fn add_entry(&mut self, dir: u32, name: &str, ino: u32, file_type: FileType) -> Result<()> {
+        validate_name(name)?;
+        if self.find(dir, name).is_ok() {
+            return Err(FileSystemError::AlreadyExists.into());
+        }
+
+        let needed = dir_entry_len(name.len());
+        let mut dir_inode = self.read_inode(dir)?;
+        let mut data = vec![0; self.block_size as usize];
+        let block_count = dir_inode.size.div_ceil(self.block_size);
+
+        // use the slack after an existing entry, or a new block
+        let mut slot = None;
+        'blocks: for index in 0..block_count {
+            let block = self.map_block(dir, &mut dir_inode, index, false)?;
+            if block == 0 {
+                continue;
+            }
+            self.device
+                .read_bytes(self.block_offset(block), &mut data)?;
+
+            let mut pos = 0;
+            while pos + 8 <= data.len() {
+                let entry_inode = read_u32(&data, pos);
+                let rec_len = read_u16(&data, pos + 4) as usize;
+                if rec_len < 8 {
+                    return Err(FileSystemError::Corrupted.into());
+                }
+
+                let used = if entry_inode == 0 {
+                    0
+                } else {
+                    dir_entry_len(data[pos + 6] as usize)
+                };
+                if rec_len >= used + needed {
+                    if used != 0 {
+                        write_u16(&mut data, pos + 4, used as u16);
+                    }
+                    slot = Some((block, pos + used, rec_len - used));
+                    break 'blocks;
+                }
+
+                pos += rec_len;
+            }
+        }
+
+        let (block, pos, rec_len) = match slot {
+            Some(slot) => slot,
+            None => {
+                let block = self.map_block(dir, &mut dir_inode, block_count, true)?;
+                dir_inode.size += self.block_size;
+                data.fill(0);
+                (block, 0, self.block_size as usize)
+            }
+        };
+
+        write_u32(&mut data, pos, ino);
+        write_u16(&mut data, pos + 4, rec_len as u16);
+        data[pos + 6] = name.len() as u8;
+        data[pos + 7] = if self.has_file_type {
+            dir_file_type(file_type)
+        } else {
+            0
+        };
+        data[pos + 8..pos + 8 + name.len()].copy_from_slice(name.as_bytes());
+        self.device.write_bytes(self.block_offset(block), &data)?;
+
+        // the hashed index is not maintained, fall back to a linear directory
+        dir_inode.flags &= !INODE_FLAG_INDEX;
+        dir_inode.mtime = self.now();
+        dir_inode.ctime = dir_inode.mtime;
+        self.write_inode(dir, &dir_inode)
+    }
+
+    fn remove_entry(&mut self, dir: u32, entry: &Ext2DirEntry) -> Result<()> {
+        let mut header = [0; 8];
+        self.device.read_bytes(entry.offset, &mut header)?;
+
+        match entry.prev_offset {
+            // merge into the previous entry
+            Some(prev_offset) => {
+                let mut prev = [0; 8];
+                self.device.read_bytes(prev_offset, &mut prev)?;
+                let rec_len = read_u16(&prev, 4) + read_u16(&header, 4);
+                write_u16(&mut prev, 4, rec_len);
+                self.device.write_bytes(prev_offset, &prev)?;
+            }
+            None => {
+                write_u32(&mut header, 0, 0);
+                self.device.write_bytes(entry.offset, &header)?;
+            }
+        }
+
+        let mut dir_inode = self.read_inode(dir)?;
+        dir_inode.flags &= !INODE_FLAG_INDEX;
+        dir_inode.mtime = self.now();
+        dir_inode.ctime = dir_inode.mtime;
+        self.write_inode(dir, &dir_inode)
+    }
+
+    fn set_parent(&mut self, dir: u32, parent: u32) -> Result<()> {
+        let entry = self.find(dir, "..")?;
+        self.device.write_bytes(entry.offset, &parent.to_le_bytes())
+    }
+
+    fn adjust_links(&mut self, ino: u32, delta: i32) -> Result<()> {
+        let mut inode = self.read_inode(ino)?;
+        inode.links_count = (inode.links_count as i32 + delta).max(0) as u16;
+        inode.ctime = self.now();
+        self.write_inode(ino, &inode)
+    }
+
+    // drops a name of the inode and frees it with its last link
+    fn drop_link(&mut self, ino: u32) -> Result<()> {
+        let mut inode = self.read_inode(ino)?;
+        inode.links_count = if inode.is_dir() {
+            0
+        } else {
+            inode.links_count.saturating_sub(1)
+        };
+        inode.ctime = self.now();
+
+        if inode.links_count == 0 {
+            if !self.is_fast_symlink(&inode) {
+                self.free_blocks_from(&mut inode, 0)?;
+            }
+            inode.size = 0;
+            inode.dtime = inode.ctime;
+            self.write_inode(ino, &inode)?;
+            return self.free_inode(ino, inode.is_dir());
+        }
+
+        self.write_inode(ino, &inode)
+    }
+
+    fn new_inode(&mut self, dir: u32, mode: u16) -> Result<(u32, Inode)> {
+        let is_dir = mode & S_IFMT == S_IFDIR;
+        let ino = self.alloc_inode(self.inode_group(dir), is_dir)?;
+        let now = self.now();
+
+        // clear the whole on-disk inode, it may hold data of a deleted file
+        let offset = self.inode_offset(ino)?;
+        let zero = vec![0; self.inode_size as usize];
+        self.device.write_bytes(offset, &zero)?;
+
+        let inode = Inode {
+            mode,
+            atime: now,
+            ctime: now,
+            mtime: now,
+            links_count: 1,
+            ..Default::default()
+        };
+        Ok((ino, inode))
+    }
+
+    fn to_ino(inode: InodeId) -> Result<u32> {
+        u32::try_from(inode).map_err(|_| FileSystemError::NotFound.into())
+    }
+}
+
+impl FileSystem for Ext2 {
+    fn name(&self) -> &'static str {
+        "ext2"
+    }
+
+    fn root(&self) -> InodeId {
+        ROOT_INODE
+    }
+
+    fn lookup(&mut self, dir: InodeId, name: &str) -> Result<InodeId> {
+        Ok(self.find(Self::to_ino(dir)?, name)?.inode as InodeId)
+    }
+
+    fn metadata(&mut self, inode: InodeId) -> Result<Metadata> {
+        let node = self.read_inode(Self::to_ino(inode)?)?;
+        let mut metadata = Metadata::new(
+            inode,
+            node.file_type(),
+            node.size as usize,
+            node.mode & 0o7777,
+        );
+        metadata.nlink = node.links_count as usize;
+        metadata.uid = node.uid;
+        metadata.gid = node.gid;
+        metadata.atime = node.atime as u64;
+        metadata.mtime = node.mtime as u64;
+        metadata.ctime = node.ctime as u64;
+        Ok(metadata)
+    }
+
+    fn read(&mut self, inode: InodeId, offset: usize, buf: &mut [u8]) -> Result<usize> {
+        let ino = Self::to_ino(inode)?;
+        let mut node = self.read_inode(ino)?;
+        match node.file_type() {
+            FileType::File => self.read_data(ino, &mut node, offset as u64, buf),
+            FileType::Directory => Err(FileSystemError::IsDirectory.into()),
+            _ => Err(FileSystemError::Unsupported.into()),
+        }
+    }
+
+    fn write(&mut self, inode: InodeId, offset: usize, buf: &[u8]) -> Result<usize> {
+        self.check_writable()?;
+        let ino = Self::to_ino(inode)?;
+        let mut node = self.read_inode(ino)?;
+        match node.file_type() {
+            FileType::File => (),
+            FileType::Directory => return Err(FileSystemError::IsDirectory.into()),
+            _ => return Err(FileSystemError::Unsupported.into()),
+        }
+
+        // keep the blocks that were allocated before running out of space
+        let res = self.write_data(ino, &mut node, offset as u64, buf);
+        node.mtime = self.now();
+        node.ctime = node.mtime;
+        self.write_inode(ino, &node)?;
+        res
+    }
+
+    fn read_dir(&mut self, dir: InodeId) -> Result<Vec<DirEntry>> {
+        let entries = self.read_entries(Self::to_ino(dir)?)?;
+        let mut dir_entries = Vec::new();
+
+        for entry in entries {
+            if entry.name == "." || entry.name == ".." {
+                continue;
+            }
+
+            dir_entries.push(DirEntry {
+                inode: entry.inode as InodeId,
+                file_type: self.read_inode(entry.inode)?.file_type(),
+                name: entry.name,
+            });
+        }
+
+        Ok(dir_entries)
+    }
+
+    fn create(&mut self, dir: InodeId, name: &str, file_type: FileType) -> Result<InodeId> {
+        self.check_writable()?;
+        let dir = Self::to_ino(dir)?;
+        validate_name(name)?;
+        if self.find(dir, name).is_ok() {
+            return Err(FileSystemError::AlreadyExists.into());
+        }
+
+        let mode = match file_type {
+            FileType::File => S_IFREG | 0o644,
+            FileType::Directory => S_IFDIR | 0o755,
+            _ => return Err(FileSystemError::Unsupported.into()),
+        };
+
+        let (ino, mut inode) = self.new_inode(dir, mode)?;
+        if file_type == FileType::Directory {
+            // "." and ".." fill the first block
+            let block = self.map_block(ino, &mut inode, 0, true)?;
+            let mut data = vec![0; self.block_size as usize];
+            let ft = if self.has_file_type { FT_DIR } else { 0 };
+
+            write_u32(&mut data, 0, ino);
+            write_u16(&mut data, 4, 12);
+            data[6] = 1;
+            data[7] = ft;
+            data[8] = b'.';
+
+            write_u32(&mut data, 12, dir);
+            write_u16(&mut data, 16, self.block_size as u16 - 12);
+            data[18] = 2;
+            data[19] = ft;
+            data[20..22].copy_from_slice(b"..");
+
+            self.device.write_bytes(self.block_offset(block), &data)?;
+            inode.size = self.block_size;
+            inode.links_count = 2;
+        }
+        self.write_inode(ino, &inode)?;
+
+        if let Err(err) = self.add_entry(dir, name, ino, file_type) {
+            self.drop_link(ino)?;
+            return Err(err);
+        }
+
+        if file_type == FileType::Directory {
+            self.adjust_links(dir, 1)?;
+        }
+
+        Ok(ino as InodeId)
+    }
+
+    fn unlink(&mut self, dir: InodeId, name: &str) -> Result<()> {
+        self.check_writable()?;
+        let dir = Self::to_ino(dir)?;
+        let entry = self.find(dir, name)?;
+        if self.read_inode(entry.inode)?.is_dir() {
+            return Err(FileSystemError::IsDirectory.into());
+        }
+
+        self.remove_entry(dir, &entry)?;
+        self.drop_link(entry.inode)
+    }
+
+    fn rmdir(&mut self, dir: InodeId, name: &str) -> Result<()> {
+        self.check_writable()?;
+        let dir = Self::to_ino(dir)?;
+        if name == "." || name == ".." {
+            return Err(FileSystemError::InvalidPath.into());
+        }
+
+        let entry = self.find(dir, name)?;
+        if !self.read_inode(entry.inode)?.is_dir() {
+            return Err(FileSystemError::NotDirectory.into());
+        }
+        if !self.is_empty_dir(entry.inode)? {
+            return Err(FileSystemError::DirectoryNotEmpty.into());
+        }
+
+        self.remove_entry(dir, &entry)?;
+        self.drop_link(entry.inode)?;
+        self.adjust_links(dir, -1)
+    }
+
+    fn rename(
+        &mut self,
+        src_dir: InodeId,
+        src_name: &str,
+        dst_dir: InodeId,
+        dst_name: &str,
+    ) -> Result<()> {
+        self.check_writable()?;
+        let src_dir = Self::to_ino(src_dir)?;
+        let dst_dir = Self::to_ino(dst_dir)?;
+
+        let src = self.find(src_dir, src_name)?;
+        let src_inode = self.read_inode(src.inode)?;
+        let is_dir = src_inode.is_dir();
+
+        // replace the destination if it exists
+        if let Ok(dst) = self.find(dst_dir, dst_name) {
+            if dst.inode == src.inode {
+                return Ok(());
+            }
+
+            let dst_is_dir = self.read_inode(dst.inode)?.is_dir();
+            match (is_dir, dst_is_dir) {
+                (true, false) => return Err(FileSystemError::NotDirectory.into()),
+                (false, true) => return Err(FileSystemError::IsDirectory.into()),
+                (true, true) if !self.is_empty_dir(dst.inode)? => {
+                    return Err(FileSystemError::DirectoryNotEmpty.into())
+                }
+                _ => (),
+            }
+
+            self.remove_entry(dst_dir, &dst)?;
+            self.drop_link(dst.inode)?;
+            if dst_is_dir {
+                self.adjust_links(dst_dir, -1)?;
+            }
+        }
+
+        self.add_entry(dst_dir, dst_name, src.inode, src_inode.file_type())?;
+        // offsets may have moved if the entry was added to the same block
+        let src = self.find(src_dir, src_name)?;
+        self.remove_entry(src_dir, &src)?;
+
+        if is_dir && src_dir != dst_dir {
+            self.set_parent(src.inode, dst_dir)?;
+            self.adjust_links(src_dir, -1)?;
+            self.adjust_links(dst_dir, 1)?;
+        }
+
+        Ok(())
+    }
+
+    fn truncate(&mut self, inode: InodeId, size: usize) -> Result<()> {
+        self.check_writable()?;
+        let ino = Self::to_ino(inode)?;
+        let mut node = self.read_inode(ino)?;
+        if node.is_dir() {
+            return Err(FileSystemError::IsDirectory.into());
+        }
+        if !node.is_file() {
+            return Err(FileSystemError::Unsupported.into());
+        }
+
+        let size = size as u64;
+        if size < node.size {
+            self.free_blocks_from(&mut node, size.div_ceil(self.block_size))?;
+
+            // growing later must not expose the old tail of the last block
+            let tail = size % self.block_size;
+            if tail != 0 {
+                let block = self.map_block(ino, &mut node, size / self.block_size, false)?;
+                if block != 0 {
+                    let zero = vec![0; (self.block_size - tail) as usize];
+                    self.device
+                        .write_bytes(self.block_offset(block) + tail, &zero)?;
+                }
+            }
+        }
+
+        // growing leaves a hole
+        node.size = size;
+        node.mtime = self.now();
+        node.ctime = node.mtime;
+        self.write_inode(ino, &node)
+    }
+
+    fn symlink(&mut self, dir: InodeId, name: &str, target: &str) -> Result<InodeId> {
+        self.check_writable()?;
+        let dir = Self::to_ino(dir)?;
+        validate_name(name)?;
+        if self.find(dir, name).is_ok() {
+            return Err(FileSystemError::AlreadyExists.into());
+        }
+
+        let (ino, mut inode) = self.new_inode(dir, S_IFLNK | 0o777)?;
+        if target.len() < FAST_SYMLINK_MAX {
+            let mut raw = [0; FAST_SYMLINK_MAX];
+            raw[..target.len()].copy_from_slice(target.as_bytes());
+            for (i, b) in inode.block.iter_mut().enumerate() {
+                *b = read_u32(&raw, i * 4);
+            }
+            inode.size = target.len() as u64;
+        } else {
+            self.write_data(ino, &mut inode, 0, target.as_bytes())?;
+        }
+        self.write_inode(ino, &inode)?;
+
+        if let Err(err) = self.add_entry(dir, name, ino, FileType::Symlink) {
+            self.drop_link(ino)?;
+            return Err(err);
+        }
+
+        Ok(ino as InodeId)
+    }
+
+    fn read_link(&mut self, inode: InodeId) -> Result<String> {
+        let ino = Self::to_ino(inode)?;
+        let mut node = self.read_inode(ino)?;
+        if !node.is_symlink() {
+            return Err(FileSystemError::InvalidPath.into());
+        }
+
+        let mut target = vec![0; node.size as usize];
+        if self.is_fast_symlink(&node) {
+            let mut raw = [0; FAST_SYMLINK_MAX];
+            for (i, b) in node.block.iter().enumerate() {
+                write_u32(&mut raw, i * 4, *b);
+            }
+            let len = target.len().min(FAST_SYMLINK_MAX);
+            target[..len].copy_from_slice(&raw[..len]);
+        } else {
+            self.read_data(ino, &mut node, 0, &mut target)?;
+        }
+
+        String::from_utf8(target).map_err(|_| FileSystemError::Corrupted.into())
+    }
+
+    fn sync(&mut self) -> Result<()> {
+        if !self.read_only {
+            self.write_super()?;
+        }
+        self.device.sync()
+    }
+}
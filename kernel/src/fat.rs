@@ -0,0 +1,1232 @@
+use crate::{
+    block::{BlockDevice, SharedBlockDevice},
+    error::Result,
+    vfs::{DirEntry, FileSystem, FileSystemError, FileType, InodeId, Metadata},
+};
+use alloc::{collections::BTreeMap, format, string::String, vec, vec::Vec};
+
+const ROOT_INODE: InodeId = 1;
+const DIR_ENTRY_SIZE: usize = 32;
+
+const ATTR_READ_ONLY: u8 = 0x01;
+const ATTR_VOLUME_ID: u8 = 0x08;
+const ATTR_DIRECTORY: u8 = 0x10;
+const ATTR_ARCHIVE: u8 = 0x20;
+const ATTR_LONG_NAME: u8 = 0x0f;
+
+const ENTRY_FREE: u8 = 0xe5;
+const ENTRY_END: u8 = 0x00;
+const ENTRY_KANJI_E5: u8 = 0x05;
+
+// NT reserved byte flags for lowercase 8.3 names
+const NT_LOWERCASE_BASE: u8 = 0x08;
+const NT_LOWERCASE_EXT: u8 = 0x10;
+
+const LFN_LAST_ENTRY: u8 = 0x40;
+const LFN_CHARS_PER_ENTRY: usize = 13;
+const LFN_CHAR_OFFSETS: [usize; LFN_CHARS_PER_ENTRY] =
+    [1, 3, 5, 7, 9, 14, 16, 18, 20, 22, 24, 28, 30];
+const LFN_MAX_LEN: usize = 255;
+
+const FSINFO_LEAD_SIGNATURE: u32 = 0x41615252;
+const FSINFO_STRUCT_SIGNATURE: u32 = 0x61417272;
+const FSINFO_TRAIL_SIGNATURE: u32 = 0xaa550000;
+const FSINFO_UNKNOWN: u32 = 0xffffffff;
+
+// there is no RTC, new entries are stamped 1980-01-01 00:00
+const DEFAULT_DATE: u16 = (1 << 5) | 1;
+const DEFAULT_TIME: u16 = 0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FatType {
+    Fat12,
+    Fat16,
+    Fat32,
+}
+
+#[derive(Debug, Clone)]
+struct FatNode {
+    parent: InodeId,
+    // device offset of the short directory entry, None for the root directory
+    entry_offset: Option<u64>,
+    first_cluster: u32,
+    size: u32,
+    attr: u8,
+    mtime: u64,
+}
+
+impl FatNode {
+    fn is_dir(&self) -> bool {
+        self.attr & ATTR_DIRECTORY != 0
+    }
+}
+
+// a parsed directory entry with the device offsets of all of its slots
+#[derive(Debug, Clone)]
+struct FatDirEntry {
+    name: String,
+    short_name: [u8; 11],
+    raw: [u8; DIR_ENTRY_SIZE],
+    offset: u64,
+    slots: Vec<u64>,
+}
+
+impl FatDirEntry {
+    fn attr(&self) -> u8 {
+        self.raw[11]
+    }
+
+    fn first_cluster(&self) -> u32 {
+        entry_cluster(&self.raw)
+    }
+
+    fn size(&self) -> u32 {
+        read_u32(&self.raw, 28)
+    }
+
+    fn matches(&self, name: &str) -> bool {
+        self.name.eq_ignore_ascii_case(name)
+            || short_name_display(&self.short_name, self.raw[12]).eq_ignore_ascii_case(name)
+    }
+}
+
+fn read_u16(buf: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes(buf[offset..offset + 2].try_into().unwrap())
+}
+
+fn read_u32(buf: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(buf[offset..offset + 4].try_into().unwrap())
+}
+
+fn write_u16(buf: &mut [u8], offset: usize, value: u16) {
+    buf[offset..offset + 2].copy_from_slice(&value.to_le_bytes());
+}
+
+fn write_u32(buf: &mut [u8], offset: usize, value: u32) {
+    buf[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+}
+
+fn entry_cluster(raw: &[u8]) -> u32 {
+    (read_u16(raw, 20) as u32) << 16 | read_u16(raw, 26) as u32
+}
+
+fn set_entry_cluster(raw: &mut [u8], cluster: u32) {
+    write_u16(raw, 20, (cluster >> 16) as u16);
+    write_u16(raw, 26, cluster as u16);
+}
+
+// converts a FAT date and time to seconds since the unix epoch
+fn fat_time_to_unix(date: u16, time: u16) -> u64 {
+    let year = 1980 + (date >> 9) as i64;
+    let month = ((date >> 5) & 0xf).clamp(1, 12) as i64;
+    let day = (date & 0x1f).max(1) as i64;
+
+    // days from civil, shifted so the year starts in March
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = y / 400;
+    let yoe = y - era * 400;
+    let mp = (month + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    let days = era * 146097 + doe - 719468;
+
+    let seconds =
+        (time >> 11) as i64 * 3600 + ((time >> 5) & 0x3f) as i64 * 60 + (time & 0x1f) as i64 * 2;
+    (days * 86400 + seconds) as u64
+}
+
+fn short_name_display(short_name: &[u8], nt_flags: u8) -> String {
+    let mut name = String::new();
+
+    for (i, &b) in short_name[0..8].iter().enumerate() {
+        let b = if i == 0 && b == ENTRY_KANJI_E5 {
+            ENTRY_FREE
+        } else {
+            b
+        };
+        if b == b' ' {
+            break;
+        }
+        let c = b as char;
+        name.push(if nt_flags & NT_LOWERCASE_BASE != 0 {
+            c.to_ascii_lowercase()
+        } else {
+            c
+        });
+    }
+
+    if short_name[8] != b' ' {
+        name.push('.');
+        for &b in short_name[8..11].iter().take_while(|&&b| b != b' ') {
+            let c = b as char;
+            name.push(if nt_flags & NT_LOWERCASE_EXT != 0 {
+                c.to_ascii_lowercase()
+            } else {
+                c
+            });
+        }
+    }
+
+    name
+}
+
+fn lfn_checksum(short_name: &[u8]) -> u8 {
+    short_name.iter().fold(0u8, |sum, &b| {
+        ((sum & 1) << 7).wrapping_add(sum >> 1).wrapping_add(b)
+    })
+}
+
+fn is_short_name_char(b: u8) -> bool {
+    b.is_ascii_uppercase() || b.is_ascii_digit() || b"!#$%&'()-@^_`{}~".contains(&b)
+}
+
+fn validate_name(name: &str) -> Result<()> {
+    if name.is_empty()
+        || name == "."
+        || name == ".."
+        || name.encode_utf16().count() > LFN_MAX_LEN
+        || name.chars().any(|c| c < ' ' || "\"*/:<>?\\|".contains(c))
+    {
+        return Err(FileSystemError::InvalidPath.into());
+    }
+
+    Ok(())
+}
+
+fn pack_short_name(base: &[u8], ext: &[u8]) -> [u8; 11] {
+    let mut short_name = [b' '; 11];
+    short_name[..base.len()].copy_from_slice(base);
+    short_name[8..8 + ext.len()].copy_from_slice(ext);
+    short_name
+}
+
+// returns the 8.3 name for a long name and whether LFN entries are needed
+fn generate_short_name(name: &str, existing: &[FatDirEntry]) -> Result<([u8; 11], bool)> {
+    let (base, ext) = match name.rfind('.') {
+        Some(i) if i > 0 => (&name[..i], &name[i + 1..]),
+        _ => (name, ""),
+    };
+
+    let mut lossy = false;
+    let mut convert = |s: &str, max: usize| {
+        let mut out = Vec::new();
+        for c in s.chars() {
+            if c == ' ' || c == '.' {
+                lossy = true;
+                continue;
+            }
+
+            let b = c.to_ascii_uppercase() as u32;
+            let b = if b < 0x80 && is_short_name_char(b as u8) {
+                b as u8
+            } else {
+                lossy = true;
+                b'_'
+            };
+
+            if out.len() < max {
+                out.push(b);
+            } else {
+                lossy = true;
+            }
+        }
+        out
+    };
+
+    let mut base = convert(base, 8);
+    let ext = convert(ext, 3);
+    if base.is_empty() {
+        base.push(b'_');
+        lossy = true;
+    }
+
+    let exists = |short_name: &[u8; 11]| existing.iter().any(|e| &e.short_name == short_name);
+
+    if !lossy {
+        let short_name = pack_short_name(&base, &ext);
+        if !exists(&short_name) {
+            return Ok((short_name, short_name_display(&short_name, 0) != name));
+        }
+    }
+
+    // numeric tail, BASE~N.EXT
+    for n in 1..1_000_000 {
+        let tail = format!("~{}", n);
+        let keep = (8 - tail.len()).min(base.len());
+        let mut tailed = base[..keep].to_vec();
+        tailed.extend_from_slice(tail.as_bytes());
+
+        let short_name = pack_short_name(&tailed, &ext);
+        if !exists(&short_name) {
+            return Ok((short_name, true));
+        }
+    }
+
+    Err(FileSystemError::AlreadyExists.into())
+}
+
+fn lfn_entries(name: &str, checksum: u8) -> Vec<[u8; DIR_ENTRY_SIZE]> {
+    let mut units: Vec<u16> = name.encode_utf16().collect();
+    let count = units.len().div_ceil(LFN_CHARS_PER_ENTRY);
+    if units.len() % LFN_CHARS_PER_ENTRY != 0 {
+        units.push(0);
+    }
+    units.resize(count * LFN_CHARS_PER_ENTRY, 0xffff);
+
+    // stored in reverse order, the last part comes first
+    (1..=count)
+        .rev()
+        .map(|seq| {
+            let mut raw = [0; DIR_ENTRY_SIZE];
+            raw[0] = seq as u8 | if seq == count { LFN_LAST_ENTRY } else { 0 };
+            raw[11] = ATTR_LONG_NAME;
+            raw[13] = checksum;
+
+            let part = &units[(seq - 1) * LFN_CHARS_PER_ENTRY..seq * LFN_CHARS_PER_ENTRY];
+            for (&unit, &offset) in part.iter().zip(LFN_CHAR_OFFSETS.iter()) {
+                write_u16(&mut raw, offset, unit);
+            }
+            raw
+        })
+        .collect()
+}
+
+fn short_entry(attr: u8, first_cluster: u32, size: u32) -> [u8; DIR_ENTRY_SIZE] {
+    let mut raw = [0; DIR_ENTRY_SIZE];
+    raw[11] = attr;
+    for offset in [14, 22] {
+        write_u16(&mut raw, offset, DEFAULT_TIME);
+    }
+    for offset in [16, 18, 24] {
+        write_u16(&mut raw, offset, DEFAULT_DATE);
+    }
+    set_entry_cluster(&mut raw, first_cluster);
+    write_u32(&mut raw, 28, size);
+    raw
+}
+
+pub struct Fat {
+    device: SharedBlockDevice,
+    fat_type: FatType,
+    bytes_per_sector: u64,
+    cluster_size: usize,
+    fat_offset: u64,
+    fat_size: u64,
+    fat_count: u64,
+    // FAT12/16 only, the root directory is a fixed region before the data area
+    root_dir_offset: u64,
+    root_dir_size: usize,
+    data_offset: u64,
+    cluster_count: u32,
+    fsinfo_offset: Option<u64>,
+    free_count: Option<u32>,
+    next_free: u32,
+    nodes: BTreeMap<InodeId, FatNode>,
+    next_inode: InodeId,
+}
+
+impl Fat {
+    pub fn new(device: SharedBlockDevice) -> Result<Self> {
+        let mut device = device;
+        let mut boot = [0; 512];
+        device.read_bytes(0, &mut boot)?;
+
+        if read_u16(&boot, 510) != 0xaa55 {
+            return Err("Invalid FAT boot sector signature".into());
+        }
+
+        let bytes_per_sector = read_u16(&boot, 11) as u64;
+        let sectors_per_cluster = boot[13] as u64;
+        let reserved_sectors = read_u16(&boot, 14) as u64;
+        let fat_count = boot[16] as u64;
+        let root_entries = read_u16(&boot, 17) as u64;
+        let total_sectors = match read_u16(&boot, 19) {
+            0 => read_u32(&boot, 32) as u64,
+            n => n as u64,
+        };
+        let fat_sectors = match read_u16(&boot, 22) {
+            0 => read_u32(&boot, 36) as u64,
+            n => n as u64,
+        };
+
+        if !matches!(bytes_per_sector, 512 | 1024 | 2048 | 4096)
+            || !sectors_per_cluster.is_power_of_two()
+            || reserved_sectors == 0
+            || fat_count == 0
+            || fat_sectors == 0
+        {
+            return Err("Invalid FAT BIOS parameter block".into());
+        }
+
+        let root_dir_sectors = (root_entries * DIR_ENTRY_SIZE as u64).div_ceil(bytes_per_sector);
+        let data_sector = reserved_sectors + fat_count * fat_sectors + root_dir_sectors;
+        if data_sector >= total_sectors {
+            return Err("Invalid FAT BIOS parameter block".into());
+        }
+
+        let cluster_count = ((total_sectors - data_sector) / sectors_per_cluster) as u32;
+        let fat_type = if cluster_count < 4085 {
+            FatType::Fat12
+        } else if cluster_count < 65525 {
+            FatType::Fat16
+        } else {
+            FatType::Fat32
+        };
+
+        let root_cluster = match fat_type {
+            FatType::Fat32 => read_u32(&boot, 44),
+            _ => 0,
+        };
+
+        let mut fat = Self {
+            device,
+            fat_type,
+            bytes_per_sector,
+            cluster_size: (sectors_per_cluster * bytes_per_sector) as usize,
+            fat_offset: reserved_sectors * bytes_per_sector,
+            fat_size: fat_sectors * bytes_per_sector,
+            fat_count,
+            root_dir_offset: (reserved_sectors + fat_count * fat_sectors) * bytes_per_sector,
+            root_dir_size: (root_dir_sectors * bytes_per_sector) as usize,
+            data_offset: data_sector * bytes_per_sector,
+            cluster_count,
+            fsinfo_offset: None,
+            free_count: None,
+            next_free: 2,
+            nodes: BTreeMap::new(),
+            next_inode: ROOT_INODE + 1,
+        };
+
+        if fat_type == FatType::Fat32 {
+            fat.read_fsinfo(read_u16(&boot, 48) as u64)?;
+        }
+
+        fat.nodes.insert(
+            ROOT_INODE,
+            FatNode {
+                parent: ROOT_INODE,
+                entry_offset: None,
+                first_cluster: root_cluster,
+                size: 0,
+                attr: ATTR_DIRECTORY,
+                mtime: 0,
+            },
+        );
+
+        Ok(fat)
+    }
+
+    pub fn fat_type(&self) -> FatType {
+        self.fat_type
+    }
+
+    fn read_fsinfo(&mut self, sector: u64) -> Result<()> {
+        if sector == 0 || sector == 0xffff {
+            return Ok(());
+        }
+
+        let offset = sector * self.bytes_per_sector;
+        let mut fsinfo = [0; 512];
+        self.device.read_bytes(offset, &mut fsinfo)?;
+
+        if read_u32(&fsinfo, 0) != FSINFO_LEAD_SIGNATURE
+            || read_u32(&fsinfo, 484) != FSINFO_STRUCT_SIGNATURE
+            || read_u32(&fsinfo, 508) != FSINFO_TRAIL_SIGNATURE
+        {
+            return Ok(());
+        }
+
+        // the values are hints and may be stale
+        let free_count = read_u32(&fsinfo, 488);
+        if free_count <= self.cluster_count {
+            self.free_count = Some(free_count);
+        }
+
+        let next_free = read_u32(&fsinfo, 492);
+        if (2..self.cluster_count + 2).contains(&next_free) {
+            self.next_free = next_free;
+        }
+
+        self.fsinfo_offset = Some(offset);
+        Ok(())
+    }
+
+    fn write_fsinfo(&mut self) -> Result<()> {
+        let offset = match self.fsinfo_offset {
+            Some(offset) => offset,
+            None => return Ok(()),
+        };
+
+        let mut values = [0; 8];
+        write_u32(&mut values, 0, self.free_count.unwrap_or(FSINFO_UNKNOWN));
+        write_u32(&mut values, 4, self.next_free);
+        self.device.write_bytes(offset + 488, &values)
+    }
+
+    fn node(&self, inode: InodeId) -> Result<&FatNode> {
+        Ok(self.nodes.get(&inode).ok_or(FileSystemError::NotFound)?)
+    }
+
+    fn node_mut(&mut self, inode: InodeId) -> Result<&mut FatNode> {
+        Ok(self
+            .nodes
+            .get_mut(&inode)
+            .ok_or(FileSystemError::NotFound)?)
+    }
+
+    // returns the inode for a directory entry, reusing the existing one if it is known
+    fn node_for(&mut self, dir: InodeId, entry: &FatDirEntry) -> InodeId {
+        if let Some((&inode, _)) = self
+            .nodes
+            .iter()
+            .find(|(_, n)| n.entry_offset == Some(entry.offset))
+        {
+            return inode;
+        }
+
+        let inode = self.next_inode;
+        self.next_inode += 1;
+        self.nodes.insert(
+            inode,
+            FatNode {
+                parent: dir,
+                entry_offset: Some(entry.offset),
+                first_cluster: entry.first_cluster(),
+                size: entry.size(),
+                attr: entry.attr(),
+                mtime: fat_time_to_unix(read_u16(&entry.raw, 24), read_u16(&entry.raw, 22)),
+            },
+        );
+
+        inode
+    }
+
+    fn cluster_offset(&self, cluster: u32) -> u64 {
+        self.data_offset + (cluster as u64 - 2) * self.cluster_size as u64
+    }
+
+    fn fat_entry_offset(&self, cluster: u32) -> u64 {
+        let n = cluster as u64;
+        self.fat_offset
+            + match self.fat_type {
+                FatType::Fat12 => n + n / 2,
+                FatType::Fat16 => n * 2,
+                FatType::Fat32 => n * 4,
+            }
+    }
+
+    fn end_of_chain(&self) -> u32 {
+        match self.fat_type {
+            FatType::Fat12 => 0xfff,
+            FatType::Fat16 => 0xffff,
+            FatType::Fat32 => 0x0fffffff,
+        }
+    }
+
+    fn is_end_of_chain(&self, value: u32) -> bool {
+        value >= self.end_of_chain() - 7
+    }
+
+    fn is_valid_cluster(&self, cluster: u32) -> bool {
+        (2..self.cluster_count + 2).contains(&cluster)
+    }
+
+    fn read_fat(&mut self, cluster: u32) -> Result<u32> {
+        let offset = self.fat_entry_offset(cluster);
+        let mut buf = [0; 4];
+
+        Ok(match self.fat_type {
+            FatType::Fat12 => {
+                self.device.read_bytes(offset, &mut buf[..2])?;
+                let value = read_u16(&buf, 0) as u32;
+                if cluster & 1 != 0 {
+                    value >> 4
+                } else {
+                    value & 0xfff
+                }
+            }
+            FatType::Fat16 => {
+                self.device.read_bytes(offset, &mut buf[..2])?;
+                read_u16(&buf, 0) as u32
+            }
+            FatType::Fat32 => {
+                self.device.read_bytes(offset, &mut buf)?;
+                read_u32(&buf, 0) & 0x0fffffff
+            }
+        })
+    }
+
+    // updates every copy of the FAT
+    fn write_fat(&mut self, cluster: u32, value: u32) -> Result<()> {
+        for i in 0..self.fat_count {
+            let offset = self.fat_entry_offset(cluster) + i * self.fat_size;
+            let mut buf = [0; 4];
+
+            match self.fat_type {
+                FatType::Fat12 => {
+                    self.device.read_bytes(offset, &mut buf[..2])?;
+                    let old = read_u16(&buf, 0);
+                    let new = if cluster & 1 != 0 {
+                        (old & 0x000f) | ((value as u16) << 4)
+                    } else {
+                        (old & 0xf000) | (value as u16 & 0x0fff)
+                    };
+                    write_u16(&mut buf, 0, new);
+                    self.device.write_bytes(offset, &buf[..2])?;
+                }
+                FatType::Fat16 => {
+                    write_u16(&mut buf, 0, value as u16);
+                    self.device.write_bytes(offset, &buf[..2])?;
+                }
+                FatType::Fat32 => {
+                    // the upper 4 bits are reserved and must be preserved
+                    self.device.read_bytes(offset, &mut buf)?;
+                    let old = read_u32(&buf, 0);
+                    write_u32(&mut buf, 0, (old & 0xf0000000) | (value & 0x0fffffff));
+                    self.device.write_bytes(offset, &buf)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn chain(&mut self, first_cluster: u32) -> Result<Vec<u32>> {
+        let mut chain = Vec::new();
+        if first_cluster == 0 {
+            return Ok(chain);
+        }
+
+        let mut cluster = first_cluster;
+        loop {
+            if !self.is_valid_cluster(cluster) || chain.len() > self.cluster_count as usize {
+                return Err(FileSystemError::Corrupted.into());
+            }
+
+            chain.push(cluster);
+            let next = self.read_fat(cluster)?;
+            if self.is_end_of_chain(next) {
+                return Ok(chain);
+            }
+            cluster = next;
+        }
+    }
+
+    fn alloc_cluster(&mut self, prev: Option<u32>) -> Result<u32> {
+        for i in 0..self.cluster_count {
+            let cluster = 2 + (self.next_free - 2 + i) % self.cluster_count;
+            if self.read_fat(cluster)? != 0 {
+                continue;
+            }
+
+            self.write_fat(cluster, self.end_of_chain())?;
+            if let Some(prev) = prev {
+                self.write_fat(prev, cluster)?;
+            }
+
+            self.next_free = if cluster + 1 < self.cluster_count + 2 {
+                cluster + 1
+            } else {
+                2
+            };
+            if let Some(free_count) = self.free_count.as_mut() {
+                *free_count = free_count.saturating_sub(1);
+            }
+
+            return Ok(cluster);
+        }
+
+        Err(FileSystemError::NoSpace.into())
+    }
+
+    fn free_clusters(&mut self, clusters: &[u32]) -> Result<()> {
+        for &cluster in clusters {
+            self.write_fat(cluster, 0)?;
+            if let Some(free_count) = self.free_count.as_mut() {
+                *free_count += 1;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn zero_cluster(&mut self, cluster: u32) -> Result<()> {
+        let zero = vec![0; self.cluster_size];
+        self.device.write_bytes(self.cluster_offset(cluster), &zero)
+    }
+
+    // makes sure the chain of the node covers len bytes, returns the chain
+    fn ensure_clusters(&mut self, inode: InodeId, len: usize) -> Result<Vec<u32>> {
+        let first_cluster = self.node(inode)?.first_cluster;
+        let mut chain = self.chain(first_cluster)?;
+        let needed = len.div_ceil(self.cluster_size);
+
+        while chain.len() < needed {
+            let cluster = self.alloc_cluster(chain.last().copied())?;
+            chain.push(cluster);
+        }
+
+        if first_cluster == 0 && !chain.is_empty() {
+            self.node_mut(inode)?.first_cluster = chain[0];
+        }
+
+        Ok(chain)
+    }
+
+    // device regions (offset, length) holding the directory
+    fn dir_regions(&mut self, dir: InodeId) -> Result<Vec<(u64, usize)>> {
+        let node = self.node(dir)?;
+        if !node.is_dir() {
+            return Err(FileSystemError::NotDirectory.into());
+        }
+
+        if node.entry_offset.is_none() && self.fat_type != FatType::Fat32 {
+            return Ok(vec![(self.root_dir_offset, self.root_dir_size)]);
+        }
+
+        let first_cluster = node.first_cluster;
+        Ok(self
+            .chain(first_cluster)?
+            .into_iter()
+            .map(|cluster| (self.cluster_offset(cluster), self.cluster_size))
+            .collect())
+    }
+
+    fn read_slots(&mut self, dir: InodeId) -> Result<Vec<(u64, [u8; DIR_ENTRY_SIZE])>> {
+        let mut slots = Vec::new();
+
+        for (offset, len) in self.dir_regions(dir)? {
+            let mut data = vec![0; len];
+            self.device.read_bytes(offset, &mut data)?;
+
+            for (i, raw) in data.chunks_exact(DIR_ENTRY_SIZE).enumerate() {
+                slots.push((
+                    offset + (i * DIR_ENTRY_SIZE) as u64,
+                    raw.try_into().unwrap(),
+                ));
+            }
+        }
+
+        Ok(slots)
+    }
+
+    // parses the directory, skipping deleted entries, volume labels, "." and ".."
+    fn entries(&mut self, dir: InodeId) -> Result<Vec<FatDirEntry>> {
+        let mut entries = Vec::new();
+        let mut lfn_parts: Vec<Option<[u16; LFN_CHARS_PER_ENTRY]>> = Vec::new();
+        let mut lfn_slots = Vec::new();
+        let mut lfn_checksum_value = 0;
+
+        for (offset, raw) in self.read_slots(dir)? {
+            if raw[0] == ENTRY_END {
+                break;
+            }
+
+            if raw[0] == ENTRY_FREE {
+                lfn_parts.clear();
+                lfn_slots.clear();
+                continue;
+            }
+
+            if raw[11] & 0x3f == ATTR_LONG_NAME {
+                let seq = (raw[0] & 0x1f) as usize;
+                if raw[0] & LFN_LAST_ENTRY != 0 {
+                    lfn_parts = vec![None; seq];
+                    lfn_slots.clear();
+                    lfn_checksum_value = raw[13];
+                }
+
+                if seq == 0 || seq > lfn_parts.len() || raw[13] != lfn_checksum_value {
+                    lfn_parts.clear();
+                    lfn_slots.clear();
+                    continue;
+                }
+
+                let mut part = [0; LFN_CHARS_PER_ENTRY];
+                for (unit, &offset) in part.iter_mut().zip(LFN_CHAR_OFFSETS.iter()) {
+                    *unit = read_u16(&raw, offset);
+                }
+                lfn_parts[seq - 1] = Some(part);
+                lfn_slots.push(offset);
+                continue;
+            }
+
+            let short_name: [u8; 11] = raw[0..11].try_into().unwrap();
+            let is_dot = short_name[0] == b'.';
+            if raw[11] & ATTR_VOLUME_ID != 0 || is_dot {
+                lfn_parts.clear();
+                lfn_slots.clear();
+                continue;
+            }
+
+            // the long name is only valid if it is complete and belongs to this entry
+            let long_name = if !lfn_parts.is_empty()
+                && lfn_parts.iter().all(|p| p.is_some())
+                && lfn_checksum(&short_name) == lfn_checksum_value
+            {
+                let units = lfn_parts
+                    .iter()
+                    .flat_map(|p| p.unwrap())
+                    .take_while(|&u| u != 0);
+                Some(
+                    char::decode_utf16(units)
+                        .map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER))
+                        .collect(),
+                )
+            } else {
+                None
+            };
+
+            let mut slots = if long_name.is_some() {
+                lfn_slots.clone()
+            } else {
+                Vec::new()
+            };
+            slots.push(offset);
+
+            entries.push(FatDirEntry {
+                name: long_name.unwrap_or_else(|| short_name_display(&short_name, raw[12])),
+                short_name,
+                raw,
+                offset,
+                slots,
+            });
+
+            lfn_parts.clear();
+            lfn_slots.clear();
+        }
+
+        Ok(entries)
+    }
+
+    fn find(&mut self, dir: InodeId, name: &str) -> Result<FatDirEntry> {
+        Ok(self
+            .entries(dir)?
+            .into_iter()
+            .find(|e| e.matches(name))
+            .ok_or(FileSystemError::NotFound)?)
+    }
+
+    // finds count consecutive free slots, growing the directory if needed
+    fn free_slots(&mut self, dir: InodeId, count: usize) -> Result<Vec<u64>> {
+        loop {
+            let slots = self.read_slots(dir)?;
+            let mut run = Vec::new();
+
+            for (offset, raw) in slots.iter() {
+                if raw[0] == ENTRY_FREE || raw[0] == ENTRY_END {
+                    run.push(*offset);
+                    if run.len() == count {
+                        return Ok(run);
+                    }
+                } else {
+                    run.clear();
+                }
+            }
+
+            // the FAT12/16 root directory cannot grow
+            let node = self.node(dir)?;
+            if node.entry_offset.is_none() && self.fat_type != FatType::Fat32 {
+                return Err(FileSystemError::NoSpace.into());
+            }
+
+            let first_cluster = node.first_cluster;
+            let last = self.chain(first_cluster)?.last().copied();
+            let cluster = self.alloc_cluster(last)?;
+            self.zero_cluster(cluster)?;
+        }
+    }
+
+    // writes the entry with the given name, returns the offset of the short entry
+    fn add_entry(
+        &mut self,
+        dir: InodeId,
+        name: &str,
+        mut raw: [u8; DIR_ENTRY_SIZE],
+    ) -> Result<u64> {
+        validate_name(name)?;
+
+        let entries = self.entries(dir)?;
+        if entries.iter().any(|e| e.matches(name)) {
+            return Err(FileSystemError::AlreadyExists.into());
+        }
+
+        let (short_name, needs_lfn) = generate_short_name(name, &entries)?;
+        raw[0..11].copy_from_slice(&short_name);
+        raw[12] = 0;
+
+        let mut slots = if needs_lfn {
+            lfn_entries(name, lfn_checksum(&short_name))
+        } else {
+            Vec::new()
+        };
+        slots.push(raw);
+
+        let offsets = self.free_slots(dir, slots.len())?;
+        for (offset, slot) in offsets.iter().zip(slots.iter()) {
+            self.device.write_bytes(*offset, slot)?;
+        }
+
+        Ok(*offsets.last().unwrap())
+    }
+
+    fn remove_entry(&mut self, entry: &FatDirEntry) -> Result<()> {
+        for &offset in entry.slots.iter() {
+            self.device.write_bytes(offset, &[ENTRY_FREE])?;
+        }
+
+        Ok(())
+    }
+
+    // writes first cluster and size of the node back to its directory entry
+    fn update_entry(&mut self, inode: InodeId) -> Result<()> {
+        let node = self.node(inode)?.clone();
+        let offset = match node.entry_offset {
+            Some(offset) => offset,
+            None => return Ok(()),
+        };
+
+        let mut raw = [0; DIR_ENTRY_SIZE];
+        self.device.read_bytes(offset, &mut raw)?;
+        raw[11] = node.attr;
+        set_entry_cluster(&mut raw, node.first_cluster);
+        write_u32(&mut raw, 28, if node.is_dir() { 0 } else { node.size });
+        self.device.write_bytes(offset, &raw)
+    }
+
+    // the ".." entry stores 0 for the root directory
+    fn dir_cluster(&self, dir: InodeId) -> Result<u32> {
+        let node = self.node(dir)?;
+        Ok(if node.entry_offset.is_none() {
+            0
+        } else {
+            node.first_cluster
+        })
+    }
+
+    fn delete(&mut self, entry: &FatDirEntry) -> Result<()> {
+        self.remove_entry(entry)?;
+        let chain = self.chain(entry.first_cluster())?;
+        self.free_clusters(&chain)?;
+        self.nodes
+            .retain(|_, n| n.entry_offset != Some(entry.offset));
+        Ok(())
+    }
+
+    fn write_data(&mut self, inode: InodeId, offset: usize, buf: &[u8]) -> Result<usize> {
+        let end = offset + buf.len();
+        if end > u32::MAX as usize {
+            return Err(FileSystemError::NoSpace.into());
+        }
+
+        let chain = self.ensure_clusters(inode, end)?;
+        let mut done = 0;
+
+        while done < buf.len() {
+            let pos = offset + done;
+            let cluster = chain[pos / self.cluster_size];
+            let cluster_offset = pos % self.cluster_size;
+            let len = (self.cluster_size - cluster_offset).min(buf.len() - done);
+
+            self.device.write_bytes(
+                self.cluster_offset(cluster) + cluster_offset as u64,
+                &buf[done..done + len],
+            )?;
+            done += len;
+        }
+
+        let node = self.node_mut(inode)?;
+        node.size = node.size.max(end as u32);
+        node.attr |= ATTR_ARCHIVE;
+        self.update_entry(inode)?;
+
+        Ok(buf.len())
+    }
+
+    fn fill_zero(&mut self, inode: InodeId, from: usize, to: usize) -> Result<()> {
+        let zero = vec![0; self.cluster_size];
+        let mut pos = from;
+
+        while pos < to {
+            let len = (to - pos).min(zero.len());
+            self.write_data(inode, pos, &zero[..len])?;
+            pos += len;
+        }
+
+        Ok(())
+    }
+}
+
+impl FileSystem for Fat {
+    fn name(&self) -> &'static str {
+        match self.fat_type {
+            FatType::Fat12 => "fat12",
+            FatType::Fat16 => "fat16",
+            FatType::Fat32 => "fat32",
+        }
+    }
+
+    fn root(&self) -> InodeId {
+        ROOT_INODE
+    }
+
+    fn lookup(&mut self, dir: InodeId, name: &str) -> Result<InodeId> {
+        match name {
+            "." => return Ok(dir),
+            ".." => return Ok(self.node(dir)?.parent),
+            _ => (),
+        }
+
+        let entry = self.find(dir, name)?;
+        Ok(self.node_for(dir, &entry))
+    }
+
+    fn metadata(&mut self, inode: InodeId) -> Result<Metadata> {
+        let node = self.node(inode)?;
+        let (file_type, mode) = if node.is_dir() {
+            (FileType::Directory, 0o755)
+        } else if node.attr & ATTR_READ_ONLY != 0 {
+            (FileType::File, 0o444)
+        } else {
+            (FileType::File, 0o644)
+        };
+
+        let mut metadata = Metadata::new(inode, file_type, node.size as usize, mode);
+        metadata.atime = node.mtime;
+        metadata.mtime = node.mtime;
+        metadata.ctime = node.mtime;
+        Ok(metadata)
+    }
+
+    fn read(&mut self, inode: InodeId, offset: usize, buf: &mut [u8]) -> Result<usize> {
+        let node = self.node(inode)?.clone();
+        if node.is_dir() {
+            return Err(FileSystemError::IsDirectory.into());
+        }
+
+        let size = node.size as usize;
+        if offset >= size {
+            return Ok(0);
+        }
+
+        let len = buf.len().min(size - offset);
+        let chain = self.chain(node.first_cluster)?;
+        let mut done = 0;
+
+        while done < len {
+            let pos = offset + done;
+            let cluster = *chain
+                .get(pos / self.cluster_size)
+                .ok_or(FileSystemError::Corrupted)?;
+            let cluster_offset = pos % self.cluster_size;
+            let n = (self.cluster_size - cluster_offset).min(len - done);
+
+            self.device.read_bytes(
+                self.cluster_offset(cluster) + cluster_offset as u64,
+                &mut buf[done..done + n],
+            )?;
+            done += n;
+        }
+
+        Ok(len)
+    }
+
+    fn write(&mut self, inode: InodeId, offset: usize, buf: &[u8]) -> Result<usize> {
+        let node = self.node(inode)?;
+        if node.is_dir() {
+            return Err(FileSystemError::IsDirectory.into());
+        }
+        if node.attr & ATTR_READ_ONLY != 0 {
+            return Err(FileSystemError::ReadOnly.into());
+        }
+
+        // clusters may hold stale data, so a gap is filled explicitly
+        let size = node.size as usize;
+        if offset > size {
+            self.fill_zero(inode, size, offset)?;
+        }
+
+        self.write_data(inode, offset, buf)
+    }
+
+    fn read_dir(&mut self, dir: InodeId) -> Result<Vec<DirEntry>> {
+        let entries = self.entries(dir)?;
+        let mut dir_entries = Vec::new();
+
+        for entry in entries {
+            dir_entries.push(DirEntry {
+                inode: self.node_for(dir, &entry),
+                file_type: if entry.attr() & ATTR_DIRECTORY != 0 {
+                    FileType::Directory
+                } else {
+                    FileType::File
+                },
+                name: entry.name,
+            });
+        }
+
+        Ok(dir_entries)
+    }
+
+    fn create(&mut self, dir: InodeId, name: &str, file_type: FileType) -> Result<InodeId> {
+        let raw = match file_type {
+            FileType::File => short_entry(ATTR_ARCHIVE, 0, 0),
+            FileType::Directory => {
+                let cluster = self.alloc_cluster(None)?;
+                self.zero_cluster(cluster)?;
+
+                let mut dot = short_entry(ATTR_DIRECTORY, cluster, 0);
+                dot[0..11].copy_from_slice(b".          ");
+                let mut dot_dot = short_entry(ATTR_DIRECTORY, self.dir_cluster(dir)?, 0);
+                dot_dot[0..11].copy_from_slice(b"..         ");
+
+                let offset = self.cluster_offset(cluster);
+                self.device.write_bytes(offset, &dot)?;
+                self.device
+                    .write_bytes(offset + DIR_ENTRY_SIZE as u64, &dot_dot)?;
+
+                short_entry(ATTR_DIRECTORY, cluster, 0)
+            }
+            _ => return Err(FileSystemError::Unsupported.into()),
+        };
+
+        match self.add_entry(dir, name, raw) {
+            Ok(_) => (),
+            Err(err) => {
+                if file_type == FileType::Directory {
+                    self.free_clusters(&[entry_cluster(&raw)])?;
+                }
+                return Err(err);
+            }
+        }
+
+        let entry = self.find(dir, name)?;
+        Ok(self.node_for(dir, &entry))
+    }
+
+    fn unlink(&mut self, dir: InodeId, name: &str) -> Result<()> {
+        let entry = self.find(dir, name)?;
+        if entry.attr() & ATTR_DIRECTORY != 0 {
+            return Err(FileSystemError::IsDirectory.into());
+        }
+
+        self.delete(&entry)
+    }
+
+    fn rmdir(&mut self, dir: InodeId, name: &str) -> Result<()> {
+        let entry = self.find(dir, name)?;
+        if entry.attr() & ATTR_DIRECTORY == 0 {
+            return Err(FileSystemError::NotDirectory.into());
+        }
+
+        let inode = self.node_for(dir, &entry);
+        if !self.entries(inode)?.is_empty() {
+            return Err(FileSystemError::DirectoryNotEmpty.into());
+        }
+
+        self.delete(&entry)
+    }
+
+    fn rename(
+        &mut self,
+        src_dir: InodeId,
+        src_name: &str,
+        dst_dir: InodeId,
+        dst_name: &str,
+    ) -> Result<()> {
+        let src = self.find(src_dir, src_name)?;
+        let inode = self.node_for(src_dir, &src);
+        let is_dir = src.attr() & ATTR_DIRECTORY != 0;
+
+        // replace the destination if it exists
+        let mut same_entry = false;
+        if let Ok(dst) = self.find(dst_dir, dst_name) {
+            if dst.offset == src.offset {
+                same_entry = true;
+                if dst.name == dst_name {
+                    return Ok(());
+                }
+            } else {
+                let dst_is_dir = dst.attr() & ATTR_DIRECTORY != 0;
+                match (is_dir, dst_is_dir) {
+                    (true, false) => return Err(FileSystemError::NotDirectory.into()),
+                    (false, true) => return Err(FileSystemError::IsDirectory.into()),
+                    (true, true) => {
+                        let dst_inode = self.node_for(dst_dir, &dst);
+                        if !self.entries(dst_inode)?.is_empty() {
+                            return Err(FileSystemError::DirectoryNotEmpty.into());
+                        }
+                    }
+                    _ => (),
+                }
+
+                self.delete(&dst)?;
+            }
+        }
+
+        // a case-only rename would collide with the old entry, so it is removed first
+        if same_entry {
+            self.remove_entry(&src)?;
+        }
+
+        let offset = match self.add_entry(dst_dir, dst_name, src.raw) {
+            Ok(offset) => offset,
+            Err(err) => {
+                if same_entry {
+                    self.add_entry(src_dir, &src.name, src.raw)?;
+                }
+                return Err(err);
+            }
+        };
+
+        if !same_entry {
+            self.remove_entry(&src)?;
+        }
+
+        if is_dir && src_dir != dst_dir {
+            let mut dot_dot = [0; DIR_ENTRY_SIZE];
+            let dot_dot_offset = self.cluster_offset(src.first_cluster()) + DIR_ENTRY_SIZE as u64;
+            self.device.read_bytes(dot_dot_offset, &mut dot_dot)?;
+            set_entry_cluster(&mut dot_dot, self.dir_cluster(dst_dir)?);
+            self.device.write_bytes(dot_dot_offset, &dot_dot)?;
+        }
+
+        let node = self.node_mut(inode)?;
+        node.parent = dst_dir;
+        node.entry_offset = Some(offset);
+        Ok(())
+    }
+
+    fn truncate(&mut self, inode: InodeId, size: usize) -> Result<()> {
+        let node = self.node(inode)?.clone();
+        if node.is_dir() {
+            return Err(FileSystemError::IsDirectory.into());
+        }
+
+        let current = node.size as usize;
+        if size > current {
+            return self.fill_zero(inode, current, size);
+        }
+
+        let chain = self.chain(node.first_cluster)?;
+        let keep = size.div_ceil(self.cluster_size);
+        if keep < chain.len() {
+            if keep > 0 {
+                self.write_fat(chain[keep - 1], self.end_of_chain())?;
+            }
+            self.free_clusters(&chain[keep..])?;
+        }
+
+        let node = self.node_mut(inode)?;
+        node.size = size as u32;
+        if keep == 0 {
+            node.first_cluster = 0;
+        }
+        self.update_entry(inode)
+    }
+
+    fn sync(&mut self) -> Result<()> {
+        self.write_fsinfo()?;
+        self.device.sync()
+    }
+}
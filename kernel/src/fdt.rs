@@ -1,3 +1,9 @@
+pub const FDT_BEGIN_NODE: u32 = 0x1;
+pub const FDT_END_NODE: u32 = 0x2;
+pub const FDT_PROP: u32 = 0x3;
+pub const FDT_NOP: u32 = 0x4;
+pub const FDT_END: u32 = 0x9;
+
 #[derive(Debug, Clone, Copy)]
 #[repr(C)]
 pub struct FdtHeader {
@@ -22,6 +28,30 @@ impl FdtHeader {
         self.totalsize.to_be()
     }
 
+    pub fn off_dt_struct(&self) -> u32 {
+        self.off_dt_struct.to_be()
+    }
+
+    pub fn off_dt_strings(&self) -> u32 {
+        self.off_dt_strings.to_be()
+    }
+
+    pub fn off_mem_rsvmap(&self) -> u32 {
+        self.off_mem_rsvmap.to_be()
+    }
+
+    pub fn version(&self) -> u32 {
+        self.version.to_be()
+    }
+
+    pub fn size_dt_strings(&self) -> u32 {
+        self.size_dt_strings.to_be()
+    }
+
+    pub fn size_dt_struct(&self) -> u32 {
+        self.size_dt_struct.to_be()
+    }
+
     pub fn is_valid(&self) -> bool {
         self.magic() == 0xd00dfeed
     }
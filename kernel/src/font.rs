@@ -1,139 +1,360 @@
-use crate::error::Result;
+use crate::{
+    error::{Error, Result},
+    mutex::Mutex,
+    vfs,
+};
+use alloc::{boxed::Box, string::String, vec::Vec};
+use core::str;
 
-//PSF font v2
-const FONT_BIN: &'static [u8] = include_bytes!("../../third-party/font.psf");
-const FONT_MAGIC_NUM: u32 = 0x864ab572;
-const UNICODE_TABLE_SEPARATOR: u8 = 0xff;
+const BUILTIN_FONT: &[u8] = include_bytes!("../../third-party/font.psf");
+const BUILTIN_FONT_NAME: &str = "builtin";
+
+const PSF1_MAGIC: u16 = 0x0436;
+const PSF1_MODE_512: u8 = 0x01;
+const PSF1_MODE_HAS_TABLE: u8 = 0x02 | 0x04;
+const PSF1_HEADER_SIZE: usize = 4;
+const PSF1_TABLE_SEPARATOR: u16 = 0xffff;
+const PSF1_SEQUENCE_START: u16 = 0xfffe;
+
+const PSF2_MAGIC: u32 = 0x864ab572;
+const PSF2_FLAG_HAS_TABLE: u32 = 0x01;
+const PSF2_HEADER_SIZE: usize = 32;
+const PSF2_TABLE_SEPARATOR: u8 = 0xff;
+const PSF2_SEQUENCE_START: u8 = 0xfe;
+
+// larger glyphs are unlikely to be fonts
+const MAX_GLYPH_SIZE: usize = 64;
+
+const REPLACEMENT_CHARS: [char; 2] = ['\u{fffd}', '?'];
 
-pub static FONT: PsfFont = PsfFont::new();
 pub const TAB_DISP_STR: &str = "    ";
 
+// fonts are never unloaded, so they are handed out as static references
+static mut FONTS: Mutex<Vec<&'static PsfFont>> = Mutex::new(Vec::new());
+// used by the framebuffer text functions and the panic screen
+static mut DEFAULT_FONT: Mutex<Option<&'static PsfFont>> = Mutex::new(None);
+
+#[derive(Debug, Clone, Copy)]
+pub struct Glyph<'a> {
+    pub width: usize,
+    pub height: usize,
+    // rows of (width + 7) / 8 bytes, most significant bit first
+    pub data: &'a [u8],
+}
+
+impl Glyph<'_> {
+    pub fn is_set(&self, x: usize, y: usize) -> bool {
+        let row_bytes = self.width.div_ceil(8);
+        self.data[y * row_bytes + x / 8] & (0x80 >> (x % 8)) != 0
+    }
+}
+
+struct GlyphTable {
+    // sorted by character
+    chars: Vec<(char, usize)>,
+    sequences: Vec<(Vec<char>, usize)>,
+}
+
+impl GlyphTable {
+    const fn new() -> Self {
+        Self {
+            chars: Vec::new(),
+            sequences: Vec::new(),
+        }
+    }
+
+    fn add(&mut self, index: usize, sequence: &[char]) {
+        match sequence {
+            [] => (),
+            [c] => self.chars.push((*c, index)),
+            _ => self.sequences.push((sequence.to_vec(), index)),
+        }
+    }
+
+    // the first glyph wins for characters mapped more than once
+    fn finish(&mut self) {
+        self.chars.sort_by_key(|&(c, _)| c);
+        self.chars.dedup_by_key(|&mut (c, _)| c);
+    }
+
+    fn find(&self, c: char) -> Option<usize> {
+        let i = self.chars.binary_search_by_key(&c, |&(c, _)| c).ok()?;
+        Some(self.chars[i].1)
+    }
+}
+
 pub struct PsfFont {
-    binary_len: usize,
-    height: usize,
+    name: String,
+    data: &'static [u8],
     width: usize,
+    height: usize,
     glyphs_len: usize,
     glyph_size: usize,
-    has_unicode_table: bool,
-    header_size: usize,
-    unicode_table_offset: usize,
+    glyphs_offset: usize,
+    // None if glyphs are indexed by code point
+    table: Option<GlyphTable>,
+    // glyph for characters missing from the font
+    replacement: usize,
+}
+
+fn read_u16(data: &[u8], offset: usize) -> Option<u16> {
+    Some(u16::from_le_bytes(
+        data.get(offset..offset + 2)?.try_into().ok()?,
+    ))
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(
+        data.get(offset..offset + 4)?.try_into().ok()?,
+    ))
 }
 
 impl PsfFont {
-    const fn new() -> Self {
-        const fn get_magic_num() -> u32 {
-            (FONT_BIN[3] as u32) << 24
-                | (FONT_BIN[2] as u32) << 16
-                | (FONT_BIN[1] as u32) << 8
-                | FONT_BIN[0] as u32
-        }
+    pub fn parse(name: &str, data: &'static [u8]) -> Result<Self> {
+        let font = if read_u16(data, 0) == Some(PSF1_MAGIC) {
+            Self::parse_psf1(name, data)
+        } else if read_u32(data, 0) == Some(PSF2_MAGIC) {
+            Self::parse_psf2(name, data)
+        } else {
+            None
+        };
 
-        const fn get_pixel_height() -> u32 {
-            (FONT_BIN[27] as u32) << 24
-                | (FONT_BIN[26] as u32) << 16
-                | (FONT_BIN[25] as u32) << 8
-                | FONT_BIN[24] as u32
-        }
+        font.ok_or("Invalid PSF font".into())
+    }
 
-        const fn get_pixel_width() -> u32 {
-            (FONT_BIN[31] as u32) << 24
-                | (FONT_BIN[30] as u32) << 16
-                | (FONT_BIN[29] as u32) << 8
-                | FONT_BIN[28] as u32
-        }
+    // https://www.win.tue.nl/~aeb/linux/kbd/font-formats-1.html
+    fn parse_psf1(name: &str, data: &'static [u8]) -> Option<Self> {
+        let mode = *data.get(2)?;
+        let height = *data.get(3)? as usize;
+        let glyphs_len = if mode & PSF1_MODE_512 != 0 { 512 } else { 256 };
 
-        const fn get_glyphs_len() -> u32 {
-            (FONT_BIN[19] as u32) << 24
-                | (FONT_BIN[18] as u32) << 16
-                | (FONT_BIN[17] as u32) << 8
-                | FONT_BIN[16] as u32
+        let mut font = Self::new(name, data, 8, height, glyphs_len, height, PSF1_HEADER_SIZE)?;
+        if mode & PSF1_MODE_HAS_TABLE == 0 {
+            return Some(font);
         }
 
-        const fn get_glyph_size() -> u32 {
-            (FONT_BIN[23] as u32) << 24
-                | (FONT_BIN[22] as u32) << 16
-                | (FONT_BIN[21] as u32) << 8
-                | FONT_BIN[20] as u32
+        // u16 code points per glyph, sequences start with 0xfffe, terminated by 0xffff
+        let mut table = GlyphTable::new();
+        let mut offset = font.table_offset();
+        for index in 0..glyphs_len {
+            let mut sequence = Vec::new();
+            let mut in_sequence = false;
+            loop {
+                let value = read_u16(data, offset)?;
+                offset += 2;
+                match value {
+                    PSF1_TABLE_SEPARATOR | PSF1_SEQUENCE_START => {
+                        if in_sequence {
+                            table.add(index, &sequence);
+                        }
+                        sequence.clear();
+                        in_sequence = true;
+                        if value == PSF1_TABLE_SEPARATOR {
+                            break;
+                        }
+                    }
+                    value => match char::from_u32(value as u32) {
+                        Some(c) if in_sequence => sequence.push(c),
+                        Some(c) => table.add(index, &[c]),
+                        None => (),
+                    },
+                }
+            }
         }
 
-        const fn has_unicode_table() -> bool {
-            let flags = (FONT_BIN[15] as u32) << 24
-                | (FONT_BIN[14] as u32) << 16
-                | (FONT_BIN[13] as u32) << 8
-                | FONT_BIN[12] as u32;
+        font.set_table(table);
+        Some(font)
+    }
 
-            flags == 1
+    // https://www.win.tue.nl/~aeb/linux/kbd/font-formats-1.html
+    fn parse_psf2(name: &str, data: &'static [u8]) -> Option<Self> {
+        let header_size = read_u32(data, 8)? as usize;
+        let flags = read_u32(data, 12)?;
+        let glyphs_len = read_u32(data, 16)? as usize;
+        let glyph_size = read_u32(data, 20)? as usize;
+        let height = read_u32(data, 24)? as usize;
+        let width = read_u32(data, 28)? as usize;
+        if header_size < PSF2_HEADER_SIZE || glyph_size != height * width.div_ceil(8) {
+            return None;
         }
 
-        const fn get_header_size() -> u32 {
-            (FONT_BIN[11] as u32) << 24
-                | (FONT_BIN[10] as u32) << 16
-                | (FONT_BIN[9] as u32) << 8
-                | FONT_BIN[8] as u32
+        let mut font = Self::new(
+            name,
+            data,
+            width,
+            height,
+            glyphs_len,
+            glyph_size,
+            header_size,
+        )?;
+        if flags & PSF2_FLAG_HAS_TABLE == 0 {
+            return Some(font);
         }
 
-        if get_magic_num() != FONT_MAGIC_NUM {
-            panic!("Invalid font binary");
+        // UTF-8 characters per glyph, then sequences each starting with 0xfe, terminated by 0xff
+        let mut table = GlyphTable::new();
+        let entries = data[font.table_offset()..].split(|&b| b == PSF2_TABLE_SEPARATOR);
+        for (index, entry) in entries.take(glyphs_len).enumerate() {
+            let mut parts = entry.split(|&b| b == PSF2_SEQUENCE_START);
+
+            if let Some(chars) = parts.next().and_then(|part| str::from_utf8(part).ok()) {
+                for c in chars.chars() {
+                    table.add(index, &[c]);
+                }
+            }
+            for sequence in parts.filter_map(|part| str::from_utf8(part).ok()) {
+                table.add(index, &sequence.chars().collect::<Vec<_>>());
+            }
         }
 
-        let binary_len = FONT_BIN.len();
-        let height = get_pixel_height() as usize;
-        let width = get_pixel_width() as usize;
-        let glyphs_len = get_glyphs_len() as usize;
-        let glyph_size = get_glyph_size() as usize;
-        let has_unicode_table = has_unicode_table();
-        let header_size = get_header_size() as usize;
-        let unicode_table_offset = header_size + glyph_size * glyphs_len;
-
-        if height > 16 || width > 8 {
-            panic!("Unsupported font size");
+        font.set_table(table);
+        Some(font)
+    }
+
+    fn new(
+        name: &str,
+        data: &'static [u8],
+        width: usize,
+        height: usize,
+        glyphs_len: usize,
+        glyph_size: usize,
+        glyphs_offset: usize,
+    ) -> Option<Self> {
+        if width == 0 || height == 0 || glyphs_len == 0 {
+            return None;
+        }
+        if width > MAX_GLYPH_SIZE || height > MAX_GLYPH_SIZE {
+            return None;
+        }
+        if data.len() < glyphs_offset + glyph_size * glyphs_len {
+            return None;
         }
 
-        Self {
-            binary_len,
-            height,
+        Some(Self {
+            name: name.into(),
+            data,
             width,
+            height,
             glyphs_len,
             glyph_size,
-            has_unicode_table,
-            header_size,
-            unicode_table_offset,
-        }
+            glyphs_offset,
+            table: None,
+            replacement: '?' as usize % glyphs_len,
+        })
+    }
+
+    fn table_offset(&self) -> usize {
+        self.glyphs_offset + self.glyph_size * self.glyphs_len
+    }
+
+    fn set_table(&mut self, mut table: GlyphTable) {
+        table.finish();
+        self.replacement = REPLACEMENT_CHARS
+            .iter()
+            .find_map(|&c| table.find(c))
+            .unwrap_or(0);
+        self.table = Some(table);
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
     }
 
     pub fn get_wh(&self) -> (usize, usize) {
         (self.width, self.height)
     }
 
-    // ascii char only
-    fn unicode_char_to_glyph_index(&self, c: char) -> usize {
-        if !self.has_unicode_table {
-            return c as usize;
+    pub fn glyphs_len(&self) -> usize {
+        self.glyphs_len
+    }
+
+    fn glyph_at(&self, index: usize) -> Glyph<'static> {
+        let offset = self.glyphs_offset + self.glyph_size * index;
+        Glyph {
+            width: self.width,
+            height: self.height,
+            data: &self.data[offset..offset + self.glyph_size],
         }
+    }
 
-        let code_point = c as u8;
-        let mut index = 0;
+    pub fn glyph(&self, c: char) -> Glyph<'static> {
+        let index = match &self.table {
+            Some(table) => table.find(c),
+            None => Some(c as usize).filter(|&index| index < self.glyphs_len),
+        };
+        self.glyph_at(index.unwrap_or(self.replacement))
+    }
 
-        for i in self.unicode_table_offset..self.binary_len {
-            if code_point == FONT_BIN[i] {
-                break;
-            }
+    // glyph for a character sequence such as a letter followed by a combining accent
+    pub fn sequence_glyph(&self, chars: &[char]) -> Option<Glyph<'static>> {
+        let table = self.table.as_ref()?;
+        let index = table.sequences.iter().find(|(s, _)| s == chars)?.1;
+        Some(self.glyph_at(index))
+    }
+}
 
-            if FONT_BIN[i] == UNICODE_TABLE_SEPARATOR {
-                index += 1;
-            }
-        }
+// the first registered font becomes the default one
+pub fn register(font: PsfFont) -> Result<&'static PsfFont> {
+    let mut fonts = unsafe { FONTS.try_lock() }?;
+    if fonts.iter().any(|f| f.name == font.name) {
+        return Err("Font already registered".into());
+    }
+
+    let font: &'static PsfFont = Box::leak(Box::new(font));
+    fonts.push(font);
 
-        index
+    let mut default_font = unsafe { DEFAULT_FONT.try_lock() }?;
+    if default_font.is_none() {
+        *default_font = Some(font);
     }
 
-    pub fn get_glyph(&self, c: char) -> Result<&'static [u8]> {
-        let index = self.unicode_char_to_glyph_index(c);
-        if index > self.glyphs_len {
-            return Err("Invalid glyph index".into());
-        }
+    Ok(font)
+}
+
+// the file is kept in memory for as long as the font is registered, and freed again if
+// it is not a valid font
+pub fn load(path: &str, name: &str) -> Result<&'static PsfFont> {
+    let ptr = Box::into_raw(vfs::read_to_end(path)?.into_boxed_slice());
+    let data: &'static [u8] = unsafe { &*ptr };
 
-        let offset = self.header_size + self.glyph_size * index;
-        Ok(&FONT_BIN[offset..offset + self.glyph_size])
+    match PsfFont::parse(name, data).and_then(register) {
+        Ok(font) => Ok(font),
+        Err(err) => {
+            // the font that referred to the data has been dropped
+            drop(unsafe { Box::from_raw(ptr) });
+            Err(err)
+        }
     }
 }
+
+pub fn get(name: &str) -> Result<&'static PsfFont> {
+    let fonts = unsafe { FONTS.try_lock() }?;
+    fonts
+        .iter()
+        .find(|f| f.name == name)
+        .copied()
+        .ok_or("Font not found".into())
+}
+
+pub fn list() -> Result<Vec<&'static PsfFont>> {
+    Ok(unsafe { FONTS.try_lock() }?.clone())
+}
+
+pub fn default_font() -> Result<&'static PsfFont> {
+    unsafe { DEFAULT_FONT.try_lock() }?.ok_or(Error::NotInitialized)
+}
+
+pub fn set_default_font(font: &'static PsfFont) -> Result<()> {
+    *unsafe { DEFAULT_FONT.try_lock() }? = Some(font);
+    Ok(())
+}
+
+// for the panic screen
+pub unsafe fn force_default_font() -> Option<&'static PsfFont> {
+    *DEFAULT_FONT.force_lock()
+}
+
+pub fn init() -> Result<()> {
+    register(PsfFont::parse(BUILTIN_FONT_NAME, BUILTIN_FONT)?)?;
+    Ok(())
+}
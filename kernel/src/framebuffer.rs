@@ -1,11 +1,13 @@
 use crate::{
     addr::VirtualAddress,
     color::ColorCode,
-    draw::Draw,
+    draw::{Draw, Rect},
     error::{Error, Result},
-    font::{FONT, TAB_DISP_STR},
+    font::Glyph,
+    mailbox,
     mutex::Mutex,
 };
+use core::{ptr, slice};
 
 static mut FB: Mutex<Framebuffer> = Mutex::new(Framebuffer::new());
 
@@ -49,27 +51,193 @@ pub struct FramebufferInfo {
     pub pixel_format: PixelFormat,
     pub buf_base: VirtualAddress,
     pub buf_size: usize,
+    // bytes per row
+    pub pitch: usize,
+}
+
+impl FramebufferInfo {
+    pub fn bytes_per_pixel(&self) -> usize {
+        self.depth.div_ceil(8)
+    }
 }
 
 struct Framebuffer {
     info: Option<FramebufferInfo>,
+    // visible page while double buffering, pages are stacked in the virtual framebuffer
+    front_page: Option<usize>,
+    // top of the visible area in the virtual framebuffer
+    visible_y: usize,
 }
 
 impl Framebuffer {
     const fn new() -> Self {
-        Self { info: None }
+        Self {
+            info: None,
+            front_page: None,
+            visible_y: 0,
+        }
     }
 
     fn info(&self) -> Result<FramebufferInfo> {
         self.info.ok_or(Error::NotInitialized)
     }
 
-    fn init(&mut self, info: FramebufferInfo) {
+    fn init(&mut self, info: FramebufferInfo) -> Result<()> {
+        if !matches!(info.depth, 8 | 16 | 24 | 32) {
+            return Err("Unsupported framebuffer depth".into());
+        }
+
         self.info = Some(info);
+        self.front_page = None;
+        self.visible_y = 0;
+        Ok(())
+    }
+
+    fn set_visible_y(&mut self, y: usize) -> Result<()> {
+        let info = self.info()?;
+        if y + info.p_height > info.v_height {
+            return Err(Error::InvalidArgument);
+        }
+
+        mailbox::set_virtual_offset(0, y as u32)?;
+        self.visible_y = y;
+        Ok(())
+    }
+
+    fn visible_rect(&self) -> Result<Rect> {
+        let info = self.info()?;
+        Ok(Rect::new(0, self.visible_y, info.p_width, info.p_height))
+    }
+
+    fn start_double_buffering(&mut self) -> Result<()> {
+        let info = self.info()?;
+        if info.v_height < info.p_height * 2 {
+            return Err("Virtual framebuffer too small for two pages".into());
+        }
+
+        self.set_visible_y(0)?;
+        self.front_page = Some(0);
+        Ok(())
+    }
+
+    fn stop_double_buffering(&mut self) -> Result<()> {
+        if self.front_page.take().is_some() {
+            self.set_visible_y(0)?;
+        }
+
+        Ok(())
+    }
+
+    fn back_page(&mut self) -> Result<Page<'_>> {
+        let info = self.info()?;
+        let front_page = self.front_page.ok_or(Error::NotInitialized)?;
+        Ok(Page {
+            y: (front_page + 1) % 2 * info.p_height,
+            info,
+            fb: self,
+        })
+    }
+
+    // the firmware switches the visible area at the next vertical blank, waiting for it keeps the
+    // old front page on screen until then so that it is not drawn into while being displayed
+    fn flip(&mut self, wait_vsync: bool) -> Result<()> {
+        let info = self.info()?;
+        let front_page = self.front_page.ok_or(Error::NotInitialized)?;
+        let back_page = (front_page + 1) % 2;
+
+        self.set_visible_y(back_page * info.p_height)?;
+        self.front_page = Some(back_page);
+        if wait_vsync {
+            mailbox::wait_for_vsync()?;
+        }
+
+        Ok(())
+    }
+
+    fn check_rect(
+        info: &FramebufferInfo,
+        x: usize,
+        y: usize,
+        width: usize,
+        height: usize,
+    ) -> Result<()> {
+        // overflowing sums are out of range as well
+        if !x
+            .checked_add(width)
+            .is_some_and(|right| right <= info.v_width)
+            || !y
+                .checked_add(height)
+                .is_some_and(|bottom| bottom <= info.v_height)
+        {
+            return Err(FramebufferError::PositionOutOfRange {
+                x: x.saturating_add(width),
+                y: y.saturating_add(height),
+            }
+            .into());
+        }
+
+        Ok(())
+    }
+
+    fn pixel_ptr(info: &FramebufferInfo, x: usize, y: usize) -> *mut u8 {
+        let offset = y * info.pitch + x * info.bytes_per_pixel();
+        info.buf_base.offset(offset).as_ptr_mut()
+    }
+
+    unsafe fn read_pixel(info: &FramebufferInfo, ptr: *const u8) -> ColorCode {
+        let value = match info.depth {
+            8 => *ptr as u32,
+            16 => *(ptr as *const u16) as u32,
+            24 => u32::from_le_bytes([*ptr, *ptr.add(1), *ptr.add(2), 0]),
+            _ => *(ptr as *const u32),
+        };
+        ColorCode::from_pixel(value, info.depth, info.pixel_format)
+    }
+
+    unsafe fn write_pixel(info: &FramebufferInfo, ptr: *mut u8, color: ColorCode) {
+        let value = color.to_pixel(info.depth, info.pixel_format);
+        match info.depth {
+            8 => *ptr = value as u8,
+            16 => *(ptr as *mut u16) = value as u16,
+            24 => ptr::copy_nonoverlapping(value.to_le_bytes().as_ptr(), ptr, 3),
+            _ => *(ptr as *mut u32) = value,
+        }
+    }
+
+    fn buf_slice_mut(&mut self) -> Result<&mut [u8]> {
+        let info = self.info()?;
+        Ok(unsafe { slice::from_raw_parts_mut(info.buf_base.as_ptr_mut(), info.buf_size) })
+    }
+
+    fn read_raw(&mut self, offset: usize, buf: &mut [u8]) -> Result<usize> {
+        let fb_buf = self.buf_slice_mut()?;
+        if offset >= fb_buf.len() {
+            return Ok(0);
+        }
+
+        let len = buf.len().min(fb_buf.len() - offset);
+        buf[..len].copy_from_slice(&fb_buf[offset..offset + len]);
+        Ok(len)
+    }
+
+    fn write_raw(&mut self, offset: usize, buf: &[u8]) -> Result<usize> {
+        let fb_buf = self.buf_slice_mut()?;
+        if offset >= fb_buf.len() {
+            return Ok(0);
+        }
+
+        let len = buf.len().min(fb_buf.len() - offset);
+        fb_buf[offset..offset + len].copy_from_slice(&buf[..len]);
+        Ok(len)
     }
 }
 
 impl Draw for Framebuffer {
+    fn bounds(&self) -> Result<Rect> {
+        let info = self.info()?;
+        Ok(Rect::new(0, 0, info.v_width, info.v_height))
+    }
+
     fn draw_rect(
         &mut self,
         x: usize,
@@ -87,105 +255,231 @@ impl Draw for Framebuffer {
         Ok(())
     }
 
-    fn draw_string(
+    fn fill(&mut self, color: ColorCode) -> Result<()> {
+        let info = self.info()?;
+
+        for y in 0..info.v_height {
+            for x in 0..info.v_width {
+                self.write(x, y, color)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn copy(&mut self, x: usize, y: usize, to_x: usize, to_y: usize) -> Result<()> {
+        let color = self.read(x, y)?;
+        self.write(to_x, to_y, color)
+    }
+
+    fn read(&self, x: usize, y: usize) -> Result<ColorCode> {
+        let info = self.info()?;
+        if x >= info.v_width || y >= info.v_height {
+            return Err(FramebufferError::PositionOutOfRange { x, y }.into());
+        }
+
+        Ok(unsafe { Self::read_pixel(&info, Self::pixel_ptr(&info, x, y)) })
+    }
+
+    fn write(&mut self, x: usize, y: usize, color: ColorCode) -> Result<()> {
+        let info = self.info()?;
+        if x >= info.v_width || y >= info.v_height {
+            return Err(FramebufferError::PositionOutOfRange { x, y }.into());
+        }
+
+        unsafe { Self::write_pixel(&info, Self::pixel_ptr(&info, x, y), color) }
+
+        Ok(())
+    }
+
+    // rows are moved as a whole, in the order that keeps overlapping source rows intact
+    fn copy_rect(
         &mut self,
         x: usize,
         y: usize,
-        s: &str,
-        fore_color: ColorCode,
-        back_color: ColorCode,
+        width: usize,
+        height: usize,
+        to_x: usize,
+        to_y: usize,
     ) -> Result<()> {
-        let (font_width, font_height) = FONT.get_wh();
-
-        let mut char_x = x;
-        let mut char_y = y;
-
-        for c in s.chars() {
-            match c {
-                '\n' => {
-                    char_y += font_height;
-                    continue;
-                }
-                '\t' => {
-                    for c in TAB_DISP_STR.chars() {
-                        self.draw_font(char_x, char_y, c, fore_color, back_color)?;
-                        char_x += font_width;
-                    }
-                }
-                _ => (),
-            }
-
-            self.draw_font(char_x, char_y, c, fore_color, back_color)?;
-            char_x += font_width;
+        let info = self.info()?;
+        Self::check_rect(&info, x, y, width, height)?;
+        Self::check_rect(&info, to_x, to_y, width, height)?;
+
+        for row in 0..height {
+            let row = if to_y > y { height - 1 - row } else { row };
+            let src = Self::pixel_ptr(&info, x, y + row);
+            let dst = Self::pixel_ptr(&info, to_x, to_y + row);
+            unsafe { ptr::copy(src, dst, width * info.bytes_per_pixel()) };
         }
 
         Ok(())
     }
 
-    fn draw_font(
+    fn blit(
         &mut self,
         x: usize,
         y: usize,
-        c: char,
-        fore_color: ColorCode,
-        back_color: ColorCode,
+        width: usize,
+        height: usize,
+        pixels: &[ColorCode],
     ) -> Result<()> {
-        let glyph = FONT.get_glyph(c)?;
-        let (font_width, font_height) = FONT.get_wh();
-
-        for h in 0..font_height {
-            for w in 0..font_width {
-                let color = if (glyph[h] << w) & 0x80 == 0x80 {
-                    fore_color
-                } else {
-                    back_color
-                };
-                self.draw_rect(x + w, y + h, 1, 1, color)?;
+        let info = self.info()?;
+        Self::check_rect(&info, x, y, width, height)?;
+        if pixels.len() < width * height {
+            return Err(Error::InvalidArgument);
+        }
+        if width == 0 {
+            return Ok(());
+        }
+
+        for (row, colors) in pixels.chunks_exact(width).take(height).enumerate() {
+            let dst = Self::pixel_ptr(&info, x, y + row);
+            for (col, &color) in colors.iter().enumerate() {
+                unsafe { Self::write_pixel(&info, dst.add(col * info.bytes_per_pixel()), color) };
             }
         }
 
         Ok(())
     }
 
-    fn fill(&mut self, color: ColorCode) -> Result<()> {
+    fn read_rect(
+        &self,
+        x: usize,
+        y: usize,
+        width: usize,
+        height: usize,
+        pixels: &mut [ColorCode],
+    ) -> Result<()> {
         let info = self.info()?;
+        Self::check_rect(&info, x, y, width, height)?;
+        if pixels.len() < width * height {
+            return Err(Error::InvalidArgument);
+        }
+        if width == 0 {
+            return Ok(());
+        }
 
-        for y in 0..info.v_height {
-            for x in 0..info.v_width {
-                self.write(x, y, color)?;
+        for (row, colors) in pixels.chunks_exact_mut(width).take(height).enumerate() {
+            let src = Self::pixel_ptr(&info, x, y + row);
+            for (col, color) in colors.iter_mut().enumerate() {
+                *color = unsafe { Self::read_pixel(&info, src.add(col * info.bytes_per_pixel())) };
             }
         }
 
         Ok(())
     }
+}
+
+// a screen sized area of the framebuffer starting at row y
+struct Page<'a> {
+    fb: &'a mut Framebuffer,
+    info: FramebufferInfo,
+    y: usize,
+}
+
+impl Page<'_> {
+    fn check_rect(&self, x: usize, y: usize, width: usize, height: usize) -> Result<()> {
+        // overflowing sums are out of range as well
+        if !x
+            .checked_add(width)
+            .is_some_and(|right| right <= self.info.p_width)
+            || !y
+                .checked_add(height)
+                .is_some_and(|bottom| bottom <= self.info.p_height)
+        {
+            return Err(FramebufferError::PositionOutOfRange {
+                x: x.saturating_add(width),
+                y: y.saturating_add(height),
+            }
+            .into());
+        }
+
+        Ok(())
+    }
+}
+
+impl Draw for Page<'_> {
+    fn bounds(&self) -> Result<Rect> {
+        Ok(Rect::new(0, 0, self.info.p_width, self.info.p_height))
+    }
+
+    fn draw_rect(
+        &mut self,
+        x: usize,
+        y: usize,
+        width: usize,
+        height: usize,
+        color: ColorCode,
+    ) -> Result<()> {
+        self.check_rect(x, y, width, height)?;
+        self.fb.draw_rect(x, self.y + y, width, height, color)
+    }
 
-    fn copy(&mut self, _x: usize, _y: usize, _to_x: usize, _to_y: usize) -> Result<()> {
-        unimplemented!()
+    fn fill(&mut self, color: ColorCode) -> Result<()> {
+        self.fb
+            .draw_rect(0, self.y, self.info.p_width, self.info.p_height, color)
+    }
+
+    fn copy(&mut self, x: usize, y: usize, to_x: usize, to_y: usize) -> Result<()> {
+        self.check_rect(x, y, 1, 1)?;
+        self.check_rect(to_x, to_y, 1, 1)?;
+        self.fb.copy(x, self.y + y, to_x, self.y + to_y)
     }
 
-    fn read(&self, _x: usize, _y: usize) -> Result<ColorCode> {
-        unimplemented!()
+    fn read(&self, x: usize, y: usize) -> Result<ColorCode> {
+        self.check_rect(x, y, 1, 1)?;
+        self.fb.read(x, self.y + y)
     }
 
     fn write(&mut self, x: usize, y: usize, color: ColorCode) -> Result<()> {
-        let info = self.info()?;
-        let offset = (y * info.v_width + x) * 4;
-        let buf_ptr: *mut u32 = info.buf_base.offset(offset).as_ptr_mut();
+        self.check_rect(x, y, 1, 1)?;
+        self.fb.write(x, self.y + y, color)
+    }
 
-        if x >= info.v_width || y >= info.v_height {
-            return Err(FramebufferError::PositionOutOfRange { x, y }.into());
-        }
+    fn copy_rect(
+        &mut self,
+        x: usize,
+        y: usize,
+        width: usize,
+        height: usize,
+        to_x: usize,
+        to_y: usize,
+    ) -> Result<()> {
+        self.check_rect(x, y, width, height)?;
+        self.check_rect(to_x, to_y, width, height)?;
+        self.fb
+            .copy_rect(x, self.y + y, width, height, to_x, self.y + to_y)
+    }
 
-        unsafe { *buf_ptr = color.to_u32(info.pixel_format) }
+    fn blit(
+        &mut self,
+        x: usize,
+        y: usize,
+        width: usize,
+        height: usize,
+        pixels: &[ColorCode],
+    ) -> Result<()> {
+        self.check_rect(x, y, width, height)?;
+        self.fb.blit(x, self.y + y, width, height, pixels)
+    }
 
-        Ok(())
+    fn read_rect(
+        &self,
+        x: usize,
+        y: usize,
+        width: usize,
+        height: usize,
+        pixels: &mut [ColorCode],
+    ) -> Result<()> {
+        self.check_rect(x, y, width, height)?;
+        self.fb.read_rect(x, self.y + y, width, height, pixels)
     }
 }
 
 pub fn init(info: FramebufferInfo) -> Result<()> {
     let mut fb = unsafe { FB.try_lock() }?;
-    fb.init(info);
-    Ok(())
+    fb.init(info)
 }
 
 pub fn fill(color: ColorCode) -> Result<()> {
@@ -209,7 +503,116 @@ pub fn draw_font(
     fb.draw_font(x, y, c, fore_color, back_color)
 }
 
+pub fn read(x: usize, y: usize) -> Result<ColorCode> {
+    let fb = unsafe { FB.try_lock() }?;
+    fb.read(x, y)
+}
+
+pub fn copy_rect(
+    x: usize,
+    y: usize,
+    width: usize,
+    height: usize,
+    to_x: usize,
+    to_y: usize,
+) -> Result<()> {
+    let mut fb = unsafe { FB.try_lock() }?;
+    fb.copy_rect(x, y, width, height, to_x, to_y)
+}
+
+pub fn blit(x: usize, y: usize, width: usize, height: usize, pixels: &[ColorCode]) -> Result<()> {
+    let mut fb = unsafe { FB.try_lock() }?;
+    fb.blit(x, y, width, height, pixels)
+}
+
+pub fn read_rect(
+    x: usize,
+    y: usize,
+    width: usize,
+    height: usize,
+    pixels: &mut [ColorCode],
+) -> Result<()> {
+    let fb = unsafe { FB.try_lock() }?;
+    fb.read_rect(x, y, width, height, pixels)
+}
+
+pub fn draw_glyph(
+    x: usize,
+    y: usize,
+    glyph: &Glyph,
+    fore_color: ColorCode,
+    back_color: ColorCode,
+) -> Result<()> {
+    let mut fb = unsafe { FB.try_lock() }?;
+    fb.draw_glyph(x, y, glyph, fore_color, back_color)
+}
+
+pub fn draw<R>(func: impl FnOnce(&mut dyn Draw, FramebufferInfo) -> R) -> Result<R> {
+    let mut fb = unsafe { FB.try_lock() }?;
+    let info = fb.info()?;
+    Ok(func(&mut *fb, info))
+}
+
+// draws without waiting for the framebuffer lock, for the panic screen
+pub unsafe fn force_draw<R>(func: impl FnOnce(&mut dyn Draw, FramebufferInfo) -> R) -> Option<R> {
+    let mut fb = FB.force_lock();
+    let info = fb.info().ok()?;
+    Some(func(&mut *fb, info))
+}
+
 pub fn get_info() -> Result<FramebufferInfo> {
     let fb = unsafe { FB.try_lock() }?;
     fb.info()
 }
+
+pub fn read_raw(offset: usize, buf: &mut [u8]) -> Result<usize> {
+    let mut fb = unsafe { FB.try_lock() }?;
+    fb.read_raw(offset, buf)
+}
+
+pub fn write_raw(offset: usize, buf: &[u8]) -> Result<usize> {
+    let mut fb = unsafe { FB.try_lock() }?;
+    fb.write_raw(offset, buf)
+}
+
+// moves the visible area within the virtual framebuffer
+pub fn set_visible_y(y: usize) -> Result<()> {
+    let mut fb = unsafe { FB.try_lock() }?;
+    fb.set_visible_y(y)
+}
+
+pub fn visible_rect() -> Result<Rect> {
+    let fb = unsafe { FB.try_lock() }?;
+    fb.visible_rect()
+}
+
+// pages the screen between the top two screen heights of the virtual framebuffer, the
+// framebuffer console stops drawing until double buffering is stopped
+pub fn start_double_buffering() -> Result<()> {
+    let mut fb = unsafe { FB.try_lock() }?;
+    fb.start_double_buffering()
+}
+
+pub fn stop_double_buffering() -> Result<()> {
+    let mut fb = unsafe { FB.try_lock() }?;
+    fb.stop_double_buffering()
+}
+
+pub fn is_double_buffering() -> Result<bool> {
+    let fb = unsafe { FB.try_lock() }?;
+    Ok(fb.front_page.is_some())
+}
+
+// draws into the page that is not visible, with coordinates relative to the page
+pub fn draw_back<R>(func: impl FnOnce(&mut dyn Draw, FramebufferInfo) -> R) -> Result<R> {
+    let mut fb = unsafe { FB.try_lock() }?;
+    let mut page = fb.back_page()?;
+    let info = page.info;
+    Ok(func(&mut page, info))
+}
+
+// shows the back page
+pub fn flip(wait_vsync: bool) -> Result<()> {
+    let mut fb = unsafe { FB.try_lock() }?;
+    fb.flip(wait_vsync)
+}
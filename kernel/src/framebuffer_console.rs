@@ -1,131 +1,636 @@
 use crate::{
+    ansi::{Action, Params, Parser},
     color::ColorCode,
+    console::{self, ConsoleBackend},
     error::{Error, Result},
-    font::{FONT, TAB_DISP_STR},
+    font::{self, PsfFont, TAB_DISP_STR},
     framebuffer::{self, FramebufferInfo},
     mutex::Mutex,
+    timer,
 };
+use alloc::{boxed::Box, collections::VecDeque, vec::Vec};
 use core::fmt::{self, Write};
 
 static mut FB_CONSOLE: Mutex<FramebufferConsole> = Mutex::new(FramebufferConsole::new());
 
+const SCROLLBACK_LINES: usize = 1000;
+const CURSOR_BLINK_MS: u64 = 500;
+
+#[derive(Debug, Clone, Copy)]
+struct Cell {
+    c: char,
+    // combining character drawn with c if the font has a glyph for the sequence
+    mark: Option<char>,
+    fore_color: ColorCode,
+    back_color: ColorCode,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Color {
+    Default,
+    Ansi(u8),
+    Rgb(ColorCode),
+}
+
+// graphic rendition set by SGR sequences
+#[derive(Debug, Clone, Copy)]
+struct Attributes {
+    fore: Color,
+    back: Color,
+    bold: bool,
+    inverse: bool,
+}
+
+impl Attributes {
+    const fn new() -> Self {
+        Self {
+            fore: Color::Default,
+            back: Color::Default,
+            bold: false,
+            inverse: false,
+        }
+    }
+}
+
 struct FramebufferConsole {
+    info: Option<FramebufferInfo>,
+    font: Option<&'static PsfFont>,
+    cols: usize,
+    rows: usize,
     cursor_x: usize,
     cursor_y: usize,
-    fb_width: Option<usize>,
-    fb_height: Option<usize>,
+    saved_cursor: (usize, usize, Attributes),
     fore_color: ColorCode,
     back_color: ColorCode,
+    attrs: Attributes,
+    parser: Parser,
+    cursor_visible: bool,
+    cursor_drawn: bool,
+    // scrollback followed by the screen, which is always the last rows lines
+    lines: VecDeque<Vec<Cell>>,
+    // number of lines the view is scrolled back from the bottom
+    view_offset: usize,
+    // top of the screen in the virtual framebuffer, the visible area is moved down instead of
+    // copying the screen while the virtual height allows
+    origin_y: usize,
+    // nothing is drawn while a program flips framebuffer pages
+    suspended: bool,
 }
 
 impl FramebufferConsole {
     const fn new() -> Self {
         Self {
+            info: None,
+            font: None,
+            cols: 0,
+            rows: 0,
             cursor_x: 0,
             cursor_y: 0,
-            fb_width: None,
-            fb_height: None,
+            saved_cursor: (0, 0, Attributes::new()),
             fore_color: ColorCode::default(),
             back_color: ColorCode::default(),
+            attrs: Attributes::new(),
+            parser: Parser::new(),
+            cursor_visible: true,
+            cursor_drawn: false,
+            lines: VecDeque::new(),
+            view_offset: 0,
+            origin_y: 0,
+            suspended: false,
         }
     }
 
-    fn fb_wh(&self) -> Result<(usize, usize)> {
-        Ok((
-            self.fb_width.ok_or(Error::NotInitialized)?,
-            self.fb_height.ok_or(Error::NotInitialized)?,
-        ))
+    fn info(&self) -> Result<FramebufferInfo> {
+        self.info.ok_or(Error::NotInitialized)
+    }
+
+    fn font(&self) -> Result<&'static PsfFont> {
+        self.font.ok_or(Error::NotInitialized)
+    }
+
+    // the screen size in characters for the font, None if a character does not fit
+    fn grid(info: &FramebufferInfo, font: &PsfFont) -> Option<(usize, usize)> {
+        let (font_width, font_height) = font.get_wh();
+        let (cols, rows) = (info.p_width / font_width, info.p_height / font_height);
+        (cols != 0 && rows != 0).then_some((cols, rows))
     }
 
     fn init(&mut self, fore_color: ColorCode, back_color: ColorCode) -> Result<()> {
         self.fore_color = fore_color;
         self.back_color = back_color;
 
-        let FramebufferInfo {
-            p_width: _,
-            p_height: _,
-            v_width,
-            v_height,
-            ..
-        } = framebuffer::get_info()?;
-        self.fb_width = Some(v_width as usize);
-        self.fb_height = Some(v_height as usize);
+        let mut info = framebuffer::get_info()?;
+        // scroll by copying if the firmware cannot move the visible area
+        if framebuffer::set_visible_y(0).is_err() {
+            info.v_height = info.p_height;
+        }
+
+        let font = match self.font {
+            Some(font) => font,
+            None => font::default_font()?,
+        };
+        (self.cols, self.rows) = Self::grid(&info, font).ok_or(Error::InvalidArgument)?;
+
+        self.info = Some(info);
+        self.font = Some(font);
+        self.cursor_x = 0;
+        self.cursor_y = 0;
+        self.attrs = Attributes::new();
+        self.parser = Parser::new();
+        self.cursor_drawn = false;
+        self.lines.clear();
+        self.lines.resize(self.rows, Vec::new());
+        self.view_offset = 0;
+        self.origin_y = 0;
+        self.suspended = false;
+
+        self.clear_rows(0)
+    }
+
+    // the text is kept up to date while suspended and redrawn once double buffering stops
+    fn update_suspended(&mut self) -> Result<()> {
+        if framebuffer::is_double_buffering()? {
+            self.suspended = true;
+            self.cursor_drawn = false;
+        } else if self.suspended {
+            self.suspended = false;
+            self.origin_y = 0;
+            framebuffer::set_visible_y(0)?;
+            self.clear_rows(0)?;
+            self.redraw()?;
+        }
 
         Ok(())
     }
 
-    fn swap_color(&mut self) {
-        if !(self.cursor_x == 0 && self.cursor_y == 0) {
-            return;
+    // colours of new cells, bold brightens the standard colours
+    fn colors(&self) -> (ColorCode, ColorCode) {
+        let resolve = |color, default, bold| match color {
+            Color::Default => default,
+            Color::Ansi(index) if bold && index < 8 => ColorCode::from_ansi(index + 8),
+            Color::Ansi(index) => ColorCode::from_ansi(index),
+            Color::Rgb(color) => color,
+        };
+
+        let fore = resolve(self.attrs.fore, self.fore_color, self.attrs.bold);
+        let back = resolve(self.attrs.back, self.back_color, false);
+        if self.attrs.inverse {
+            (back, fore)
+        } else {
+            (fore, back)
         }
+    }
 
-        let tmp = self.back_color;
-        self.back_color = self.fore_color;
-        self.fore_color = tmp;
+    fn blank(&self) -> Cell {
+        let (fore_color, back_color) = self.colors();
+        Cell {
+            c: ' ',
+            mark: None,
+            fore_color,
+            back_color,
+        }
     }
 
-    fn inc_cursor(&mut self) -> Result<()> {
-        let (fb_width, fb_height) = self.fb_wh()?;
-        let (font_width, font_height) = FONT.get_wh();
+    fn line_mut(&mut self, row: usize) -> &mut Vec<Cell> {
+        let index = self.lines.len() - self.rows + row;
+        &mut self.lines[index]
+    }
 
-        self.cursor_x += 1;
+    // clears from the row to the bottom of the visible area
+    fn clear_rows(&self, row: usize) -> Result<()> {
+        if self.suspended {
+            return Ok(());
+        }
 
-        if (self.cursor_x + 1) * font_width >= fb_width {
-            self.cursor_x = 0;
-            self.cursor_y += 1;
-            if (self.cursor_y + 1) * font_height >= fb_height {
-                self.cursor_y = 0;
-            }
+        let info = self.info()?;
+        let (_, font_height) = self.font()?.get_wh();
+        let y = row * font_height;
+        framebuffer::draw_rect(
+            0,
+            self.origin_y + y,
+            info.p_width,
+            info.p_height.saturating_sub(y),
+            self.back_color,
+        )
+    }
+
+    fn draw_cell(&self, col: usize, row: usize, cell: &Cell) -> Result<()> {
+        if self.suspended {
+            return Ok(());
+        }
+
+        let (font_width, font_height) = self.font()?.get_wh();
+        let (x, y) = (col * font_width, self.origin_y + row * font_height);
+
+        let font = self.font()?;
+        let glyph = cell
+            .mark
+            .and_then(|mark| font.sequence_glyph(&[cell.c, mark]))
+            .unwrap_or_else(|| font.glyph(cell.c));
+        framebuffer::draw_glyph(x, y, &glyph, cell.fore_color, cell.back_color)
+    }
+
+    // draws a row of the screen as shown by the current view
+    fn draw_row(&self, row: usize) -> Result<()> {
+        if self.suspended {
+            return Ok(());
+        }
+
+        let info = self.info()?;
+        let (font_width, font_height) = self.font()?.get_wh();
+        let line = &self.lines[self.lines.len() - self.view_offset - self.rows + row];
+        // lines written with a smaller font may be wider than the screen
+        let len = line.len().min(self.cols);
+
+        for (col, cell) in line[..len].iter().enumerate() {
+            self.draw_cell(col, row, cell)?;
+        }
+        framebuffer::draw_rect(
+            len * font_width,
+            self.origin_y + row * font_height,
+            info.p_width - len * font_width,
+            font_height,
+            self.back_color,
+        )
+    }
+
+    fn redraw(&mut self) -> Result<()> {
+        self.cursor_drawn = false;
+        for row in 0..self.rows {
+            self.draw_row(row)?;
         }
 
-        self.swap_color();
         Ok(())
     }
 
-    fn inc_cursor_new_line(&mut self) -> Result<()> {
-        let (_, fb_height) = self.fb_wh()?;
-        let (_, font_height) = FONT.get_wh();
+    // the cursor is drawn as the cell under it with swapped colours
+    fn draw_cursor(&mut self, visible: bool) -> Result<()> {
+        let col = self.cursor_x.min(self.cols - 1);
+        let row = self.cursor_y;
+        let blank = Cell {
+            c: ' ',
+            mark: None,
+            fore_color: self.fore_color,
+            back_color: self.back_color,
+        };
+        let cell = self.line_mut(row).get(col).copied().unwrap_or(blank);
 
-        self.cursor_x = 0;
-        self.cursor_y += 1;
+        self.cursor_drawn = visible;
+        if visible {
+            let inverted = Cell {
+                fore_color: cell.back_color,
+                back_color: cell.fore_color,
+                ..cell
+            };
+            self.draw_cell(col, row, &inverted)
+        } else {
+            self.draw_cell(col, row, &cell)
+        }
+    }
 
-        if (self.cursor_y + 1) * font_height >= fb_height {
-            self.cursor_y = 0;
+    fn hide_cursor(&mut self) -> Result<()> {
+        if self.cursor_drawn {
+            self.draw_cursor(false)?;
         }
 
-        self.swap_color();
         Ok(())
     }
 
+    fn blink_cursor(&mut self) -> Result<()> {
+        self.info()?;
+        self.update_suspended()?;
+
+        if !self.cursor_visible || self.view_offset != 0 {
+            return self.hide_cursor();
+        }
+
+        let on = (timer::uptime_ms() / CURSOR_BLINK_MS) % 2 == 0;
+        if on != self.cursor_drawn {
+            self.draw_cursor(on)?;
+        }
+
+        Ok(())
+    }
+
+    // moves the screen contents up by one line and clears the last row
+    fn scroll(&mut self) -> Result<()> {
+        if self.suspended {
+            return Ok(());
+        }
+
+        let info = self.info()?;
+        let (_, font_height) = self.font()?.get_wh();
+
+        if self.origin_y + info.p_height + font_height <= info.v_height {
+            self.origin_y += font_height;
+            framebuffer::set_visible_y(self.origin_y)?;
+        } else {
+            // wrap around to the top of the virtual framebuffer
+            framebuffer::copy_rect(
+                0,
+                self.origin_y + font_height,
+                info.p_width,
+                (self.rows - 1) * font_height,
+                0,
+                0,
+            )?;
+            if self.origin_y != 0 {
+                self.origin_y = 0;
+                framebuffer::set_visible_y(0)?;
+            }
+        }
+
+        self.clear_rows(self.rows - 1)
+    }
+
+    fn line_feed(&mut self) -> Result<()> {
+        if self.cursor_y + 1 < self.rows {
+            self.cursor_y += 1;
+            return Ok(());
+        }
+
+        self.lines.push_back(Vec::new());
+        if self.lines.len() > SCROLLBACK_LINES {
+            self.lines.pop_front();
+        }
+        self.scroll()
+    }
+
     fn tab(&mut self) -> Result<()> {
         for c in TAB_DISP_STR.chars() {
-            self.write_char(c)?;
+            self.put_char(c)?;
         }
 
         Ok(())
     }
 
+    // combines with the previous character, marks without a glyph for the sequence are dropped
+    fn put_mark(&mut self, mark: char) -> Result<()> {
+        let font = self.font()?;
+        let (col, row) = match self.cursor_x {
+            0 => return Ok(()),
+            x => (x - 1, self.cursor_y),
+        };
+        let cell = match self.line_mut(row).get_mut(col) {
+            Some(cell) => cell,
+            None => return Ok(()),
+        };
+        if font.sequence_glyph(&[cell.c, mark]).is_none() {
+            return Ok(());
+        }
+
+        cell.mark = Some(mark);
+        let cell = *cell;
+        self.draw_cell(col, row, &cell)
+    }
+
+    fn put_char(&mut self, c: char) -> Result<()> {
+        if is_combining_mark(c) {
+            return self.put_mark(c);
+        }
+
+        // wrap when the next character is written so that the last column can be used
+        if self.cursor_x >= self.cols {
+            self.cursor_x = 0;
+            self.line_feed()?;
+        }
+
+        let cell = Cell { c, ..self.blank() };
+        let blank = self.blank();
+        let (col, row) = (self.cursor_x, self.cursor_y);
+        let line = self.line_mut(row);
+        if line.len() <= col {
+            line.resize(col + 1, blank);
+        }
+        line[col] = cell;
+
+        self.draw_cell(col, row, &cell)?;
+        self.cursor_x += 1;
+        Ok(())
+    }
+
+    // erases columns [start, end) of a screen row with the current background
+    fn erase(&mut self, row: usize, start: usize, end: usize) -> Result<()> {
+        let blank = self.blank();
+        let cols = self.cols;
+        // cells past the end of a line are drawn with the default background
+        let truncate = end >= cols && blank.back_color == self.back_color;
+        let line = self.line_mut(row);
+
+        if truncate {
+            line.truncate(start);
+        } else {
+            let end = end.min(cols);
+            if line.len() < end {
+                line.resize(end, blank);
+            }
+            line[start.min(end)..end].fill(blank);
+        }
+
+        self.draw_row(row)
+    }
+
+    fn select_graphic_rendition(&mut self, params: &[u16]) {
+        if params.is_empty() {
+            self.attrs = Attributes::new();
+            return;
+        }
+
+        let mut iter = params.iter().copied();
+        while let Some(param) = iter.next() {
+            match param {
+                0 => self.attrs = Attributes::new(),
+                1 => self.attrs.bold = true,
+                22 => self.attrs.bold = false,
+                7 => self.attrs.inverse = true,
+                27 => self.attrs.inverse = false,
+                30..=37 => self.attrs.fore = Color::Ansi((param - 30) as u8),
+                39 => self.attrs.fore = Color::Default,
+                40..=47 => self.attrs.back = Color::Ansi((param - 40) as u8),
+                49 => self.attrs.back = Color::Default,
+                90..=97 => self.attrs.fore = Color::Ansi((param - 90 + 8) as u8),
+                100..=107 => self.attrs.back = Color::Ansi((param - 100 + 8) as u8),
+                // 38;5;<index> or 38;2;<r>;<g>;<b>
+                38 | 48 => {
+                    let color = match iter.next() {
+                        Some(5) => iter.next().map(|index| Color::Ansi(index as u8)),
+                        Some(2) => {
+                            let mut next = || iter.next().unwrap_or(0) as u8;
+                            Some(Color::Rgb(ColorCode::new(next(), next(), next())))
+                        }
+                        _ => None,
+                    };
+                    match (param, color) {
+                        (38, Some(color)) => self.attrs.fore = color,
+                        (48, Some(color)) => self.attrs.back = color,
+                        _ => (),
+                    }
+                }
+                _ => (),
+            }
+        }
+    }
+
+    fn control_sequence(
+        &mut self,
+        private: Option<char>,
+        params: &Params,
+        final_char: char,
+    ) -> Result<()> {
+        let n = params.get(0, 1) as usize;
+        let (max_x, max_y) = (self.cols - 1, self.rows - 1);
+
+        match (private, final_char) {
+            (None, 'A') => self.cursor_y = self.cursor_y.saturating_sub(n),
+            (None, 'B') => self.cursor_y = (self.cursor_y + n).min(max_y),
+            (None, 'C') => self.cursor_x = (self.cursor_x + n).min(max_x),
+            (None, 'D') => self.cursor_x = self.cursor_x.min(max_x).saturating_sub(n),
+            (None, 'G') => self.cursor_x = (n - 1).min(max_x),
+            (None, 'd') => self.cursor_y = (n - 1).min(max_y),
+            (None, 'H') | (None, 'f') => {
+                self.cursor_y = (params.get(0, 1) as usize - 1).min(max_y);
+                self.cursor_x = (params.get(1, 1) as usize - 1).min(max_x);
+            }
+            (None, 'J') => {
+                let (x, y) = (self.cursor_x, self.cursor_y);
+                let rows = match params.get(0, 0) {
+                    0 => {
+                        self.erase(y, x, self.cols)?;
+                        y + 1..self.rows
+                    }
+                    1 => {
+                        self.erase(y, 0, x + 1)?;
+                        0..y
+                    }
+                    // 3 also drops the scrollback
+                    mode => {
+                        if mode == 3 {
+                            let scrollback = self.lines.len() - self.rows;
+                            self.lines.drain(..scrollback);
+                        }
+                        0..self.rows
+                    }
+                };
+                for row in rows {
+                    self.erase(row, 0, self.cols)?;
+                }
+            }
+            (None, 'K') => {
+                let (x, y) = (self.cursor_x, self.cursor_y);
+                match params.get(0, 0) {
+                    0 => self.erase(y, x, self.cols)?,
+                    1 => self.erase(y, 0, x + 1)?,
+                    _ => self.erase(y, 0, self.cols)?,
+                }
+            }
+            (None, 'm') => self.select_graphic_rendition(params.as_slice()),
+            (None, 's') => self.save_cursor(),
+            (None, 'u') => self.restore_cursor(),
+            (Some('?'), 'h') | (Some('?'), 'l') if params.as_slice().contains(&25) => {
+                self.cursor_visible = final_char == 'h';
+            }
+            _ => (),
+        }
+
+        Ok(())
+    }
+
+    fn save_cursor(&mut self) {
+        self.saved_cursor = (self.cursor_x, self.cursor_y, self.attrs);
+    }
+
+    fn restore_cursor(&mut self) {
+        let (x, y, attrs) = self.saved_cursor;
+        self.cursor_x = x.min(self.cols - 1);
+        self.cursor_y = y.min(self.rows - 1);
+        self.attrs = attrs;
+    }
+
     fn write_char(&mut self, c: char) -> Result<()> {
-        match c {
-            '\n' => return self.inc_cursor_new_line(),
-            '\t' => return self.tab(),
+        self.info()?;
+        self.update_suspended()?;
+        self.hide_cursor()?;
+
+        // output returns the view to the bottom
+        if self.view_offset != 0 {
+            self.view_offset = 0;
+            self.redraw()?;
+        }
+
+        match self.parser.advance(c) {
+            Some(Action::Print(c)) => self.put_char(c)?,
+            Some(Action::Control('\n')) => {
+                self.cursor_x = 0;
+                self.line_feed()?;
+            }
+            Some(Action::Control('\r')) => self.cursor_x = 0,
+            Some(Action::Control('\t')) => self.tab()?,
+            Some(Action::Control('\x08')) => {
+                self.cursor_x = self.cursor_x.min(self.cols - 1).saturating_sub(1);
+            }
+            Some(Action::Escape('7')) => self.save_cursor(),
+            Some(Action::Escape('8')) => self.restore_cursor(),
+            Some(Action::Csi {
+                private,
+                params,
+                final_char,
+            }) => self.control_sequence(private, &params, final_char)?,
             _ => (),
         }
 
-        let (font_width, font_height) = FONT.get_wh();
-        framebuffer::draw_font(
-            self.cursor_x * font_width,
-            self.cursor_y * font_height,
-            c,
-            self.fore_color,
-            self.back_color,
-        )?;
+        Ok(())
+    }
+
+    // the text is kept and redrawn on the new grid
+    fn set_font(&mut self, font: &'static PsfFont) -> Result<()> {
+        let info = self.info()?;
+        self.update_suspended()?;
+        let (cols, rows) = Self::grid(&info, font).ok_or(Error::InvalidArgument)?;
+        self.hide_cursor()?;
+
+        // keep the cursor at the same distance from the bottom of the screen
+        let from_bottom = (self.rows - 1 - self.cursor_y).min(rows - 1);
+        while self.lines.len() < rows {
+            self.lines.push_front(Vec::new());
+        }
+
+        self.font = Some(font);
+        self.cols = cols;
+        self.rows = rows;
+        self.cursor_x = self.cursor_x.min(cols);
+        self.cursor_y = rows - 1 - from_bottom;
+        self.view_offset = 0;
+        if self.origin_y != 0 && !self.suspended {
+            self.origin_y = 0;
+            framebuffer::set_visible_y(0)?;
+        }
+
+        self.clear_rows(0)?;
+        self.redraw()
+    }
+
+    // positive lines scroll back into the history
+    fn scroll_view(&mut self, lines: isize) -> Result<()> {
+        self.info()?;
+        self.update_suspended()?;
+        self.hide_cursor()?;
+
+        let max = self.lines.len() - self.rows;
+        let offset = (self.view_offset as isize + lines).clamp(0, max as isize) as usize;
+        if offset != self.view_offset {
+            self.view_offset = offset;
+            self.redraw()?;
+        }
 
-        self.inc_cursor()?;
         Ok(())
     }
 }
 
+// combining diacritical marks
+fn is_combining_mark(c: char) -> bool {
+    matches!(c, '\u{0300}'..='\u{036f}' | '\u{1ab0}'..='\u{1aff}' | '\u{20d0}'..='\u{20ff}')
+}
+
 impl fmt::Write for FramebufferConsole {
     fn write_str(&mut self, s: &str) -> fmt::Result {
         for c in s.chars() {
@@ -137,8 +642,40 @@ impl fmt::Write for FramebufferConsole {
     }
 }
 
+pub struct FramebufferConsoleBackend;
+
+impl ConsoleBackend for FramebufferConsoleBackend {
+    fn name(&self) -> &'static str {
+        "fb"
+    }
+
+    fn write_str(&mut self, s: &str) -> Result<()> {
+        write_str(s)
+    }
+
+    fn poll(&mut self) {
+        if let Ok(mut fb_console) = unsafe { FB_CONSOLE.try_lock() } {
+            let _ = fb_console.blink_cursor();
+        }
+    }
+}
+
+// registers the console on first use
 pub fn init(fore_color: ColorCode, back_color: ColorCode) -> Result<()> {
+    unsafe { FB_CONSOLE.try_lock() }?.init(fore_color, back_color)?;
+
+    let backend = FramebufferConsoleBackend;
+    if !console::list()?.iter().any(|c| c.name == backend.name()) {
+        console::register(Box::new(backend), true)?;
+    }
+
+    Ok(())
+}
+
+// picks up a new framebuffer mode, keeping the colours and the font
+pub fn reset() -> Result<()> {
     let mut fb_console = unsafe { FB_CONSOLE.try_lock() }?;
+    let (fore_color, back_color) = (fb_console.fore_color, fb_console.back_color);
     fb_console.init(fore_color, back_color)
 }
 
@@ -147,3 +684,38 @@ pub fn write_fmt(args: fmt::Arguments) -> Result<()> {
     let _ = fb_console.write_fmt(args);
     Ok(())
 }
+
+pub fn is_initialized() -> Result<bool> {
+    Ok(unsafe { FB_CONSOLE.try_lock() }?.info.is_some())
+}
+
+pub unsafe fn force_is_initialized() -> bool {
+    FB_CONSOLE.force_lock().info.is_some()
+}
+
+// scrolls the view by half a screen, e.g. for Shift+PageUp and Shift+PageDown
+pub fn page_up() -> Result<()> {
+    let mut fb_console = unsafe { FB_CONSOLE.try_lock() }?;
+    let lines = (fb_console.rows / 2).max(1) as isize;
+    fb_console.scroll_view(lines)
+}
+
+pub fn page_down() -> Result<()> {
+    let mut fb_console = unsafe { FB_CONSOLE.try_lock() }?;
+    let lines = (fb_console.rows / 2).max(1) as isize;
+    fb_console.scroll_view(-lines)
+}
+
+pub fn set_font(font: &'static PsfFont) -> Result<()> {
+    unsafe { FB_CONSOLE.try_lock() }?.set_font(font)
+}
+
+pub fn font() -> Result<&'static PsfFont> {
+    unsafe { FB_CONSOLE.try_lock() }?.font()
+}
+
+pub fn write_str(s: &str) -> Result<()> {
+    let mut fb_console = unsafe { FB_CONSOLE.try_lock() }?;
+    let _ = fb_console.write_str(s);
+    Ok(())
+}
@@ -0,0 +1,541 @@
+use crate::{
+    asm,
+    breakpoint::{self, WatchKind, Watchpoint},
+    cpu,
+    error::{Error, Result},
+    exception::{ExceptionClass, TrapFrame},
+    mutex::Mutex,
+    uart::{self, UartPort},
+};
+use alloc::{format, string::String, vec::Vec};
+use core::fmt::Write;
+
+// GDB remote serial protocol stub
+// https://sourceware.org/gdb/current/onlinedocs/gdb.html/Remote-Protocol.html
+
+static mut GDB: Mutex<GdbStub> = Mutex::new(GdbStub::new());
+
+// brk immediate used to enter the stub, see asm::gdb_breakpoint
+const BRK_ENTRY_IMM: u32 = 0xf000;
+const BRK_INSTRUCTION: u32 = 0xd4200000;
+const MAX_BREAKPOINTS: usize = 32;
+const PACKET_SIZE: usize = 0x1000;
+
+const SIGTRAP: u8 = 5;
+
+// x0-x30, sp, pc and cpsr
+const REG_SP: usize = 31;
+const REG_PC: usize = 32;
+const REG_CPSR: usize = 33;
+const REG_COUNT: usize = 34;
+
+const MDSCR_SS: u64 = 1 << 0;
+const MDSCR_KDE: u64 = 1 << 13;
+const SPSR_SS: u64 = 1 << 21;
+const SPSR_D: u64 = 1 << 9;
+const SPSR_IF: u64 = 0b11 << 6;
+
+// ISS of a watchpoint exception
+const ESR_WNR: u64 = 1 << 6;
+
+const BREAK_CHAR: u8 = 0x03;
+
+#[derive(Debug, Clone, Copy)]
+struct Breakpoint {
+    addr: u64,
+    instruction: u32,
+}
+
+struct GdbStub {
+    port: Option<UartPort>,
+    breakpoints: Vec<Breakpoint>,
+    // interrupt mask of the stepped code, restored after the step
+    step_daif: Option<u64>,
+}
+
+enum Resume {
+    Continue,
+    Step,
+}
+
+enum Response {
+    Reply(String),
+    Resume(Resume),
+}
+
+fn hex_digit(value: u8) -> u8 {
+    b"0123456789abcdef"[(value & 0xf) as usize]
+}
+
+fn from_hex_digit(c: u8) -> Option<u8> {
+    match c {
+        b'0'..=b'9' => Some(c - b'0'),
+        b'a'..=b'f' => Some(c - b'a' + 10),
+        b'A'..=b'F' => Some(c - b'A' + 10),
+        _ => None,
+    }
+}
+
+fn encode_hex(out: &mut String, bytes: &[u8]) {
+    for &b in bytes {
+        out.push(hex_digit(b >> 4) as char);
+        out.push(hex_digit(b) as char);
+    }
+}
+
+fn decode_hex(s: &[u8]) -> Option<Vec<u8>> {
+    if s.len() % 2 != 0 {
+        return None;
+    }
+
+    s.chunks_exact(2)
+        .map(|c| Some(from_hex_digit(c[0])? << 4 | from_hex_digit(c[1])?))
+        .collect()
+}
+
+fn parse_hex(s: &[u8]) -> Option<u64> {
+    if s.is_empty() || s.len() > 16 {
+        return None;
+    }
+
+    s.iter()
+        .try_fold(0, |acc, &c| Some(acc << 4 | from_hex_digit(c)? as u64))
+}
+
+// "addr,len" as in m, M and Z packets
+fn parse_addr_len(s: &[u8]) -> Option<(u64, usize)> {
+    let comma = s.iter().position(|&c| c == b',')?;
+    Some((
+        parse_hex(&s[..comma])?,
+        parse_hex(&s[comma + 1..])? as usize,
+    ))
+}
+
+// RAM only, reading peripheral registers can have side effects
+fn is_valid_range(addr: u64, len: usize) -> bool {
+    addr.checked_add(len as u64)
+        .is_some_and(|end| end <= cpu::mmio_base() as u64)
+}
+
+fn target_xml() -> String {
+    let mut xml = String::from(
+        "<?xml version=\"1.0\"?><!DOCTYPE target SYSTEM \"gdb-target.dtd\">\
+         <target><architecture>aarch64</architecture><feature name=\"org.gnu.gdb.aarch64.core\">",
+    );
+    for i in 0..31 {
+        let _ = write!(xml, "<reg name=\"x{}\" bitsize=\"64\"/>", i);
+    }
+    xml.push_str(
+        "<reg name=\"sp\" bitsize=\"64\" type=\"data_ptr\"/>\
+         <reg name=\"pc\" bitsize=\"64\" type=\"code_ptr\"/>\
+         <reg name=\"cpsr\" bitsize=\"32\"/></feature></target>",
+    );
+    xml
+}
+
+impl GdbStub {
+    const fn new() -> Self {
+        Self {
+            port: None,
+            breakpoints: Vec::new(),
+            step_daif: None,
+        }
+    }
+
+    fn port(&self) -> Result<UartPort> {
+        self.port.ok_or(Error::NotInitialized)
+    }
+
+    fn receive_byte(&self) -> Result<u8> {
+        // only ASCII is used by the protocol
+        Ok(uart::receive_port(self.port()?)? as u32 as u8)
+    }
+
+    fn send_bytes(&self, buf: &[u8]) -> Result<()> {
+        uart::write_bytes(self.port()?, buf)
+    }
+
+    fn receive_packet(&self) -> Result<Vec<u8>> {
+        loop {
+            // skip acks and break requests until the start of a packet
+            while self.receive_byte()? != b'$' {}
+
+            let mut data = Vec::new();
+            let mut checksum: u8 = 0;
+            loop {
+                match self.receive_byte()? {
+                    b'#' => break,
+                    c => {
+                        checksum = checksum.wrapping_add(c);
+                        if data.len() < PACKET_SIZE {
+                            data.push(c);
+                        }
+                    }
+                }
+            }
+
+            let expected = (self.receive_byte()?, self.receive_byte()?);
+            match parse_hex(&[expected.0, expected.1]) {
+                Some(expected) if expected as u8 == checksum => {
+                    self.send_bytes(b"+")?;
+                    return Ok(data);
+                }
+                _ => self.send_bytes(b"-")?,
+            }
+        }
+    }
+
+    fn send_packet(&self, data: &[u8]) -> Result<()> {
+        let checksum = data.iter().fold(0u8, |acc, &c| acc.wrapping_add(c));
+
+        // retransmit until acknowledged
+        loop {
+            self.send_bytes(b"$")?;
+            self.send_bytes(data)?;
+            self.send_bytes(&[b'#', hex_digit(checksum >> 4), hex_digit(checksum)])?;
+
+            match self.receive_byte()? {
+                b'-' => continue,
+                _ => return Ok(()),
+            }
+        }
+    }
+
+    fn read_register(frame: &TrapFrame, index: usize) -> Option<Vec<u8>> {
+        let value = match index {
+            0..=30 => frame.x[index],
+            REG_SP => frame.sp,
+            REG_PC => frame.elr,
+            REG_CPSR => return Some((frame.spsr as u32).to_le_bytes().to_vec()),
+            _ => return None,
+        };
+        Some(value.to_le_bytes().to_vec())
+    }
+
+    // sp cannot be changed, it is restored by the exception return path
+    fn write_register(frame: &mut TrapFrame, index: usize, value: &[u8]) -> Option<()> {
+        match (index, value.len()) {
+            (0..=30, 8) => frame.x[index] = u64::from_le_bytes(value.try_into().ok()?),
+            (REG_SP, 8) => (),
+            (REG_PC, 8) => frame.elr = u64::from_le_bytes(value.try_into().ok()?),
+            (REG_CPSR, 4) => {
+                frame.spsr = u32::from_le_bytes(value.try_into().ok()?) as u64;
+            }
+            _ => return None,
+        }
+        Some(())
+    }
+
+    fn read_memory(addr: u64, len: usize) -> Option<Vec<u8>> {
+        if !is_valid_range(addr, len) {
+            return None;
+        }
+
+        Some(
+            (0..len)
+                .map(|i| unsafe { ((addr + i as u64) as *const u8).read_volatile() })
+                .collect(),
+        )
+    }
+
+    fn write_memory(addr: u64, data: &[u8]) -> Option<()> {
+        if !is_valid_range(addr, data.len()) {
+            return None;
+        }
+
+        for (i, &b) in data.iter().enumerate() {
+            unsafe { ((addr + i as u64) as *mut u8).write_volatile(b) };
+        }
+        asm::invalidate_icache();
+        Some(())
+    }
+
+    fn insert_breakpoint(&mut self, addr: u64) -> Option<()> {
+        if self.breakpoints.iter().any(|bp| bp.addr == addr) {
+            return Some(());
+        }
+        if addr % 4 != 0 || self.breakpoints.len() >= MAX_BREAKPOINTS {
+            return None;
+        }
+
+        let instruction = u32::from_le_bytes(Self::read_memory(addr, 4)?.try_into().ok()?);
+        Self::write_memory(addr, &BRK_INSTRUCTION.to_le_bytes())?;
+        self.breakpoints.push(Breakpoint { addr, instruction });
+        Some(())
+    }
+
+    fn remove_breakpoint(&mut self, addr: u64) -> Option<()> {
+        let index = self.breakpoints.iter().position(|bp| bp.addr == addr)?;
+        let bp = self.breakpoints.remove(index);
+        Self::write_memory(bp.addr, &bp.instruction.to_le_bytes())
+    }
+
+    fn remove_all_breakpoints(&mut self) {
+        while let Some(bp) = self.breakpoints.pop() {
+            let _ = Self::write_memory(bp.addr, &bp.instruction.to_le_bytes());
+        }
+    }
+
+    // returns None for unsupported or malformed packets, which are answered with an empty packet
+    fn handle_packet(&mut self, packet: &[u8], frame: &mut TrapFrame) -> Option<Response> {
+        let mut reply = String::new();
+        let (&command, args) = packet.split_first()?;
+
+        match command {
+            b'?' => reply = format!("S{:02x}", SIGTRAP),
+            b'g' => {
+                for i in 0..REG_COUNT {
+                    encode_hex(&mut reply, &Self::read_register(frame, i)?);
+                }
+            }
+            b'G' => {
+                let data = decode_hex(args)?;
+                let mut regs = data.as_slice();
+                for i in 0..REG_COUNT {
+                    let size = if i == REG_CPSR { 4 } else { 8 };
+                    if regs.len() < size {
+                        break;
+                    }
+                    Self::write_register(frame, i, &regs[..size])?;
+                    regs = &regs[size..];
+                }
+                reply.push_str("OK");
+            }
+            b'p' => {
+                let index = parse_hex(args)? as usize;
+                encode_hex(&mut reply, &Self::read_register(frame, index)?);
+            }
+            b'P' => {
+                let eq = args.iter().position(|&c| c == b'=')?;
+                let index = parse_hex(&args[..eq])? as usize;
+                Self::write_register(frame, index, &decode_hex(&args[eq + 1..])?)?;
+                reply.push_str("OK");
+            }
+            b'm' => {
+                let (addr, len) = parse_addr_len(args)?;
+                match Self::read_memory(addr, len.min(PACKET_SIZE / 2)) {
+                    Some(data) => encode_hex(&mut reply, &data),
+                    None => reply.push_str("E14"),
+                }
+            }
+            b'M' => {
+                let colon = args.iter().position(|&c| c == b':')?;
+                let (addr, len) = parse_addr_len(&args[..colon])?;
+                let data = decode_hex(&args[colon + 1..])?;
+                match Self::write_memory(addr, &data[..len.min(data.len())]) {
+                    Some(()) => reply.push_str("OK"),
+                    None => reply.push_str("E14"),
+                }
+            }
+            b'c' | b's' => {
+                if let Some(addr) = parse_hex(args) {
+                    frame.elr = addr;
+                }
+                let resume = if command == b'c' {
+                    Resume::Continue
+                } else {
+                    Resume::Step
+                };
+                return Some(Response::Resume(resume));
+            }
+            b'Z' | b'z' if args.starts_with(b"0,") => {
+                let (addr, _kind) = parse_addr_len(&args[2..])?;
+                let res = if command == b'Z' {
+                    self.insert_breakpoint(addr)
+                } else {
+                    self.remove_breakpoint(addr)
+                };
+                match res {
+                    Some(()) => reply.push_str("OK"),
+                    None => reply.push_str("E22"),
+                }
+            }
+            b'Z' | b'z' if args.starts_with(b"1,") => {
+                let (addr, _kind) = parse_addr_len(&args[2..])?;
+                let res = if command == b'Z' {
+                    breakpoint::add_breakpoint(addr).map(|_| ())
+                } else {
+                    breakpoint::breakpoints().and_then(|bps| {
+                        let bp = bps.iter().find(|bp| bp.value == addr);
+                        breakpoint::remove_breakpoint(bp.ok_or(Error::InvalidArgument)?.index)
+                    })
+                };
+                match res {
+                    Ok(()) => reply.push_str("OK"),
+                    Err(_) => reply.push_str("E22"),
+                }
+            }
+            b'Z' | b'z' if matches!(args, [b'2'..=b'4', b',', ..]) => {
+                let (addr, len) = parse_addr_len(&args[2..])?;
+                let kind = match args[0] {
+                    b'2' => WatchKind::Write,
+                    b'3' => WatchKind::Read,
+                    _ => WatchKind::Access,
+                };
+                let res = if command == b'Z' {
+                    breakpoint::add_watchpoint(addr, len as u64, kind).map(|_| ())
+                } else {
+                    breakpoint::watchpoints().and_then(|wps| {
+                        let watchpoint = Watchpoint {
+                            addr,
+                            len: len as u64,
+                            kind,
+                        };
+                        let wp = wps.iter().find(|wp| wp.value == watchpoint);
+                        breakpoint::remove_watchpoint(wp.ok_or(Error::InvalidArgument)?.index)
+                    })
+                };
+                match res {
+                    Ok(()) => reply.push_str("OK"),
+                    Err(_) => reply.push_str("E22"),
+                }
+            }
+            b'D' | b'k' => {
+                self.remove_all_breakpoints();
+                let _ = self.send_packet(b"OK");
+                return Some(Response::Resume(Resume::Continue));
+            }
+            b'H' => reply.push_str("OK"),
+            b'q' if args.starts_with(b"Supported") => {
+                reply = format!(
+                    "PacketSize={:x};qXfer:features:read+;swbreak+;hwbreak+",
+                    PACKET_SIZE
+                );
+            }
+            b'q' if args == b"Attached" => reply.push('1'),
+            b'q' if args.starts_with(b"Xfer:features:read:target.xml:") => {
+                let (offset, len) = parse_addr_len(&args[30..])?;
+                let xml = target_xml();
+                let offset = (offset as usize).min(xml.len());
+                let end = offset.saturating_add(len).min(xml.len());
+                reply.push(if end == xml.len() { 'l' } else { 'm' });
+                reply.push_str(&xml[offset..end]);
+            }
+            _ => return None,
+        }
+
+        Some(Response::Reply(reply))
+    }
+
+    fn run(&mut self, frame: &mut TrapFrame) -> Result<Resume> {
+        let stop_reason = match frame.exception_class() {
+            ExceptionClass::BreakpointSameEl => format!("T{:02x}hwbreak:;", SIGTRAP),
+            ExceptionClass::WatchpointSameEl => {
+                let kind = if frame.esr & ESR_WNR != 0 {
+                    "watch"
+                } else {
+                    "rwatch"
+                };
+                format!("T{:02x}{}:{:x};", SIGTRAP, kind, frame.far)
+            }
+            _ => format!("S{:02x}", SIGTRAP),
+        };
+        self.send_packet(stop_reason.as_bytes())?;
+
+        loop {
+            let packet = self.receive_packet()?;
+            match self.handle_packet(&packet, frame) {
+                Some(Response::Reply(reply)) => self.send_packet(reply.as_bytes())?,
+                Some(Response::Resume(resume)) => return Ok(resume),
+                None => self.send_packet(b"")?,
+            }
+        }
+    }
+
+    fn handle_exception(&mut self, frame: &mut TrapFrame) -> Result<()> {
+        self.port()?;
+
+        // end of a single step
+        if let Some(daif) = self.step_daif.take() {
+            asm::write_mdscr_el1(asm::read_mdscr_el1() & !MDSCR_SS);
+            frame.spsr = (frame.spsr & !(SPSR_SS | SPSR_IF)) | daif;
+        }
+
+        let resume = self.run(frame)?;
+
+        // compiled in entry breakpoints are skipped, GDB moves over its own breakpoints
+        let instruction = unsafe { (frame.elr as *const u32).read_volatile() };
+        if instruction == BRK_INSTRUCTION | BRK_ENTRY_IMM << 5 {
+            frame.elr += 4;
+        }
+
+        if let Resume::Step = resume {
+            // step with interrupts masked so that the step does not land in a handler
+            self.step_daif = Some(frame.spsr & SPSR_IF);
+            asm::unlock_os_lock();
+            asm::write_mdscr_el1(asm::read_mdscr_el1() | MDSCR_SS | MDSCR_KDE);
+            frame.spsr = (frame.spsr | SPSR_SS | SPSR_IF) & !SPSR_D;
+        }
+
+        Ok(())
+    }
+}
+
+pub fn init(port: UartPort) -> Result<()> {
+    let mut gdb = unsafe { GDB.try_lock() }?;
+    // the PL011 is set up by the firmware
+    if port == UartPort::Mini {
+        uart::init_port(port)?;
+    }
+    gdb.port = Some(port);
+    Ok(())
+}
+
+pub fn port() -> Option<UartPort> {
+    unsafe { GDB.try_lock() }.ok()?.port
+}
+
+// stops in the stub, e.g. from the shell or the panic handler
+pub fn enter() -> Result<()> {
+    if port().is_none() {
+        return Err(Error::NotInitialized);
+    }
+
+    asm::gdb_breakpoint();
+    Ok(())
+}
+
+// Ctrl-C received by the console on the port GDB shares with it, e.g. the PL011 and the mini
+// UART on the same pins, enters the stub, returns true if the character was taken
+pub fn check_console_break(console_input: Option<&str>, c: char) -> bool {
+    match port() {
+        Some(port)
+            if console_input == Some(uart::port_name(port)) && c as u32 == BREAK_CHAR as u32 =>
+        {
+            asm::gdb_breakpoint();
+            true
+        }
+        _ => false,
+    }
+}
+
+// a break request or a packet from GDB on a port that is not used for console input, see
+// check_console_break for a shared port
+pub fn poll(console_input: Option<&str>) {
+    let port = match port() {
+        Some(port) if console_input != Some(uart::port_name(port)) => port,
+        _ => return,
+    };
+
+    if let Ok(Some(c)) = uart::try_receive_port(port) {
+        if c as u32 == BREAK_CHAR as u32 || c == '$' {
+            asm::gdb_breakpoint();
+        }
+    }
+}
+
+// called for debug exceptions, returns false if not handled
+pub fn handle_exception(frame: &mut TrapFrame) -> bool {
+    match frame.exception_class() {
+        ExceptionClass::Brk
+        | ExceptionClass::SoftwareStepSameEl
+        | ExceptionClass::BreakpointSameEl
+        | ExceptionClass::WatchpointSameEl => (),
+        _ => return false,
+    }
+
+    match unsafe { GDB.try_lock() } {
+        Ok(mut gdb) => gdb.handle_exception(frame).is_ok(),
+        Err(_) => false,
+    }
+}
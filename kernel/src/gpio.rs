@@ -1,9 +1,45 @@
-use crate::addr::MmioAddress;
+use crate::{
+    addr::MmioAddress,
+    error::{Error, Result},
+};
+
+pub const PIN_COUNT: u32 = 54;
 
 fn mmio_base_gpio() -> MmioAddress {
     MmioAddress::new(0x200000)
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u32)]
+pub enum PinFunction {
+    Input = 0b000,
+    Output = 0b001,
+    Alt0 = 0b100,
+    Alt1 = 0b101,
+    Alt2 = 0b110,
+    Alt3 = 0b111,
+    Alt4 = 0b011,
+    Alt5 = 0b010,
+}
+
+impl TryFrom<u32> for PinFunction {
+    type Error = Error;
+
+    fn try_from(value: u32) -> Result<Self> {
+        match value {
+            0b000 => Ok(Self::Input),
+            0b001 => Ok(Self::Output),
+            0b100 => Ok(Self::Alt0),
+            0b101 => Ok(Self::Alt1),
+            0b110 => Ok(Self::Alt2),
+            0b111 => Ok(Self::Alt3),
+            0b011 => Ok(Self::Alt4),
+            0b010 => Ok(Self::Alt5),
+            _ => Err(Error::InvalidArgument),
+        }
+    }
+}
+
 pub fn read_gpfsel1() -> u32 {
     mmio_base_gpio().offset(0x04).read()
 }
@@ -27,3 +63,48 @@ pub fn read_gppudclk0() -> u32 {
 pub fn write_gppudclk0(value: u32) {
     mmio_base_gpio().offset(0x98).write(value);
 }
+
+fn check_pin(pin: u32) -> Result<()> {
+    if pin >= PIN_COUNT {
+        return Err(Error::InvalidArgument);
+    }
+
+    Ok(())
+}
+
+pub fn get_function(pin: u32) -> Result<PinFunction> {
+    check_pin(pin)?;
+    let gpfsel = mmio_base_gpio().offset((pin / 10) as usize * 4);
+    PinFunction::try_from((gpfsel.read() >> ((pin % 10) * 3)) & 0b111)
+}
+
+pub fn set_function(pin: u32, function: PinFunction) -> Result<()> {
+    check_pin(pin)?;
+    let gpfsel = mmio_base_gpio().offset((pin / 10) as usize * 4);
+    let shift = (pin % 10) * 3;
+    let value = gpfsel.read() & !(0b111 << shift);
+    gpfsel.write(value | (function as u32) << shift);
+    Ok(())
+}
+
+pub fn get_level(pin: u32) -> Result<bool> {
+    check_pin(pin)?;
+    let gplev = mmio_base_gpio().offset(0x34 + (pin / 32) as usize * 4);
+    Ok(gplev.read() & (1 << (pin % 32)) != 0)
+}
+
+pub fn set_level(pin: u32, high: bool) -> Result<()> {
+    check_pin(pin)?;
+    // GPSETn or GPCLRn
+    let offset = if high { 0x1c } else { 0x28 };
+    mmio_base_gpio()
+        .offset(offset + (pin / 32) as usize * 4)
+        .write(1 << (pin % 32));
+    Ok(())
+}
+
+pub fn read_levels() -> u64 {
+    let gplev0 = mmio_base_gpio().offset(0x34).read() as u64;
+    let gplev1 = mmio_base_gpio().offset(0x38).read() as u64;
+    gplev1 << 32 | gplev0
+}
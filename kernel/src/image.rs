@@ -0,0 +1,594 @@
+// decoders for BMP (uncompressed, bit fields, RLE8 and RLE4), QOI and binary PPM images
+// https://learn.microsoft.com/en-us/windows/win32/gdi/bitmap-storage
+// https://qoiformat.org/qoi-specification.pdf
+// https://netpbm.sourceforge.net/doc/ppm.html
+
+use crate::{
+    color::ColorCode,
+    error::{Error, Result},
+    framebuffer, vfs,
+};
+use alloc::{format, vec::Vec};
+
+// bounds the allocation for a corrupt header
+const MAX_DIMENSION: usize = 8192;
+// pixels a single byte can encode at most, a QOI run
+const QOI_MAX_RUN: usize = 62;
+
+const BMP_MAGIC: &[u8] = b"BM";
+const BMP_FILE_HEADER_SIZE: usize = 14;
+const BMP_CORE_HEADER_SIZE: usize = 12;
+const BMP_INFO_HEADER_SIZE: usize = 40;
+const BMP_RGB: u32 = 0;
+const BMP_RLE8: u32 = 1;
+const BMP_RLE4: u32 = 2;
+const BMP_BITFIELDS: u32 = 3;
+const BMP_ALPHABITFIELDS: u32 = 6;
+
+const QOI_MAGIC: &[u8] = b"qoif";
+const QOI_HEADER_SIZE: usize = 14;
+const QOI_OP_RGB: u8 = 0xfe;
+const QOI_OP_RGBA: u8 = 0xff;
+const QOI_OP_INDEX: u8 = 0x00;
+const QOI_OP_DIFF: u8 = 0x40;
+const QOI_OP_LUMA: u8 = 0x80;
+const QOI_MASK: u8 = 0xc0;
+
+const PPM_MAGIC: &[u8] = b"P6";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Image {
+    width: usize,
+    height: usize,
+    // row by row from the top, alpha is dropped
+    pixels: Vec<ColorCode>,
+}
+
+fn read_u16(data: &[u8], offset: usize) -> Option<u16> {
+    Some(u16::from_le_bytes(
+        data.get(offset..offset + 2)?.try_into().ok()?,
+    ))
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(
+        data.get(offset..offset + 4)?.try_into().ok()?,
+    ))
+}
+
+fn read_u32_be(data: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_be_bytes(
+        data.get(offset..offset + 4)?.try_into().ok()?,
+    ))
+}
+
+// a channel of a pixel selected by a BMP bit field mask
+#[derive(Debug, Clone, Copy)]
+struct BitField {
+    shift: u32,
+    max: u32,
+}
+
+impl BitField {
+    fn new(mask: u32) -> Self {
+        let shift = if mask == 0 { 0 } else { mask.trailing_zeros() };
+        Self {
+            shift,
+            max: mask >> shift,
+        }
+    }
+
+    fn get(&self, value: u32) -> u8 {
+        if self.max == 0 {
+            return 0;
+        }
+        // masks can be up to 32 bits wide
+        ((value >> self.shift & self.max) as u64 * 255 / self.max as u64) as u8
+    }
+}
+
+struct BmpHeader {
+    width: usize,
+    height: usize,
+    top_down: bool,
+    bpp: usize,
+    compression: u32,
+    palette: Vec<ColorCode>,
+    fields: [BitField; 3],
+    pixels_offset: usize,
+}
+
+impl Image {
+    pub fn new(width: usize, height: usize, pixels: Vec<ColorCode>) -> Result<Self> {
+        if width == 0 || height == 0 || pixels.len() != width * height {
+            return Err(Error::InvalidArgument);
+        }
+
+        Ok(Self {
+            width,
+            height,
+            pixels,
+        })
+    }
+
+    // the format is detected from the magic number
+    pub fn decode(data: &[u8]) -> Result<Self> {
+        if data.starts_with(BMP_MAGIC) {
+            Self::decode_bmp(data)
+        } else if data.starts_with(QOI_MAGIC) {
+            Self::decode_qoi(data)
+        } else if data.starts_with(PPM_MAGIC) {
+            Self::decode_ppm(data)
+        } else {
+            Err("Unknown image format".into())
+        }
+    }
+
+    // decoders check that the data can fill the image before calling this
+    fn blank(width: usize, height: usize) -> Result<Self> {
+        if width == 0 || height == 0 || width > MAX_DIMENSION || height > MAX_DIMENSION {
+            return Err("Invalid image size".into());
+        }
+
+        let mut pixels = pixel_buffer(width * height)?;
+        pixels.resize(width * height, ColorCode::BLACK);
+        Ok(Self {
+            width,
+            height,
+            pixels,
+        })
+    }
+
+    fn bmp_header(data: &[u8]) -> Option<BmpHeader> {
+        let pixels_offset = read_u32(data, 10)? as usize;
+        let header_size = read_u32(data, BMP_FILE_HEADER_SIZE)? as usize;
+
+        let (width, height, bpp, compression) = if header_size == BMP_CORE_HEADER_SIZE {
+            let width = read_u16(data, 18)? as i32;
+            let height = read_u16(data, 20)? as i32;
+            (width, height, read_u16(data, 24)?, BMP_RGB)
+        } else if header_size >= BMP_INFO_HEADER_SIZE {
+            let width = read_u32(data, 18)? as i32;
+            let height = read_u32(data, 22)? as i32;
+            (width, height, read_u16(data, 28)?, read_u32(data, 30)?)
+        } else {
+            return None;
+        };
+        if width <= 0 || height == 0 {
+            return None;
+        }
+
+        // masks follow the info header, later headers include them
+        let fields = match compression {
+            BMP_BITFIELDS | BMP_ALPHABITFIELDS => {
+                let offset = BMP_FILE_HEADER_SIZE + BMP_INFO_HEADER_SIZE;
+                [
+                    BitField::new(read_u32(data, offset)?),
+                    BitField::new(read_u32(data, offset + 4)?),
+                    BitField::new(read_u32(data, offset + 8)?),
+                ]
+            }
+            // 5 bits per channel
+            _ if bpp == 16 => [
+                BitField::new(0x7c00),
+                BitField::new(0x03e0),
+                BitField::new(0x001f),
+            ],
+            _ => [
+                BitField::new(0xff0000),
+                BitField::new(0x00ff00),
+                BitField::new(0x0000ff),
+            ],
+        };
+
+        // BGR entries, padded to 4 bytes unless the header is the old core header
+        let mut palette = Vec::new();
+        if bpp <= 8 {
+            let (entry_size, colors_used) = if header_size == BMP_CORE_HEADER_SIZE {
+                (3, 0)
+            } else {
+                (4, read_u32(data, 46)? as usize)
+            };
+            let len = match colors_used {
+                0 => 1 << bpp,
+                n => n.min(1 << bpp),
+            };
+            let offset = BMP_FILE_HEADER_SIZE + header_size;
+            for entry in data
+                .get(offset..offset + len * entry_size)?
+                .chunks_exact(entry_size)
+            {
+                palette.push(ColorCode::new(entry[2], entry[1], entry[0]));
+            }
+        }
+
+        Some(BmpHeader {
+            width: width as usize,
+            height: height.unsigned_abs() as usize,
+            top_down: height < 0,
+            bpp: bpp as usize,
+            compression,
+            palette,
+            fields,
+            pixels_offset,
+        })
+    }
+
+    fn decode_bmp(data: &[u8]) -> Result<Self> {
+        const INVALID: &str = "Invalid BMP image";
+
+        let header = Self::bmp_header(data).ok_or(INVALID)?;
+        let pixels = data.get(header.pixels_offset..).ok_or(INVALID)?;
+
+        let rle = match (header.compression, header.bpp) {
+            (BMP_RGB, 1 | 4 | 8 | 16 | 24 | 32) => false,
+            (BMP_BITFIELDS | BMP_ALPHABITFIELDS, 16 | 32) => false,
+            (BMP_RLE8, 8) | (BMP_RLE4, 4) => true,
+            _ => return Err(INVALID.into()),
+        };
+        // delta moves let a few RLE bytes skip any number of pixels, so only
+        // uncompressed rows can be checked up front
+        let stride = (header.width * header.bpp).div_ceil(32) * 4;
+        if !rle
+            && stride
+                .checked_mul(header.height)
+                .is_none_or(|len| len > pixels.len())
+        {
+            return Err(INVALID.into());
+        }
+
+        let mut image = Self::blank(header.width, header.height)?;
+        if rle {
+            image.bmp_rle(&header, pixels).ok_or(INVALID)?;
+        } else {
+            image.bmp_rows(&header, pixels).ok_or(INVALID)?;
+        }
+
+        Ok(image)
+    }
+
+    // maps a row of the file to a row of the image
+    fn bmp_row(&self, header: &BmpHeader, row: usize) -> usize {
+        if header.top_down {
+            row
+        } else {
+            self.height - 1 - row
+        }
+    }
+
+    // rows are padded to 4 bytes
+    fn bmp_rows(&mut self, header: &BmpHeader, pixels: &[u8]) -> Option<()> {
+        let stride = (self.width * header.bpp).div_ceil(32) * 4;
+        let palette = |index: usize| header.palette.get(index).copied();
+
+        for row in 0..self.height {
+            let data = pixels.get(row * stride..(row + 1) * stride)?;
+            let y = self.bmp_row(header, row);
+
+            for x in 0..self.width {
+                let color = match header.bpp {
+                    1 => palette((data[x / 8] >> (7 - x % 8) & 1) as usize)?,
+                    4 => palette((data[x / 2] >> (4 - x % 2 * 4) & 0xf) as usize)?,
+                    8 => palette(data[x] as usize)?,
+                    16 => {
+                        let value = read_u16(data, x * 2)? as u32;
+                        let [r, g, b] = header.fields.map(|field| field.get(value));
+                        ColorCode::new(r, g, b)
+                    }
+                    24 => ColorCode::new(data[x * 3 + 2], data[x * 3 + 1], data[x * 3]),
+                    _ => {
+                        let value = read_u32(data, x * 4)?;
+                        let [r, g, b] = header.fields.map(|field| field.get(value));
+                        ColorCode::new(r, g, b)
+                    }
+                };
+                self.pixels[y * self.width + x] = color;
+            }
+        }
+
+        Some(())
+    }
+
+    // pairs of a count and a palette index (two for RLE4) to repeat, a zero count escapes to
+    // end of line, end of bitmap, a delta move or a run of literal indexes
+    fn bmp_rle(&mut self, header: &BmpHeader, pixels: &[u8]) -> Option<()> {
+        let rle4 = header.compression == BMP_RLE4;
+        let (mut x, mut row) = (0, 0);
+        let mut offset = 0;
+
+        let put = |image: &mut Self, x: usize, row: usize, index: u8| -> Option<()> {
+            // pixels past the edges are dropped
+            if x < image.width && row < image.height {
+                let y = image.bmp_row(header, row);
+                image.pixels[y * image.width + x] = *header.palette.get(index as usize)?;
+            }
+            Some(())
+        };
+
+        while row < self.height {
+            let (count, value) = (*pixels.get(offset)?, *pixels.get(offset + 1)?);
+            offset += 2;
+
+            match (count, value) {
+                (0, 0) => {
+                    x = 0;
+                    row += 1;
+                }
+                (0, 1) => break,
+                (0, 2) => {
+                    x += *pixels.get(offset)? as usize;
+                    row += *pixels.get(offset + 1)? as usize;
+                    offset += 2;
+                }
+                (0, len) => {
+                    let len = len as usize;
+                    let bytes = if rle4 { len.div_ceil(2) } else { len };
+                    let literal = pixels.get(offset..offset + bytes)?;
+                    for i in 0..len {
+                        let index = if rle4 {
+                            literal[i / 2] >> (4 - i % 2 * 4) & 0xf
+                        } else {
+                            literal[i]
+                        };
+                        put(self, x, row, index)?;
+                        x += 1;
+                    }
+                    // runs are padded to 16 bits
+                    offset += bytes.next_multiple_of(2);
+                }
+                (count, value) => {
+                    for i in 0..count as usize {
+                        let index = match (rle4, i % 2) {
+                            (false, _) => value,
+                            (true, 0) => value >> 4,
+                            (true, _) => value & 0xf,
+                        };
+                        put(self, x, row, index)?;
+                        x += 1;
+                    }
+                }
+            }
+        }
+
+        Some(())
+    }
+
+    fn decode_qoi(data: &[u8]) -> Result<Self> {
+        let width = read_u32_be(data, 4).ok_or("Invalid QOI image")? as usize;
+        let height = read_u32_be(data, 8).ok_or("Invalid QOI image")? as usize;
+        let ops_len = data.len().saturating_sub(QOI_HEADER_SIZE);
+        if width.saturating_mul(height) > ops_len.saturating_mul(QOI_MAX_RUN) {
+            return Err("Invalid QOI image".into());
+        }
+
+        let mut image = Self::blank(width, height)?;
+        image.qoi_pixels(data).ok_or("Invalid QOI image")?;
+        Ok(image)
+    }
+
+    fn qoi_pixels(&mut self, data: &[u8]) -> Option<()> {
+        let mut index = [[0u8; 4]; 64];
+        let mut px = [0u8, 0, 0, 255];
+        let mut offset = QOI_HEADER_SIZE;
+        let mut run = 0;
+
+        for pixel in self.pixels.iter_mut() {
+            if run > 0 {
+                run -= 1;
+            } else {
+                let op = *data.get(offset)?;
+                offset += 1;
+
+                match op {
+                    QOI_OP_RGB => {
+                        px[..3].copy_from_slice(data.get(offset..offset + 3)?);
+                        offset += 3;
+                    }
+                    QOI_OP_RGBA => {
+                        px.copy_from_slice(data.get(offset..offset + 4)?);
+                        offset += 4;
+                    }
+                    _ => match op & QOI_MASK {
+                        QOI_OP_INDEX => px = index[op as usize],
+                        QOI_OP_DIFF => {
+                            px[0] = px[0].wrapping_add((op >> 4 & 3).wrapping_sub(2));
+                            px[1] = px[1].wrapping_add((op >> 2 & 3).wrapping_sub(2));
+                            px[2] = px[2].wrapping_add((op & 3).wrapping_sub(2));
+                        }
+                        QOI_OP_LUMA => {
+                            let next = *data.get(offset)?;
+                            offset += 1;
+                            let dg = (op & 0x3f).wrapping_sub(32);
+                            px[0] = px[0].wrapping_add(dg.wrapping_add(next >> 4).wrapping_sub(8));
+                            px[1] = px[1].wrapping_add(dg);
+                            px[2] = px[2].wrapping_add(dg.wrapping_add(next & 0xf).wrapping_sub(8));
+                        }
+                        // run, the two longest are taken by the RGB and RGBA tags
+                        _ => run = (op & 0x3f) as usize,
+                    },
+                }
+
+                let [r, g, b, a] = px.map(|c| c as usize);
+                index[(r * 3 + g * 5 + b * 7 + a * 11) % 64] = px;
+            }
+
+            *pixel = ColorCode::new(px[0], px[1], px[2]);
+        }
+
+        Some(())
+    }
+
+    fn decode_ppm(data: &[u8]) -> Result<Self> {
+        let (width, height, max, offset) = Self::ppm_header(data).ok_or("Invalid PPM image")?;
+
+        // samples above 255 take two bytes, most significant first
+        let sample_size = if max < 256 { 1 } else { 2 };
+        let samples = width
+            .checked_mul(height)
+            .and_then(|len| len.checked_mul(3 * sample_size))
+            .and_then(|len| data.get(offset..offset.checked_add(len)?))
+            .ok_or("Invalid PPM image")?;
+        let scale = |sample: &[u8]| {
+            let value = sample.iter().fold(0, |value, &b| value << 8 | b as usize);
+            (value.min(max) * 255 / max) as u8
+        };
+
+        let mut image = Self::blank(width, height)?;
+        for (pixel, rgb) in image
+            .pixels
+            .iter_mut()
+            .zip(samples.chunks_exact(sample_size * 3))
+        {
+            let channel = |i: usize| scale(&rgb[i * sample_size..(i + 1) * sample_size]);
+            *pixel = ColorCode::new(channel(0), channel(1), channel(2));
+        }
+
+        Ok(image)
+    }
+
+    // returns the width, height, maximum sample value and offset of the samples
+    fn ppm_header(data: &[u8]) -> Option<(usize, usize, usize, usize)> {
+        // width, height and maximum value separated by whitespace and comments
+        let mut offset = PPM_MAGIC.len();
+        let mut fields = [0usize; 3];
+        for field in fields.iter_mut() {
+            loop {
+                match *data.get(offset)? {
+                    b'#' => {
+                        while *data.get(offset)? != b'\n' {
+                            offset += 1;
+                        }
+                    }
+                    c if c.is_ascii_whitespace() => offset += 1,
+                    _ => break,
+                }
+            }
+
+            let start = offset;
+            while data.get(offset).is_some_and(|c| c.is_ascii_digit()) {
+                offset += 1;
+            }
+            *field = core::str::from_utf8(&data[start..offset])
+                .ok()?
+                .parse()
+                .ok()?;
+        }
+        // a single whitespace character precedes the samples
+        offset += 1;
+
+        let [width, height, max] = fields;
+        if max == 0 || max > 0xffff {
+            return None;
+        }
+
+        Some((width, height, max, offset))
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn pixels(&self) -> &[ColorCode] {
+        &self.pixels
+    }
+
+    // nearest neighbour
+    pub fn scale(&self, width: usize, height: usize) -> Result<Self> {
+        if width == 0 || height == 0 || width > MAX_DIMENSION || height > MAX_DIMENSION {
+            return Err(Error::InvalidArgument);
+        }
+
+        let mut pixels = pixel_buffer(width * height)?;
+        for y in 0..height {
+            let row = y * self.height / height * self.width;
+            for x in 0..width {
+                pixels.push(self.pixels[row + x * self.width / width]);
+            }
+        }
+
+        Self::new(width, height, pixels)
+    }
+
+    // the largest size with the same aspect ratio that fits, never enlarged
+    pub fn fit(&self, width: usize, height: usize) -> Result<Self> {
+        if self.width <= width && self.height <= height {
+            return Ok(self.clone());
+        }
+
+        let (w, h) = if self.width * height > self.height * width {
+            (width, self.height * width / self.width)
+        } else {
+            (self.width * height / self.height, height)
+        };
+        self.scale(w.max(1), h.max(1))
+    }
+}
+
+// 24-bit, followed by the rows bottom-up, see encode_bmp_row
+pub fn bmp_file_header(width: usize, height: usize) -> Vec<u8> {
+    let stride = (width * 3).next_multiple_of(4);
+    let pixels_offset = BMP_FILE_HEADER_SIZE + BMP_INFO_HEADER_SIZE;
+    let size = pixels_offset + stride * height;
+
+    let mut data = Vec::with_capacity(pixels_offset);
+    data.extend_from_slice(BMP_MAGIC);
+    data.extend_from_slice(&(size as u32).to_le_bytes());
+    data.extend_from_slice(&[0; 4]); // reserved
+    data.extend_from_slice(&(pixels_offset as u32).to_le_bytes());
+
+    data.extend_from_slice(&(BMP_INFO_HEADER_SIZE as u32).to_le_bytes());
+    data.extend_from_slice(&(width as u32).to_le_bytes());
+    data.extend_from_slice(&(height as u32).to_le_bytes());
+    data.extend_from_slice(&1u16.to_le_bytes()); // planes
+    data.extend_from_slice(&24u16.to_le_bytes()); // bits per pixel
+    data.extend_from_slice(&BMP_RGB.to_le_bytes());
+    data.extend_from_slice(&((stride * height) as u32).to_le_bytes());
+    data.extend_from_slice(&[0; 16]); // resolution and palette sizes
+    data
+}
+
+// rows are padded to 4 bytes
+pub fn encode_bmp_row(row: &[ColorCode], data: &mut Vec<u8>) {
+    for color in row {
+        data.extend_from_slice(&[color.b, color.g, color.r]);
+    }
+    data.resize(
+        data.len() + (row.len() * 3).next_multiple_of(4) - row.len() * 3,
+        0,
+    );
+}
+
+// followed by the rows top-down, see encode_ppm_row
+pub fn ppm_file_header(width: usize, height: usize) -> Vec<u8> {
+    format!("P6\n{} {}\n255\n", width, height).into_bytes()
+}
+
+pub fn encode_ppm_row(row: &[ColorCode], data: &mut Vec<u8>) {
+    for color in row {
+        data.extend_from_slice(&[color.r, color.g, color.b]);
+    }
+}
+
+// pixel buffers are large, running out of memory is an error rather than a panic
+fn pixel_buffer(len: usize) -> Result<Vec<ColorCode>> {
+    let mut pixels = Vec::new();
+    pixels.try_reserve_exact(len).map_err(|_| "Out of memory")?;
+    Ok(pixels)
+}
+
+pub fn load(path: &str) -> Result<Image> {
+    Image::decode(&vfs::read_to_end(path)?)
+}
+
+// centred on the screen and scaled down to fit, e.g. as a boot splash
+pub fn show(path: &str) -> Result<()> {
+    let screen = framebuffer::visible_rect()?;
+    let image = load(path)?.fit(screen.width, screen.height)?;
+    let x = screen.x + (screen.width - image.width()) / 2;
+    let y = screen.y + (screen.height - image.height()) / 2;
+    framebuffer::draw(|draw, _| draw.draw_image(x as isize, y as isize, &image))?
+}
@@ -0,0 +1,190 @@
+use crate::{
+    addr::MmioAddress,
+    asm,
+    error::{Error, Result},
+    mutex::Mutex,
+};
+
+// 0-63: GPU peripheral interrupts, 64-71: ARM basic interrupts
+pub const LINE_COUNT: usize = 72;
+
+pub const LINE_SYSTEM_TIMER_1: usize = 1;
+pub const LINE_SYSTEM_TIMER_3: usize = 3;
+pub const LINE_AUX: usize = 29;
+pub const LINE_GPIO_0: usize = 49;
+pub const LINE_UART: usize = 57;
+pub const LINE_EMMC: usize = 62;
+pub const LINE_ARM_TIMER: usize = 64;
+
+static mut INTERRUPT_CONTROLLER: Mutex<InterruptController> =
+    Mutex::new(InterruptController::new());
+
+fn mmio_base_interrupt() -> MmioAddress {
+    MmioAddress::new(0xb200)
+}
+
+fn read_irq_basic_pending() -> u32 {
+    mmio_base_interrupt().offset(0x00).read()
+}
+
+fn read_irq_pending(bank: usize) -> u32 {
+    mmio_base_interrupt().offset(0x04 + bank * 4).read()
+}
+
+fn write_enable_irqs(bank: usize, value: u32) {
+    mmio_base_interrupt().offset(0x10 + bank * 4).write(value);
+}
+
+fn write_enable_basic_irqs(value: u32) {
+    mmio_base_interrupt().offset(0x18).write(value);
+}
+
+fn write_disable_irqs(bank: usize, value: u32) {
+    mmio_base_interrupt().offset(0x1c + bank * 4).write(value);
+}
+
+fn write_disable_basic_irqs(value: u32) {
+    mmio_base_interrupt().offset(0x24).write(value);
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct InterruptLine {
+    pub name: &'static str,
+    pub count: u64,
+    pub enabled: bool,
+}
+
+struct InterruptController {
+    handlers: [Option<fn()>; LINE_COUNT],
+    lines: [InterruptLine; LINE_COUNT],
+    spurious_count: u64,
+}
+
+impl InterruptController {
+    const fn new() -> Self {
+        Self {
+            handlers: [None; LINE_COUNT],
+            lines: [InterruptLine {
+                name: "",
+                count: 0,
+                enabled: false,
+            }; LINE_COUNT],
+            spurious_count: 0,
+        }
+    }
+
+    fn set_enabled(&mut self, line: usize, enabled: bool) {
+        let mask = 1 << (line % 32);
+        match (line / 32, enabled) {
+            (bank @ (0 | 1), true) => write_enable_irqs(bank, mask),
+            (bank @ (0 | 1), false) => write_disable_irqs(bank, mask),
+            (_, true) => write_enable_basic_irqs(mask),
+            (_, false) => write_disable_basic_irqs(mask),
+        }
+
+        self.lines[line].enabled = enabled;
+    }
+
+    fn register(&mut self, line: usize, name: &'static str, handler: fn()) -> Result<()> {
+        if line >= LINE_COUNT {
+            return Err(Error::InvalidArgument);
+        }
+
+        if self.handlers[line].is_some() {
+            return Err("Interrupt handler is already registered".into());
+        }
+
+        self.handlers[line] = Some(handler);
+        self.lines[line].name = name;
+        self.set_enabled(line, true);
+        Ok(())
+    }
+
+    fn unregister(&mut self, line: usize) -> Result<()> {
+        if line >= LINE_COUNT {
+            return Err(Error::InvalidArgument);
+        }
+
+        self.set_enabled(line, false);
+        self.handlers[line] = None;
+        self.lines[line].name = "";
+        Ok(())
+    }
+
+    fn pending_line(&self) -> Option<usize> {
+        let basic = read_irq_basic_pending();
+        if basic & 0xff != 0 {
+            return Some(64 + basic.trailing_zeros() as usize);
+        }
+
+        for bank in 0..2 {
+            let pending = read_irq_pending(bank);
+            if pending != 0 {
+                return Some(bank * 32 + pending.trailing_zeros() as usize);
+            }
+        }
+
+        None
+    }
+}
+
+pub fn handle_irq() {
+    loop {
+        // do not hold the lock while calling the handler
+        let handler = {
+            let mut controller = match unsafe { INTERRUPT_CONTROLLER.try_lock() } {
+                Ok(controller) => controller,
+                Err(_) => return,
+            };
+
+            let line = match controller.pending_line() {
+                Some(line) => line,
+                None => {
+                    controller.spurious_count += 1;
+                    return;
+                }
+            };
+
+            controller.lines[line].count += 1;
+            match controller.handlers[line] {
+                Some(handler) => handler,
+                None => {
+                    // avoid an interrupt storm from a line nobody handles
+                    controller.set_enabled(line, false);
+                    continue;
+                }
+            }
+        };
+
+        handler();
+    }
+}
+
+pub fn register_handler(line: usize, name: &'static str, handler: fn()) -> Result<()> {
+    asm::disabled_int(|| unsafe { INTERRUPT_CONTROLLER.try_lock() }?.register(line, name, handler))
+}
+
+pub fn unregister_handler(line: usize) -> Result<()> {
+    asm::disabled_int(|| unsafe { INTERRUPT_CONTROLLER.try_lock() }?.unregister(line))
+}
+
+pub fn lines() -> Result<[InterruptLine; LINE_COUNT]> {
+    asm::disabled_int(|| Ok(unsafe { INTERRUPT_CONTROLLER.try_lock() }?.lines))
+}
+
+pub fn spurious_count() -> Result<u64> {
+    asm::disabled_int(|| Ok(unsafe { INTERRUPT_CONTROLLER.try_lock() }?.spurious_count))
+}
+
+pub fn init() -> Result<()> {
+    asm::disabled_int(|| -> Result<()> {
+        let mut controller = unsafe { INTERRUPT_CONTROLLER.try_lock() }?;
+        for line in 0..LINE_COUNT {
+            controller.set_enabled(line, false);
+        }
+        Ok(())
+    })?;
+
+    asm::enable_int();
+    Ok(())
+}
@@ -0,0 +1,305 @@
+use crate::{asm, console, error::Result, mutex::Mutex, timer};
+use alloc::{collections::VecDeque, format, string::String, vec::Vec};
+use core::fmt::{self, Write};
+
+static mut LOGGER: Mutex<Logger> = Mutex::new(Logger::new());
+
+// messages above these levels are compiled out
+pub const STATIC_MAX_LEVEL: Level = Level::Trace;
+// per module overrides, the longest matching module path wins, e.g. ("kernel::emmc", Level::Info)
+const STATIC_MODULE_LEVELS: &[(&str, Level)] = &[];
+
+const DEFAULT_LEVEL: Level = Level::Info;
+// number of buffered records
+const LOG_RECORD_COUNT: usize = 256;
+// longer messages are truncated
+const LOG_MESSAGE_SIZE: usize = 200;
+
+// Off is only meaningful as a filter
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[repr(u8)]
+pub enum Level {
+    Off = 0,
+    Error = 1,
+    Warn = 2,
+    Info = 3,
+    Debug = 4,
+    Trace = 5,
+}
+
+impl Level {
+    pub fn parse(s: &str) -> Option<Self> {
+        let level = match s {
+            "off" => Self::Off,
+            "error" => Self::Error,
+            "warn" => Self::Warn,
+            "info" => Self::Info,
+            "debug" => Self::Debug,
+            "trace" => Self::Trace,
+            _ => return None,
+        };
+
+        Some(level)
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Off => "OFF",
+            Self::Error => "ERROR",
+            Self::Warn => "WARN",
+            Self::Info => "INFO",
+            Self::Debug => "DEBUG",
+            Self::Trace => "TRACE",
+        }
+    }
+}
+
+// fixed size so that formatting a message does not allocate
+#[derive(Clone)]
+pub struct Message {
+    buf: [u8; LOG_MESSAGE_SIZE],
+    len: usize,
+}
+
+impl Message {
+    const fn new() -> Self {
+        Self {
+            buf: [0; LOG_MESSAGE_SIZE],
+            len: 0,
+        }
+    }
+
+    pub fn as_str(&self) -> &str {
+        // only whole characters are copied in
+        core::str::from_utf8(&self.buf[..self.len]).unwrap_or_default()
+    }
+}
+
+impl fmt::Write for Message {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let mut len = s.len().min(LOG_MESSAGE_SIZE - self.len);
+        while !s.is_char_boundary(len) {
+            len -= 1;
+        }
+
+        self.buf[self.len..self.len + len].copy_from_slice(&s.as_bytes()[..len]);
+        self.len += len;
+        Ok(())
+    }
+}
+
+impl fmt::Debug for Message {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self.as_str(), f)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Record {
+    pub timestamp_us: u64,
+    pub core: u8,
+    pub level: Level,
+    pub module: &'static str,
+    pub message: Message,
+}
+
+impl fmt::Display for Record {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "[{:>5}.{:06}] {} {:<5} {}: {}",
+            self.timestamp_us / 1_000_000,
+            self.timestamp_us % 1_000_000,
+            self.core,
+            self.level.as_str(),
+            self.module,
+            self.message.as_str()
+        )
+    }
+}
+
+struct Logger {
+    max_level: Level,
+    module_levels: Vec<(String, Level)>,
+    records: VecDeque<Record>,
+    dropped: usize,
+}
+
+impl Logger {
+    const fn new() -> Self {
+        Self {
+            max_level: DEFAULT_LEVEL,
+            module_levels: Vec::new(),
+            records: VecDeque::new(),
+            dropped: 0,
+        }
+    }
+
+    fn level(&self, module: &str) -> Level {
+        self.module_levels
+            .iter()
+            .filter(|(prefix, _)| module_matches(module.as_bytes(), prefix.as_bytes()))
+            .max_by_key(|(prefix, _)| prefix.len())
+            .map_or(self.max_level, |(_, level)| *level)
+    }
+
+    fn push(&mut self, record: Record) {
+        // the buffer is allocated once, later pushes never grow it
+        if self.records.capacity() < LOG_RECORD_COUNT
+            && self.records.try_reserve_exact(LOG_RECORD_COUNT).is_err()
+        {
+            self.dropped += 1;
+            return;
+        }
+
+        if self.records.len() >= LOG_RECORD_COUNT {
+            self.records.pop_front();
+            self.dropped += 1;
+        }
+        self.records.push_back(record);
+    }
+}
+
+// "kernel::emmc" matches "kernel::emmc" and "kernel::emmc::foo" but not "kernel::emmc2"
+const fn module_matches(module: &[u8], prefix: &[u8]) -> bool {
+    if module.len() < prefix.len() {
+        return false;
+    }
+
+    let mut i = 0;
+    while i < prefix.len() {
+        if module[i] != prefix[i] {
+            return false;
+        }
+        i += 1;
+    }
+
+    module.len() == prefix.len() || (module[i] == b':' && module.len() > i + 1)
+}
+
+pub const fn static_enabled(level: Level, module: &str) -> bool {
+    let mut max_level = STATIC_MAX_LEVEL;
+    let mut matched_len = 0;
+
+    let mut i = 0;
+    while i < STATIC_MODULE_LEVELS.len() {
+        let (prefix, module_level) = STATIC_MODULE_LEVELS[i];
+        if prefix.len() >= matched_len && module_matches(module.as_bytes(), prefix.as_bytes()) {
+            max_level = module_level;
+            matched_len = prefix.len();
+        }
+        i += 1;
+    }
+
+    level as u8 <= max_level as u8
+}
+
+// module paths may be given without the crate name
+fn module_path(module: &str) -> String {
+    if module == "kernel" || module.starts_with("kernel::") {
+        module.into()
+    } else {
+        format!("kernel::{}", module)
+    }
+}
+
+pub fn set_max_level(level: Level) -> Result<()> {
+    let mut logger = unsafe { LOGGER.try_lock() }?;
+    logger.max_level = level;
+    Ok(())
+}
+
+pub fn max_level() -> Result<Level> {
+    let logger = unsafe { LOGGER.try_lock() }?;
+    Ok(logger.max_level)
+}
+
+pub fn set_module_level(module: &str, level: Level) -> Result<()> {
+    let mut logger = unsafe { LOGGER.try_lock() }?;
+    let module = module_path(module);
+    logger.module_levels.retain(|(m, _)| *m != module);
+    logger.module_levels.push((module, level));
+    Ok(())
+}
+
+pub fn module_levels() -> Result<Vec<(String, Level)>> {
+    let logger = unsafe { LOGGER.try_lock() }?;
+    Ok(logger.module_levels.clone())
+}
+
+pub fn enabled(level: Level, module: &str) -> bool {
+    match unsafe { LOGGER.try_lock() } {
+        Ok(logger) => level <= logger.level(module),
+        // logging from inside the logger
+        Err(_) => level <= DEFAULT_LEVEL,
+    }
+}
+
+pub fn records() -> Result<Vec<Record>> {
+    let logger = unsafe { LOGGER.try_lock() }?;
+    Ok(logger.records.iter().cloned().collect())
+}
+
+pub fn dropped_count() -> Result<usize> {
+    let logger = unsafe { LOGGER.try_lock() }?;
+    Ok(logger.dropped)
+}
+
+// does not allocate itself, but console backends may, so logging from
+// exception or interrupt context is best effort and can lose output
+pub fn _log(level: Level, module: &'static str, args: fmt::Arguments) {
+    if !enabled(level, module) {
+        return;
+    }
+
+    let mut message = Message::new();
+    let _ = message.write_fmt(args);
+    let record = Record {
+        timestamp_us: timer::uptime_us(),
+        core: (asm::read_mpidr() & 0xff) as u8,
+        level,
+        module,
+        message,
+    };
+
+    console::_print(format_args!("{}\n", record));
+
+    if let Ok(mut logger) = unsafe { LOGGER.try_lock() } {
+        logger.push(record);
+    }
+}
+
+#[macro_export]
+macro_rules! log {
+    ($level:expr, $($arg:tt)+) => {{
+        const ENABLED: bool = $crate::log::static_enabled($level, module_path!());
+        if ENABLED {
+            $crate::log::_log($level, module_path!(), format_args!($($arg)+));
+        }
+    }};
+}
+
+#[macro_export]
+macro_rules! error {
+    ($($arg:tt)+) => ($crate::log!($crate::log::Level::Error, $($arg)+));
+}
+
+#[macro_export]
+macro_rules! warn {
+    ($($arg:tt)+) => ($crate::log!($crate::log::Level::Warn, $($arg)+));
+}
+
+#[macro_export]
+macro_rules! info {
+    ($($arg:tt)+) => ($crate::log!($crate::log::Level::Info, $($arg)+));
+}
+
+#[macro_export]
+macro_rules! debug {
+    ($($arg:tt)+) => ($crate::log!($crate::log::Level::Debug, $($arg)+));
+}
+
+#[macro_export]
+macro_rules! trace {
+    ($($arg:tt)+) => ($crate::log!($crate::log::Level::Trace, $($arg)+));
+}
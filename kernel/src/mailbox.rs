@@ -1,8 +1,10 @@
 use crate::{
     addr::{MmioAddress, VirtualAddress},
-    error::Result,
+    color::ColorCode,
+    error::{Error, Result},
     framebuffer::{FramebufferInfo, PixelFormat},
 };
+use alloc::{string::String, vec::Vec};
 
 // https://github.com/raspberrypi/firmware/wiki/Mailbox-property-interface
 // https://github.com/qemu/qemu/blob/master/hw/misc/bcm2835_property.c
@@ -120,11 +122,13 @@ enum TagId {
     FramebufferGetPalette = 0x4000b,
     FramebufferTestPalette = 0x4400b,
     FramebufferSetPalette = 0x4800b,
+    FramebufferSetVsync = 0x4800e,
     FramebufferSetCursorInfo = 0x8010,
     FramebufferSetCursorState = 0x8011,
     FramebufferSetScreenGamma = 0x8012,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[repr(u32)]
 pub enum ClockId {
     Emmc = 0x1,
@@ -143,6 +147,30 @@ pub enum ClockId {
     PixelBvb = 0xe,
 }
 
+// how the display treats the alpha byte of 32-bit pixels, the framebuffer writes it as 0
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u32)]
+pub enum AlphaMode {
+    // 0 is opaque
+    Enabled = 0,
+    // 0 is transparent
+    Reversed = 1,
+    Ignored = 2,
+}
+
+impl TryFrom<u32> for AlphaMode {
+    type Error = Error;
+
+    fn try_from(value: u32) -> Result<Self> {
+        match value {
+            0 => Ok(Self::Enabled),
+            1 => Ok(Self::Reversed),
+            2 => Ok(Self::Ignored),
+            _ => Err(Error::InvalidArgument),
+        }
+    }
+}
+
 #[repr(u32)]
 enum TagStatus {
     Request = 0,
@@ -151,6 +179,7 @@ enum TagStatus {
 }
 
 const TAG_LAST: u32 = 0;
+const PALETTE_LEN: usize = 256;
 
 #[repr(C)]
 struct Tag<const N: usize>([u32; N]);
@@ -175,12 +204,13 @@ impl<const N: usize> Tag<N> {
     }
 }
 
+// large enough for the command line tag
 #[repr(C, align(16))]
-struct Mailbox([u32; 36]);
+struct Mailbox([u32; 288]);
 
 impl Mailbox {
     fn new() -> Self {
-        Self([0; 36])
+        Self([0; 288])
     }
 
     fn inner_slice(&self) -> &[u32] {
@@ -279,6 +309,96 @@ pub fn get_firmware_revision() -> Result<u32> {
     Ok(tag_s[3])
 }
 
+pub fn get_board_model() -> Result<u32> {
+    let mut mbox = Mailbox::new();
+    let mut tag: Tag<5> = Tag::new(TagId::HardwareGetBoardModel, TagStatus::Request);
+    let tag_s = tag.slice_mut();
+    tag_s[3] = 0; // response buffer
+    tag_s[4] = TAG_LAST; // last
+    let offset = mbox.write_tag(tag.slice())?;
+    mbox.call(Channel::PropertyTags)?;
+    let tag_s: &[u32] = &mbox.inner_slice()[offset..offset + 5];
+
+    if tag_s[2] & TagStatus::Response as u32 == 0 {
+        return Err("Mailbox response error".into());
+    }
+
+    Ok(tag_s[3])
+}
+
+pub fn get_board_revision() -> Result<u32> {
+    let mut mbox = Mailbox::new();
+    let mut tag: Tag<5> = Tag::new(TagId::HardwareGetBoardRevision, TagStatus::Request);
+    let tag_s = tag.slice_mut();
+    tag_s[3] = 0; // response buffer
+    tag_s[4] = TAG_LAST; // last
+    let offset = mbox.write_tag(tag.slice())?;
+    mbox.call(Channel::PropertyTags)?;
+    let tag_s: &[u32] = &mbox.inner_slice()[offset..offset + 5];
+
+    if tag_s[2] & TagStatus::Response as u32 == 0 {
+        return Err("Mailbox response error".into());
+    }
+
+    Ok(tag_s[3])
+}
+
+pub fn get_board_serial() -> Result<u64> {
+    let mut mbox = Mailbox::new();
+    let mut tag: Tag<6> = Tag::new(TagId::HardwareGetBoardSerial, TagStatus::Request);
+    let tag_s = tag.slice_mut();
+    tag_s[3] = 0; // response buffer
+    tag_s[4] = 0; // response buffer
+    tag_s[5] = TAG_LAST; // last
+    let offset = mbox.write_tag(tag.slice())?;
+    mbox.call(Channel::PropertyTags)?;
+    let tag_s: &[u32] = &mbox.inner_slice()[offset..offset + 6];
+
+    if tag_s[2] & TagStatus::Response as u32 == 0 {
+        return Err("Mailbox response error".into());
+    }
+
+    Ok((tag_s[4] as u64) << 32 | tag_s[3] as u64)
+}
+
+// returns (base address, size)
+pub fn get_arm_memory() -> Result<(u32, u32)> {
+    let mut mbox = Mailbox::new();
+    let mut tag: Tag<6> = Tag::new(TagId::HardwareGetArmMemory, TagStatus::Request);
+    let tag_s = tag.slice_mut();
+    tag_s[3] = 0; // response buffer
+    tag_s[4] = 0; // response buffer
+    tag_s[5] = TAG_LAST; // last
+    let offset = mbox.write_tag(tag.slice())?;
+    mbox.call(Channel::PropertyTags)?;
+    let tag_s: &[u32] = &mbox.inner_slice()[offset..offset + 6];
+
+    if tag_s[2] & TagStatus::Response as u32 == 0 {
+        return Err("Mailbox response error".into());
+    }
+
+    Ok((tag_s[3], tag_s[4]))
+}
+
+// returns (base address, size)
+pub fn get_videocore_memory() -> Result<(u32, u32)> {
+    let mut mbox = Mailbox::new();
+    let mut tag: Tag<6> = Tag::new(TagId::HardwareGetVideoCoreMemory, TagStatus::Request);
+    let tag_s = tag.slice_mut();
+    tag_s[3] = 0; // response buffer
+    tag_s[4] = 0; // response buffer
+    tag_s[5] = TAG_LAST; // last
+    let offset = mbox.write_tag(tag.slice())?;
+    mbox.call(Channel::PropertyTags)?;
+    let tag_s: &[u32] = &mbox.inner_slice()[offset..offset + 6];
+
+    if tag_s[2] & TagStatus::Response as u32 == 0 {
+        return Err("Mailbox response error".into());
+    }
+
+    Ok((tag_s[3], tag_s[4]))
+}
+
 // https://jsandler18.github.io/extra/prop-channel.html
 pub fn init_framebuffer(
     scr_wh: (u32, u32),
@@ -384,9 +504,143 @@ pub fn init_framebuffer(
         info.buf_size = tag_s[4] as usize;
     }
 
+    // get pitch, rows may be padded beyond the virtual width
+    {
+        let mut mbox = Mailbox::new();
+        let mut tag: Tag<5> = Tag::new(TagId::FramebufferGetPitch, TagStatus::Request);
+        let tag_s = tag.slice_mut();
+        tag_s[3] = 0; // response buffer
+        tag_s[4] = TAG_LAST; // last
+        let offset = mbox.write_tag(tag.slice())?;
+        mbox.call(Channel::PropertyTags)?;
+        let tag_s: &[u32] = &mbox.inner_slice()[offset..offset + 5];
+
+        if tag_s[2] & TagStatus::Response as u32 == 0 {
+            return Err("Mailbox response error".into());
+        }
+
+        info.pitch = tag_s[3] as usize;
+    }
+
+    // 8-bit pixels index the xterm palette
+    if info.depth == 8 {
+        let mut palette = [0; PALETTE_LEN];
+        for (i, entry) in palette.iter_mut().enumerate() {
+            *entry = ColorCode::from_ansi(i as u8).to_u32(PixelFormat::Bgr);
+        }
+        set_palette(0, &palette)?;
+    }
+
     Ok(info)
 }
 
+// entries are 0x00bbggrr
+pub fn set_palette(first: u32, entries: &[u32]) -> Result<()> {
+    if first as usize + entries.len() > PALETTE_LEN {
+        return Err(Error::InvalidArgument);
+    }
+
+    let mut mbox = Mailbox::new();
+    let mut tag: Tag<{ PALETTE_LEN + 6 }> =
+        Tag::new(TagId::FramebufferSetPalette, TagStatus::Request);
+    let tag_s = tag.slice_mut();
+    tag_s[3] = first; // offset
+    tag_s[4] = entries.len() as u32; // length
+    tag_s[5..5 + entries.len()].copy_from_slice(entries); // palette
+    tag_s[PALETTE_LEN + 5] = TAG_LAST; // last
+    let offset = mbox.write_tag(tag.slice())?;
+    mbox.call(Channel::PropertyTags)?;
+    let tag_s: &[u32] = &mbox.inner_slice()[offset..offset + 4];
+
+    if tag_s[2] & TagStatus::Response as u32 == 0 {
+        return Err("Mailbox response error".into());
+    }
+
+    // 0 if the palette was accepted
+    if tag_s[3] != 0 {
+        return Err(Error::InvalidArgument);
+    }
+
+    Ok(())
+}
+
+// moves the visible area within the virtual framebuffer
+pub fn set_virtual_offset(x: u32, y: u32) -> Result<()> {
+    let mut mbox = Mailbox::new();
+    let mut tag: Tag<6> = Tag::new(TagId::FramebufferSetVirtualOffset, TagStatus::Request);
+    let tag_s = tag.slice_mut();
+    tag_s[3] = x; // x
+    tag_s[4] = y; // y
+    tag_s[5] = TAG_LAST; // last
+    let offset = mbox.write_tag(tag.slice())?;
+    mbox.call(Channel::PropertyTags)?;
+    let tag_s: &[u32] = &mbox.inner_slice()[offset..offset + 6];
+
+    if tag_s[2] & TagStatus::Response as u32 == 0 {
+        return Err("Mailbox response error".into());
+    }
+
+    // the firmware returns the offset it applied
+    if (tag_s[3], tag_s[4]) != (x, y) {
+        return Err(Error::InvalidArgument);
+    }
+
+    Ok(())
+}
+
+pub fn get_alpha_mode() -> Result<AlphaMode> {
+    let mut mbox = Mailbox::new();
+    let mut tag: Tag<5> = Tag::new(TagId::FramebufferGetAlphaMode, TagStatus::Request);
+    let tag_s = tag.slice_mut();
+    tag_s[3] = 0; // response buffer
+    tag_s[4] = TAG_LAST; // last
+    let offset = mbox.write_tag(tag.slice())?;
+    mbox.call(Channel::PropertyTags)?;
+    let tag_s: &[u32] = &mbox.inner_slice()[offset..offset + 5];
+
+    if tag_s[2] & TagStatus::Response as u32 == 0 {
+        return Err("Mailbox response error".into());
+    }
+
+    AlphaMode::try_from(tag_s[3])
+}
+
+// returns the mode the firmware applied
+pub fn set_alpha_mode(mode: AlphaMode) -> Result<AlphaMode> {
+    let mut mbox = Mailbox::new();
+    let mut tag: Tag<5> = Tag::new(TagId::FramebufferSetAlphaMode, TagStatus::Request);
+    let tag_s = tag.slice_mut();
+    tag_s[3] = mode as u32; // alpha mode
+    tag_s[4] = TAG_LAST; // last
+    let offset = mbox.write_tag(tag.slice())?;
+    mbox.call(Channel::PropertyTags)?;
+    let tag_s: &[u32] = &mbox.inner_slice()[offset..offset + 5];
+
+    if tag_s[2] & TagStatus::Response as u32 == 0 {
+        return Err("Mailbox response error".into());
+    }
+
+    AlphaMode::try_from(tag_s[3])
+}
+
+// returns after the next vertical blank
+pub fn wait_for_vsync() -> Result<()> {
+    let mut mbox = Mailbox::new();
+    let mut tag: Tag<5> = Tag::new(TagId::FramebufferSetVsync, TagStatus::Request);
+    let tag_s = tag.slice_mut();
+    tag_s[3] = 0; // unused
+    tag_s[4] = TAG_LAST; // last
+    let offset = mbox.write_tag(tag.slice())?;
+    mbox.call(Channel::PropertyTags)?;
+    let tag_s: &[u32] = &mbox.inner_slice()[offset..offset + 5];
+
+    if tag_s[2] & TagStatus::Response as u32 == 0 {
+        return Err("Mailbox response error".into());
+    }
+
+    Ok(())
+}
+
 pub fn set_clock_rate(clock_id: ClockId, rate: u32) -> Result<()> {
     let mut mbox = Mailbox::new();
     let mut tag: Tag<7> = Tag::new(TagId::ClocksSetClockRate, TagStatus::Request);
@@ -405,3 +659,70 @@ pub fn set_clock_rate(clock_id: ClockId, rate: u32) -> Result<()> {
 
     Ok(())
 }
+
+pub fn get_clock_rate(clock_id: ClockId) -> Result<u32> {
+    let mut mbox = Mailbox::new();
+    let mut tag: Tag<6> = Tag::new(TagId::ClocksGetClockRate, TagStatus::Request);
+    let tag_s = tag.slice_mut();
+    tag_s[3] = clock_id as u32; // clock id
+    tag_s[4] = 0; // response buffer
+    tag_s[5] = TAG_LAST; // last
+    let offset = mbox.write_tag(tag.slice())?;
+    mbox.call(Channel::PropertyTags)?;
+    let tag_s: &[u32] = &mbox.inner_slice()[offset..offset + 6];
+
+    if tag_s[2] & TagStatus::Response as u32 == 0 {
+        return Err("Mailbox response error".into());
+    }
+
+    Ok(tag_s[4])
+}
+
+// returns the mask of DMA channels usable by the ARM
+pub fn get_dma_channels() -> Result<u32> {
+    let mut mbox = Mailbox::new();
+    let mut tag: Tag<5> = Tag::new(
+        TagId::SharedResourceManagementGetDmaChannels,
+        TagStatus::Request,
+    );
+    let tag_s = tag.slice_mut();
+    tag_s[3] = 0; // response buffer
+    tag_s[4] = TAG_LAST; // last
+    let offset = mbox.write_tag(tag.slice())?;
+    mbox.call(Channel::PropertyTags)?;
+    let tag_s: &[u32] = &mbox.inner_slice()[offset..offset + 5];
+
+    if tag_s[2] & TagStatus::Response as u32 == 0 {
+        return Err("Mailbox response error".into());
+    }
+
+    Ok(tag_s[3])
+}
+
+// returns the command line passed by the firmware (cmdline.txt and its own additions)
+pub fn get_command_line() -> Result<String> {
+    const BUF_WORDS: usize = 256;
+
+    let mut mbox = Mailbox::new();
+    let mut tag: Tag<{ BUF_WORDS + 4 }> = Tag::new(TagId::ConfigGetCommandLine, TagStatus::Request);
+    let tag_s = tag.slice_mut();
+    tag_s[BUF_WORDS + 3] = TAG_LAST; // last
+    let offset = mbox.write_tag(tag.slice())?;
+    mbox.call(Channel::PropertyTags)?;
+    let tag_s: &[u32] = &mbox.inner_slice()[offset..offset + BUF_WORDS + 4];
+
+    if tag_s[2] & TagStatus::Response as u32 == 0 {
+        return Err("Mailbox response error".into());
+    }
+
+    // response length in bytes, truncated if the buffer is too small
+    let len = ((tag_s[2] & !(TagStatus::Response as u32)) as usize).min(BUF_WORDS * 4);
+    let bytes: Vec<u8> = tag_s[3..3 + BUF_WORDS]
+        .iter()
+        .flat_map(|w| w.to_le_bytes())
+        .take(len)
+        .take_while(|&b| b != 0)
+        .collect();
+
+    Ok(String::from_utf8_lossy(&bytes).into_owned())
+}
@@ -2,29 +2,60 @@
 #![no_main]
 #![feature(sync_unsafe_cell)]
 
+extern crate alloc;
+
 use addr::VirtualAddress;
+use alloc::{boxed::Box, rc::Rc};
+use cmdline::VideoMode;
 use color::ColorCode;
-use device_tree::DeviceTree;
+use core::cell::RefCell;
 use framebuffer::PixelFormat;
 
 mod addr;
+mod allocator;
+mod ansi;
 mod asm;
+mod backtrace;
+mod block;
 mod boot;
+mod breakpoint;
+mod cmdline;
 mod color;
 mod console;
 mod cpu;
+mod devfs;
 mod device_tree;
+mod dma;
 mod draw;
+mod emmc;
 mod error;
+mod exception;
+mod ext2;
+mod fat;
 mod fdt;
 mod font;
 mod framebuffer;
 mod framebuffer_console;
+mod gdb;
 mod gpio;
+mod image;
+mod interrupt;
+mod log;
 mod mailbox;
 mod mutex;
 mod panic;
+mod panic_screen;
+mod partition;
+mod power;
+mod procfs;
+mod rng;
+mod screenshot;
+mod shell;
+mod symbols;
+mod timer;
+mod tmpfs;
 mod uart;
+mod vfs;
 
 #[no_mangle]
 pub extern "C" fn kernel_main() -> ! {
@@ -38,31 +69,158 @@ pub extern "C" fn kernel_main() -> ! {
     unreachable!();
 }
 
+fn init_video(mode: VideoMode) -> error::Result<()> {
+    let wh = (mode.width, mode.height);
+    // the console scrolls by moving the visible area within twice the screen height
+    let virt_height = mode
+        .height
+        .checked_mul(2)
+        .ok_or(error::Error::InvalidArgument)?;
+    let virt_wh = (mode.width, virt_height);
+    let fb_info = mailbox::init_framebuffer(wh, virt_wh, mode.depth, PixelFormat::default())?;
+    framebuffer::init(fb_info)?;
+    framebuffer_console::init(ColorCode::GREEN, ColorCode::BLACK)
+}
+
 fn kernel_main2(fdt_addr: VirtualAddress) -> error::Result<()> {
-    let _device_tree = DeviceTree::new(fdt_addr)?;
+    allocator::init()?;
+    font::init()?;
+    console::init()?;
+    uart::register_consoles()?;
+    exception::init()?;
+    breakpoint::init()?;
+    interrupt::init()?;
+    device_tree::init(fdt_addr)?;
     let cpu_model = cpu::detect_cpu_model()?;
+    cmdline::init()?;
+    let cmdline = cmdline::get()?;
+    if let Some(level) = cmdline.log_level() {
+        log::set_max_level(level)?;
+    }
+    for (module, level) in cmdline.module_log_levels() {
+        log::set_module_level(module, level)?;
+    }
 
     // uart::init()?;
-    println!("Starting kernel...");
-    println!("FDT addr: {:?}", fdt_addr);
-    println!("CPU: {:?}", cpu_model);
+    let consoles = cmdline.consoles();
+
+    // the framebuffer is only set up when asked for
+    let video_mode = match cmdline.video_mode() {
+        Some(mode) => Some(mode),
+        None if consoles.contains(&"fb") => Some(VideoMode {
+            width: 640,
+            height: 480,
+            depth: 32,
+        }),
+        None => None,
+    };
+    if let Some(mode) = video_mode {
+        if let Err(err) = init_video(mode) {
+            warn!("Framebuffer is not available: {:?}", err);
+        }
+    }
+
+    if !consoles.is_empty() {
+        console::select(&consoles)?;
+    }
+
+    if let Some(port) = cmdline.gdb_port() {
+        gdb::init(port)?;
+    }
+
+    info!("Starting kernel...");
+    info!("Command line: {}", cmdline.raw());
+    info!("FDT addr: {:?}", fdt_addr);
+    info!("CPU: {:?}", cpu_model);
     // println!(
     //     "Firmware revision: 0x{:x}",
     //     mailbox::get_firmware_revision()?
     // );
 
-    // let fb_info = mailbox::init_framebuffer((480, 320), (480, 320), 32, PixelFormat::default())?;
-    // framebuffer::init(fb_info)?;
-    // framebuffer_console::init(ColorCode::GREEN, ColorCode::BLACK)?;
-    // println!("Framebuffer: {:?}", fb_info);
+    // the root filesystem lives on the SD card, tmpfs stands in when there is none
+    let mut boot_fs = None;
+    let mut root_fs = None;
+    match emmc::init(emmc::TransferMode::InterruptDma) {
+        Ok(()) => {
+            info!("SD card: {:?}", emmc::card_info()?);
+            let device = block::BufferCache::new(emmc::EmmcBlockDevice::new()?, 64);
+            block::register("mmcblk0", Rc::new(RefCell::new(device)))?;
+            match partition::scan("mmcblk0") {
+                Ok(partitions) => {
+                    for partition in partitions {
+                        info!("mmcblk0p{}: {:?}", partition.index, partition);
+                    }
+                }
+                Err(err) => warn!("No partitions found on mmcblk0: {:?}", err),
+            }
 
-    loop {
-        let c = match uart::receive() {
-            Ok(c) => c,
-            Err(_) => {
-                continue;
+            // the first partition holds the firmware, config.txt and the kernel
+            match block::get("mmcblk0p1").and_then(fat::Fat::new) {
+                Ok(fs) => boot_fs = Some(fs),
+                Err(err) => warn!("Boot partition is not available: {:?}", err),
             }
-        };
-        print!("{}", c);
+
+            // a bare filesystem image has no partition table
+            let root_device = if block::get("mmcblk0p2").is_ok() {
+                "mmcblk0p2"
+            } else {
+                "mmcblk0"
+            };
+            match block::get(root_device).and_then(ext2::Ext2::new) {
+                Ok(fs) => root_fs = Some((root_device, fs)),
+                Err(err) => warn!("Root filesystem is not available: {:?}", err),
+            }
+        }
+        Err(err) => warn!("SD card is not available: {:?}", err),
+    }
+
+    let root: Box<dyn vfs::FileSystem> = match root_fs {
+        Some((device, fs)) => {
+            info!("Mounting {} at /", device);
+            Box::new(fs)
+        }
+        None => Box::new(tmpfs::Tmpfs::new()),
+    };
+    vfs::mount("/", root)?;
+
+    // a disk root may already have the mount points
+    for dir in ["/tmp", "/dev", "/proc", "/boot"] {
+        match vfs::mkdir(dir) {
+            Ok(()) | Err(error::Error::FileSystemError(vfs::FileSystemError::AlreadyExists)) => {}
+            Err(err) => return Err(err),
+        }
     }
+    vfs::mount("/tmp", Box::new(tmpfs::Tmpfs::new()))?;
+    vfs::mount("/dev", Box::new(devfs::Devfs::new()))?;
+    vfs::mount("/proc", Box::new(procfs::Procfs::new()))?;
+    if let Some(fs) = boot_fs {
+        vfs::mount("/boot", Box::new(fs))?;
+    }
+
+    if let Some(path) = cmdline.font() {
+        // without a framebuffer console the font only becomes the default
+        let res = font::load(path, path).and_then(|font| {
+            if framebuffer_console::is_initialized()? {
+                framebuffer_console::set_font(font)?;
+            }
+            font::set_default_font(font)
+        });
+        if let Err(err) = res {
+            warn!("Failed to load font {}: {:?}", path, err);
+        }
+    }
+
+    if let Some(path) = cmdline.splash() {
+        if let Err(err) = image::show(path) {
+            warn!("Failed to show {}: {:?}", path, err);
+        }
+    }
+
+    if let Some(init) = cmdline.init_program() {
+        if let Err(err) = shell::run_script(init) {
+            error!("Failed to run {}: {:?}", init, err);
+        }
+    }
+
+    shell::run()
 }
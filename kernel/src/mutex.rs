@@ -28,6 +28,14 @@ impl<T: Sized> Mutex<T> {
         })
     }
 
+    // takes the lock even if it is held, only for paths that never return to the holder (panic)
+    pub unsafe fn force_lock(&self) -> MutexGuard<T> {
+        asm::disabled_int(|| {
+            self.set_locked(true);
+            MutexGuard::new(self, &self.value)
+        })
+    }
+
     fn locked(&self) -> bool {
         unsafe { *self.locked.get() }
     }
@@ -1,10 +1,69 @@
-use crate::println;
-use core::panic::PanicInfo;
+use crate::{asm, backtrace, console::ConsoleWriter, exception, gdb, panic_screen, uart};
+use core::{
+    fmt::{self, Write},
+    panic::PanicInfo,
+    ptr::{self, addr_of_mut},
+};
+
+// set while a panic is reported, only the boot core runs kernel code and interrupts are
+// disabled, so a second panic can only come from the report itself
+static mut PANICKING: bool = false;
+
+// checked by the secondary cores waiting in boot.s, they halt once it is set
+#[no_mangle]
+static mut PARK_SECONDARY_CORES: u32 = 0;
+
+fn halt_other_cores() {
+    unsafe { ptr::write_volatile(addr_of_mut!(PARK_SECONDARY_CORES), 1) };
+    asm::send_event();
+}
+
+fn park() -> ! {
+    asm::disable_int();
+    loop {
+        asm::wait_for_interrupt();
+    }
+}
+
+fn report(w: &mut dyn Write, core: u64, info: &PanicInfo, fp: u64) -> fmt::Result {
+    writeln!(w, "kernel panic on core {}: {}", core, info.message())?;
+    if let Some(location) = info.location() {
+        writeln!(w, "at {}", location)?;
+    }
+
+    match exception::fault_frame() {
+        Some((kind, frame)) => {
+            writeln!(w, "{:?} exception, registers:", kind)?;
+            frame.write_registers(w)?;
+            backtrace::write(w, Some(frame.elr), frame.x[29])
+        }
+        None => backtrace::write(w, None, fp),
+    }
+}
 
 #[panic_handler]
 fn panic(info: &PanicInfo) -> ! {
-    println!("{:?}", info.message());
-    println!("{:?}", info.location());
+    asm::disable_int();
+    let fp = backtrace::current_fp();
+
+    if unsafe { PANICKING } {
+        uart::debug_puts("\npanic while panicking\n");
+        park();
+    }
+    unsafe { PANICKING = true };
+    halt_other_cores();
+
+    let core = asm::read_mpidr() & 0x3;
+
+    let _ = writeln!(ConsoleWriter);
+    let _ = report(&mut ConsoleWriter, core, info, fp);
+    panic_screen::show(|w| report(w, core, info, fp));
+
+    // the state can still be inspected if a debugger is attached
+    if gdb::port().is_some() {
+        let _ = writeln!(ConsoleWriter, "waiting for GDB");
+        let _ = gdb::enter();
+    }
 
-    loop {}
+    park()
 }
@@ -0,0 +1,107 @@
+use crate::{
+    color::ColorCode,
+    draw::Draw,
+    font::{self, PsfFont},
+    framebuffer::{self, FramebufferInfo},
+    framebuffer_console, mailbox,
+};
+use core::fmt::{self, Write};
+
+const BACK_COLOR: ColorCode = ColorCode::new(0x80, 0x00, 0x00);
+const FORE_COLOR: ColorCode = ColorCode::WHITE;
+const MARGIN: usize = 8;
+const TITLE: &str = "KERNEL PANIC";
+
+struct PanicScreen<'a> {
+    fb: &'a mut dyn Draw,
+    info: FramebufferInfo,
+    font: &'static PsfFont,
+    x: usize,
+    y: usize,
+}
+
+impl PanicScreen<'_> {
+    fn new_line(&mut self) {
+        let (_, font_height) = self.font.get_wh();
+        self.x = MARGIN;
+        self.y += font_height;
+    }
+
+    fn is_full(&self) -> bool {
+        let (_, font_height) = self.font.get_wh();
+        self.y + font_height + MARGIN > self.info.p_height
+    }
+}
+
+impl fmt::Write for PanicScreen<'_> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let (font_width, _) = self.font.get_wh();
+
+        for c in s.chars() {
+            // anything below the screen is only on the serial console
+            if self.is_full() {
+                return Ok(());
+            }
+
+            if c == '\n' {
+                self.new_line();
+                continue;
+            }
+
+            if self.x + font_width + MARGIN > self.info.p_width {
+                self.new_line();
+                if self.is_full() {
+                    return Ok(());
+                }
+            }
+
+            let glyph = self.font.glyph(c);
+            let _ = self
+                .fb
+                .draw_glyph(self.x, self.y, &glyph, FORE_COLOR, BACK_COLOR);
+            self.x += font_width;
+        }
+
+        Ok(())
+    }
+}
+
+// takes over the framebuffer if the framebuffer console is in use, ignoring its locks
+pub fn show(report: impl FnOnce(&mut dyn Write) -> fmt::Result) -> bool {
+    if !unsafe { framebuffer_console::force_is_initialized() } {
+        return false;
+    }
+    let font = match unsafe { font::force_default_font() } {
+        Some(font) => font,
+        None => return false,
+    };
+
+    let res = unsafe {
+        framebuffer::force_draw(|fb, info| {
+            let (font_width, font_height) = font.get_wh();
+            // the console may have scrolled the visible area
+            let _ = mailbox::set_virtual_offset(0, 0);
+            let _ = fb.fill(BACK_COLOR);
+
+            // title bar
+            let _ = fb.draw_rect(0, 0, info.p_width, font_height + MARGIN * 2, FORE_COLOR);
+            let title_x = info.p_width.saturating_sub(TITLE.len() * font_width) / 2;
+            for (i, c) in TITLE.chars().enumerate() {
+                let glyph = font.glyph(c);
+                let x = title_x + i * font_width;
+                let _ = fb.draw_glyph(x, MARGIN, &glyph, BACK_COLOR, FORE_COLOR);
+            }
+
+            let mut screen = PanicScreen {
+                fb,
+                info,
+                font,
+                x: MARGIN,
+                y: font_height * 2 + MARGIN * 2,
+            };
+            let _ = report(&mut screen);
+        })
+    };
+
+    res.is_some()
+}
@@ -0,0 +1,310 @@
+use crate::{
+    block::{self, BlockDevice, SharedBlockDevice},
+    error::Result,
+};
+use alloc::{format, rc::Rc, string::String, vec, vec::Vec};
+use core::cell::RefCell;
+
+const MBR_SIGNATURE: u16 = 0xaa55;
+const MBR_PARTITION_TABLE_OFFSET: usize = 446;
+const MBR_PARTITION_ENTRY_COUNT: usize = 4;
+const MBR_TYPE_EMPTY: u8 = 0x00;
+const MBR_TYPE_EXTENDED_CHS: u8 = 0x05;
+const MBR_TYPE_EXTENDED_LBA: u8 = 0x0f;
+const MBR_TYPE_GPT_PROTECTIVE: u8 = 0xee;
+const MBR_MAX_LOGICAL_PARTITIONS: usize = 64;
+
+const GPT_SIGNATURE: &[u8; 8] = b"EFI PART";
+const GPT_HEADER_LBA: u64 = 1;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PartitionKind {
+    Mbr { partition_type: u8, bootable: bool },
+    Gpt { type_guid: [u8; 16], name: String },
+}
+
+#[derive(Debug, Clone)]
+pub struct PartitionInfo {
+    pub index: usize,
+    pub start_lba: u64,
+    pub block_count: u64,
+    pub kind: PartitionKind,
+}
+
+pub struct Partition {
+    parent: SharedBlockDevice,
+    start_lba: u64,
+    block_count: u64,
+}
+
+impl Partition {
+    pub fn new(parent: SharedBlockDevice, start_lba: u64, block_count: u64) -> Self {
+        Self {
+            parent,
+            start_lba,
+            block_count,
+        }
+    }
+}
+
+impl BlockDevice for Partition {
+    fn block_size(&self) -> usize {
+        self.parent.block_size()
+    }
+
+    fn block_count(&self) -> u64 {
+        self.block_count
+    }
+
+    fn read_blocks(&mut self, lba: u64, buf: &mut [u8]) -> Result<()> {
+        self.check_range(lba, buf.len())?;
+        self.parent.read_blocks(self.start_lba + lba, buf)
+    }
+
+    fn write_blocks(&mut self, lba: u64, buf: &[u8]) -> Result<()> {
+        self.check_range(lba, buf.len())?;
+        self.parent.write_blocks(self.start_lba + lba, buf)
+    }
+
+    fn sync(&mut self) -> Result<()> {
+        self.parent.sync()
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct MbrEntry {
+    bootable: bool,
+    partition_type: u8,
+    start_lba: u64,
+    block_count: u64,
+}
+
+fn read_u16(buf: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes(buf[offset..offset + 2].try_into().unwrap())
+}
+
+fn read_u32(buf: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(buf[offset..offset + 4].try_into().unwrap())
+}
+
+fn read_u64(buf: &[u8], offset: usize) -> u64 {
+    u64::from_le_bytes(buf[offset..offset + 8].try_into().unwrap())
+}
+
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xffff_ffff_u32;
+
+    for &b in data {
+        crc ^= b as u32;
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xedb8_8320 & mask);
+        }
+    }
+
+    !crc
+}
+
+fn read_mbr_entries(sector: &[u8]) -> Option<[MbrEntry; MBR_PARTITION_ENTRY_COUNT]> {
+    if read_u16(sector, 510) != MBR_SIGNATURE {
+        return None;
+    }
+
+    let mut entries = [MbrEntry {
+        bootable: false,
+        partition_type: MBR_TYPE_EMPTY,
+        start_lba: 0,
+        block_count: 0,
+    }; MBR_PARTITION_ENTRY_COUNT];
+
+    for (i, entry) in entries.iter_mut().enumerate() {
+        let offset = MBR_PARTITION_TABLE_OFFSET + i * 16;
+        entry.bootable = sector[offset] & 0x80 != 0;
+        entry.partition_type = sector[offset + 4];
+        entry.start_lba = read_u32(sector, offset + 8) as u64;
+        entry.block_count = read_u32(sector, offset + 12) as u64;
+    }
+
+    Some(entries)
+}
+
+fn is_extended(partition_type: u8) -> bool {
+    partition_type == MBR_TYPE_EXTENDED_CHS || partition_type == MBR_TYPE_EXTENDED_LBA
+}
+
+fn parse_mbr(
+    device: &mut SharedBlockDevice,
+    entries: &[MbrEntry; MBR_PARTITION_ENTRY_COUNT],
+) -> Result<Vec<PartitionInfo>> {
+    let mut partitions = Vec::new();
+
+    // primary partitions are numbered 1-4, logical partitions start at 5
+    for (i, entry) in entries.iter().enumerate() {
+        if entry.partition_type == MBR_TYPE_EMPTY || is_extended(entry.partition_type) {
+            continue;
+        }
+
+        partitions.push(PartitionInfo {
+            index: i + 1,
+            start_lba: entry.start_lba,
+            block_count: entry.block_count,
+            kind: PartitionKind::Mbr {
+                partition_type: entry.partition_type,
+                bootable: entry.bootable,
+            },
+        });
+    }
+
+    let extended = match entries.iter().find(|e| is_extended(e.partition_type)) {
+        Some(extended) => *extended,
+        None => return Ok(partitions),
+    };
+
+    // logical partitions are a linked list of EBRs, addresses are relative to the extended partition
+    let mut sector = vec![0; device.block_size()];
+    let mut ebr_lba = extended.start_lba;
+
+    for index in 5..5 + MBR_MAX_LOGICAL_PARTITIONS {
+        device.read_blocks(ebr_lba, &mut sector)?;
+        let ebr = match read_mbr_entries(&sector) {
+            Some(ebr) => ebr,
+            None => break,
+        };
+
+        if ebr[0].partition_type != MBR_TYPE_EMPTY {
+            partitions.push(PartitionInfo {
+                index,
+                start_lba: ebr_lba + ebr[0].start_lba,
+                block_count: ebr[0].block_count,
+                kind: PartitionKind::Mbr {
+                    partition_type: ebr[0].partition_type,
+                    bootable: ebr[0].bootable,
+                },
+            });
+        }
+
+        if !is_extended(ebr[1].partition_type) {
+            break;
+        }
+        ebr_lba = extended.start_lba + ebr[1].start_lba;
+    }
+
+    Ok(partitions)
+}
+
+fn parse_gpt(device: &mut SharedBlockDevice) -> Result<Vec<PartitionInfo>> {
+    let block_size = device.block_size();
+    let mut header = vec![0; block_size];
+    device.read_blocks(GPT_HEADER_LBA, &mut header)?;
+
+    if &header[0..8] != GPT_SIGNATURE {
+        return Err("Invalid GPT signature".into());
+    }
+
+    let header_size = read_u32(&header, 12) as usize;
+    if header_size < 92 || header_size > block_size {
+        return Err("Invalid GPT header size".into());
+    }
+
+    // header CRC is calculated with the CRC field zeroed
+    let header_crc = read_u32(&header, 16);
+    let mut crc_buf = header[..header_size].to_vec();
+    crc_buf[16..20].fill(0);
+    if crc32(&crc_buf) != header_crc {
+        return Err("Invalid GPT header CRC".into());
+    }
+
+    let entries_lba = read_u64(&header, 72);
+    let entry_count = read_u32(&header, 80) as usize;
+    let entry_size = read_u32(&header, 84) as usize;
+    let entries_crc = read_u32(&header, 88);
+
+    // entries are 128 * 2^n bytes, anything past a block is bogus
+    if entry_size < 128
+        || entry_size > block_size
+        || !entry_size.is_power_of_two()
+        || entry_count > 1024
+    {
+        return Err("Invalid GPT partition entries".into());
+    }
+
+    let entries_len = (entry_count * entry_size).div_ceil(block_size) * block_size;
+    let mut entries = vec![0; entries_len];
+    device.read_blocks(entries_lba, &mut entries)?;
+
+    if crc32(&entries[..entry_count * entry_size]) != entries_crc {
+        return Err("Invalid GPT partition entries CRC".into());
+    }
+
+    let mut partitions = Vec::new();
+    for i in 0..entry_count {
+        let entry = &entries[i * entry_size..(i + 1) * entry_size];
+        let type_guid: [u8; 16] = entry[0..16].try_into().unwrap();
+        if type_guid == [0; 16] {
+            continue;
+        }
+
+        let first_lba = read_u64(entry, 32);
+        let last_lba = read_u64(entry, 40);
+
+        // skip entries that are inverted or run past the end of the device
+        let block_count = match last_lba.checked_sub(first_lba) {
+            Some(count) if last_lba < device.block_count() => count + 1,
+            _ => continue,
+        };
+
+        // name is UTF-16LE, NUL terminated
+        let name_units = entry[56..128]
+            .chunks_exact(2)
+            .map(|c| u16::from_le_bytes([c[0], c[1]]))
+            .take_while(|&c| c != 0);
+        let name = char::decode_utf16(name_units)
+            .map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER))
+            .collect();
+
+        partitions.push(PartitionInfo {
+            index: i + 1,
+            start_lba: first_lba,
+            block_count,
+            kind: PartitionKind::Gpt { type_guid, name },
+        });
+    }
+
+    Ok(partitions)
+}
+
+pub fn read_partition_table(device: &mut SharedBlockDevice) -> Result<Vec<PartitionInfo>> {
+    let mut sector = vec![0; device.block_size()];
+    device.read_blocks(0, &mut sector)?;
+
+    let entries = read_mbr_entries(&sector).ok_or("No partition table found")?;
+    if entries
+        .iter()
+        .any(|e| e.partition_type == MBR_TYPE_GPT_PROTECTIVE)
+    {
+        return parse_gpt(device);
+    }
+
+    parse_mbr(device, &entries)
+}
+
+// registers each partition of the device as "<name>p<index>"
+pub fn scan(name: &str) -> Result<Vec<PartitionInfo>> {
+    let mut device = block::get(name)?;
+    let partitions = read_partition_table(&mut device)?;
+
+    for partition in partitions.iter() {
+        if partition.start_lba + partition.block_count > device.block_count() {
+            continue;
+        }
+
+        let partition_device =
+            Partition::new(device.clone(), partition.start_lba, partition.block_count);
+        block::register(
+            &format!("{}p{}", name, partition.index),
+            Rc::new(RefCell::new(partition_device)),
+        )?;
+    }
+
+    Ok(partitions)
+}
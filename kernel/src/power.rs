@@ -0,0 +1,34 @@
+use crate::{addr::MmioAddress, asm};
+
+const PM_PASSWORD: u32 = 0x5a000000;
+const PM_RSTC_WRCFG_CLR: u32 = 0xffffffcf;
+const PM_RSTC_WRCFG_FULL_RESET: u32 = 0x00000020;
+
+// watchdog ticks are ~16us
+const PM_WDOG_RESET_TICKS: u32 = 10;
+
+fn mmio_base_pm() -> MmioAddress {
+    MmioAddress::new(0x100000)
+}
+
+fn read_rstc() -> u32 {
+    mmio_base_pm().offset(0x1c).read()
+}
+
+fn write_rstc(value: u32) {
+    mmio_base_pm().offset(0x1c).write(value);
+}
+
+fn write_wdog(value: u32) {
+    mmio_base_pm().offset(0x24).write(value);
+}
+
+pub fn reboot() -> ! {
+    asm::disable_int();
+    write_wdog(PM_PASSWORD | PM_WDOG_RESET_TICKS);
+    write_rstc(PM_PASSWORD | (read_rstc() & PM_RSTC_WRCFG_CLR) | PM_RSTC_WRCFG_FULL_RESET);
+
+    loop {
+        asm::wait_for_interrupt();
+    }
+}
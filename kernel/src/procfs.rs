@@ -0,0 +1,356 @@
+use crate::{
+    allocator, asm, cmdline,
+    cpu::{self, CpuModel},
+    device_tree::{self, DeviceTreeNode},
+    error::Result,
+    framebuffer, interrupt, mailbox, timer,
+    vfs::{DirEntry, FileSystem, FileSystemError, FileType, InodeId, Metadata},
+};
+use alloc::{
+    string::{String, ToString},
+    vec::Vec,
+};
+use core::fmt::Write;
+
+const ROOT_INODE: InodeId = 1;
+const DT_NODE_INODE_BASE: InodeId = 0x1_0000_0000;
+const DT_PROP_INODE_BASE: InodeId = 0x2_0000_0000;
+const CORE_COUNT: u64 = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ProcFile {
+    CpuInfo,
+    Firmware,
+    Board,
+    MemMap,
+    MemInfo,
+    Frames,
+    Uptime,
+    Interrupts,
+    Processes,
+    CmdLine,
+}
+
+const FILES: [(&str, ProcFile); 10] = [
+    ("cpuinfo", ProcFile::CpuInfo),
+    ("firmware", ProcFile::Firmware),
+    ("board", ProcFile::Board),
+    ("memmap", ProcFile::MemMap),
+    ("meminfo", ProcFile::MemInfo),
+    ("frames", ProcFile::Frames),
+    ("uptime", ProcFile::Uptime),
+    ("interrupts", ProcFile::Interrupts),
+    ("processes", ProcFile::Processes),
+    ("cmdline", ProcFile::CmdLine),
+];
+
+const DEVICE_TREE_DIR_NAME: &str = "device-tree";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ProcNode {
+    Root,
+    File(ProcFile),
+    DtNode(usize),
+    DtProp(usize, usize),
+}
+
+// returns (start, end) of the kernel image including .bss
+fn kernel_range() -> (usize, usize) {
+    extern "C" {
+        static _boot: u8;
+        static __bss_end: u8;
+    }
+
+    let start = unsafe { &_boot as *const u8 as usize };
+    let end = unsafe { &__bss_end as *const u8 as usize };
+    (start, end)
+}
+
+pub struct Procfs {
+    device_tree: Vec<DeviceTreeNode<'static>>,
+}
+
+impl Default for Procfs {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Procfs {
+    pub fn new() -> Self {
+        Self {
+            device_tree: device_tree::nodes().unwrap_or_default(),
+        }
+    }
+
+    fn node(&self, inode: InodeId) -> Result<ProcNode> {
+        let node = match inode {
+            ROOT_INODE => ProcNode::Root,
+            i if i >= DT_PROP_INODE_BASE => {
+                let node = ((i - DT_PROP_INODE_BASE) >> 16) as usize;
+                let prop = ((i - DT_PROP_INODE_BASE) & 0xffff) as usize;
+                self.device_tree
+                    .get(node)
+                    .and_then(|n| n.properties.get(prop))
+                    .ok_or(FileSystemError::NotFound)?;
+                ProcNode::DtProp(node, prop)
+            }
+            i if i >= DT_NODE_INODE_BASE => {
+                let node = (i - DT_NODE_INODE_BASE) as usize;
+                if node >= self.device_tree.len() {
+                    return Err(FileSystemError::NotFound.into());
+                }
+                ProcNode::DtNode(node)
+            }
+            i => {
+                let index = i
+                    .checked_sub(ROOT_INODE + 1)
+                    .ok_or(FileSystemError::NotFound)? as usize;
+                ProcNode::File(FILES.get(index).ok_or(FileSystemError::NotFound)?.1)
+            }
+        };
+
+        Ok(node)
+    }
+
+    fn file_inode(index: usize) -> InodeId {
+        ROOT_INODE + 1 + index as InodeId
+    }
+
+    fn dt_node_inode(node: usize) -> InodeId {
+        DT_NODE_INODE_BASE + node as InodeId
+    }
+
+    fn dt_prop_inode(node: usize, prop: usize) -> InodeId {
+        DT_PROP_INODE_BASE + ((node as InodeId) << 16 | prop as InodeId)
+    }
+
+    fn content(&self, file: ProcFile) -> Result<String> {
+        let mut s = String::new();
+
+        // writing into a String never fails
+        match file {
+            ProcFile::CpuInfo => {
+                let model = cpu::detect_cpu_model()
+                    .unwrap_or_else(|_| CpuModel::Unknown((asm::read_main_id_reg() >> 4) & 0xfff));
+                let _ = writeln!(s, "model: {:?}", model);
+                let _ = writeln!(s, "midr: 0x{:08x}", asm::read_main_id_reg());
+                let _ = writeln!(s, "el: {}", asm::read_current_el());
+            }
+            ProcFile::Firmware => {
+                let _ = writeln!(s, "revision: 0x{:x}", mailbox::get_firmware_revision()?);
+            }
+            ProcFile::Board => {
+                let _ = writeln!(s, "model: 0x{:x}", mailbox::get_board_model()?);
+                let _ = writeln!(s, "revision: 0x{:x}", mailbox::get_board_revision()?);
+                let _ = writeln!(s, "serial: 0x{:016x}", mailbox::get_board_serial()?);
+            }
+            ProcFile::MemMap => {
+                let (arm_base, arm_size) = mailbox::get_arm_memory()?;
+                let (vc_base, vc_size) = mailbox::get_videocore_memory()?;
+                let (kernel_start, kernel_end) = kernel_range();
+                let heap = allocator::stats()?;
+                let mmio_base = cpu::mmio_base() as usize;
+
+                let _ = writeln!(s, "0x{:08x}-0x{:08x}: arm", arm_base, arm_base + arm_size);
+                let _ = writeln!(s, "0x{:08x}-0x{:08x}: kernel", kernel_start, kernel_end);
+                let _ = writeln!(
+                    s,
+                    "0x{:08x}-0x{:08x}: heap",
+                    heap.base,
+                    heap.base + heap.size
+                );
+                let _ = writeln!(
+                    s,
+                    "0x{:08x}-0x{:08x}: videocore",
+                    vc_base,
+                    vc_base + vc_size
+                );
+                if let Ok(info) = framebuffer::get_info() {
+                    let fb_base = info.buf_base.get() as usize;
+                    let _ = writeln!(
+                        s,
+                        "0x{:08x}-0x{:08x}: framebuffer",
+                        fb_base,
+                        fb_base + info.buf_size
+                    );
+                }
+                let _ = writeln!(s, "0x{:08x}-0x{:08x}: mmio", mmio_base, 0x40000000);
+            }
+            ProcFile::MemInfo => {
+                let heap = allocator::stats()?;
+                let _ = writeln!(s, "heap_total: {}", heap.size);
+                let _ = writeln!(s, "heap_used: {}", heap.used);
+                let _ = writeln!(s, "heap_free: {}", heap.size - heap.used);
+                let _ = writeln!(s, "heap_peak: {}", heap.peak);
+                let _ = writeln!(s, "heap_alloc_count: {}", heap.alloc_count);
+                let _ = writeln!(s, "heap_free_count: {}", heap.free_count);
+            }
+            // there is no frame allocator yet, memmap shows how memory is laid out
+            ProcFile::Frames => return Err(FileSystemError::Unsupported.into()),
+            ProcFile::Uptime => {
+                let us = timer::uptime_us();
+                let _ = writeln!(s, "{}.{:06}", us / 1_000_000, us % 1_000_000);
+            }
+            ProcFile::Interrupts => {
+                for (line, info) in interrupt::lines()?.iter().enumerate() {
+                    if info.count == 0 && !info.enabled {
+                        continue;
+                    }
+
+                    let _ = writeln!(s, "{:>3}: {:>10} {}", line, info.count, info.name);
+                }
+                let _ = writeln!(s, "spurious: {}", interrupt::spurious_count()?);
+            }
+            ProcFile::Processes => {
+                // the kernel runs as a single task on the boot core, the
+                // secondary cores stay parked in the boot code
+                let boot_core = asm::read_mpidr() & 0x3;
+                let _ = writeln!(s, "pid core state   name");
+                let _ = writeln!(s, "{:>3} {:>4} running kernel", 0, boot_core);
+                for core in (0..CORE_COUNT).filter(|core| *core != boot_core) {
+                    let _ = writeln!(s, "  - {:>4} parked  idle", core);
+                }
+            }
+            ProcFile::CmdLine => {
+                let _ = writeln!(s, "{}", cmdline::get()?.raw());
+            }
+        }
+
+        Ok(s)
+    }
+
+    fn read_bytes(data: &[u8], offset: usize, buf: &mut [u8]) -> usize {
+        if offset >= data.len() {
+            return 0;
+        }
+
+        let len = buf.len().min(data.len() - offset);
+        buf[..len].copy_from_slice(&data[offset..offset + len]);
+        len
+    }
+}
+
+impl FileSystem for Procfs {
+    fn name(&self) -> &'static str {
+        "procfs"
+    }
+
+    fn root(&self) -> InodeId {
+        ROOT_INODE
+    }
+
+    fn lookup(&mut self, dir: InodeId, name: &str) -> Result<InodeId> {
+        match self.node(dir)? {
+            ProcNode::Root => {
+                if name == DEVICE_TREE_DIR_NAME && !self.device_tree.is_empty() {
+                    return Ok(Self::dt_node_inode(0));
+                }
+
+                let index = FILES
+                    .iter()
+                    .position(|(n, _)| *n == name)
+                    .ok_or(FileSystemError::NotFound)?;
+                Ok(Self::file_inode(index))
+            }
+            ProcNode::DtNode(node) => {
+                let dt_node = &self.device_tree[node];
+
+                if let Some(&child) = dt_node
+                    .children
+                    .iter()
+                    .find(|&&c| self.device_tree[c].name == name)
+                {
+                    return Ok(Self::dt_node_inode(child));
+                }
+
+                let prop = dt_node
+                    .properties
+                    .iter()
+                    .position(|p| p.name == name)
+                    .ok_or(FileSystemError::NotFound)?;
+                Ok(Self::dt_prop_inode(node, prop))
+            }
+            _ => Err(FileSystemError::NotDirectory.into()),
+        }
+    }
+
+    fn metadata(&mut self, inode: InodeId) -> Result<Metadata> {
+        let metadata = match self.node(inode)? {
+            ProcNode::Root => Metadata::new(inode, FileType::Directory, FILES.len() + 1, 0o555),
+            ProcNode::DtNode(node) => {
+                let dt_node = &self.device_tree[node];
+                let size = dt_node.children.len() + dt_node.properties.len();
+                Metadata::new(inode, FileType::Directory, size, 0o555)
+            }
+            ProcNode::DtProp(node, prop) => {
+                let size = self.device_tree[node].properties[prop].value.len();
+                Metadata::new(inode, FileType::File, size, 0o444)
+            }
+            // contents are generated on read
+            ProcNode::File(_) => Metadata::new(inode, FileType::File, 0, 0o444),
+        };
+
+        Ok(metadata)
+    }
+
+    fn read(&mut self, inode: InodeId, offset: usize, buf: &mut [u8]) -> Result<usize> {
+        match self.node(inode)? {
+            ProcNode::File(file) => {
+                let content = self.content(file)?;
+                Ok(Self::read_bytes(content.as_bytes(), offset, buf))
+            }
+            ProcNode::DtProp(node, prop) => {
+                let value = self.device_tree[node].properties[prop].value;
+                Ok(Self::read_bytes(value, offset, buf))
+            }
+            _ => Err(FileSystemError::IsDirectory.into()),
+        }
+    }
+
+    fn read_dir(&mut self, dir: InodeId) -> Result<Vec<DirEntry>> {
+        let mut entries = Vec::new();
+
+        match self.node(dir)? {
+            ProcNode::Root => {
+                for (i, (name, _)) in FILES.iter().enumerate() {
+                    entries.push(DirEntry {
+                        name: name.to_string(),
+                        inode: Self::file_inode(i),
+                        file_type: FileType::File,
+                    });
+                }
+
+                if !self.device_tree.is_empty() {
+                    entries.push(DirEntry {
+                        name: DEVICE_TREE_DIR_NAME.to_string(),
+                        inode: Self::dt_node_inode(0),
+                        file_type: FileType::Directory,
+                    });
+                }
+            }
+            ProcNode::DtNode(node) => {
+                let dt_node = &self.device_tree[node];
+
+                for &child in dt_node.children.iter() {
+                    entries.push(DirEntry {
+                        name: self.device_tree[child].name.to_string(),
+                        inode: Self::dt_node_inode(child),
+                        file_type: FileType::Directory,
+                    });
+                }
+
+                for (i, prop) in dt_node.properties.iter().enumerate() {
+                    entries.push(DirEntry {
+                        name: prop.name.to_string(),
+                        inode: Self::dt_prop_inode(node, i),
+                        file_type: FileType::File,
+                    });
+                }
+            }
+            _ => return Err(FileSystemError::NotDirectory.into()),
+        }
+
+        Ok(entries)
+    }
+}
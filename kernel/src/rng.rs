@@ -0,0 +1,80 @@
+use crate::{addr::MmioAddress, asm, error::Result, mutex::Mutex};
+
+static mut RNG: Mutex<Rng> = Mutex::new(Rng::new());
+
+fn mmio_base_rng() -> MmioAddress {
+    MmioAddress::new(0x104000)
+}
+
+fn read_rng_ctrl() -> u32 {
+    mmio_base_rng().offset(0x00).read()
+}
+
+fn write_rng_ctrl(value: u32) {
+    mmio_base_rng().offset(0x00).write(value);
+}
+
+fn read_rng_status() -> u32 {
+    mmio_base_rng().offset(0x04).read()
+}
+
+fn write_rng_status(value: u32) {
+    mmio_base_rng().offset(0x04).write(value);
+}
+
+fn read_rng_data() -> u32 {
+    mmio_base_rng().offset(0x08).read()
+}
+
+fn read_rng_int_mask() -> u32 {
+    mmio_base_rng().offset(0x10).read()
+}
+
+fn write_rng_int_mask(value: u32) {
+    mmio_base_rng().offset(0x10).write(value);
+}
+
+struct Rng {
+    initialized: bool,
+}
+
+impl Rng {
+    const fn new() -> Self {
+        Self { initialized: false }
+    }
+
+    fn init(&mut self) {
+        write_rng_status(0x40000); // warm up count
+        write_rng_int_mask(read_rng_int_mask() | 1); // mask interrupt
+        write_rng_ctrl(read_rng_ctrl() | 1); // enable
+        self.initialized = true;
+    }
+
+    fn next_u32(&mut self) -> u32 {
+        if !self.initialized {
+            self.init();
+        }
+
+        // wait for entropy
+        while (read_rng_status() >> 24) == 0 {
+            asm::wait_cycles(1);
+        }
+
+        read_rng_data()
+    }
+}
+
+pub fn next_u32() -> Result<u32> {
+    Ok(unsafe { RNG.try_lock() }?.next_u32())
+}
+
+pub fn fill_bytes(buf: &mut [u8]) -> Result<()> {
+    let mut rng = unsafe { RNG.try_lock() }?;
+
+    for chunk in buf.chunks_mut(4) {
+        let value = rng.next_u32().to_le_bytes();
+        chunk.copy_from_slice(&value[..chunk.len()]);
+    }
+
+    Ok(())
+}
@@ -0,0 +1,184 @@
+// captures the visible area of the framebuffer as a BMP or PPM image, saved through the VFS or
+// sent base64 encoded over a UART between marker lines, which can be decoded from a serial log
+// with the following, the UART sends lines ending in CRLF:
+//   sed -n '/^-----BEGIN SCREENSHOT/,/^-----END SCREENSHOT/{//!p}' log | tr -d '\r' \
+//     | base64 -d > screen.bmp
+
+use crate::{
+    color::ColorCode,
+    error::{Error, Result},
+    framebuffer, image,
+    uart::{self, UartPort},
+    vfs::{self, FileSystemError},
+};
+use alloc::{format, string::String, vec::Vec};
+
+const BASE64_CHARS: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+// as in MIME
+const BASE64_LINE_LEN: usize = 76;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Bmp,
+    Ppm,
+}
+
+impl Format {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "bmp" => Some(Self::Bmp),
+            "ppm" => Some(Self::Ppm),
+            _ => None,
+        }
+    }
+
+    // BMP unless the path ends with .ppm
+    pub fn from_path(path: &str) -> Self {
+        match path.rsplit_once('.') {
+            Some((_, extension)) if extension.eq_ignore_ascii_case("ppm") => Self::Ppm,
+            _ => Self::Bmp,
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::Bmp => "bmp",
+            Self::Ppm => "ppm",
+        }
+    }
+}
+
+// a frame is several MiB, so it is read and encoded one row at a time and handed to write,
+// pixels are converted from the framebuffer depth and pixel order, returns the encoded size
+pub fn encode(format: Format, mut write: impl FnMut(&[u8]) -> Result<()>) -> Result<usize> {
+    let screen = framebuffer::visible_rect()?;
+    let header = match format {
+        Format::Bmp => image::bmp_file_header(screen.width, screen.height),
+        Format::Ppm => image::ppm_file_header(screen.width, screen.height),
+    };
+    write(&header)?;
+    let mut size = header.len();
+
+    // reserved up front so that running out of memory is an error, e.g. when taken with F12
+    let mut colors = Vec::new();
+    let mut row = Vec::new();
+    colors
+        .try_reserve_exact(screen.width)
+        .and_then(|_| row.try_reserve_exact((screen.width * 3).next_multiple_of(4)))
+        .map_err(|_| "Out of memory")?;
+    colors.resize(screen.width, ColorCode::default());
+
+    for i in 0..screen.height {
+        row.clear();
+        match format {
+            // rows are stored bottom-up
+            Format::Bmp => {
+                let y = screen.y + screen.height - 1 - i;
+                framebuffer::read_rect(screen.x, y, screen.width, 1, &mut colors)?;
+                image::encode_bmp_row(&colors, &mut row);
+            }
+            Format::Ppm => {
+                framebuffer::read_rect(screen.x, screen.y + i, screen.width, 1, &mut colors)?;
+                image::encode_ppm_row(&colors, &mut row);
+            }
+        }
+        write(&row)?;
+        size += row.len();
+    }
+
+    Ok(size)
+}
+
+// replaces an existing file
+pub fn save(path: &str) -> Result<usize> {
+    match vfs::create(path) {
+        Ok(()) => (),
+        Err(Error::FileSystemError(FileSystemError::AlreadyExists)) => vfs::truncate(path, 0)?,
+        Err(err) => return Err(err),
+    }
+
+    let mut offset = 0;
+    encode(Format::from_path(path), |mut data| {
+        while !data.is_empty() {
+            match vfs::write(path, offset, data)? {
+                0 => return Err("Short write".into()),
+                len => {
+                    offset += len;
+                    data = &data[len..];
+                }
+            }
+        }
+
+        Ok(())
+    })
+}
+
+// saves to the first unused screenshot-<n>.bmp in the directory
+pub fn save_next(dir: &str) -> Result<String> {
+    let dir = dir.trim_end_matches('/');
+    let path = (0..)
+        .map(|n| format!("{}/screenshot-{}.{}", dir, n, Format::Bmp.extension()))
+        .find(|path| vfs::metadata(path).is_err())
+        .unwrap();
+
+    save(&path)?;
+    Ok(path)
+}
+
+pub fn send(port: UartPort, format: Format) -> Result<()> {
+    let name = format!("screenshot.{}", format.extension());
+    uart::puts_port(port, &format!("\n-----BEGIN SCREENSHOT {}-----\n", name))?;
+
+    // encoded rows are collected into whole lines, the last one may be shorter
+    let mut chunk = [0; BASE64_LINE_LEN / 4 * 3];
+    let mut len = 0;
+    encode(format, |mut data| {
+        while !data.is_empty() {
+            let n = data.len().min(chunk.len() - len);
+            chunk[len..len + n].copy_from_slice(&data[..n]);
+            len += n;
+            data = &data[n..];
+
+            if len == chunk.len() {
+                send_line(port, &chunk)?;
+                len = 0;
+            }
+        }
+
+        Ok(())
+    })?;
+    if len > 0 {
+        send_line(port, &chunk[..len])?;
+    }
+
+    uart::puts_port(port, "-----END SCREENSHOT-----\n")
+}
+
+fn send_line(port: UartPort, data: &[u8]) -> Result<()> {
+    let mut line = [0; BASE64_LINE_LEN + 1];
+    let len = base64_encode(data, &mut line);
+    line[len] = b'\n';
+    uart::write_bytes(port, &line[..len + 1])
+}
+
+// returns the number of characters written, the output must hold 4 for every 3 input bytes
+fn base64_encode(input: &[u8], output: &mut [u8]) -> usize {
+    let mut len = 0;
+    for group in input.chunks(3) {
+        let value = group
+            .iter()
+            .enumerate()
+            .fold(0u32, |value, (i, &b)| value | (b as u32) << (16 - i * 8));
+
+        for i in 0..4 {
+            output[len + i] = if i <= group.len() {
+                BASE64_CHARS[(value >> (18 - i * 6) & 0x3f) as usize]
+            } else {
+                b'='
+            };
+        }
+        len += 4;
+    }
+
+    len
+}
@@ -0,0 +1,1120 @@
+use crate::{
+    addr::VirtualAddress,
+    block,
+    breakpoint::{self, WatchKind, Watchpoint},
+    color::ColorCode,
+    console,
+    device_tree::{self, DeviceTreeNode, DeviceTreeProperty},
+    draw::{Clip, Draw, Rect},
+    error::{Error, Result},
+    font,
+    framebuffer::{self, PixelFormat},
+    framebuffer_console, gdb, image,
+    log::{self, Level},
+    mailbox::{self, AlphaMode, ClockId},
+    power, print, println,
+    screenshot::{self, Format},
+    symbols, timer,
+    uart::{self, UartPort},
+    vfs::{self, FileType},
+};
+use alloc::{
+    string::{String, ToString},
+    vec::Vec,
+};
+use core::ptr;
+
+const PROMPT: &str = "> ";
+const SCREENSHOT_DIR: &str = "/tmp";
+const HISTORY_SIZE: usize = 32;
+
+const KEY_CTRL_A: char = '\x01';
+const KEY_CTRL_C: char = '\x03';
+const KEY_CTRL_E: char = '\x05';
+const KEY_CTRL_L: char = '\x0c';
+const KEY_BACKSPACE: char = '\x08';
+const KEY_TAB: char = '\t';
+const KEY_ESC: char = '\x1b';
+const KEY_DEL: char = '\x7f';
+
+const CLOCKS: [(&str, ClockId); 14] = [
+    ("emmc", ClockId::Emmc),
+    ("uart", ClockId::Uart),
+    ("arm", ClockId::Arm),
+    ("core", ClockId::Core),
+    ("v3d", ClockId::V3d),
+    ("h264", ClockId::H264),
+    ("isp", ClockId::Isp),
+    ("sdram", ClockId::Sdram),
+    ("pixel", ClockId::Pixel),
+    ("pwm", ClockId::Pwm),
+    ("hevc", ClockId::Hevc),
+    ("emmc2", ClockId::Emmc2),
+    ("m2mc", ClockId::M2mc),
+    ("pixel_bvb", ClockId::PixelBvb),
+];
+
+struct Command {
+    name: &'static str,
+    usage: &'static str,
+    description: &'static str,
+    func: fn(&[&str]) -> Result<()>,
+}
+
+const COMMANDS: [Command; 21] = [
+    Command {
+        name: "help",
+        usage: "help",
+        description: "show this help",
+        func: cmd_help,
+    },
+    Command {
+        name: "info",
+        usage: "info",
+        description: "show CPU, board and firmware information",
+        func: cmd_info,
+    },
+    Command {
+        name: "mem",
+        usage: "mem",
+        description: "show memory map and heap usage",
+        func: cmd_mem,
+    },
+    Command {
+        name: "dt",
+        usage: "dt [path]",
+        description: "dump the device tree",
+        func: cmd_dt,
+    },
+    Command {
+        name: "clock",
+        usage: "clock [name [rate]]",
+        description: "query or set clock rates",
+        func: cmd_clock,
+    },
+    Command {
+        name: "peek",
+        usage: "peek <addr> [count]",
+        description: "read 32-bit words from memory or MMIO",
+        func: cmd_peek,
+    },
+    Command {
+        name: "poke",
+        usage: "poke <addr> <value>",
+        description: "write a 32-bit word to memory or MMIO",
+        func: cmd_poke,
+    },
+    Command {
+        name: "ls",
+        usage: "ls [path]",
+        description: "list a directory",
+        func: cmd_ls,
+    },
+    Command {
+        name: "cat",
+        usage: "cat <path>...",
+        description: "print files",
+        func: cmd_cat,
+    },
+    Command {
+        name: "fb",
+        usage: "fb init [width height [depth]] | info | fill <rrggbb> | copy <x y w h to_x to_y> | flip [frames] | shapes | alpha [enabled|reversed|ignored] | test",
+        description: "framebuffer tests",
+        func: cmd_fb,
+    },
+    Command {
+        name: "font",
+        usage: "font [load <path> [name] | use <name>]",
+        description: "list, load or select console fonts",
+        func: cmd_font,
+    },
+    Command {
+        name: "image",
+        usage: "image <path> [x y [width height]]",
+        description: "show a BMP, QOI or PPM image, centred and fitted to the screen by default",
+        func: cmd_image,
+    },
+    Command {
+        name: "screenshot",
+        usage: "screenshot [<path> | send [bmp|ppm] [pl011|mini]]",
+        description: "save the screen as BMP or PPM, by default to /tmp, or send it base64 encoded",
+        func: cmd_screenshot,
+    },
+    Command {
+        name: "console",
+        usage: "console [log | <name> on|off|primary]",
+        description: "list, enable or disable console backends",
+        func: cmd_console,
+    },
+    Command {
+        name: "dmesg",
+        usage: "dmesg",
+        description: "print the kernel log buffer",
+        func: cmd_dmesg,
+    },
+    Command {
+        name: "log",
+        usage: "log [level | <module> <level>]",
+        description: "show or set log levels",
+        func: cmd_log,
+    },
+    Command {
+        name: "break",
+        usage: "break [<addr|symbol> | del <n>]",
+        description: "list, set or remove hardware breakpoints",
+        func: cmd_break,
+    },
+    Command {
+        name: "watch",
+        usage: "watch [<addr|symbol> [len] [r|w|rw] | del <n>]",
+        description: "list, set or remove hardware watchpoints",
+        func: cmd_watch,
+    },
+    Command {
+        name: "gdb",
+        usage: "gdb [pl011|mini]",
+        description: "stop in the GDB stub",
+        func: cmd_gdb,
+    },
+    Command {
+        name: "uptime",
+        usage: "uptime",
+        description: "show time since boot",
+        func: cmd_uptime,
+    },
+    Command {
+        name: "reboot",
+        usage: "reboot",
+        description: "sync filesystems and reset the board",
+        func: cmd_reboot,
+    },
+];
+
+fn parse_number(s: &str) -> Result<u64> {
+    let res = match s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        Some(hex) => u64::from_str_radix(hex, 16),
+        None => s.parse(),
+    };
+
+    res.map_err(|_| Error::InvalidArgument)
+}
+
+fn arg<'a>(args: &[&'a str], index: usize) -> Result<&'a str> {
+    args.get(index).copied().ok_or(Error::InvalidArgument)
+}
+
+fn cmd_help(_args: &[&str]) -> Result<()> {
+    for command in COMMANDS.iter() {
+        println!("{:<48} {}", command.usage, command.description);
+    }
+
+    Ok(())
+}
+
+fn print_file(path: &str) -> Result<()> {
+    let data = vfs::read_to_end(path)?;
+    print!("{}", String::from_utf8_lossy(&data));
+    Ok(())
+}
+
+fn cmd_info(_args: &[&str]) -> Result<()> {
+    for path in ["/proc/cpuinfo", "/proc/board", "/proc/firmware"] {
+        print_file(path)?;
+    }
+
+    cmd_uptime(&[])
+}
+
+fn cmd_mem(_args: &[&str]) -> Result<()> {
+    print_file("/proc/memmap")?;
+    print_file("/proc/meminfo")
+}
+
+fn format_property(property: &DeviceTreeProperty) -> String {
+    let value = property.value;
+    if value.is_empty() {
+        return String::new();
+    }
+
+    // NUL terminated printable strings
+    let is_strings = value.ends_with(&[0])
+        && value[0] != 0
+        && !value.windows(2).any(|w| w == [0, 0])
+        && value.iter().all(|&b| b == 0 || (0x20..0x7f).contains(&b));
+    if is_strings {
+        let strings: Vec<String> = property
+            .as_str_list()
+            .map(|s| ["\"", s, "\""].concat())
+            .collect();
+        return [" = ", &strings.join(", ")].concat();
+    }
+
+    let mut s = String::from(" = ");
+    if value.len() % 4 == 0 {
+        let cells: Vec<String> = value
+            .chunks_exact(4)
+            .map(|c| alloc::format!("0x{:x}", u32::from_be_bytes(c.try_into().unwrap())))
+            .collect();
+        s.push('<');
+        s.push_str(&cells.join(" "));
+        s.push('>');
+    } else {
+        let bytes: Vec<String> = value.iter().map(|b| alloc::format!("{:02x}", b)).collect();
+        s.push('[');
+        s.push_str(&bytes.join(" "));
+        s.push(']');
+    }
+    s
+}
+
+fn print_dt_node(nodes: &[DeviceTreeNode], index: usize, depth: usize) {
+    let indent = "    ".repeat(depth);
+    let node = &nodes[index];
+    let name = if node.parent.is_none() {
+        "/"
+    } else {
+        node.name
+    };
+
+    println!("{}{} {{", indent, name);
+    for property in node.properties.iter() {
+        println!(
+            "{}    {}{};",
+            indent,
+            property.name,
+            format_property(property)
+        );
+    }
+    for &child in node.children.iter() {
+        print_dt_node(nodes, child, depth + 1);
+    }
+    println!("{}}};", indent);
+}
+
+fn cmd_dt(args: &[&str]) -> Result<()> {
+    let path = args.first().copied().unwrap_or("/");
+    let index = device_tree::find_node(path)?;
+    let nodes = device_tree::nodes()?;
+    print_dt_node(&nodes, index, 0);
+    Ok(())
+}
+
+fn cmd_clock(args: &[&str]) -> Result<()> {
+    if args.is_empty() {
+        for (name, id) in CLOCKS.iter() {
+            match mailbox::get_clock_rate(*id) {
+                Ok(rate) => println!("{:<10} {} Hz", name, rate),
+                Err(err) => println!("{:<10} {:?}", name, err),
+            }
+        }
+        return Ok(());
+    }
+
+    let (name, id) = CLOCKS
+        .iter()
+        .find(|(name, _)| *name == args[0])
+        .ok_or("Unknown clock")?;
+
+    if let Some(rate) = args.get(1) {
+        let rate = u32::try_from(parse_number(rate)?).map_err(|_| Error::InvalidArgument)?;
+        mailbox::set_clock_rate(*id, rate)?;
+    }
+
+    println!("{} {} Hz", name, mailbox::get_clock_rate(*id)?);
+    Ok(())
+}
+
+fn word_address(s: &str) -> Result<VirtualAddress> {
+    let addr = parse_number(s)?;
+    if addr % 4 != 0 {
+        return Err(Error::InvalidArgument);
+    }
+
+    Ok(VirtualAddress::new(addr))
+}
+
+fn cmd_peek(args: &[&str]) -> Result<()> {
+    let addr = word_address(arg(args, 0)?)?;
+    let count = match args.get(1) {
+        Some(count) => parse_number(count)? as usize,
+        None => 1,
+    };
+
+    for i in 0..count {
+        let addr = addr.offset(i * 4);
+        let value = unsafe { ptr::read_volatile(addr.as_ptr::<u32>()) };
+        println!("0x{:08x}: 0x{:08x}", addr.get(), value);
+    }
+
+    Ok(())
+}
+
+fn cmd_poke(args: &[&str]) -> Result<()> {
+    let addr = word_address(arg(args, 0)?)?;
+    let value = u32::try_from(parse_number(arg(args, 1)?)?).map_err(|_| Error::InvalidArgument)?;
+    unsafe { ptr::write_volatile(addr.as_ptr_mut::<u32>(), value) };
+    Ok(())
+}
+
+fn cmd_ls(args: &[&str]) -> Result<()> {
+    let path = args.first().copied().unwrap_or("/");
+    for entry in vfs::read_dir(path)? {
+        match entry.file_type {
+            FileType::Directory => println!("{}/", entry.name),
+            FileType::Symlink => println!("{}@", entry.name),
+            _ => println!("{}", entry.name),
+        }
+    }
+
+    Ok(())
+}
+
+fn cmd_cat(args: &[&str]) -> Result<()> {
+    if args.is_empty() {
+        return Err(Error::InvalidArgument);
+    }
+
+    for path in args {
+        print_file(path)?;
+    }
+
+    Ok(())
+}
+
+fn cmd_fb(args: &[&str]) -> Result<()> {
+    match arg(args, 0)? {
+        "init" => {
+            let parse_u32 = |s| u32::try_from(parse_number(s)?).map_err(|_| Error::InvalidArgument);
+            let (width, height, depth) = match args.len() {
+                1 => (640, 480, 32),
+                3 | 4 => (
+                    parse_u32(args[1])?,
+                    parse_u32(args[2])?,
+                    args.get(3).map_or(Ok(32), |s| parse_u32(s))?,
+                ),
+                _ => return Err(Error::InvalidArgument),
+            };
+            // the second screen height is used for scrolling and double buffering
+            let virt_height = height.checked_mul(2).ok_or(Error::InvalidArgument)?;
+            let info = mailbox::init_framebuffer(
+                (width, height),
+                (width, virt_height),
+                depth,
+                PixelFormat::default(),
+            )?;
+            framebuffer::init(info)?;
+            if framebuffer_console::is_initialized()? {
+                framebuffer_console::reset()?;
+            }
+            println!("{:?}", info);
+        }
+        "info" => println!("{:?}", framebuffer::get_info()?),
+        "fill" => {
+            let value = parse_number(arg(args, 1)?)? as u32;
+            let color = ColorCode::from_u32(value, PixelFormat::Rgb);
+            framebuffer::fill(color)?;
+        }
+        "copy" => {
+            let mut n = [0; 6];
+            for (i, value) in n.iter_mut().enumerate() {
+                *value = parse_number(arg(args, i + 1)?)? as usize;
+            }
+            framebuffer::copy_rect(n[0], n[1], n[2], n[3], n[4], n[5])?;
+        }
+        "test" => {
+            let info = framebuffer::get_info()?;
+            let colors = [
+                ColorCode::WHITE,
+                ColorCode::YELLOW,
+                ColorCode::CYAN,
+                ColorCode::GREEN,
+                ColorCode::MAGENTA,
+                ColorCode::RED,
+                ColorCode::BLUE,
+                ColorCode::BLACK,
+            ];
+
+            // color bars
+            let bar_width = info.v_width / colors.len();
+            for (i, color) in colors.iter().enumerate() {
+                framebuffer::draw_rect(i * bar_width, 0, bar_width, info.v_height, *color)?;
+            }
+
+            for (i, c) in "framebuffer test".chars().enumerate() {
+                framebuffer::draw_font(8 + i * 8, 8, c, ColorCode::WHITE, ColorCode::BLACK)?;
+            }
+        }
+        "shapes" => framebuffer::draw(|draw, info| {
+            let (w, h) = (info.p_width as isize, info.p_height as isize);
+            draw.fill(ColorCode::BLACK)?;
+            draw.draw_line(0, 0, w - 1, h - 1, ColorCode::WHITE)?;
+            draw.draw_line(0, h - 1, w - 1, 0, ColorCode::WHITE)?;
+            draw.fill_circle(w / 4, h / 4, (h / 6) as usize, ColorCode::RED)?;
+            draw.draw_ellipse(
+                w * 3 / 4,
+                h / 4,
+                (w / 6) as usize,
+                (h / 8) as usize,
+                ColorCode::CYAN,
+            )?;
+            draw.fill_polygon(
+                &[(w / 8, h * 7 / 8), (w / 4, h / 2), (w * 3 / 8, h * 7 / 8)],
+                ColorCode::YELLOW,
+            )?;
+            draw.fill_round_rect(
+                w / 2,
+                h / 2,
+                (w / 3) as usize,
+                (h / 3) as usize,
+                16,
+                ColorCode::BLUE,
+            )?;
+            draw.draw_round_rect(
+                w / 2,
+                h / 2,
+                (w / 3) as usize,
+                (h / 3) as usize,
+                16,
+                ColorCode::WHITE,
+            )?;
+            draw.blend_rect(
+                0,
+                (h / 3) as usize,
+                w as usize,
+                (h / 3) as usize,
+                ColorCode::GREEN,
+                96,
+            )?;
+
+            // a circle clipped to the lower right quarter, partly off screen
+            let quarter = Rect::new(
+                info.p_width / 2,
+                info.p_height / 2,
+                info.p_width / 2,
+                info.p_height / 2,
+            );
+            Clip::new(draw, quarter).draw_circle(w, h, (h / 3) as usize, ColorCode::MAGENTA)
+        })??,
+        "alpha" => {
+            let mode = match args.get(1) {
+                None => mailbox::get_alpha_mode()?,
+                Some(&"enabled") => mailbox::set_alpha_mode(AlphaMode::Enabled)?,
+                Some(&"reversed") => mailbox::set_alpha_mode(AlphaMode::Reversed)?,
+                Some(&"ignored") => mailbox::set_alpha_mode(AlphaMode::Ignored)?,
+                Some(_) => return Err(Error::InvalidArgument),
+            };
+            println!("{:?}", mode);
+        }
+        "flip" => {
+            let frames = args.get(1).map_or(Ok(120), |s| parse_number(s))? as usize;
+            framebuffer::start_double_buffering()?;
+            let res = (0..frames).try_for_each(|frame| {
+                framebuffer::draw_back(|draw, info| {
+                    // a square bouncing between the sides of the screen
+                    let size = info.p_width.min(info.p_height) / 4;
+                    let range = info.p_width - size;
+                    if range == 0 {
+                        return Err(Error::InvalidArgument);
+                    }
+                    let x = match frame * 8 % (range * 2) {
+                        x if x > range => range * 2 - x,
+                        x => x,
+                    };
+                    draw.fill(ColorCode::BLACK)?;
+                    draw.draw_rect(x, (info.p_height - size) / 2, size, size, ColorCode::GREEN)
+                })??;
+                framebuffer::flip(true)
+            });
+            framebuffer::stop_double_buffering()?;
+            res?;
+        }
+        _ => return Err(Error::InvalidArgument),
+    }
+
+    Ok(())
+}
+
+fn cmd_font(args: &[&str]) -> Result<()> {
+    match args {
+        [] => {
+            let console_font = framebuffer_console::font().ok();
+            for font in font::list()? {
+                let (width, height) = font.get_wh();
+                let marker = if console_font.is_some_and(|f| ptr::eq(f, font)) {
+                    "*"
+                } else {
+                    " "
+                };
+                println!(
+                    "{} {:<16} {}x{} {} glyphs",
+                    marker,
+                    font.name(),
+                    width,
+                    height,
+                    font.glyphs_len()
+                );
+            }
+        }
+        ["load", path, rest @ ..] if rest.len() <= 1 => {
+            // named after the file by default
+            let name = match rest {
+                [name] => name,
+                _ => path.rsplit('/').next().unwrap_or(path),
+            };
+            let font = font::load(path, name)?;
+            let (width, height) = font.get_wh();
+            println!("loaded {} ({}x{})", font.name(), width, height);
+        }
+        ["use", name] => {
+            // the default only changes once the console accepted the font
+            let font = font::get(name)?;
+            framebuffer_console::set_font(font)?;
+            font::set_default_font(font)?;
+        }
+        _ => return Err(Error::InvalidArgument),
+    }
+
+    Ok(())
+}
+
+fn cmd_image(args: &[&str]) -> Result<()> {
+    let path = arg(args, 0)?;
+    let mut n = [0; 4];
+    for (value, s) in n.iter_mut().zip(&args[1..]) {
+        *value = parse_number(s)? as usize;
+    }
+
+    match args.len() {
+        1 => image::show(path),
+        3 | 5 => {
+            let mut image = image::load(path)?;
+            if args.len() == 5 {
+                image = image.scale(n[2], n[3])?;
+            }
+            framebuffer::draw(|draw, _| draw.draw_image(n[0] as isize, n[1] as isize, &image))?
+        }
+        _ => Err(Error::InvalidArgument),
+    }
+}
+
+fn cmd_screenshot(args: &[&str]) -> Result<()> {
+    match args {
+        [] => println!("saved {}", screenshot::save_next(SCREENSHOT_DIR)?),
+        ["send", args @ ..] => {
+            let format = match args.first() {
+                Some(name) => Format::parse(name).ok_or(Error::InvalidArgument)?,
+                None => Format::Bmp,
+            };
+            let port = match args.get(1) {
+                Some(name) => uart::parse_port(name).ok_or(Error::InvalidArgument)?,
+                None => UartPort::Pl011,
+            };
+            screenshot::send(port, format)?;
+        }
+        [path] => println!("saved {} bytes", screenshot::save(path)?),
+        _ => return Err(Error::InvalidArgument),
+    }
+
+    Ok(())
+}
+
+fn cmd_console(args: &[&str]) -> Result<()> {
+    match args {
+        [] => {
+            for info in console::list()? {
+                println!(
+                    "{:<8} {:<8} {}",
+                    info.name,
+                    if info.enabled { "enabled" } else { "disabled" },
+                    if info.primary { "primary" } else { "" }
+                );
+            }
+        }
+        ["log"] => print!("{}", String::from_utf8_lossy(&console::memory_log()?)),
+        [name, "on"] => console::set_enabled(name, true)?,
+        [name, "off"] => console::set_enabled(name, false)?,
+        [name, "primary"] => console::set_primary(name)?,
+        _ => return Err(Error::InvalidArgument),
+    }
+
+    Ok(())
+}
+
+fn cmd_dmesg(_args: &[&str]) -> Result<()> {
+    let dropped = log::dropped_count()?;
+    if dropped > 0 {
+        println!("({} older messages dropped)", dropped);
+    }
+
+    for record in log::records()? {
+        println!("{}", record);
+    }
+
+    Ok(())
+}
+
+fn cmd_log(args: &[&str]) -> Result<()> {
+    let parse_level = |s: &str| Level::parse(s).ok_or(Error::InvalidArgument);
+
+    match args {
+        [] => {
+            println!("{:<24} {}", "*", log::max_level()?.as_str());
+            for (module, level) in log::module_levels()? {
+                println!("{:<24} {}", module, level.as_str());
+            }
+        }
+        [level] => log::set_max_level(parse_level(level)?)?,
+        [module, level] => log::set_module_level(module, parse_level(level)?)?,
+        _ => return Err(Error::InvalidArgument),
+    }
+
+    Ok(())
+}
+
+// returns the address and the size of the symbol if known
+fn symbol_address(s: &str) -> Result<(u64, Option<u64>)> {
+    match symbols::find(s) {
+        Some(symbol) => Ok((symbol.addr, Some(symbol.size).filter(|&size| size != 0))),
+        None => Ok((parse_number(s)?, None)),
+    }
+}
+
+// the longest range at the start of an object that one watchpoint can cover, either
+// bytes within a doubleword or an aligned power of two, see Watchpoint::encode
+fn watch_len(addr: u64, size: u64) -> u64 {
+    (3..=31)
+        .rev()
+        .map(|shift| 1 << shift)
+        .find(|&len| len <= size && addr % len == 0)
+        .unwrap_or(size.min(8 - addr % 8))
+}
+
+fn print_hit(hits: u64, last_pc: u64) {
+    match symbols::lookup(last_pc) {
+        Some((symbol, offset)) if hits != 0 => {
+            println!(
+                " hits {} last 0x{:x} {}+0x{:x}",
+                hits, last_pc, symbol.name, offset
+            )
+        }
+        _ if hits != 0 => println!(" hits {} last 0x{:x}", hits, last_pc),
+        _ => println!(),
+    }
+}
+
+fn cmd_break(args: &[&str]) -> Result<()> {
+    match args {
+        [] => {
+            for bp in breakpoint::breakpoints()? {
+                print!("{}: 0x{:016x}", bp.index, bp.value);
+                print_hit(bp.hits, bp.last_pc);
+            }
+        }
+        ["del", index] => breakpoint::remove_breakpoint(parse_number(index)? as usize)?,
+        [addr] => {
+            let (addr, _) = symbol_address(addr)?;
+            let index = breakpoint::add_breakpoint(addr)?;
+            println!("breakpoint {} at 0x{:x}", index, addr);
+        }
+        _ => return Err(Error::InvalidArgument),
+    }
+
+    Ok(())
+}
+
+fn cmd_watch(args: &[&str]) -> Result<()> {
+    match args {
+        [] => {
+            for wp in breakpoint::watchpoints()? {
+                let Watchpoint { addr, len, kind } = wp.value;
+                print!(
+                    "{}: 0x{:016x} len {} {}",
+                    wp.index,
+                    addr,
+                    len,
+                    kind.as_str()
+                );
+                print_hit(wp.hits, wp.last_pc);
+            }
+        }
+        ["del", index] => breakpoint::remove_watchpoint(parse_number(index)? as usize)?,
+        [addr, rest @ ..] if rest.len() <= 2 => {
+            let (addr, size) = symbol_address(addr)?;
+            // the kind may be given without a length
+            let (len, kind) = match rest {
+                [] => (None, None),
+                [kind] if WatchKind::parse(kind).is_some() => (None, Some(*kind)),
+                [len] => (Some(*len), None),
+                [len, kind] => (Some(*len), Some(*kind)),
+                _ => return Err(Error::InvalidArgument),
+            };
+            let len = match len {
+                Some(len) => parse_number(len)?,
+                None => {
+                    let len = watch_len(addr, size.unwrap_or(8));
+                    if let Some(size) = size.filter(|&size| size > len) {
+                        println!("only the first {} of {} bytes are watched", len, size);
+                    }
+                    len
+                }
+            };
+            let kind = match kind {
+                Some(kind) => WatchKind::parse(kind).ok_or(Error::InvalidArgument)?,
+                None => WatchKind::Write,
+            };
+
+            let index = breakpoint::add_watchpoint(addr, len, kind)?;
+            println!(
+                "watchpoint {} at 0x{:x} len {} {}",
+                index,
+                addr,
+                len,
+                kind.as_str()
+            );
+        }
+        _ => return Err(Error::InvalidArgument),
+    }
+
+    Ok(())
+}
+
+fn cmd_gdb(args: &[&str]) -> Result<()> {
+    if let Some(port) = args.first() {
+        gdb::init(uart::parse_port(port).ok_or(Error::InvalidArgument)?)?;
+    }
+
+    println!("Waiting for GDB...");
+    gdb::enter()
+}
+
+fn cmd_uptime(_args: &[&str]) -> Result<()> {
+    let ms = timer::uptime_ms();
+    println!("uptime: {}.{:03}s", ms / 1000, ms % 1000);
+    Ok(())
+}
+
+fn cmd_reboot(_args: &[&str]) -> Result<()> {
+    println!("Rebooting...");
+    if let Err(err) = vfs::sync().and_then(|_| block::sync_all()) {
+        println!("sync failed: {:?}", err);
+    }
+
+    power::reboot()
+}
+
+pub fn execute(line: &str) -> Result<()> {
+    let args: Vec<&str> = line.split_whitespace().collect();
+    let name = match args.first() {
+        Some(name) => *name,
+        None => return Ok(()),
+    };
+
+    let command = COMMANDS
+        .iter()
+        .find(|c| c.name == name)
+        .ok_or("Unknown command, try \"help\"")?;
+
+    let res = (command.func)(&args[1..]);
+    if res == Err(Error::InvalidArgument) {
+        println!("usage: {}", command.usage);
+    }
+
+    res
+}
+
+// runs each line of a script, blank lines and lines starting with '#' are skipped
+pub fn run_script(path: &str) -> Result<()> {
+    let data = vfs::read_to_end(path)?;
+    let script = core::str::from_utf8(&data).map_err(|_| "Script is not valid UTF-8")?;
+
+    for line in script.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        println!("{}{}", PROMPT, line);
+        if let Err(err) = execute(line) {
+            println!("error: {:?}", err);
+        }
+    }
+
+    Ok(())
+}
+
+fn common_prefix(candidates: &[String]) -> String {
+    let mut prefix = candidates[0].clone();
+    for candidate in candidates[1..].iter() {
+        let len = prefix
+            .chars()
+            .zip(candidate.chars())
+            .take_while(|(a, b)| a == b)
+            .map(|(a, _)| a.len_utf8())
+            .sum();
+        prefix.truncate(len);
+    }
+    prefix
+}
+
+struct LineEditor {
+    buf: Vec<char>,
+    cursor: usize,
+    history: Vec<String>,
+    // position while browsing the history, history.len() is the line being edited
+    history_index: usize,
+    saved_line: Vec<char>,
+}
+
+impl LineEditor {
+    const fn new() -> Self {
+        Self {
+            buf: Vec::new(),
+            cursor: 0,
+            history: Vec::new(),
+            history_index: 0,
+            saved_line: Vec::new(),
+        }
+    }
+
+    fn refresh(&self) {
+        let line: String = self.buf.iter().collect();
+        print!("\r{}{}\x1b[K", PROMPT, line);
+
+        let back = self.buf.len() - self.cursor;
+        if back > 0 {
+            print!("\x1b[{}D", back);
+        }
+    }
+
+    fn set_line(&mut self, line: Vec<char>) {
+        self.buf = line;
+        self.cursor = self.buf.len();
+        self.refresh();
+    }
+
+    fn insert(&mut self, c: char) {
+        self.buf.insert(self.cursor, c);
+        self.cursor += 1;
+        self.refresh();
+    }
+
+    fn history_prev(&mut self) {
+        if self.history_index == 0 {
+            return;
+        }
+
+        if self.history_index == self.history.len() {
+            self.saved_line = self.buf.clone();
+        }
+        self.history_index -= 1;
+        self.set_line(self.history[self.history_index].chars().collect());
+    }
+
+    fn history_next(&mut self) {
+        if self.history_index >= self.history.len() {
+            return;
+        }
+
+        self.history_index += 1;
+        let line = match self.history.get(self.history_index) {
+            Some(line) => line.chars().collect(),
+            None => self.saved_line.clone(),
+        };
+        self.set_line(line);
+    }
+
+    fn push_history(&mut self, line: &str) {
+        if !line.trim().is_empty() && self.history.last().map(|l| l.as_str()) != Some(line) {
+            if self.history.len() == HISTORY_SIZE {
+                self.history.remove(0);
+            }
+            self.history.push(line.to_string());
+        }
+        self.history_index = self.history.len();
+    }
+
+    fn completions(&self, word: &str, is_command: bool) -> Vec<String> {
+        if is_command {
+            return COMMANDS
+                .iter()
+                .filter(|c| c.name.starts_with(word))
+                .map(|c| [c.name, " "].concat())
+                .collect();
+        }
+
+        // only absolute paths are completed
+        let (dir, prefix) = match word.rfind('/') {
+            Some(i) => (&word[..i + 1], &word[i + 1..]),
+            None => return Vec::new(),
+        };
+
+        vfs::read_dir(dir)
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|e| e.name.starts_with(prefix))
+            .map(|e| {
+                let suffix = if e.file_type == FileType::Directory {
+                    "/"
+                } else {
+                    " "
+                };
+                [dir, &e.name, suffix].concat()
+            })
+            .collect()
+    }
+
+    fn complete(&mut self) {
+        let start = self.buf[..self.cursor]
+            .iter()
+            .rposition(|c| c.is_whitespace())
+            .map_or(0, |i| i + 1);
+        let is_command = self.buf[..start].iter().all(|c| c.is_whitespace());
+        let word: String = self.buf[start..self.cursor].iter().collect();
+
+        let candidates = self.completions(&word, is_command);
+        if candidates.is_empty() {
+            return;
+        }
+
+        let completion = if candidates.len() == 1 {
+            candidates[0].clone()
+        } else {
+            common_prefix(&candidates)
+        };
+
+        if completion.chars().count() > word.chars().count() {
+            self.buf.splice(start..self.cursor, completion.chars());
+            self.cursor = start + completion.chars().count();
+        } else {
+            println!();
+            for candidate in candidates.iter() {
+                print!("{}  ", candidate.trim_end());
+            }
+            println!();
+        }
+
+        self.refresh();
+    }
+
+    // reads the rest of an escape sequence, ESC [ <params> <final> or ESC O <final>
+    fn escape(&mut self) -> Result<()> {
+        let mut params = String::new();
+        let c = match console::receive()? {
+            '[' | 'O' => loop {
+                let c = console::receive()?;
+                if c.is_ascii_digit() || c == ';' {
+                    params.push(c);
+                } else {
+                    break c;
+                }
+            },
+            _ => return Ok(()),
+        };
+
+        match (c, params.as_str()) {
+            ('A', _) => self.history_prev(),
+            ('B', _) => self.history_next(),
+            ('C', _) if self.cursor < self.buf.len() => {
+                self.cursor += 1;
+                self.refresh();
+            }
+            ('D', _) if self.cursor > 0 => {
+                self.cursor -= 1;
+                self.refresh();
+            }
+            ('H', _) | ('~', "1") | ('~', "7") => {
+                self.cursor = 0;
+                self.refresh();
+            }
+            ('F', _) | ('~', "4") | ('~', "8") => {
+                self.cursor = self.buf.len();
+                self.refresh();
+            }
+            // Shift+PageUp and Shift+PageDown page through the framebuffer console
+            ('~', "5;2") => {
+                let _ = framebuffer_console::page_up();
+            }
+            ('~', "6;2") => {
+                let _ = framebuffer_console::page_down();
+            }
+            // F12 saves a screenshot, taken before the message is printed
+            ('~', "24") => {
+                let res = screenshot::save_next(SCREENSHOT_DIR);
+                print!("\r\x1b[K");
+                match res {
+                    Ok(path) => println!("saved {}", path),
+                    Err(err) => println!("error: {:?}", err),
+                }
+                self.refresh();
+            }
+            ('~', "3") if self.cursor < self.buf.len() => {
+                self.buf.remove(self.cursor);
+                self.refresh();
+            }
+            _ => (),
+        }
+
+        Ok(())
+    }
+
+    fn read_line(&mut self) -> Result<String> {
+        self.buf.clear();
+        self.cursor = 0;
+        self.history_index = self.history.len();
+        print!("{}", PROMPT);
+
+        loop {
+            match console::receive()? {
+                '\r' | '\n' => break,
+                KEY_BACKSPACE | KEY_DEL => {
+                    if self.cursor > 0 {
+                        self.cursor -= 1;
+                        self.buf.remove(self.cursor);
+                        self.refresh();
+                    }
+                }
+                KEY_TAB => self.complete(),
+                KEY_ESC => self.escape()?,
+                KEY_CTRL_A => {
+                    self.cursor = 0;
+                    self.refresh();
+                }
+                KEY_CTRL_E => {
+                    self.cursor = self.buf.len();
+                    self.refresh();
+                }
+                KEY_CTRL_C => {
+                    println!("^C");
+                    self.buf.clear();
+                    self.cursor = 0;
+                    print!("{}", PROMPT);
+                }
+                KEY_CTRL_L => {
+                    print!("\x1b[2J\x1b[H");
+                    self.refresh();
+                }
+                c if !c.is_control() => self.insert(c),
+                _ => (),
+            }
+        }
+
+        println!();
+        let line: String = self.buf.iter().collect();
+        self.push_history(&line);
+        Ok(line)
+    }
+}
+
+pub fn run() -> ! {
+    let mut editor = LineEditor::new();
+
+    loop {
+        let line = match editor.read_line() {
+            Ok(line) => line,
+            Err(_) => continue,
+        };
+
+        if let Err(err) = execute(&line) {
+            println!("error: {:?}", err);
+        }
+    }
+}
@@ -0,0 +1,88 @@
+use core::str;
+
+// generated by gen_ksyms in task.py, little endian:
+//   "KSYM", count: u32, count * (addr: u64, size: u32, name_offset: u32), NUL terminated names
+static KSYMS: &[u8] = include_bytes!(concat!(env!("OUT_DIR"), "/ksyms.bin"));
+
+const KSYMS_MAGIC: &[u8; 4] = b"KSYM";
+const HEADER_SIZE: usize = 8;
+const ENTRY_SIZE: usize = 16;
+
+#[derive(Debug, Clone, Copy)]
+pub struct Symbol {
+    pub name: &'static str,
+    pub addr: u64,
+    pub size: u64,
+}
+
+fn read_u32(offset: usize) -> u32 {
+    u32::from_le_bytes(KSYMS[offset..offset + 4].try_into().unwrap())
+}
+
+fn read_u64(offset: usize) -> u64 {
+    u64::from_le_bytes(KSYMS[offset..offset + 8].try_into().unwrap())
+}
+
+pub fn count() -> usize {
+    if KSYMS.len() < HEADER_SIZE || &KSYMS[..4] != KSYMS_MAGIC {
+        return 0;
+    }
+
+    let count = read_u32(4) as usize;
+    if KSYMS.len() < HEADER_SIZE + count * ENTRY_SIZE {
+        return 0;
+    }
+
+    count
+}
+
+fn symbol(index: usize) -> Symbol {
+    let entry = HEADER_SIZE + index * ENTRY_SIZE;
+    let names = HEADER_SIZE + count() * ENTRY_SIZE;
+    let name_start = (names + read_u32(entry + 12) as usize).min(KSYMS.len());
+    let name_len = KSYMS[name_start..]
+        .iter()
+        .position(|&b| b == 0)
+        .unwrap_or(KSYMS.len() - name_start);
+
+    Symbol {
+        name: str::from_utf8(&KSYMS[name_start..name_start + name_len]).unwrap_or("?"),
+        addr: read_u64(entry),
+        size: read_u32(entry + 8) as u64,
+    }
+}
+
+// by full path or by the last path component, e.g. "FB_CONSOLE"
+pub fn find(name: &str) -> Option<Symbol> {
+    (0..count()).map(symbol).find(|symbol| {
+        symbol.name == name
+            || symbol
+                .name
+                .strip_suffix(name)
+                .is_some_and(|prefix| prefix.ends_with("::"))
+    })
+}
+
+// returns the symbol containing addr and the offset into it
+pub fn lookup(addr: u64) -> Option<(Symbol, u64)> {
+    let count = count();
+
+    // entries are sorted by address, find the last one at or below addr
+    let (mut lo, mut hi) = (0, count);
+    while lo < hi {
+        let mid = (lo + hi) / 2;
+        if symbol(mid).addr <= addr {
+            lo = mid + 1;
+        } else {
+            hi = mid;
+        }
+    }
+
+    let symbol = symbol(lo.checked_sub(1)?);
+    let offset = addr - symbol.addr;
+    if symbol.size != 0 && offset >= symbol.size {
+        return None;
+    }
+
+    Some((symbol, offset))
+}
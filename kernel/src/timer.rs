@@ -0,0 +1,41 @@
+use crate::{addr::MmioAddress, asm};
+
+// BCM2835 system timer, free running 1MHz counter
+fn mmio_base_system_timer() -> MmioAddress {
+    MmioAddress::new(0x3000)
+}
+
+fn read_counter_lo() -> u32 {
+    mmio_base_system_timer().offset(0x04).read()
+}
+
+fn read_counter_hi() -> u32 {
+    mmio_base_system_timer().offset(0x08).read()
+}
+
+pub fn uptime_us() -> u64 {
+    loop {
+        let hi = read_counter_hi();
+        let lo = read_counter_lo();
+
+        // retry if the low word wrapped between the two reads
+        if hi == read_counter_hi() {
+            return (hi as u64) << 32 | lo as u64;
+        }
+    }
+}
+
+pub fn uptime_ms() -> u64 {
+    uptime_us() / 1000
+}
+
+pub fn wait_us(us: u64) {
+    let start = uptime_us();
+    while uptime_us() - start < us {
+        asm::wait_cycles(1);
+    }
+}
+
+pub fn wait_ms(ms: u64) {
+    wait_us(ms * 1000);
+}
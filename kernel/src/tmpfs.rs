@@ -0,0 +1,292 @@
+use crate::{
+    error::Result,
+    vfs::{DirEntry, FileSystem, FileSystemError, FileType, InodeId, Metadata},
+};
+use alloc::{
+    collections::BTreeMap,
+    string::{String, ToString},
+    vec::Vec,
+};
+
+const ROOT_INODE: InodeId = 1;
+
+enum TmpfsNodeData {
+    File(Vec<u8>),
+    Directory(BTreeMap<String, InodeId>),
+    Symlink(String),
+}
+
+struct TmpfsNode {
+    metadata: Metadata,
+    data: TmpfsNodeData,
+}
+
+impl TmpfsNode {
+    fn new(inode: InodeId, file_type: FileType) -> Result<Self> {
+        let (data, mode) = match file_type {
+            FileType::File => (TmpfsNodeData::File(Vec::new()), 0o644),
+            FileType::Directory => (TmpfsNodeData::Directory(BTreeMap::new()), 0o755),
+            FileType::Symlink => (TmpfsNodeData::Symlink(String::new()), 0o777),
+            _ => return Err(FileSystemError::Unsupported.into()),
+        };
+
+        Ok(Self {
+            metadata: Metadata::new(inode, file_type, 0, mode),
+            data,
+        })
+    }
+}
+
+// files live on the heap, running out of it is reported as a full filesystem
+fn resize_file(data: &mut Vec<u8>, size: usize) -> Result<()> {
+    if let Some(additional) = size.checked_sub(data.len()) {
+        data.try_reserve(additional)
+            .or_else(|_| data.try_reserve_exact(additional))
+            .map_err(|_| FileSystemError::NoSpace)?;
+    }
+
+    data.resize(size, 0);
+    Ok(())
+}
+
+pub struct Tmpfs {
+    nodes: BTreeMap<InodeId, TmpfsNode>,
+    next_inode: InodeId,
+}
+
+impl Default for Tmpfs {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Tmpfs {
+    pub fn new() -> Self {
+        let mut nodes = BTreeMap::new();
+        nodes.insert(
+            ROOT_INODE,
+            TmpfsNode::new(ROOT_INODE, FileType::Directory).unwrap(),
+        );
+
+        Self {
+            nodes,
+            next_inode: ROOT_INODE + 1,
+        }
+    }
+
+    fn node(&self, inode: InodeId) -> Result<&TmpfsNode> {
+        Ok(self.nodes.get(&inode).ok_or(FileSystemError::NotFound)?)
+    }
+
+    fn node_mut(&mut self, inode: InodeId) -> Result<&mut TmpfsNode> {
+        Ok(self
+            .nodes
+            .get_mut(&inode)
+            .ok_or(FileSystemError::NotFound)?)
+    }
+
+    fn dir_entries(&self, dir: InodeId) -> Result<&BTreeMap<String, InodeId>> {
+        match &self.node(dir)?.data {
+            TmpfsNodeData::Directory(entries) => Ok(entries),
+            _ => Err(FileSystemError::NotDirectory.into()),
+        }
+    }
+
+    fn dir_entries_mut(&mut self, dir: InodeId) -> Result<&mut BTreeMap<String, InodeId>> {
+        match &mut self.node_mut(dir)?.data {
+            TmpfsNodeData::Directory(entries) => Ok(entries),
+            _ => Err(FileSystemError::NotDirectory.into()),
+        }
+    }
+
+    fn file_data_mut(&mut self, inode: InodeId) -> Result<&mut Vec<u8>> {
+        match &mut self.node_mut(inode)?.data {
+            TmpfsNodeData::File(data) => Ok(data),
+            TmpfsNodeData::Directory(_) => Err(FileSystemError::IsDirectory.into()),
+            TmpfsNodeData::Symlink(_) => Err(FileSystemError::Unsupported.into()),
+        }
+    }
+
+    fn is_empty_dir(&self, inode: InodeId) -> Result<bool> {
+        Ok(self.dir_entries(inode)?.is_empty())
+    }
+
+    fn add_node(&mut self, dir: InodeId, name: &str, file_type: FileType) -> Result<InodeId> {
+        if name.is_empty() || name.contains('/') {
+            return Err(FileSystemError::InvalidPath.into());
+        }
+
+        if self.dir_entries(dir)?.contains_key(name) {
+            return Err(FileSystemError::AlreadyExists.into());
+        }
+
+        let inode = self.next_inode;
+        self.nodes.insert(inode, TmpfsNode::new(inode, file_type)?);
+        self.next_inode += 1;
+        self.dir_entries_mut(dir)?.insert(name.to_string(), inode);
+
+        Ok(inode)
+    }
+
+    fn remove_node(&mut self, inode: InodeId) {
+        if let Some(TmpfsNode {
+            data: TmpfsNodeData::Directory(entries),
+            ..
+        }) = self.nodes.remove(&inode)
+        {
+            for (_, child) in entries {
+                self.remove_node(child);
+            }
+        }
+    }
+}
+
+impl FileSystem for Tmpfs {
+    fn name(&self) -> &'static str {
+        "tmpfs"
+    }
+
+    fn root(&self) -> InodeId {
+        ROOT_INODE
+    }
+
+    fn lookup(&mut self, dir: InodeId, name: &str) -> Result<InodeId> {
+        Ok(*self
+            .dir_entries(dir)?
+            .get(name)
+            .ok_or(FileSystemError::NotFound)?)
+    }
+
+    fn metadata(&mut self, inode: InodeId) -> Result<Metadata> {
+        let node = self.node(inode)?;
+        let mut metadata = node.metadata;
+        metadata.size = match &node.data {
+            TmpfsNodeData::File(data) => data.len(),
+            TmpfsNodeData::Directory(entries) => entries.len(),
+            TmpfsNodeData::Symlink(target) => target.len(),
+        };
+
+        Ok(metadata)
+    }
+
+    fn read(&mut self, inode: InodeId, offset: usize, buf: &mut [u8]) -> Result<usize> {
+        let data = self.file_data_mut(inode)?;
+        if offset >= data.len() {
+            return Ok(0);
+        }
+
+        let len = buf.len().min(data.len() - offset);
+        buf[..len].copy_from_slice(&data[offset..offset + len]);
+        Ok(len)
+    }
+
+    fn write(&mut self, inode: InodeId, offset: usize, buf: &[u8]) -> Result<usize> {
+        let data = self.file_data_mut(inode)?;
+        let end = offset
+            .checked_add(buf.len())
+            .ok_or(FileSystemError::NoSpace)?;
+        if data.len() < end {
+            resize_file(data, end)?;
+        }
+
+        data[offset..end].copy_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn read_dir(&mut self, dir: InodeId) -> Result<Vec<DirEntry>> {
+        let entries = self.dir_entries(dir)?;
+        let mut dir_entries = Vec::new();
+
+        for (name, &inode) in entries {
+            dir_entries.push(DirEntry {
+                name: name.clone(),
+                inode,
+                file_type: self.node(inode)?.metadata.file_type,
+            });
+        }
+
+        Ok(dir_entries)
+    }
+
+    fn create(&mut self, dir: InodeId, name: &str, file_type: FileType) -> Result<InodeId> {
+        match file_type {
+            FileType::File | FileType::Directory => self.add_node(dir, name, file_type),
+            _ => Err(FileSystemError::Unsupported.into()),
+        }
+    }
+
+    fn unlink(&mut self, dir: InodeId, name: &str) -> Result<()> {
+        let inode = self.lookup(dir, name)?;
+        if self.node(inode)?.metadata.file_type == FileType::Directory {
+            return Err(FileSystemError::IsDirectory.into());
+        }
+
+        self.dir_entries_mut(dir)?.remove(name);
+        self.remove_node(inode);
+        Ok(())
+    }
+
+    fn rmdir(&mut self, dir: InodeId, name: &str) -> Result<()> {
+        let inode = self.lookup(dir, name)?;
+        if !self.is_empty_dir(inode)? {
+            return Err(FileSystemError::DirectoryNotEmpty.into());
+        }
+
+        self.dir_entries_mut(dir)?.remove(name);
+        self.remove_node(inode);
+        Ok(())
+    }
+
+    fn rename(
+        &mut self,
+        src_dir: InodeId,
+        src_name: &str,
+        dst_dir: InodeId,
+        dst_name: &str,
+    ) -> Result<()> {
+        let inode = self.lookup(src_dir, src_name)?;
+        let is_dir = self.node(inode)?.metadata.file_type == FileType::Directory;
+
+        // replace the destination if it exists
+        if let Ok(dst_inode) = self.lookup(dst_dir, dst_name) {
+            if dst_inode == inode {
+                return Ok(());
+            }
+
+            let dst_is_dir = self.node(dst_inode)?.metadata.file_type == FileType::Directory;
+            match (is_dir, dst_is_dir) {
+                (true, false) => return Err(FileSystemError::NotDirectory.into()),
+                (false, true) => return Err(FileSystemError::IsDirectory.into()),
+                (true, true) if !self.is_empty_dir(dst_inode)? => {
+                    return Err(FileSystemError::DirectoryNotEmpty.into())
+                }
+                _ => (),
+            }
+
+            self.remove_node(dst_inode);
+        }
+
+        self.dir_entries_mut(dst_dir)?;
+        self.dir_entries_mut(src_dir)?.remove(src_name);
+        self.dir_entries_mut(dst_dir)?
+            .insert(dst_name.to_string(), inode);
+        Ok(())
+    }
+
+    fn truncate(&mut self, inode: InodeId, size: usize) -> Result<()> {
+        resize_file(self.file_data_mut(inode)?, size)
+    }
+
+    fn symlink(&mut self, dir: InodeId, name: &str, target: &str) -> Result<InodeId> {
+        let inode = self.add_node(dir, name, FileType::Symlink)?;
+        self.node_mut(inode)?.data = TmpfsNodeData::Symlink(target.to_string());
+        Ok(inode)
+    }
+
+    fn read_link(&mut self, inode: InodeId) -> Result<String> {
+        match &self.node(inode)?.data {
+            TmpfsNodeData::Symlink(target) => Ok(target.clone()),
+            _ => Err(FileSystemError::InvalidPath.into()),
+        }
+    }
+}
@@ -1,4 +1,12 @@
-use crate::{addr::MmioAddress, asm, error::Result, gpio, mailbox, mutex::Mutex};
+use crate::{
+    addr::MmioAddress,
+    asm,
+    console::{self, ConsoleBackend},
+    error::{Error, Result},
+    gpio, mailbox,
+    mutex::{Mutex, MutexGuard},
+};
+use alloc::boxed::Box;
 
 static mut MINI_UART: Mutex<MiniUart> = Mutex::new(MiniUart::new());
 static mut PL011_UART: Mutex<Pl011Uart> = Mutex::new(Pl011Uart::new());
@@ -118,11 +126,15 @@ impl Pl011UartIoRegister {
 
 struct MiniUart {
     io_register: Option<MiniUartIoRegister>,
+    initialized: bool,
 }
 
 impl MiniUart {
     const fn new() -> Self {
-        Self { io_register: None }
+        Self {
+            io_register: None,
+            initialized: false,
+        }
     }
 
     fn io_register(&mut self) -> &MiniUartIoRegister {
@@ -160,11 +172,12 @@ impl MiniUart {
         gpio::write_gppudclk0(0); // deassert clock
 
         io_register.write_aux_mu_cntl(0x03); // enable TX and RX
+        self.initialized = true;
     }
 
-    fn send(&mut self, c: char) {
-        if c == '\n' {
-            self.send('\r');
+    fn send_byte(&mut self, b: u8) {
+        if b == b'\n' {
+            self.send_byte(b'\r');
         }
 
         let io_register = self.io_register();
@@ -178,19 +191,21 @@ impl MiniUart {
             asm::wait_cycles(1);
         }
 
-        io_register.write_aux_mu_io(c as u32);
+        io_register.write_aux_mu_io(b as u32);
     }
 
-    fn receive(&mut self) -> char {
-        let io_register = self.io_register();
+    fn send(&mut self, c: char) {
+        let mut buf = [0; 4];
+        for b in c.encode_utf8(&mut buf).bytes() {
+            self.send_byte(b);
+        }
+    }
 
-        // wait
-        loop {
-            if io_register.read_aux_mu_lsr() & 0x01 != 0 {
-                break;
-            }
+    fn try_receive(&mut self) -> Option<char> {
+        let io_register = self.io_register();
 
-            asm::wait_cycles(1);
+        if io_register.read_aux_mu_lsr() & 0x01 == 0 {
+            return None;
         }
 
         let mut c = io_register.read_aux_mu_io() as u8 as char;
@@ -199,7 +214,18 @@ impl MiniUart {
             c = '\n';
         }
 
-        c
+        Some(c)
+    }
+
+    fn receive(&mut self) -> char {
+        // wait
+        loop {
+            if let Some(c) = self.try_receive() {
+                return c;
+            }
+
+            asm::wait_cycles(1);
+        }
     }
 
     fn puts(&mut self, s: &str) {
@@ -255,9 +281,9 @@ impl Pl011Uart {
         Ok(())
     }
 
-    fn send(&mut self, c: char) {
-        if c == '\n' {
-            self.send('\r');
+    fn send_byte(&mut self, b: u8) {
+        if b == b'\n' {
+            self.send_byte(b'\r');
         }
 
         let io_register = self.io_register();
@@ -271,19 +297,21 @@ impl Pl011Uart {
             asm::wait_cycles(1);
         }
 
-        io_register.write_dr(c as u32);
+        io_register.write_dr(b as u32);
     }
 
-    fn receive(&mut self) -> char {
-        let io_register = self.io_register();
+    fn send(&mut self, c: char) {
+        let mut buf = [0; 4];
+        for b in c.encode_utf8(&mut buf).bytes() {
+            self.send_byte(b);
+        }
+    }
 
-        // wait
-        loop {
-            if io_register.read_fr() & 0x10 == 0 {
-                break;
-            }
+    fn try_receive(&mut self) -> Option<char> {
+        let io_register = self.io_register();
 
-            asm::wait_cycles(1);
+        if io_register.read_fr() & 0x10 != 0 {
+            return None;
         }
 
         let mut c = io_register.read_dr() as u8 as char;
@@ -292,7 +320,18 @@ impl Pl011Uart {
             c = '\n';
         }
 
-        c
+        Some(c)
+    }
+
+    fn receive(&mut self) -> char {
+        // wait
+        loop {
+            if let Some(c) = self.try_receive() {
+                return c;
+            }
+
+            asm::wait_cycles(1);
+        }
     }
 
     fn puts(&mut self, s: &str) {
@@ -302,32 +341,166 @@ impl Pl011Uart {
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UartPort {
+    Pl011,
+    Mini,
+}
+
+pub struct UartConsole {
+    port: UartPort,
+    initialized: bool,
+}
+
+impl UartConsole {
+    // the PL011 is set up by the firmware
+    pub const fn new(port: UartPort) -> Self {
+        Self {
+            port,
+            initialized: matches!(port, UartPort::Pl011),
+        }
+    }
+
+    fn ensure_initialized(&mut self) -> Result<()> {
+        if !self.initialized {
+            init_port(self.port)?;
+            self.initialized = true;
+        }
+        Ok(())
+    }
+}
+
+impl ConsoleBackend for UartConsole {
+    fn name(&self) -> &'static str {
+        port_name(self.port)
+    }
+
+    fn write_str(&mut self, s: &str) -> Result<()> {
+        self.ensure_initialized()?;
+        puts_port(self.port, s)
+    }
+
+    fn has_input(&self) -> bool {
+        true
+    }
+
+    fn try_receive(&mut self) -> Result<Option<char>> {
+        self.ensure_initialized()?;
+        try_receive_port(self.port)
+    }
+}
+
+pub fn port_name(port: UartPort) -> &'static str {
+    match port {
+        UartPort::Pl011 => "pl011",
+        UartPort::Mini => "mini",
+    }
+}
+
+pub fn parse_port(name: &str) -> Option<UartPort> {
+    match name {
+        "pl011" => Some(UartPort::Pl011),
+        "mini" => Some(UartPort::Mini),
+        _ => None,
+    }
+}
+
 pub fn init() -> Result<()> {
-    // unsafe { MINI_UART.try_lock() }?.init();
-    unsafe { PL011_UART.try_lock() }?.init()?;
-    Ok(())
+    init_port(UartPort::Pl011)
+}
+
+// the mini UART takes over the PL011 pins once used, so it starts disabled
+pub fn register_consoles() -> Result<()> {
+    console::register(Box::new(UartConsole::new(UartPort::Pl011)), true)?;
+    console::register(Box::new(UartConsole::new(UartPort::Mini)), false)
 }
 
 pub fn receive() -> Result<char> {
-    // let c = unsafe { MINI_UART.try_lock() }?.receive();
-    let c = unsafe { PL011_UART.try_lock() }?.receive();
-    Ok(c)
+    receive_port(UartPort::Pl011)
 }
 
 pub fn send(c: char) -> Result<()> {
-    // unsafe { MINI_UART.try_lock() }?.send(c);
-    unsafe { PL011_UART.try_lock() }?.send(c);
-    Ok(())
+    send_port(UartPort::Pl011, c)
 }
 
 pub fn puts(s: &str) -> Result<()> {
-    // unsafe { MINI_UART.try_lock() }?.puts(s);
-    unsafe { PL011_UART.try_lock() }?.puts(s);
+    puts_port(UartPort::Pl011, s)
+}
+
+// the transmitter never becomes ready while the mini UART is disabled
+fn mini_uart() -> Result<MutexGuard<'static, MiniUart>> {
+    let uart = unsafe { MINI_UART.try_lock() }?;
+    if !uart.initialized {
+        return Err(Error::NotInitialized);
+    }
+    Ok(uart)
+}
+
+pub fn init_port(port: UartPort) -> Result<()> {
+    match port {
+        UartPort::Pl011 => unsafe { PL011_UART.try_lock() }?.init()?,
+        UartPort::Mini => unsafe { MINI_UART.try_lock() }?.init(),
+    }
+
+    Ok(())
+}
+
+pub fn receive_port(port: UartPort) -> Result<char> {
+    let c = match port {
+        UartPort::Pl011 => unsafe { PL011_UART.try_lock() }?.receive(),
+        UartPort::Mini => mini_uart()?.receive(),
+    };
+
+    Ok(c)
+}
+
+pub fn try_receive_port(port: UartPort) -> Result<Option<char>> {
+    let c = match port {
+        UartPort::Pl011 => unsafe { PL011_UART.try_lock() }?.try_receive(),
+        UartPort::Mini => mini_uart()?.try_receive(),
+    };
+
+    Ok(c)
+}
+
+pub fn send_port(port: UartPort, c: char) -> Result<()> {
+    match port {
+        UartPort::Pl011 => unsafe { PL011_UART.try_lock() }?.send(c),
+        UartPort::Mini => mini_uart()?.send(c),
+    }
+
+    Ok(())
+}
+
+pub fn puts_port(port: UartPort, s: &str) -> Result<()> {
+    match port {
+        UartPort::Pl011 => unsafe { PL011_UART.try_lock() }?.puts(s),
+        UartPort::Mini => mini_uart()?.puts(s),
+    }
+
+    Ok(())
+}
+
+pub fn write_bytes(port: UartPort, buf: &[u8]) -> Result<()> {
+    match port {
+        UartPort::Pl011 => {
+            let mut uart = unsafe { PL011_UART.try_lock() }?;
+            for &b in buf {
+                uart.send_byte(b);
+            }
+        }
+        UartPort::Mini => {
+            let mut uart = mini_uart()?;
+            for &b in buf {
+                uart.send_byte(b);
+            }
+        }
+    }
+
     Ok(())
 }
 
+// lock free, for use when the console is busy
 pub fn debug_puts(s: &str) {
-    unsafe { PL011_DEBUG_UART.puts("[DEBUG]: ") };
     unsafe { PL011_DEBUG_UART.puts(s) };
-    unsafe { PL011_DEBUG_UART.puts("\n") };
 }
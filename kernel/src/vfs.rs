@@ -0,0 +1,498 @@
+use crate::{
+    error::{Error, Result},
+    mutex::Mutex,
+};
+use alloc::{
+    boxed::Box,
+    string::{String, ToString},
+    vec::Vec,
+};
+
+const MAX_SYMLINK_DEPTH: usize = 8;
+
+static mut VFS: Mutex<Vfs> = Mutex::new(Vfs::new());
+
+pub type InodeId = u64;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileSystemError {
+    NotFound,
+    AlreadyExists,
+    NotDirectory,
+    IsDirectory,
+    DirectoryNotEmpty,
+    InvalidPath,
+    ReadOnly,
+    Unsupported,
+    NoSpace,
+    Busy,
+    CrossDevice,
+    TooManySymlinks,
+    Corrupted,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileType {
+    File,
+    Directory,
+    Symlink,
+    CharDevice,
+    BlockDevice,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Metadata {
+    pub inode: InodeId,
+    pub file_type: FileType,
+    pub size: usize,
+    pub mode: u16,
+    pub nlink: usize,
+    pub uid: u32,
+    pub gid: u32,
+    pub atime: u64,
+    pub mtime: u64,
+    pub ctime: u64,
+}
+
+impl Metadata {
+    pub const fn new(inode: InodeId, file_type: FileType, size: usize, mode: u16) -> Self {
+        Self {
+            inode,
+            file_type,
+            size,
+            mode,
+            nlink: 1,
+            uid: 0,
+            gid: 0,
+            atime: 0,
+            mtime: 0,
+            ctime: 0,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct DirEntry {
+    pub name: String,
+    pub inode: InodeId,
+    pub file_type: FileType,
+}
+
+// default implementations are for read-only file systems
+pub trait FileSystem {
+    fn name(&self) -> &'static str;
+    fn root(&self) -> InodeId;
+    fn lookup(&mut self, dir: InodeId, name: &str) -> Result<InodeId>;
+    fn metadata(&mut self, inode: InodeId) -> Result<Metadata>;
+    fn read(&mut self, inode: InodeId, offset: usize, buf: &mut [u8]) -> Result<usize>;
+    fn read_dir(&mut self, dir: InodeId) -> Result<Vec<DirEntry>>;
+
+    fn write(&mut self, _inode: InodeId, _offset: usize, _buf: &[u8]) -> Result<usize> {
+        Err(FileSystemError::ReadOnly.into())
+    }
+
+    fn create(&mut self, _dir: InodeId, _name: &str, _file_type: FileType) -> Result<InodeId> {
+        Err(FileSystemError::ReadOnly.into())
+    }
+
+    fn unlink(&mut self, _dir: InodeId, _name: &str) -> Result<()> {
+        Err(FileSystemError::ReadOnly.into())
+    }
+
+    fn rmdir(&mut self, _dir: InodeId, _name: &str) -> Result<()> {
+        Err(FileSystemError::ReadOnly.into())
+    }
+
+    fn rename(
+        &mut self,
+        _src_dir: InodeId,
+        _src_name: &str,
+        _dst_dir: InodeId,
+        _dst_name: &str,
+    ) -> Result<()> {
+        Err(FileSystemError::ReadOnly.into())
+    }
+
+    fn truncate(&mut self, _inode: InodeId, _size: usize) -> Result<()> {
+        Err(FileSystemError::ReadOnly.into())
+    }
+
+    fn symlink(&mut self, _dir: InodeId, _name: &str, _target: &str) -> Result<InodeId> {
+        Err(FileSystemError::ReadOnly.into())
+    }
+
+    fn read_link(&mut self, _inode: InodeId) -> Result<String> {
+        Err(FileSystemError::Unsupported.into())
+    }
+
+    fn ioctl(&mut self, _inode: InodeId, _cmd: u32, _arg: &mut [u8]) -> Result<usize> {
+        Err(FileSystemError::Unsupported.into())
+    }
+
+    fn sync(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+struct Mount {
+    path: Vec<String>,
+    fs: Box<dyn FileSystem>,
+}
+
+struct Vfs {
+    mounts: Vec<Mount>,
+}
+
+impl Vfs {
+    const fn new() -> Self {
+        Self { mounts: Vec::new() }
+    }
+
+    // returns the index of the mount with the longest matching prefix and the remaining components
+    fn find_mount<'a>(&self, components: &'a [String]) -> Result<(usize, &'a [String])> {
+        let mut found: Option<(usize, usize)> = None;
+
+        for (i, mount) in self.mounts.iter().enumerate() {
+            let len = mount.path.len();
+            if len > components.len() || mount.path[..] != components[..len] {
+                continue;
+            }
+
+            if found.is_none_or(|(_, found_len)| len >= found_len) {
+                found = Some((i, len));
+            }
+        }
+
+        let (index, len) = found.ok_or(FileSystemError::NotFound)?;
+        Ok((index, &components[len..]))
+    }
+
+    fn resolve(&mut self, path: &str, follow_last: bool) -> Result<(usize, InodeId)> {
+        let mut components = normalize(path)?;
+
+        for _ in 0..MAX_SYMLINK_DEPTH {
+            match self.walk(&components, follow_last)? {
+                Walk::Found(mount_index, inode) => return Ok((mount_index, inode)),
+                Walk::Symlink(next) => components = next,
+            }
+        }
+
+        Err(FileSystemError::TooManySymlinks.into())
+    }
+
+    fn walk(&mut self, components: &[String], follow_last: bool) -> Result<Walk> {
+        let (mount_index, rest) = self.find_mount(components)?;
+        let mount_len = components.len() - rest.len();
+        let fs = &mut self.mounts[mount_index].fs;
+        let mut inode = fs.root();
+
+        for (i, name) in rest.iter().enumerate() {
+            inode = fs.lookup(inode, name)?;
+
+            let is_last = i == rest.len() - 1;
+            if is_last && !follow_last {
+                break;
+            }
+
+            if fs.metadata(inode)?.file_type != FileType::Symlink {
+                continue;
+            }
+
+            let target = fs.read_link(inode)?;
+            let mut next = if target.starts_with('/') {
+                Vec::new()
+            } else {
+                components[..mount_len + i].to_vec()
+            };
+            next.push(target);
+            next.extend_from_slice(&rest[i + 1..]);
+
+            return Ok(Walk::Symlink(normalize(&join(&next))?));
+        }
+
+        Ok(Walk::Found(mount_index, inode))
+    }
+
+    // resolves the parent directory, returns (mount index, parent inode, file name)
+    fn resolve_parent(&mut self, path: &str) -> Result<(usize, InodeId, String)> {
+        let mut components = normalize(path)?;
+        let name = components.pop().ok_or(FileSystemError::InvalidPath)?;
+        let (mount_index, parent) = self.resolve(&join(&components), true)?;
+
+        if self.mounts[mount_index].fs.metadata(parent)?.file_type != FileType::Directory {
+            return Err(FileSystemError::NotDirectory.into());
+        }
+
+        Ok((mount_index, parent, name))
+    }
+
+    fn is_mount_point(&self, path: &str) -> Result<bool> {
+        let components = normalize(path)?;
+        Ok(self.mounts.iter().any(|m| m.path == components))
+    }
+
+    fn mount(&mut self, path: &str, fs: Box<dyn FileSystem>) -> Result<()> {
+        let components = normalize(path)?;
+
+        if self.mounts.iter().any(|m| m.path == components) {
+            return Err(FileSystemError::Busy.into());
+        }
+
+        if !components.is_empty() {
+            let (mount_index, inode) = self.resolve(path, true)?;
+            if self.mounts[mount_index].fs.metadata(inode)?.file_type != FileType::Directory {
+                return Err(FileSystemError::NotDirectory.into());
+            }
+        }
+
+        self.mounts.push(Mount {
+            path: components,
+            fs,
+        });
+        Ok(())
+    }
+
+    fn unmount(&mut self, path: &str) -> Result<Box<dyn FileSystem>> {
+        let components = normalize(path)?;
+        let index = self
+            .mounts
+            .iter()
+            .position(|m| m.path == components)
+            .ok_or(FileSystemError::NotFound)?;
+
+        // cannot unmount if there are other mounts under it
+        let nested = self.mounts.iter().any(|m| {
+            m.path.len() > components.len() && m.path[..components.len()] == components[..]
+        });
+        if nested {
+            return Err(FileSystemError::Busy.into());
+        }
+
+        // the filesystem stays mounted if its data cannot be written back
+        self.mounts[index].fs.sync()?;
+        Ok(self.mounts.remove(index).fs)
+    }
+}
+
+enum Walk {
+    Found(usize, InodeId),
+    Symlink(Vec<String>),
+}
+
+fn normalize(path: &str) -> Result<Vec<String>> {
+    if !path.starts_with('/') {
+        return Err(FileSystemError::InvalidPath.into());
+    }
+
+    let mut components: Vec<String> = Vec::new();
+    for c in path.split('/') {
+        match c {
+            "" | "." => (),
+            ".." => {
+                components.pop();
+            }
+            _ => components.push(c.to_string()),
+        }
+    }
+
+    Ok(components)
+}
+
+fn join(components: &[String]) -> String {
+    let mut path = String::from("/");
+    path.push_str(&components.join("/"));
+    path
+}
+
+pub fn mount(path: &str, fs: Box<dyn FileSystem>) -> Result<()> {
+    unsafe { VFS.try_lock() }?.mount(path, fs)
+}
+
+pub fn unmount(path: &str) -> Result<Box<dyn FileSystem>> {
+    unsafe { VFS.try_lock() }?.unmount(path)
+}
+
+pub fn mounts() -> Result<Vec<(String, &'static str)>> {
+    let vfs = unsafe { VFS.try_lock() }?;
+    Ok(vfs
+        .mounts
+        .iter()
+        .map(|m| (join(&m.path), m.fs.name()))
+        .collect())
+}
+
+pub fn metadata(path: &str) -> Result<Metadata> {
+    let mut vfs = unsafe { VFS.try_lock() }?;
+    let (mount_index, inode) = vfs.resolve(path, true)?;
+    vfs.mounts[mount_index].fs.metadata(inode)
+}
+
+pub fn symlink_metadata(path: &str) -> Result<Metadata> {
+    let mut vfs = unsafe { VFS.try_lock() }?;
+    let (mount_index, inode) = vfs.resolve(path, false)?;
+    vfs.mounts[mount_index].fs.metadata(inode)
+}
+
+pub fn read(path: &str, offset: usize, buf: &mut [u8]) -> Result<usize> {
+    let mut vfs = unsafe { VFS.try_lock() }?;
+    let (mount_index, inode) = vfs.resolve(path, true)?;
+    let fs = &mut vfs.mounts[mount_index].fs;
+
+    if fs.metadata(inode)?.file_type == FileType::Directory {
+        return Err(FileSystemError::IsDirectory.into());
+    }
+
+    fs.read(inode, offset, buf)
+}
+
+// devices can be endless, e.g. /dev/zero, so only regular files are read, generated files
+// report a size of 0 and are read until they end
+pub fn read_to_end(path: &str) -> Result<Vec<u8>> {
+    let metadata = metadata(path)?;
+    match metadata.file_type {
+        FileType::File => (),
+        FileType::Directory => return Err(FileSystemError::IsDirectory.into()),
+        _ => return Err(FileSystemError::Unsupported.into()),
+    }
+
+    let mut data = Vec::new();
+    data.try_reserve_exact(metadata.size)
+        .map_err(|_| "Out of memory")?;
+    let mut buf = [0; 512];
+
+    loop {
+        let len = read(path, data.len(), &mut buf)?;
+        if len == 0 {
+            break;
+        }
+        data.try_reserve(len).map_err(|_| "Out of memory")?;
+        data.extend_from_slice(&buf[..len]);
+    }
+
+    Ok(data)
+}
+
+pub fn write(path: &str, offset: usize, buf: &[u8]) -> Result<usize> {
+    let mut vfs = unsafe { VFS.try_lock() }?;
+    let (mount_index, inode) = vfs.resolve(path, true)?;
+    let fs = &mut vfs.mounts[mount_index].fs;
+
+    if fs.metadata(inode)?.file_type == FileType::Directory {
+        return Err(FileSystemError::IsDirectory.into());
+    }
+
+    fs.write(inode, offset, buf)
+}
+
+pub fn append(path: &str, buf: &[u8]) -> Result<usize> {
+    let size = metadata(path)?.size;
+    write(path, size, buf)
+}
+
+pub fn create(path: &str) -> Result<()> {
+    let mut vfs = unsafe { VFS.try_lock() }?;
+    let (mount_index, parent, name) = vfs.resolve_parent(path)?;
+    vfs.mounts[mount_index]
+        .fs
+        .create(parent, &name, FileType::File)?;
+    Ok(())
+}
+
+pub fn mkdir(path: &str) -> Result<()> {
+    let mut vfs = unsafe { VFS.try_lock() }?;
+    let (mount_index, parent, name) = vfs.resolve_parent(path)?;
+    vfs.mounts[mount_index]
+        .fs
+        .create(parent, &name, FileType::Directory)?;
+    Ok(())
+}
+
+pub fn symlink(target: &str, path: &str) -> Result<()> {
+    let mut vfs = unsafe { VFS.try_lock() }?;
+    let (mount_index, parent, name) = vfs.resolve_parent(path)?;
+    vfs.mounts[mount_index].fs.symlink(parent, &name, target)?;
+    Ok(())
+}
+
+pub fn read_link(path: &str) -> Result<String> {
+    let mut vfs = unsafe { VFS.try_lock() }?;
+    let (mount_index, inode) = vfs.resolve(path, false)?;
+    vfs.mounts[mount_index].fs.read_link(inode)
+}
+
+pub fn unlink(path: &str) -> Result<()> {
+    let mut vfs = unsafe { VFS.try_lock() }?;
+    let (mount_index, parent, name) = vfs.resolve_parent(path)?;
+    vfs.mounts[mount_index].fs.unlink(parent, &name)
+}
+
+pub fn rmdir(path: &str) -> Result<()> {
+    let mut vfs = unsafe { VFS.try_lock() }?;
+    if vfs.is_mount_point(path)? {
+        return Err(FileSystemError::Busy.into());
+    }
+
+    let (mount_index, parent, name) = vfs.resolve_parent(path)?;
+    vfs.mounts[mount_index].fs.rmdir(parent, &name)
+}
+
+pub fn rename(old_path: &str, new_path: &str) -> Result<()> {
+    let old_components = normalize(old_path)?;
+    let new_components = normalize(new_path)?;
+
+    // cannot move a directory into itself
+    if new_components.len() > old_components.len()
+        && new_components[..old_components.len()] == old_components[..]
+    {
+        return Err(Error::InvalidArgument);
+    }
+
+    let mut vfs = unsafe { VFS.try_lock() }?;
+    if vfs.is_mount_point(old_path)? || vfs.is_mount_point(new_path)? {
+        return Err(FileSystemError::Busy.into());
+    }
+
+    let (src_mount, src_dir, src_name) = vfs.resolve_parent(old_path)?;
+    let (dst_mount, dst_dir, dst_name) = vfs.resolve_parent(new_path)?;
+
+    if src_mount != dst_mount {
+        return Err(FileSystemError::CrossDevice.into());
+    }
+
+    vfs.mounts[src_mount]
+        .fs
+        .rename(src_dir, &src_name, dst_dir, &dst_name)
+}
+
+pub fn truncate(path: &str, size: usize) -> Result<()> {
+    let mut vfs = unsafe { VFS.try_lock() }?;
+    let (mount_index, inode) = vfs.resolve(path, true)?;
+    vfs.mounts[mount_index].fs.truncate(inode, size)
+}
+
+pub fn read_dir(path: &str) -> Result<Vec<DirEntry>> {
+    let mut vfs = unsafe { VFS.try_lock() }?;
+    let (mount_index, inode) = vfs.resolve(path, true)?;
+    let fs = &mut vfs.mounts[mount_index].fs;
+
+    if fs.metadata(inode)?.file_type != FileType::Directory {
+        return Err(FileSystemError::NotDirectory.into());
+    }
+
+    fs.read_dir(inode)
+}
+
+pub fn ioctl(path: &str, cmd: u32, arg: &mut [u8]) -> Result<usize> {
+    let mut vfs = unsafe { VFS.try_lock() }?;
+    let (mount_index, inode) = vfs.resolve(path, true)?;
+    vfs.mounts[mount_index].fs.ioctl(inode, cmd, arg)
+}
+
+pub fn sync() -> Result<()> {
+    let mut vfs = unsafe { VFS.try_lock() }?;
+    for mount in vfs.mounts.iter_mut() {
+        mount.fs.sync()?;
+    }
+
+    Ok(())
+}
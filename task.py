@@ -1,4 +1,6 @@
 import os
+import re
+import struct
 import subprocess
 import sys
 
@@ -11,6 +13,7 @@ KERNEL_FILE = "kernel8.img"
 KERNEL_OUT = "target/aarch64-raspi3-kernel/debug/kernel"
 DTB_FILE = "bcm2710-rpi-3-b-plus.dtb"
 FONT_FILE = "font.psf"
+KSYMS_FILE = "ksyms.bin"
 COZETTE_BDF = "cozette.bdf"
 
 ARCH_TOOLCHAIN = "aarch64-linux-gnu-"
@@ -87,9 +90,57 @@ def build_cozette():
         run_cmd(f"rm ./{COZETTE_BDF}", dir=d)
 
 
+# symbol table embedded into the kernel for backtraces, see kernel/src/symbols.rs
+def gen_ksyms(elf: str, out: str) -> bytes:
+    output = subprocess.check_output(
+        f"{ARCH_TOOLCHAIN}nm -n -S -C --defined-only {elf}", shell=True, text=True
+    )
+
+    symbols = []
+    for line in output.splitlines():
+        fields = line.split(maxsplit=3)
+        if len(fields) == 4:
+            addr, size, kind, name = fields
+        elif len(fields) == 3:
+            (addr, kind, name), size = fields, "0"
+        else:
+            continue
+
+        # code and data, statics can be watched by name
+        if kind not in "tTwWdDbBrR":
+            continue
+
+        # drop the hash of legacy Rust mangling
+        name = re.sub(r"::h[0-9a-f]{16}$", "", name)
+        symbols.append((int(addr, 16), int(size, 16), name))
+
+    entries = b""
+    names = b""
+    for addr, size, name in symbols:
+        entries += struct.pack("<QII", addr, size, len(names))
+        names += name.encode() + b"\0"
+
+    data = b"KSYM" + struct.pack("<I", len(symbols)) + entries + names
+    with open(out, "wb") as f:
+        f.write(data)
+
+    return data
+
+
 def build_kernel():
     d = f"./{KERNEL_DIR}"
-    run_cmd("cargo build", d)
+    ksyms = os.path.abspath(f"{OUTPUT_DIR}/{KSYMS_FILE}")
+
+    # embedding the table does not move code, so this settles after a second link
+    prev = None
+    for _ in range(3):
+        env = f"KSYMS={ksyms}" if prev is not None else "KSYMS="
+        run_cmd(f"{env} cargo build", d)
+        data = gen_ksyms(KERNEL_OUT, ksyms)
+        if data == prev:
+            break
+        prev = data
+
     run_cmd(
         f"{ARCH_TOOLCHAIN}objcopy --strip-all -O binary {KERNEL_OUT} {OUTPUT_DIR}/{KERNEL_FILE}"
     )